@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use thiserror::Error;
+use tokio::fs;
+use tokio::io;
+use utility::hash::hash::Hash;
+use utility::buffer::buffer_writer::BufferWriter;
+use utility::buffer::buffer_reader::BufferReader;
+use utility::buffer::buffer_reader::BufferReaderError;
+use utility::storage::chunk_store::ChunkStore;
+use utility::storage::chunk_store::ChunkStoreError;
+use crate::mainblock::mainblock::Mainblock;
+use crate::mainstate::mainstate::MainstateOutpoint;
+
+const ADDRESS_INDEX_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum AddressIndexError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("ChunkStore error: {0}")]
+    ChunkStoreError(#[from] ChunkStoreError),
+    #[error("Buffer reader error: {0}")]
+    BufferReaderError(#[from] BufferReaderError),
+
+    #[error("Unknown address index version: {0}")]
+    UnknownVersion(u32),
+    #[error("Mainblock {actual:?} is not the last indexed mainblock {expected:?}")]
+    TipMismatch { expected: Hash, actual: Hash },
+}
+
+/// The transaction spending an indexed output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressSpend {
+    pub tx_hash: Hash,
+    pub mainblock_height: u32,
+}
+
+/// An output paying to an address, with the transaction that spent it if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressIndexEntry {
+    pub tx_hash: Hash,
+    pub output_index: u32,
+    pub value: u64,
+    pub mainblock_height: u32,
+    pub spent_by: Option<AddressSpend>,
+}
+
+impl AddressIndexEntry {
+    pub fn is_unspent(&self) -> bool {
+        self.spent_by.is_none()
+    }
+}
+
+/// An output of an indexed mainblock paying to an address.
+struct AddressRecordOutput {
+    tx_hash: Hash,
+    output_index: u32,
+    address: Hash,
+    value: u64,
+}
+
+/// An indexed output spent by a transaction of an indexed mainblock.
+struct AddressRecordSpend {
+    outpoint: MainstateOutpoint,
+    address: Hash,
+    tx_hash: Hash,
+}
+
+/// What an indexed mainblock changed in the index, enough to replay it on load and to undo it.
+struct AddressRecord {
+    mainblock_hash: Hash,
+    outputs: Vec<AddressRecordOutput>,
+    spends: Vec<AddressRecordSpend>,
+}
+
+/// Index from an address to every confirmed output paying to it. Each indexed mainblock is a record of the
+/// `ChunkStore` at its height, holding the outputs it created and the outputs it spent, so that connecting
+/// or disconnecting a mainblock appends or removes a single record.
+pub struct AddressIndex {
+    ai_path: PathBuf,
+    ai_cs: ChunkStore,
+    /// Entries of each address in the order their outputs were confirmed.
+    entries: HashMap<Hash, Vec<AddressIndexEntry>>,
+    /// Address of every unspent indexed output, to find the entry an input spends.
+    addresses: HashMap<MainstateOutpoint, Hash>,
+    /// Hash of every indexed mainblock, to detect an index left behind by a reorganization.
+    mainblock_hashes: Vec<Hash>,
+}
+
+impl AddressIndex {
+    /// Creates the index stored in the `ai_path` directory, `load` must be called before use.
+    pub async fn new<P: AsRef<Path>>(ai_path: P) -> Result<Self, AddressIndexError> {
+        let ai_path = ai_path.as_ref().to_path_buf();
+        let ai_cs = ChunkStore::new(&ai_path, String::from("AddressIndex")).await?;
+        Ok(Self {
+            ai_path,
+            ai_cs,
+            entries: HashMap::new(),
+            addresses: HashMap::new(),
+            mainblock_hashes: Vec::new(),
+        })
+    }
+
+    /// Number of mainblocks whose transactions are indexed.
+    pub fn get_mainblocks_count(&self) -> usize {
+        self.mainblock_hashes.len()
+    }
+
+    /// Hash of the last indexed mainblock, empty when no mainblock is indexed.
+    pub fn get_tip_hash(&self) -> Hash {
+        self.mainblock_hashes.last().cloned().unwrap_or_else(Hash::new_empty)
+    }
+
+    pub fn get_addresses_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Number of indexed outputs that are still unspent.
+    pub fn get_unspent_count(&self) -> usize {
+        self.addresses.len()
+    }
+
+    /// Every output paying to `address`, spent or not, oldest first.
+    pub fn get_history(&self, address: &Hash) -> Vec<AddressIndexEntry> {
+        self.entries.get(address).cloned().unwrap_or_default()
+    }
+
+    pub fn get_unspent(&self, address: &Hash) -> Vec<AddressIndexEntry> {
+        self.get_history(address).into_iter().filter(|entry| entry.is_unspent()).collect()
+    }
+
+    pub fn get_balance(&self, address: &Hash) -> u64 {
+        self.entries
+            .get(address)
+            .map(|entries| entries.iter().filter(|entry| entry.is_unspent()).map(|entry| entry.value).sum())
+            .unwrap_or(0)
+    }
+
+    fn get_entry_mut(&mut self, address: &Hash, outpoint: &MainstateOutpoint) -> Option<&mut AddressIndexEntry> {
+        self.entries
+            .get_mut(address)?
+            .iter_mut()
+            .find(|entry| entry.tx_hash == outpoint.hash && entry.output_index == outpoint.index)
+    }
+
+    /// The record of `mb`, whose inputs are looked up in the outputs indexed so far and in its own outputs.
+    /// Outputs that are not ecdsa and inputs that are not ecdsa (reward inputs) are ignored.
+    fn new_record(&self, mb: &Mainblock) -> AddressRecord {
+        let mut record = AddressRecord { mainblock_hash: mb.get_hash(), outputs: Vec::new(), spends: Vec::new() };
+        let mut created: HashMap<MainstateOutpoint, Hash> = HashMap::new();
+        for tx in mb.transactions.iter() {
+            let tx_hash = tx.compute_hash();
+            for txin in tx.vin.iter() {
+                let outpoint = match (txin.get_hash(), txin.get_index()) {
+                    (Ok(hash), Ok(index)) => MainstateOutpoint { hash, index },
+                    _ => continue,
+                };
+                let address = match created.remove(&outpoint).or_else(|| self.addresses.get(&outpoint).cloned()) {
+                    Some(address) => address,
+                    None => continue,
+                };
+                record.spends.push(AddressRecordSpend { outpoint, address, tx_hash: tx_hash.clone() });
+            }
+            for (output_index, txout) in tx.vout.iter().enumerate() {
+                let (address, value) = match (txout.get_address(), txout.get_value()) {
+                    (Ok(address), Ok(value)) => (address, value),
+                    _ => continue,
+                };
+                let output_index = output_index as u32;
+                created.insert(MainstateOutpoint { hash: tx_hash.clone(), index: output_index }, address.clone());
+                record.outputs.push(AddressRecordOutput { tx_hash: tx_hash.clone(), output_index, address, value });
+            }
+        }
+        record
+    }
+
+    /// Applies a record as the mainblock at height `get_mainblocks_count()`. The outputs go first, so that
+    /// an output spent in its own mainblock is found.
+    fn apply_record(&mut self, record: AddressRecord) {
+        let mainblock_height = self.mainblock_hashes.len() as u32;
+        for output in record.outputs {
+            self.addresses.insert(MainstateOutpoint { hash: output.tx_hash.clone(), index: output.output_index }, output.address.clone());
+            self.entries.entry(output.address).or_default().push(AddressIndexEntry {
+                tx_hash: output.tx_hash,
+                output_index: output.output_index,
+                value: output.value,
+                mainblock_height,
+                spent_by: None,
+            });
+        }
+        for spend in record.spends {
+            self.addresses.remove(&spend.outpoint);
+            if let Some(entry) = self.get_entry_mut(&spend.address, &spend.outpoint) {
+                entry.spent_by = Some(AddressSpend { tx_hash: spend.tx_hash, mainblock_height });
+            }
+        }
+        self.mainblock_hashes.push(record.mainblock_hash);
+    }
+
+    /// Undoes the record of the last indexed mainblock: the outputs it spent are unspent again and the
+    /// outputs it created are removed.
+    fn undo_record(&mut self, record: AddressRecord) {
+        for spend in record.spends.iter() {
+            if let Some(entry) = self.get_entry_mut(&spend.address, &spend.outpoint) {
+                entry.spent_by = None;
+            }
+            self.addresses.insert(spend.outpoint.clone(), spend.address.clone());
+        }
+        for output in record.outputs.iter() {
+            self.addresses.remove(&MainstateOutpoint { hash: output.tx_hash.clone(), index: output.output_index });
+            if let Some(entries) = self.entries.get_mut(&output.address) {
+                entries.retain(|entry| entry.tx_hash != output.tx_hash || entry.output_index != output.output_index);
+                if entries.is_empty() {
+                    self.entries.remove(&output.address);
+                }
+            }
+        }
+        self.mainblock_hashes.pop();
+    }
+
+    /// Indexes the outputs and inputs of the mainblock at height `get_mainblocks_count()` and stores its record.
+    pub async fn connect_mainblock(&mut self, mb: &Mainblock) -> Result<(), AddressIndexError> {
+        let record = self.new_record(mb);
+        self.ai_cs.add_chunk(&serialize_record(&record)).await?;
+        self.apply_record(record);
+        Ok(())
+    }
+
+    /// Removes the outputs of the last indexed mainblock, which must be `mb`, and marks the outputs it spent as unspent.
+    pub async fn disconnect_mainblock(&mut self, mb: &Mainblock) -> Result<(), AddressIndexError> {
+        if self.mainblock_hashes.last() != Some(&mb.get_hash()) {
+            return Err(AddressIndexError::TipMismatch { expected: self.get_tip_hash(), actual: mb.get_hash() });
+        }
+        self.remove_last_mainblock().await
+    }
+
+    /// Undoes the last indexed mainblock, read back from its record.
+    pub async fn remove_last_mainblock(&mut self) -> Result<(), AddressIndexError> {
+        let height = match self.mainblock_hashes.len().checked_sub(1) {
+            Some(height) => height,
+            None => return Ok(()),
+        };
+        let record = unserialize_record(self.ai_cs.get_chunk(height).await?)?;
+        self.ai_cs.remove_chunk(height).await?;
+        self.undo_record(record);
+        Ok(())
+    }
+
+    /// Removes every record.
+    pub async fn reset(&mut self) -> Result<(), AddressIndexError> {
+        self.entries.clear();
+        self.addresses.clear();
+        self.mainblock_hashes.clear();
+        if self.ai_path.exists() {
+            fs::remove_dir_all(&self.ai_path).await?;
+        }
+        self.ai_cs = ChunkStore::new(&self.ai_path, String::from("AddressIndex")).await?;
+        self.ai_cs.init().await?;
+        Ok(())
+    }
+
+    /// Loads the records, a record left incomplete by an interrupted write is dropped.
+    pub async fn load(&mut self) -> Result<(), AddressIndexError> {
+        self.entries.clear();
+        self.addresses.clear();
+        self.mainblock_hashes.clear();
+        self.ai_cs.init().await?;
+        for height in 0..self.ai_cs.chunk_count() {
+            let record = unserialize_record(self.ai_cs.get_chunk(height).await?)?;
+            self.apply_record(record);
+        }
+        Ok(())
+    }
+}
+
+fn serialize_record(record: &AddressRecord) -> Vec<u8> {
+    let mut bw = BufferWriter::new();
+    bw.put_var_u32(ADDRESS_INDEX_VERSION);
+    bw.put_hash(record.mainblock_hash.clone());
+    bw.put_var_u64(record.outputs.len() as u64);
+    for output in record.outputs.iter() {
+        bw.put_hash(output.tx_hash.clone());
+        bw.put_var_u32(output.output_index);
+        bw.put_hash(output.address.clone());
+        bw.put_var_u64(output.value);
+    }
+    bw.put_var_u64(record.spends.len() as u64);
+    for spend in record.spends.iter() {
+        bw.put_hash(spend.outpoint.hash.clone());
+        bw.put_var_u32(spend.outpoint.index);
+        bw.put_hash(spend.address.clone());
+        bw.put_hash(spend.tx_hash.clone());
+    }
+    bw.get_bytes()
+}
+
+fn unserialize_record(rawbytes: Vec<u8>) -> Result<AddressRecord, AddressIndexError> {
+    let mut br = BufferReader::new(rawbytes);
+    let version = br.get_var_u32()?;
+    if version != ADDRESS_INDEX_VERSION {
+        return Err(AddressIndexError::UnknownVersion(version));
+    }
+    let mainblock_hash = br.get_hash()?;
+    let outputs_count = br.get_var_u64()?;
+    let mut outputs = Vec::new();
+    for _ in 0..outputs_count {
+        let tx_hash = br.get_hash()?;
+        let output_index = br.get_var_u32()?;
+        let address = br.get_hash()?;
+        let value = br.get_var_u64()?;
+        outputs.push(AddressRecordOutput { tx_hash, output_index, address, value });
+    }
+    let spends_count = br.get_var_u64()?;
+    let mut spends = Vec::new();
+    for _ in 0..spends_count {
+        let hash = br.get_hash()?;
+        let index = br.get_var_u32()?;
+        let address = br.get_hash()?;
+        let tx_hash = br.get_hash()?;
+        spends.push(AddressRecordSpend { outpoint: MainstateOutpoint { hash, index }, address, tx_hash });
+    }
+    Ok(AddressRecord { mainblock_hash, outputs, spends })
+}
@@ -0,0 +1,47 @@
+//! Rebuilds an optional index of a data directory from its stored mainblocks.
+//!
+//! usage: reindex <datadir> <mainnet|testnet|regtest> <maintx|address>
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::maincore_inner::maincore_inner::MaincoreInner;
+use maincore::maincore_inner::maincore_inner::MaincoreInnerError;
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 4 {
+        eprintln!("usage: {} <datadir> <mainnet|testnet|regtest> <maintx|address>", args[0]);
+        std::process::exit(2);
+    }
+    let chain_params = match args[2].as_str() {
+        "mainnet" => ChainParams::mainnet(),
+        "testnet" => ChainParams::testnet(),
+        "regtest" => ChainParams::regtest(),
+        network => {
+            eprintln!("unknown network {}", network);
+            std::process::exit(2);
+        }
+    };
+    let index = args[3].clone();
+    if index != "maintx" && index != "address" {
+        eprintln!("unknown index {}", index);
+        std::process::exit(2);
+    }
+    let result = async {
+        let mut mci = MaincoreInner::new(&args[1], chain_params).await?;
+        mci.init().await?;
+        if index == "maintx" {
+            mci.reindex_maintx_index().await?;
+        } else {
+            mci.reindex_address_index().await?;
+        }
+        Ok::<String, MaincoreInnerError>(format!("{} index rebuilt over {} mainblocks", index, mci.get_mainblocks_count()))
+    }
+    .await;
+    match result {
+        Ok(message) => println!("{}", message),
+        Err(e) => {
+            eprintln!("reindex failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
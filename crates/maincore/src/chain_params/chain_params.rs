@@ -0,0 +1,156 @@
+use utility::hash::hash::Hash;
+use maintx::maintx::maintx::Maintx;
+use maintx::maintx::maintx::new_reward_transaction;
+use crate::mainheader::mainheader::Mainheader;
+use crate::mainblock::mainblock::Mainblock;
+use crate::mainblock::mainblock_validation::compute_mainblock_root_hash;
+use crate::miner::miner::MAINHEADER_VERSION;
+
+/// Text hashed into the address paid by the genesis mainblocks reward.
+pub const GENESIS_REWARD_MESSAGE: &str = "global genesis mainblock";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainNetwork {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+/// Consensus parameters of a chain, shared by validation, mining and storage.
+#[derive(Debug, Clone)]
+pub struct ChainParams {
+    pub network: ChainNetwork,
+    /// Number of mainblocks between two target adjustments.
+    pub retarget_interval: usize,
+    /// Expected number of seconds between two mainblocks.
+    pub target_spacing: i64,
+    /// The bits are never adjusted when set.
+    pub no_retargeting: bool,
+    /// Easiest allowed target, in compact form.
+    pub pow_limit_bits: u32,
+    /// Largest factor by which a target can grow or shrink at one adjustment.
+    pub max_adjustment_factor: u64,
+    /// Number of seconds a mainheader timestamp may be ahead of the local clock.
+    pub max_future_block_time: i64,
+    /// Number of mainblocks on top of a mainblock after which it can no longer be reorganized.
+    pub confirmation_depth: usize,
+    /// Subsidy of the first mainblocks, halved every `subsidy_halving_interval` mainblocks.
+    pub initial_mainblock_subsidy: u64,
+    pub subsidy_halving_interval: usize,
+    /// Number of mainblocks to build on top of a reward before it can be spent.
+    pub reward_maturity: usize,
+    pub max_mainblock_size: usize,
+    pub genesis_timestamp: i64,
+    pub genesis_bits: u32,
+    pub genesis_nonce: u32,
+    /// First bytes of every p2p message, nodes of different networks never talk to each other.
+    pub p2p_magic: u32,
+    pub default_p2p_port: u16,
+}
+
+impl ChainParams {
+    pub fn mainnet() -> Self {
+        Self {
+            network: ChainNetwork::Mainnet,
+            retarget_interval: 4032,
+            target_spacing: 300,
+            no_retargeting: false,
+            pow_limit_bits: 0x1f7fffff,
+            max_adjustment_factor: 4,
+            max_future_block_time: 2 * 60 * 60,
+            confirmation_depth: 6,
+            initial_mainblock_subsidy: 50_000_000,
+            // about four years of 300-second mainblocks
+            subsidy_halving_interval: 420_000,
+            reward_maturity: 100,
+            max_mainblock_size: 1_000_000,
+            genesis_timestamp: 1735689600,
+            genesis_bits: 0x1e0fffff,
+            genesis_nonce: 2147569402,
+            p2p_magic: 0x474c4d4e,
+            default_p2p_port: 8633,
+        }
+    }
+
+    pub fn testnet() -> Self {
+        Self {
+            network: ChainNetwork::Testnet,
+            genesis_timestamp: 1735689601,
+            genesis_bits: 0x1f0fffff,
+            genesis_nonce: 2965,
+            p2p_magic: 0x474c5454,
+            default_p2p_port: 18633,
+            ..Self::mainnet()
+        }
+    }
+
+    /// Local test chain: targets are never adjusted and mainblocks are found almost instantly.
+    pub fn regtest() -> Self {
+        Self {
+            network: ChainNetwork::Regtest,
+            retarget_interval: 144,
+            target_spacing: 1,
+            no_retargeting: true,
+            pow_limit_bits: 0x207fffff,
+            confirmation_depth: 6,
+            subsidy_halving_interval: 150,
+            reward_maturity: 10,
+            genesis_timestamp: 1735689602,
+            genesis_bits: 0x207fffff,
+            genesis_nonce: 0,
+            p2p_magic: 0x474c5254,
+            default_p2p_port: 28633,
+            ..Self::mainnet()
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self.network {
+            ChainNetwork::Mainnet => "mainnet",
+            ChainNetwork::Testnet => "testnet",
+            ChainNetwork::Regtest => "regtest",
+        }
+    }
+
+    /// Newly created value allowed in the reward transaction of the mainblock at `height`.
+    pub fn get_mainblock_subsidy(&self, height: usize) -> u64 {
+        let halvings = height / self.subsidy_halving_interval.max(1);
+        if halvings >= u64::BITS as usize {
+            return 0;
+        }
+        self.initial_mainblock_subsidy >> halvings
+    }
+
+    /// Address receiving the genesis reward, nobody knows its private key.
+    pub fn get_genesis_reward_address(&self) -> Hash {
+        Hash::compute_hash(GENESIS_REWARD_MESSAGE.as_bytes())
+    }
+
+    fn get_genesis_transactions(&self) -> Vec<Maintx> {
+        vec![new_reward_transaction(0, self.get_mainblock_subsidy(0), 0, self.get_genesis_reward_address())]
+    }
+
+    /// The genesis mainheader, whose nonce has been mined once and hard-coded.
+    pub fn get_genesis_mainheader(&self) -> Mainheader {
+        let root_hash = compute_mainblock_root_hash(&self.get_genesis_transactions());
+        let candidate = Mainheader::new(
+            MAINHEADER_VERSION,
+            Hash::new_empty(),
+            root_hash.clone(),
+            self.genesis_timestamp,
+            self.genesis_bits,
+            self.genesis_nonce,
+            Hash::new_empty(),
+        );
+        let hash = candidate.compute_hash();
+        Mainheader::new(MAINHEADER_VERSION, Hash::new_empty(), root_hash, self.genesis_timestamp, self.genesis_bits, self.genesis_nonce, hash)
+    }
+
+    pub fn get_genesis_mainblock(&self) -> Mainblock {
+        Mainblock::new(self.get_genesis_mainheader(), self.get_genesis_transactions())
+    }
+
+    pub fn get_genesis_hash(&self) -> Hash {
+        self.get_genesis_mainheader().get_hash()
+    }
+}
@@ -1,6 +1,15 @@
+pub mod address_index;
+pub mod chain_params;
 pub mod mainheader;
 pub mod mainblock;
 pub mod maincore_inner;
+pub mod mainstate;
+pub mod maintx_index;
+pub mod maintxspool;
+pub mod miner;
+pub mod p2p;
+pub mod retarget;
+pub mod syncpool;
 /*
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
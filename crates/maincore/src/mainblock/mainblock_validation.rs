@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+use thiserror::Error;
+use utility::hash::hash::Hash;
+use utility::hash::tree::compute_root;
+use utility::hash::tree::compute_root_checked;
+use utility::hash::tree::TreeError;
+use maintx::maintx::maintx::Maintx;
+use maintx::maintx_in::maintx_in::MaintxIn;
+use crate::mainheader::mainheader::Mainheader;
+use crate::mainblock::mainblock::Mainblock;
+
+#[derive(Debug, Error)]
+pub enum MainblockValidationError {
+    #[error("Invalid prev_hash: expected {expected:?}, got {actual:?}")]
+    InvalidPrevHash { expected: Hash, actual: Hash },
+
+    #[error("Mainheader hash does not match its content")]
+    InvalidHash,
+
+    #[error("Mainheader hash does not meet the target of its bits")]
+    InvalidTarget,
+
+    #[error("Invalid bits: expected {expected}, got {actual}")]
+    InvalidBits { expected: u32, actual: u32 },
+
+    #[error("Timestamp {timestamp} is not above the median time past {median_time_past}")]
+    TimestampTooOld { timestamp: i64, median_time_past: i64 },
+
+    #[error("Timestamp {timestamp} is after the latest allowed timestamp {max_timestamp}")]
+    TimestampTooFarInFuture { timestamp: i64, max_timestamp: i64 },
+
+    #[error("Invalid root_hash: expected {expected:?}, got {actual:?}")]
+    InvalidRootHash { expected: Hash, actual: Hash },
+
+    #[error("Transactions have an ambiguous root_hash: {0}")]
+    MutatedRootHash(TreeError),
+
+    #[error("Mainblock has no transactions")]
+    NoTransactions,
+
+    #[error("Invalid reward transaction: {0}")]
+    InvalidRewardTransaction(String),
+
+    #[error("Invalid reward height: expected {expected}, got {actual}")]
+    InvalidRewardHeight { expected: usize, actual: u32 },
+
+    #[error("Invalid reward value: expected {expected}, got {actual}")]
+    InvalidRewardValue { expected: u64, actual: u64 },
+
+    #[error("Reward value overflow")]
+    RewardValueOverflow,
+
+    #[error("Unexpected reward input in transaction {0}")]
+    UnexpectedRewardInput(usize),
+
+    #[error("Transaction {0} has no inputs")]
+    MaintxWithoutInputs(usize),
+
+    #[error("Transaction {0} has no outputs")]
+    MaintxWithoutOutputs(usize),
+
+    #[error("Invalid signature in transaction {0}")]
+    InvalidSignature(usize),
+
+    #[error("Transaction {0} spends the same output twice")]
+    DuplicateInput(usize),
+}
+
+/// Context a mainblock is validated against: its height, the mainheader it must extend
+/// (`None` for the genesis mainblock), the bits expected at its height and the
+/// bounds of its timestamp.
+pub struct MainblockValidationContext {
+    pub height: usize,
+    pub prev_mainheader: Option<Mainheader>,
+    pub expected_bits: Option<u32>,
+    /// The timestamp must be strictly above the median timestamp of the previous mainheaders.
+    pub median_time_past: Option<i64>,
+    /// The timestamp must not be above this value, derived from the local clock.
+    pub max_timestamp: Option<i64>,
+}
+
+/// Runs every consensus check on a mainblock before it is allowed to reach storage.
+pub fn validate_mainblock(mb: &Mainblock, context: &MainblockValidationContext) -> Result<(), MainblockValidationError> {
+    validate_mainheader(&mb.header, context)?;
+    validate_root_hash(mb)?;
+    validate_reward_transaction(mb, context.height)?;
+    validate_transactions(mb)?;
+    Ok(())
+}
+
+/// Checks the linkage, proof of work, bits and timestamp of a mainheader.
+pub fn validate_mainheader(mh: &Mainheader, context: &MainblockValidationContext) -> Result<(), MainblockValidationError> {
+    let expected_prev_hash = match &context.prev_mainheader {
+        Some(prev_mainheader) => prev_mainheader.get_hash(),
+        None => Hash::new_empty(),
+    };
+    if mh.get_prev_hash() != expected_prev_hash {
+        return Err(MainblockValidationError::InvalidPrevHash {
+            expected: expected_prev_hash,
+            actual: mh.get_prev_hash(),
+        });
+    }
+    if !mh.check_hash() {
+        return Err(MainblockValidationError::InvalidHash);
+    }
+    if !mh.check_target() {
+        return Err(MainblockValidationError::InvalidTarget);
+    }
+    if let Some(expected_bits) = context.expected_bits {
+        if mh.get_bits() != expected_bits {
+            return Err(MainblockValidationError::InvalidBits {
+                expected: expected_bits,
+                actual: mh.get_bits(),
+            });
+        }
+    }
+    if let Some(median_time_past) = context.median_time_past {
+        if mh.get_timestamp() <= median_time_past {
+            return Err(MainblockValidationError::TimestampTooOld {
+                timestamp: mh.get_timestamp(),
+                median_time_past,
+            });
+        }
+    }
+    if let Some(max_timestamp) = context.max_timestamp {
+        if mh.get_timestamp() > max_timestamp {
+            return Err(MainblockValidationError::TimestampTooFarInFuture {
+                timestamp: mh.get_timestamp(),
+                max_timestamp,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Computes the Merkle root of the transactions of a mainblock.
+pub fn compute_mainblock_root_hash(transactions: &[Maintx]) -> Hash {
+    let hashes: Vec<Hash> = transactions.iter().map(|tx| tx.compute_hash()).collect();
+    compute_root(&hashes)
+}
+
+fn validate_root_hash(mb: &Mainblock) -> Result<(), MainblockValidationError> {
+    let hashes: Vec<Hash> = mb.transactions.iter().map(|tx| tx.compute_hash()).collect();
+    let expected = compute_root_checked(&hashes).map_err(MainblockValidationError::MutatedRootHash)?;
+    if mb.header.get_root_hash() != expected {
+        return Err(MainblockValidationError::InvalidRootHash {
+            expected,
+            actual: mb.header.get_root_hash(),
+        });
+    }
+    Ok(())
+}
+
+fn validate_reward_transaction(mb: &Mainblock, height: usize) -> Result<(), MainblockValidationError> {
+    let reward_tx = mb.transactions.first().ok_or(MainblockValidationError::NoTransactions)?;
+    if reward_tx.vin.len() != 1 {
+        return Err(MainblockValidationError::InvalidRewardTransaction(format!(
+            "expected 1 input, got {}",
+            reward_tx.vin.len()
+        )));
+    }
+    let reward_in = match &reward_tx.vin[0] {
+        MaintxIn::MaintxInMainblockRewardVariant(reward_in) => reward_in,
+        _ => {
+            return Err(MainblockValidationError::InvalidRewardTransaction(String::from(
+                "input is not a mainblock reward input",
+            )))
+        }
+    };
+    if reward_in.mainblock_height as usize != height {
+        return Err(MainblockValidationError::InvalidRewardHeight {
+            expected: height,
+            actual: reward_in.mainblock_height,
+        });
+    }
+    if reward_tx.vout.is_empty() {
+        return Err(MainblockValidationError::InvalidRewardTransaction(String::from("no outputs")));
+    }
+    Ok(())
+}
+
+fn validate_transactions(mb: &Mainblock) -> Result<(), MainblockValidationError> {
+    for (i, tx) in mb.transactions.iter().enumerate().skip(1) {
+        if tx.vin.is_empty() {
+            return Err(MainblockValidationError::MaintxWithoutInputs(i));
+        }
+        if tx.vout.is_empty() {
+            return Err(MainblockValidationError::MaintxWithoutOutputs(i));
+        }
+        if tx.vin.iter().any(|vin| !vin.is_ecdsa()) {
+            return Err(MainblockValidationError::UnexpectedRewardInput(i));
+        }
+        let mut outpoints = HashSet::new();
+        for vin in tx.vin.iter() {
+            if !outpoints.insert((vin.get_hash().ok(), vin.get_index().ok())) {
+                return Err(MainblockValidationError::DuplicateInput(i));
+            }
+        }
+        if !tx.verify_signatures() {
+            return Err(MainblockValidationError::InvalidSignature(i));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the reward transaction pays exactly the subsidy plus the fees of the other transactions,
+/// as computed from their input and output values.
+pub fn validate_reward_value(mb: &Mainblock, subsidy: u64, fees: u64) -> Result<(), MainblockValidationError> {
+    let reward_tx = mb.transactions.first().ok_or(MainblockValidationError::NoTransactions)?;
+    let mut actual: u64 = 0;
+    for vout in reward_tx.vout.iter() {
+        let value = vout
+            .get_value()
+            .map_err(|e| MainblockValidationError::InvalidRewardTransaction(e.to_string()))?;
+        actual = actual.checked_add(value).ok_or(MainblockValidationError::RewardValueOverflow)?;
+    }
+    let expected = subsidy.checked_add(fees).ok_or(MainblockValidationError::RewardValueOverflow)?;
+    if actual != expected {
+        return Err(MainblockValidationError::InvalidRewardValue { expected, actual });
+    }
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use thiserror::Error;
+use utility::hash::hash::Hash;
+use utility::hash::tree::compute_proof;
+use utility::hash::tree::verify_proof;
+use utility::hash::tree::MerkleProof;
+use utility::hash::tree::TreeError;
+use utility::buffer::buffer_writer::BufferWriter;
+use utility::buffer::buffer_reader::BufferReader;
+use utility::buffer::buffer_reader::BufferReaderError;
+use maintx::maintx::maintx::Maintx;
+use maintx::maintx::maintx::MaintxError;
+use maintx::maintx::maintx::unserialize_maintx;
+use crate::mainheader::mainheader::Mainheader;
+use crate::mainblock::mainblock::Mainblock;
+
+/// A proof never has more siblings than the depth of a tree of `u32::MAX` leaves.
+pub const MAINTX_PROOF_MAX_SIBLINGS: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum MaintxProofError {
+    #[error("Buffer reader error: {0}")]
+    BufferReaderError(#[from] BufferReaderError),
+    #[error("Maintx error: {0}")]
+    MaintxError(#[from] MaintxError),
+    #[error("Merkle tree error: {0}")]
+    TreeError(#[from] TreeError),
+    #[error("Proof has {0} siblings")]
+    TooManySiblings(u64),
+}
+
+/// A confirmed maintx with the Merkle proof that it is in the mainblock at `mainblock_height`,
+/// checked against the root_hash of that mainblock's mainheader.
+#[derive(Debug, Clone)]
+pub struct MaintxProof {
+    pub mainblock_height: u32,
+    pub maintx: Maintx,
+    pub proof: MerkleProof,
+}
+
+impl MaintxProof {
+    /// Position of the maintx in its mainblock.
+    pub fn get_position(&self) -> u32 {
+        self.proof.index
+    }
+
+    /// Checks that the maintx is in the mainblock of `mh`, which must be the mainheader at `mainblock_height`.
+    pub fn verify(&self, mh: &Mainheader) -> Result<(), MaintxProofError> {
+        verify_proof(&self.maintx.compute_hash(), &self.proof, &mh.get_root_hash())?;
+        Ok(())
+    }
+
+    pub fn serialize(&self, bw: &mut BufferWriter) {
+        bw.put_var_u32(self.mainblock_height);
+        bw.put_var_bytes(&self.maintx.serialize());
+        bw.put_var_u32(self.proof.index);
+        bw.put_var_u32(self.proof.leaves_count);
+        bw.put_var_u64(self.proof.siblings.len() as u64);
+        for sibling in self.proof.siblings.iter() {
+            bw.put_hash(sibling.clone());
+        }
+    }
+}
+
+/// Builds the proof of the maintx at `position` in `mb`.
+pub fn new_maintx_proof(mb: &Mainblock, mainblock_height: u32, position: usize) -> Result<MaintxProof, MaintxProofError> {
+    let hashes: Vec<Hash> = mb.transactions.iter().map(|tx| tx.compute_hash()).collect();
+    let proof = compute_proof(&hashes, position)?;
+    Ok(MaintxProof { mainblock_height, maintx: mb.transactions[position].clone(), proof })
+}
+
+pub fn unserialize_maintx_proof(br: &mut BufferReader) -> Result<MaintxProof, MaintxProofError> {
+    let mainblock_height = br.get_var_u32()?;
+    let maintx = unserialize_maintx(br.get_var_bytes()?)?;
+    let index = br.get_var_u32()?;
+    let leaves_count = br.get_var_u32()?;
+    let siblings_count = br.get_var_u64()?;
+    if siblings_count > MAINTX_PROOF_MAX_SIBLINGS as u64 {
+        return Err(MaintxProofError::TooManySiblings(siblings_count));
+    }
+    let mut siblings = Vec::with_capacity(siblings_count as usize);
+    for _ in 0..siblings_count {
+        siblings.push(br.get_hash()?);
+    }
+    Ok(MaintxProof { mainblock_height, maintx, proof: MerkleProof { index, leaves_count, siblings } })
+}
@@ -1 +1,3 @@
-pub mod mainblock;
\ No newline at end of file
+pub mod mainblock;
+pub mod mainblock_validation;
+pub mod maintx_proof;
@@ -1,15 +1,58 @@
 use tokio::fs;
 use tokio::io;
+use tokio::sync::broadcast;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
+use num_bigint::BigUint;
 use thiserror::Error; 
 use utility::storage::storage_directory::StorageDirectory;
 use utility::storage::storage_directory::StorageDirectoryError;
+use utility::storage::chunk_store::ChunkStore;
+use utility::storage::chunk_store::ChunkStoreError;
+use utility::storage::chunk_store::migrate_storage_directory;
 use utility::hash::bigint;
+use crate::chain_params::chain_params::ChainParams;
 use crate::mainheader::mainheader::Mainheader;
+use crate::mainheader::mainheader_store::MainheaderStore;
+use crate::mainheader::mainheader_store::MainheaderStoreError;
 use crate::mainblock::mainblock::Mainblock;
 use crate::mainblock::mainblock::unserialize_mainblock;
 use crate::mainblock::mainblock::MainblockError;
+use crate::mainblock::mainblock_validation::validate_mainblock;
+use crate::mainblock::mainblock_validation::validate_reward_value;
+use crate::mainblock::mainblock_validation::MainblockValidationContext;
+use crate::mainblock::mainblock_validation::MainblockValidationError;
+use crate::mainblock::maintx_proof::new_maintx_proof;
+use crate::mainblock::maintx_proof::MaintxProof;
+use crate::mainblock::maintx_proof::MaintxProofError;
+use crate::mainstate::mainstate::Mainstate;
+use crate::mainstate::mainstate::MainstateError;
+use crate::mainstate::mainstate::MainstateOutpoint;
+use crate::mainstate::mainstate::MainstateOutput;
+use crate::maintxspool::maintxspool::Maintxspool;
+use crate::maintxspool::maintxspool::MaintxspoolError;
+use crate::miner::miner::Miner;
+use crate::retarget::retarget::compute_next_bits;
+use crate::retarget::retarget::get_median_time_past;
+use crate::retarget::retarget::RetargetError;
+use crate::syncpool::syncpool::Syncpool;
+use crate::syncpool::syncpool::SyncpoolError;
+use crate::syncpool::syncpool::SyncpoolProgress;
+use crate::maintx_index::maintx_index::MaintxIndex;
+use crate::maintx_index::maintx_index::MaintxIndexError;
+use crate::maintx_index::maintx_index::MaintxLocation;
+use crate::address_index::address_index::AddressIndex;
+use crate::address_index::address_index::AddressIndexEntry;
+use crate::address_index::address_index::AddressIndexError;
+use crate::miner::miner::MinerError;
+use crate::miner::miner::MainblockTemplate;
+use maintx::maintx::maintx::Maintx;
+use maintx::maintx::maintx::new_reward_transaction;
+use utility::hash::hash::Hash;
+use utility::system::time::timestamp_now;
 
 
 // MainCoreError Definition
@@ -35,176 +78,746 @@ pub enum MaincoreInnerError {
     //BufferReaderError(#[from] BufferReaderError),
     #[error("Storage directory error: {0}")]
     StorageDirectoryError(#[from] StorageDirectoryError),
+    #[error("Chunk store error: {0}")]
+    ChunkStoreError(#[from] ChunkStoreError),
     #[error("Mainblock error: {0}")]
     MainblockError(#[from] MainblockError),
+    #[error("Mainheader store error: {0}")]
+    MainheaderStoreError(#[from] MainheaderStoreError),
+    #[error("Mainblock validation error: {0}")]
+    MainblockValidationError(#[from] MainblockValidationError),
+    #[error("Mainstate error: {0}")]
+    MainstateError(#[from] MainstateError),
+    #[error("Maintxspool error: {0}")]
+    MaintxspoolError(#[from] MaintxspoolError),
+    #[error("Miner error: {0}")]
+    MinerError(#[from] MinerError),
+    #[error("Retarget error: {0}")]
+    RetargetError(#[from] RetargetError),
+    #[error("Syncpool error: {0}")]
+    SyncpoolError(#[from] SyncpoolError),
+    #[error("Maintx index error: {0}")]
+    MaintxIndexError(#[from] MaintxIndexError),
+    #[error("Maintx index is not enabled")]
+    MaintxIndexDisabled,
+    #[error("Address index error: {0}")]
+    AddressIndexError(#[from] AddressIndexError),
+    #[error("Address index is not enabled")]
+    AddressIndexDisabled,
+    #[error("Maintx proof error: {0}")]
+    MaintxProofError(#[from] MaintxProofError),
+    #[error("No mainheader loaded")]
+    NoMainheader,
+    #[error("Mainblock {0:?} is already known")]
+    DuplicateMainblock(Hash),
+    #[error("Mainblock parent {0:?} is unknown")]
+    OrphanMainblock(Hash),
+    #[error("Mainblock conflicts with the final mainblock at height {0}")]
+    FinalMainblockConflict(usize),
+    #[error("Reorganization of {depth} mainblocks exceeds the {available} mainblocks that can be disconnected")]
+    ReorganizationTooDeep { depth: usize, available: usize },
+    #[error("Genesis mainblock mismatch: expected {expected:?}, got {actual:?}")]
+    GenesisMismatch { expected: Hash, actual: Hash },
+}
+
+/// Changes of the main chain and of the txspool, published to the subscribers of `subscribe_events`.
+#[derive(Debug, Clone)]
+pub enum MaincoreEvent {
+    MainblockConnected { hash: Hash, height: usize },
+    MaintxAdded(Hash),
+}
+
+const MAINCORE_EVENTS_CAPACITY: usize = 1024;
+/// Mainblocks read at most by one `get_maintx_proofs` call when there is no address index.
+const MAINTX_PROOFS_MAX_SCANNED_MAINBLOCKS: usize = 1000;
+
+/// A known mainheader that is not part of the main chain, with the total work of its branch.
+#[derive(Debug, Clone)]
+pub struct SideMainheader {
+    pub header: Mainheader,
+    pub height: usize,
+    pub chainwork: BigUint,
 }
 
 // Define the MainCoreInner struct
 //#[derive(Debug)] // Implementing Debug trait for MainCoreInner
 pub struct MaincoreInner {
     mci_path: PathBuf,
-    main_sd: StorageDirectory,
+    main_cs: ChunkStore,
+    side_sd: StorageDirectory,
+    header_store: MainheaderStore,
     header_vector:Vec<Mainheader>,
-    confimation_depth:usize,
-    //syncpool:Syncpool,
-    //mainstate:Mainstate,
-    //txspool:Maintxspool,
-    //miner:Miner,
-    
+    chainwork_vector:Vec<BigUint>,
+    mainheader_heights:HashMap<Hash,usize>,
+    side_mainheaders:HashMap<Hash,SideMainheader>,
+    chain_params:ChainParams,
+    syncpool:Syncpool,
+    mainstate:Mainstate,
+    maintx_index:Option<MaintxIndex>,
+    address_index:Option<AddressIndex>,
+    txspool:Maintxspool,
+    miner:Miner,
+    events:broadcast::Sender<MaincoreEvent>,
 }
 
 impl MaincoreInner{
     /// Creates a new `StorageDirectory` instance.
-    pub async fn new<P: AsRef<Path>>(mci_path: P) -> Result<Self,MaincoreInnerError> {
+    pub async fn new<P: AsRef<Path>>(mci_path: P,chain_params: ChainParams) -> Result<Self,MaincoreInnerError> {
         let mci_path = mci_path.as_ref().to_path_buf();
         if !mci_path.exists() {
             fs::create_dir_all(&mci_path).await?;
         }
         
-        println!("MaincoreInner - path:{:?} ", mci_path);
+        println!("MaincoreInner - path:{:?} network:{}", mci_path, chain_params.get_name());
 
         let sd_sub_path_buf=PathBuf::from("Mainblocks");// can be string but should be PathBuf
         let sd_path_buf=mci_path.join(sd_sub_path_buf);
-        let main_sd= StorageDirectory::new(sd_path_buf,String::from("Mainblock")).await?;
+        let main_cs= ChunkStore::new(sd_path_buf,String::from("Mainblock")).await?;
+        let side_sd= StorageDirectory::new(mci_path.join("Sidemainblocks"),String::from("Sidemainblock")).await?;
+        let header_store=MainheaderStore::new(mci_path.join("Mainheaders.dat"));
+        let mut mainstate=Mainstate::new(mci_path.join("Mainstate"));
+        mainstate.set_undo_depth(chain_params.confirmation_depth);
+        mainstate.set_reward_maturity(chain_params.reward_maturity);
 
         //Ok(Self { mci_path,main_sd, confimation_depth:100})
 
         Ok(Self { 
             mci_path,
-            main_sd,
+            main_cs,
+            side_sd,
+            header_store,
             header_vector: Vec::new(),//
-            confimation_depth: 6,
-            //syncpool:Syncpool::new(),
-            //mainstate:tmp_ms,
-            //txspool:Maintxspool::new(),
-            //miner:Miner::new(),
+            chainwork_vector: Vec::new(),
+            mainheader_heights: HashMap::new(),
+            side_mainheaders: HashMap::new(),
+            chain_params,
+            syncpool:Syncpool::new(),
+            mainstate,
+            maintx_index:None,
+            address_index:None,
+            txspool:Maintxspool::new(),
+            miner:Miner::new(),
+            events:broadcast::channel(MAINCORE_EVENTS_CAPACITY).0,
         })
     }
     pub async fn init(&mut self)-> Result<(),MaincoreInnerError> {
         self.init_storage_directory().await?;
-        //self.syncpool.init()?;
+        self.init_mainstate().await?;
         Ok(())
     }
+    /// Opens the mainblock chunk store, moving in the mainblocks of the former one-file-per-mainblock layout,
+    /// and writes the genesis mainblock if the store is empty.
     pub async fn init_storage_directory(&mut self)-> Result<(),MaincoreInnerError> {
-        match self.main_sd.init().await {
-            Ok(_)=> {
-                println!("StorageDirectory init success");
-                //
-                let index_result=self.main_sd.get_storage_files_last_index();
-                match index_result {
-                    Some(index) =>{
-                        println!("get_storage_files_last_index: {}", index);
-                    },
-                    None => {
-                        println!("storage files last index not initialized");
-                        //TODO add genesis block here
-                    }, 
-                }        
-                return Ok(());
+        self.main_cs.init().await?;
+        self.side_sd.init().await?;
+        let mut old_sd=StorageDirectory::new(self.mci_path.join("Mainblocks"),String::from("Mainblock")).await?;
+        old_sd.init().await?;
+        if old_sd.chunk_count()>0 {
+            println!("migrating the mainblocks to the chunk store");
+            migrate_storage_directory(&mut old_sd, &mut self.main_cs, true).await?;
+        }
+        if self.main_cs.chunk_count()==0 {
+            println!("chunk store is empty, adding the {} genesis mainblock",self.chain_params.get_name());
+            let genesis_mainblock=self.chain_params.get_genesis_mainblock();
+            self.main_cs.add_chunk(genesis_mainblock.serialize().as_slice()).await?;
+        }
+        println!("chunk store holds {} mainblocks",self.main_cs.chunk_count());
+        Ok(())
+    }
+    /// Loads the mainstate and replays the mainblocks it has not seen yet. The mainstate is rebuilt if it
+    /// does not match the stored mainblocks, e.g. after a crash in the middle of a reorganization.
+    pub async fn init_mainstate(&mut self)-> Result<(),MaincoreInnerError> {
+        self.mainstate.load().await?;
+        let tmpblocks_count=self.get_mainblocks_count();
+        let mainstate_count=self.mainstate.get_mainblocks_count();
+        if mainstate_count>tmpblocks_count {
+            println!("mainstate is ahead of the mainblocks, rebuilding it");
+            self.mainstate.reset();
+        } else if mainstate_count>0 && self.get_mainheader(mainstate_count-1).await?.get_hash()!=self.mainstate.get_tip_hash() {
+            println!("mainstate does not match the mainblocks, rebuilding it");
+            self.mainstate.reset();
+        }
+        if self.mainstate.get_mainblocks_count()==tmpblocks_count {
+            return Ok(());
+        }
+        for i in self.mainstate.get_mainblocks_count()..tmpblocks_count {
+            let mb=self.get_mainblock(i).await?;
+            self.mainstate.connect_mainblock(&mb)?;
+        }
+        self.mainstate.save().await?;
+        println!("mainstate replayed up to {} mainblocks",tmpblocks_count);
+        Ok(())
+    }
+    pub fn get_mainblocks_count(&self) -> usize {
+        self.main_cs.chunk_count()
+    }
+    /// Turns on the maintx index (`MaintxIndex` directory next to the `Mainblocks` directory), catching up with
+    /// the mainblocks it has not seen yet. The records that do not match the stored mainblocks are removed first.
+    pub async fn enable_maintx_index(&mut self)-> Result<(),MaincoreInnerError> {
+        let mut maintx_index=MaintxIndex::new(self.mci_path.join("MaintxIndex")).await?;
+        match maintx_index.load().await {
+            Ok(_) => {}
+            Err(MaintxIndexError::UnknownVersion(_)) | Err(MaintxIndexError::BufferReaderError(_)) => {
+                println!("maintx index is unreadable, rebuilding it");
+                maintx_index.reset().await?;
             }
-            Err(e)=> {
-                println!("StorageDirectory init error {:?}",e);
-                return Err(MaincoreInnerError::StorageDirectoryError(e))
+            Err(e) => return Err(e.into()),
+        }
+        loop {
+            let indexed_count=maintx_index.get_mainblocks_count();
+            if indexed_count==0 {
+                break;
             }
+            if indexed_count<=self.get_mainblocks_count() && self.get_mainheader(indexed_count-1).await?.get_hash()==maintx_index.get_tip_hash() {
+                break;
+            }
+            println!("maintx index does not match the mainblock at height {}, removing it",indexed_count-1);
+            maintx_index.remove_last_mainblock().await?;
         }
+        self.maintx_index=Some(maintx_index);
+        self.catch_up_maintx_index().await
+    }
+    /// Rebuilds the maintx index from scratch, enabling it if needed.
+    pub async fn reindex_maintx_index(&mut self)-> Result<(),MaincoreInnerError> {
+        let maintx_index=match self.maintx_index.as_mut() {
+            Some(maintx_index) => maintx_index,
+            None => self.maintx_index.insert(MaintxIndex::new(self.mci_path.join("MaintxIndex")).await?),
+        };
+        maintx_index.reset().await?;
+        self.catch_up_maintx_index().await
+    }
+    async fn catch_up_maintx_index(&mut self)-> Result<(),MaincoreInnerError> {
+        let maintx_index=self.maintx_index.as_mut().ok_or(MaincoreInnerError::MaintxIndexDisabled)?;
+        for i in maintx_index.get_mainblocks_count()..self.main_cs.chunk_count() {
+            let mb=unserialize_mainblock(self.main_cs.get_chunk(i).await?)?;
+            maintx_index.connect_mainblock(&mb).await?;
+        }
+        println!("maintx index holds {} maintxs of {} mainblocks",maintx_index.get_maintxs_count(),maintx_index.get_mainblocks_count());
         Ok(())
     }
-    pub fn get_mainblocks_count(&self) -> usize {
-        let index_result=self.main_sd.get_storage_files_last_index();
-        match index_result {
-            Some(index) => return index+1,//println!("get_storage_files_last_index: {}", index),
-            None => return 0,//println!("storage files last index not initialized"), 
+    /// Turns on the address index (`AddressIndex` directory next to the `Mainblocks` directory), catching up with
+    /// the mainblocks it has not seen yet. The records that do not match the stored mainblocks are removed first.
+    pub async fn enable_address_index(&mut self)-> Result<(),MaincoreInnerError> {
+        let mut address_index=AddressIndex::new(self.mci_path.join("AddressIndex")).await?;
+        match address_index.load().await {
+            Ok(_) => {}
+            Err(AddressIndexError::UnknownVersion(_)) | Err(AddressIndexError::BufferReaderError(_)) => {
+                println!("address index is unreadable, rebuilding it");
+                address_index.reset().await?;
+            }
+            Err(e) => return Err(e.into()),
         }
+        loop {
+            let indexed_count=address_index.get_mainblocks_count();
+            if indexed_count==0 {
+                break;
+            }
+            if indexed_count<=self.get_mainblocks_count() && self.get_mainheader(indexed_count-1).await?.get_hash()==address_index.get_tip_hash() {
+                break;
+            }
+            println!("address index does not match the mainblock at height {}, removing it",indexed_count-1);
+            address_index.remove_last_mainblock().await?;
+        }
+        self.address_index=Some(address_index);
+        self.catch_up_address_index().await
+    }
+    /// Rebuilds the address index from scratch, enabling it if needed.
+    pub async fn reindex_address_index(&mut self)-> Result<(),MaincoreInnerError> {
+        let address_index=match self.address_index.as_mut() {
+            Some(address_index) => address_index,
+            None => self.address_index.insert(AddressIndex::new(self.mci_path.join("AddressIndex")).await?),
+        };
+        address_index.reset().await?;
+        self.catch_up_address_index().await
+    }
+    async fn catch_up_address_index(&mut self)-> Result<(),MaincoreInnerError> {
+        let address_index=self.address_index.as_mut().ok_or(MaincoreInnerError::AddressIndexDisabled)?;
+        for i in address_index.get_mainblocks_count()..self.main_cs.chunk_count() {
+            let mb=unserialize_mainblock(self.main_cs.get_chunk(i).await?)?;
+            address_index.connect_mainblock(&mb).await?;
+        }
+        println!("address index holds {} addresses of {} mainblocks",address_index.get_addresses_count(),address_index.get_mainblocks_count());
+        Ok(())
     }
+    pub fn get_address_index(&self)-> Option<&AddressIndex> {
+        self.address_index.as_ref()
+    }
+    /// Confirmed balance of an address, from the address index.
+    pub fn get_address_balance(&self,address: &Hash)-> Result<u64,MaincoreInnerError> {
+        let address_index=self.address_index.as_ref().ok_or(MaincoreInnerError::AddressIndexDisabled)?;
+        Ok(address_index.get_balance(address))
+    }
+    /// Every confirmed output paying to an address and the maintx that spent it, oldest first.
+    pub fn get_address_history(&self,address: &Hash)-> Result<Vec<AddressIndexEntry>,MaincoreInnerError> {
+        let address_index=self.address_index.as_ref().ok_or(MaincoreInnerError::AddressIndexDisabled)?;
+        Ok(address_index.get_history(address))
+    }
+    /// Proofs of the main chain maintxs paying to or spending from `addresses`, from the mainblock at
+    /// `from_height`, for light clients. Returns the height of the last mainblock covered with the proofs:
+    /// the proofs stop after the mainblock where `max_count` is reached. Without the address index the
+    /// mainblocks are scanned, the inputs being matched by the address of their public key.
+    pub async fn get_maintx_proofs(&mut self,addresses: &[Hash],from_height: usize,max_count: usize)-> Result<(usize,Vec<MaintxProof>),MaincoreInnerError> {
+        let tmpblocks_count=self.get_mainblocks_count();
+        let mut last_height=tmpblocks_count.saturating_sub(1);
+        let mut proofs=Vec::new();
+        if from_height>=tmpblocks_count {
+            return Ok((last_height,proofs));
+        }
+        let mut maintx_hashes: BTreeMap<usize,HashSet<Hash>>=BTreeMap::new();
+        let heights: Vec<usize>=match self.address_index.as_ref() {
+            Some(address_index) => {
+                for address in addresses.iter() {
+                    for entry in address_index.get_history(address) {
+                        maintx_hashes.entry(entry.mainblock_height as usize).or_default().insert(entry.tx_hash);
+                        if let Some(spend)=entry.spent_by {
+                            maintx_hashes.entry(spend.mainblock_height as usize).or_default().insert(spend.tx_hash);
+                        }
+                    }
+                }
+                maintx_hashes.range(from_height..).map(|(height,_)| *height).collect()
+            }
+            None => {
+                last_height=last_height.min(from_height+MAINTX_PROOFS_MAX_SCANNED_MAINBLOCKS-1);
+                (from_height..=last_height).collect()
+            }
+        };
+        let addresses: HashSet<&Hash>=addresses.iter().collect();
+        for height in heights {
+            let mb=self.get_mainblock(height).await?;
+            for (position,tx) in mb.transactions.iter().enumerate() {
+                let is_match=match maintx_hashes.get(&height) {
+                    Some(hashes) => hashes.contains(&tx.compute_hash()),
+                    None => tx.vout.iter().any(|vout| vout.get_address().map(|address| addresses.contains(&address)).unwrap_or(false))
+                        || tx.vin.iter().any(|vin| vin.get_publickey().map(|publickey| addresses.contains(&Hash::compute_hash(&publickey))).unwrap_or(false)),
+                };
+                if is_match {
+                    proofs.push(new_maintx_proof(&mb, height as u32, position)?);
+                }
+            }
+            if proofs.len()>=max_count {
+                last_height=height;
+                break;
+            }
+        }
+        Ok((last_height,proofs))
+    }
+    pub fn get_maintx_index(&self)-> Option<&MaintxIndex> {
+        self.maintx_index.as_ref()
+    }
+    /// Height and position of a confirmed maintx of the main chain.
+    pub fn get_maintx_location(&self,hash: &Hash)-> Result<Option<MaintxLocation>,MaincoreInnerError> {
+        let maintx_index=self.maintx_index.as_ref().ok_or(MaincoreInnerError::MaintxIndexDisabled)?;
+        Ok(maintx_index.get_location(hash))
+    }
+    /// Loads a confirmed maintx of the main chain with its location.
+    pub async fn get_confirmed_maintx(&mut self,hash: &Hash)-> Result<Option<(Maintx,MaintxLocation)>,MaincoreInnerError> {
+        let location=match self.get_maintx_location(hash)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let mut mb=self.get_mainblock(location.mainblock_height as usize).await?;
+        if (location.position as usize)>=mb.transactions.len() {
+            return Ok(None);
+        }
+        Ok(Some((mb.transactions.swap_remove(location.position as usize),location)))
+    }
+    /// Adds a mainblock extending the main chain or one of the side chains.
+    /// The main chain switches to a side chain as soon as the side chain has more work.
     pub async fn add_confirmed_mainblock(&mut self,mb: Mainblock)-> Result<(),MaincoreInnerError> {
         println!("************ add_confirmed_mainblock");
-        let tmpheader=mb.get_mainheader();
-        self.header_vector.push(tmpheader);
-        if self.header_vector.len()>1 {
-            /*
-            for i in 1..self.header_vector.len(){
-                println!("i {}",i);
-                println!("self.header_vector[i].get_timestamp() {} self.header_vector[i-1].get_timestamp() {}",self.header_vector[i].get_timestamp(),self.header_vector[i-1].get_timestamp());
-                process::exit(1);
-            }
-            */
-            println!("self.header_vector.len() {}",self.header_vector.len());
-            let tmp_height=self.header_vector.len()-1;
-            let deltatimestamp=self.header_vector[tmp_height].get_timestamp()-self.header_vector[tmp_height-1].get_timestamp();
-            println!("deltatimestamp {}",deltatimestamp);
-            /*
-            if deltatimestamp==0 {
-                process::exit(1);
-            }
-            */
-        }
-        //All the txs that have been included in a confimred block will be removed from the txspoll (self.txspool.remove(tmp_hash))
-        //TODONOW with self.txspool.remove(tmp_hash)
-
-        //All the txs that have been frozen because they have been included in a certain block height WILL BE reset (reset_tx_with_mainblock_height)
-        //TODONOW with reset_tx_with_mainblock_height 
-
+        let mb_hash=mb.get_hash();
+        if self.mainheader_heights.contains_key(&mb_hash) || self.side_mainheaders.contains_key(&mb_hash) {
+            return Err(MaincoreInnerError::DuplicateMainblock(mb_hash));
+        }
+        if self.header_vector.is_empty() || mb.header.get_prev_hash()==self.get_last_inmem_mainheader()?.get_hash() {
+            return self.connect_mainblock(mb).await;
+        }
+        self.add_side_mainblock(mb).await
+    }
+    /// Validates a mainblock extending the tip and appends it to the main chain.
+    async fn connect_mainblock(&mut self,mb: Mainblock)-> Result<(),MaincoreInnerError> {
+        self.validate_mainblock(&mb)?;
+        let fees=self.mainstate.check_mainblock(&mb)?;
+        validate_reward_value(&mb, self.chain_params.get_mainblock_subsidy(self.header_vector.len()), fees)?;
         let mb_rawbytes=mb.serialize();
-        match self.main_sd.add_chunk(mb_rawbytes.as_slice()).await {
+        match self.main_cs.add_chunk(mb_rawbytes.as_slice()).await {
             Ok(_)=> {
-                println!("StorageDirectory add_chunk success");
+                println!("ChunkStore add_chunk success");
+                self.push_mainheader(mb.get_mainheader());
+                self.header_store.append(&mb.get_mainheader()).await?;
+                self.miner.cancel();
+                self.mainstate.connect_mainblock(&mb)?;
+                self.mainstate.save().await?;
+                if let Some(maintx_index)=self.maintx_index.as_mut() {
+                    maintx_index.connect_mainblock(&mb).await?;
+                }
+                if let Some(address_index)=self.address_index.as_mut() {
+                    address_index.connect_mainblock(&mb).await?;
+                }
+                //All the txs that have been included in a confimred block will be removed from the txspool
+                self.txspool.remove_confirmed_mainblock(&mb);
+                //All the txs that have been frozen because they have been included in a certain block height WILL BE reset
+                self.txspool.reset_tx_with_mainblock_height(self.header_vector.len()-1);
+                if self.side_mainheaders.remove(&mb.get_hash()).is_some() {
+                    self.remove_side_mainblock(&mb.get_hash()).await?;
+                }
+                // there may be no subscriber
+                let _ = self.events.send(MaincoreEvent::MainblockConnected { hash: mb.get_hash(), height: self.header_vector.len()-1 });
                 Ok(())
             }
             Err(e)=> {
-                println!("StorageDirectory add_chunk error {:?}",e);
-                return Err(MaincoreInnerError::StorageDirectoryError(e))
+                println!("ChunkStore add_chunk error {:?}",e);
+                Err(MaincoreInnerError::ChunkStoreError(e))
+            }
+        }
+    }
+    /// Stores a mainblock that does not extend the tip and reorganizes if its branch has more work.
+    async fn add_side_mainblock(&mut self,mb: Mainblock)-> Result<(),MaincoreInnerError> {
+        let mb_hash=mb.get_hash();
+        let prev_hash=mb.header.get_prev_hash();
+        let (prev_height,prev_chainwork)=match self.get_known_mainheader_work(&prev_hash) {
+            Some(known) => known,
+            None => return Err(MaincoreInnerError::OrphanMainblock(prev_hash)),
+        };
+        let fork_height=self.get_fork_height(&prev_hash)?;
+        if self.is_mainblock_final(fork_height+1) {
+            return Err(MaincoreInnerError::FinalMainblockConflict(fork_height+1));
+        }
+        let branch_mainheaders=self.get_branch_mainheaders(&prev_hash)?;
+        let context=self.get_validation_context(&branch_mainheaders)?;
+        validate_mainblock(&mb, &context)?;
+
+        let chainwork=prev_chainwork+bigint::work_from_compact(mb.header.get_bits());
+        self.save_side_mainblock(&mb).await?;
+        self.side_mainheaders.insert(mb_hash.clone(), SideMainheader {
+            header: mb.get_mainheader(),
+            height: prev_height+1,
+            chainwork: chainwork.clone(),
+        });
+        println!("side mainblock added at height {}",prev_height+1);
+        if chainwork>self.get_chainwork() {
+            self.reorganize(&mb_hash).await?;
+        }
+        Ok(())
+    }
+    /// Switches the main chain to the side chain ending with `new_tip_hash`.
+    async fn reorganize(&mut self,new_tip_hash: &Hash)-> Result<(),MaincoreInnerError> {
+        let mut side_path=Vec::new();
+        let mut cursor=new_tip_hash.clone();
+        while let Some(side_mainheader)=self.side_mainheaders.get(&cursor) {
+            side_path.push(cursor.clone());
+            cursor=side_mainheader.header.get_prev_hash();
+        }
+        side_path.reverse();
+        let fork_height=match self.mainheader_heights.get(&cursor) {
+            Some(height) => *height,
+            None => return Err(MaincoreInnerError::OrphanMainblock(cursor)),
+        };
+        let depth=self.header_vector.len()-1-fork_height;
+        if depth>self.mainstate.get_undo_count() {
+            return Err(MaincoreInnerError::ReorganizationTooDeep { depth, available: self.mainstate.get_undo_count() });
+        }
+        println!("reorganize: disconnecting {} mainblocks and connecting {} mainblocks",depth,side_path.len());
+
+        let disconnected=self.disconnect_mainblocks_above(fork_height).await?;
+        for mb in disconnected.iter() {
+            self.txspool.readmit_mainblock(mb, &self.mainstate);
+        }
+        for (i,side_hash) in side_path.iter().enumerate() {
+            let mb=self.load_side_mainblock(side_hash).await?;
+            if let Err(e)=self.connect_mainblock(mb).await {
+                println!("reorganize: side mainblock {:?} is invalid: {}",side_hash,e);
+                self.remove_side_branch(&side_path[i]).await?;
+                self.disconnect_mainblocks_above(fork_height).await?;
+                for mb in disconnected.into_iter() {
+                    self.connect_mainblock(mb).await?;
+                }
+                self.txspool.revalidate(&self.mainstate);
+                return Err(e);
             }
         }
+        self.txspool.revalidate(&self.mainstate);
+        self.prune_side_mainblocks().await?;
+        Ok(())
+    }
+    /// Disconnects the mainblocks above `fork_height` and keeps them as side mainblocks.
+    /// Returns them from the lowest to the highest.
+    async fn disconnect_mainblocks_above(&mut self,fork_height: usize)-> Result<Vec<Mainblock>,MaincoreInnerError> {
+        let mut disconnected=Vec::new();
+        while self.header_vector.len()>fork_height+1 {
+            let height=self.header_vector.len()-1;
+            let mb=self.get_mainblock(height).await?;
+            self.mainstate.disconnect_mainblock(&mb)?;
+            if let Some(maintx_index)=self.maintx_index.as_mut() {
+                maintx_index.disconnect_mainblock(&mb).await?;
+            }
+            if let Some(address_index)=self.address_index.as_mut() {
+                address_index.disconnect_mainblock(&mb).await?;
+            }
+            self.main_cs.remove_chunk(height).await?;
+            let chainwork=self.chainwork_vector[height].clone();
+            self.pop_mainheader();
+            self.save_side_mainblock(&mb).await?;
+            self.side_mainheaders.insert(mb.get_hash(), SideMainheader {
+                header: mb.get_mainheader(),
+                height,
+                chainwork,
+            });
+            disconnected.push(mb);
+        }
+        self.header_store.truncate(self.header_vector.len()).await?;
+        self.mainstate.save().await?;
+        disconnected.reverse();
+        Ok(disconnected)
+    }
+    /// Forgets an invalid side mainblock and every side mainblock built on top of it.
+    async fn remove_side_branch(&mut self,invalid_hash: &Hash)-> Result<(),MaincoreInnerError> {
+        let mut invalid_hashes=HashSet::new();
+        invalid_hashes.insert(invalid_hash.clone());
+        loop {
+            let descendants: Vec<Hash>=self.side_mainheaders.iter()
+                .filter(|(hash,side)| !invalid_hashes.contains(*hash) && invalid_hashes.contains(&side.header.get_prev_hash()))
+                .map(|(hash,_)| hash.clone())
+                .collect();
+            if descendants.is_empty() {
+                break;
+            }
+            invalid_hashes.extend(descendants);
+        }
+        for hash in invalid_hashes.iter() {
+            if self.side_mainheaders.remove(hash).is_some() {
+                self.remove_side_mainblock(hash).await?;
+            }
+        }
+        Ok(())
+    }
+    /// Forgets the side mainblocks that can no longer replace a mainblock because it is final.
+    async fn prune_side_mainblocks(&mut self)-> Result<(),MaincoreInnerError> {
+        let final_hashes: Vec<Hash>=self.side_mainheaders.iter()
+            .filter(|(_,side)| self.is_mainblock_final(side.height))
+            .map(|(hash,_)| hash.clone())
+            .collect();
+        for hash in final_hashes.iter() {
+            self.side_mainheaders.remove(hash);
+            self.remove_side_mainblock(hash).await?;
+        }
+        Ok(())
+    }
+    /// Returns the height and the chainwork of a mainheader of the main chain or of a side chain.
+    fn get_known_mainheader_work(&self,hash: &Hash)-> Option<(usize,BigUint)> {
+        if let Some(height)=self.mainheader_heights.get(hash) {
+            return Some((*height,self.chainwork_vector[*height].clone()));
+        }
+        self.side_mainheaders.get(hash).map(|side| (side.height,side.chainwork.clone()))
+    }
+    /// Returns the height of the last main chain mainheader that is an ancestor of `hash`.
+    fn get_fork_height(&self,hash: &Hash)-> Result<usize,MaincoreInnerError> {
+        let mut cursor=hash.clone();
+        while let Some(side_mainheader)=self.side_mainheaders.get(&cursor) {
+            cursor=side_mainheader.header.get_prev_hash();
+        }
+        match self.mainheader_heights.get(&cursor) {
+            Some(height) => Ok(*height),
+            None => Err(MaincoreInnerError::OrphanMainblock(cursor)),
+        }
+    }
+    /// Returns the mainheaders from the first mainblock up to `hash`, which may be on a side chain.
+    fn get_branch_mainheaders(&self,hash: &Hash)-> Result<Vec<Mainheader>,MaincoreInnerError> {
+        let mut side_branch=Vec::new();
+        let mut cursor=hash.clone();
+        while let Some(side_mainheader)=self.side_mainheaders.get(&cursor) {
+            side_branch.push(side_mainheader.header.clone());
+            cursor=side_mainheader.header.get_prev_hash();
+        }
+        let fork_height=match self.mainheader_heights.get(&cursor) {
+            Some(height) => *height,
+            None => return Err(MaincoreInnerError::OrphanMainblock(cursor)),
+        };
+        let mut branch_mainheaders=self.header_vector[..=fork_height].to_vec();
+        side_branch.reverse();
+        branch_mainheaders.extend(side_branch);
+        Ok(branch_mainheaders)
+    }
+    fn get_side_mainblock_filename(hash: &Hash)-> String {
+        format!("Sidemainblock_{}",hash.to_hex_string())
+    }
+    async fn save_side_mainblock(&mut self,mb: &Mainblock)-> Result<(),MaincoreInnerError> {
+        let filename=Self::get_side_mainblock_filename(&mb.get_hash());
+        self.side_sd.save_bytes_to_file(&filename, &mb.serialize()).await?;
+        Ok(())
+    }
+    async fn load_side_mainblock(&mut self,hash: &Hash)-> Result<Mainblock,MaincoreInnerError> {
+        let filename=Self::get_side_mainblock_filename(hash);
+        let mb_rawbytes=self.side_sd.load_bytes_from_file(&filename).await?;
+        Ok(unserialize_mainblock(mb_rawbytes)?)
+    }
+    async fn remove_side_mainblock(&mut self,hash: &Hash)-> Result<(),MaincoreInnerError> {
+        let filename=Self::get_side_mainblock_filename(hash);
+        match self.side_sd.remove_file(&filename).await {
+            Ok(_) | Err(StorageDirectoryError::FileNotFound(_)) => Ok(()),
+            Err(e) => Err(MaincoreInnerError::StorageDirectoryError(e)),
+        }
+    }
+    /// Reloads the side mainblocks kept on disk, dropping the ones that no longer attach to a known mainheader.
+    pub async fn load_side_mainblocks(&mut self)-> Result<(),MaincoreInnerError> {
+        let mut pending=Vec::new();
+        for path in self.side_sd.list_files().await? {
+            let filename=match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let mb_rawbytes=self.side_sd.load_bytes_from_file(&filename).await?;
+            match unserialize_mainblock(mb_rawbytes) {
+                Ok(mb) => pending.push(mb.get_mainheader()),
+                Err(e) => {
+                    println!("load_side_mainblocks dropping {}: {}",filename,e);
+                    self.side_sd.remove_file(&filename).await?;
+                }
+            }
+        }
+        loop {
+            let mut linked=false;
+            let mut unlinked=Vec::new();
+            for mh in pending.into_iter() {
+                match self.get_known_mainheader_work(&mh.get_prev_hash()) {
+                    Some((prev_height,prev_chainwork)) if !self.mainheader_heights.contains_key(&mh.get_hash()) => {
+                        let chainwork=prev_chainwork+bigint::work_from_compact(mh.get_bits());
+                        self.side_mainheaders.insert(mh.get_hash(), SideMainheader { header: mh, height: prev_height+1, chainwork });
+                        linked=true;
+                    }
+                    _ => unlinked.push(mh),
+                }
+            }
+            pending=unlinked;
+            if !linked {
+                break;
+            }
+        }
+        for mh in pending.iter() {
+            self.remove_side_mainblock(&mh.get_hash()).await?;
+        }
+        self.prune_side_mainblocks().await?;
+        println!("load_side_mainblocks finished with {} side mainheaders",self.side_mainheaders.len());
+        Ok(())
+    }
+    fn push_mainheader(&mut self,mh: Mainheader) {
+        let prev_chainwork=self.chainwork_vector.last().cloned().unwrap_or_default();
+        self.chainwork_vector.push(prev_chainwork+bigint::work_from_compact(mh.get_bits()));
+        self.mainheader_heights.insert(mh.get_hash(), self.header_vector.len());
+        self.header_vector.push(mh);
+    }
+    fn pop_mainheader(&mut self)-> Option<Mainheader> {
+        let mh=self.header_vector.pop()?;
+        self.chainwork_vector.pop();
+        self.mainheader_heights.remove(&mh.get_hash());
+        Some(mh)
+    }
+    /// Total work of the main chain.
+    pub fn get_chainwork(&self)-> BigUint {
+        self.chainwork_vector.last().cloned().unwrap_or_default()
+    }
+    /// A mainblock is final once `confirmation_depth` mainblocks have been built on top of it,
+    /// it can no longer be replaced by a reorganization.
+    pub fn is_mainblock_final(&self,height: usize)-> bool {
+        height+self.chain_params.confirmation_depth<self.header_vector.len()
+    }
+    pub fn get_confirmation_depth(&self)-> usize {
+        self.chain_params.confirmation_depth
+    }
+    pub fn get_chain_params(&self)-> &ChainParams {
+        &self.chain_params
+    }
+    pub fn get_side_mainheaders_count(&self)-> usize {
+        self.side_mainheaders.len()
+    }
+    /// Checks a mainblock against the current tip before it can be added.
+    pub fn validate_mainblock(&mut self,mb: &Mainblock)-> Result<(),MaincoreInnerError> {
+        let context=if self.header_vector.is_empty() {
+            let genesis_hash=self.chain_params.get_genesis_hash();
+            if mb.get_hash()!=genesis_hash {
+                return Err(MaincoreInnerError::GenesisMismatch { expected: genesis_hash, actual: mb.get_hash() });
+            }
+            MainblockValidationContext {
+                height: 0,
+                prev_mainheader: None,
+                expected_bits: None,
+                median_time_past: None,
+                max_timestamp: None,
+            }
+        } else {
+            self.get_validation_context(&self.header_vector)?
+        };
+        validate_mainblock(mb, &context)?;
+        Ok(())
+    }
+    /// Builds the context of a mainblock extending the last of `branch_mainheaders`, which must not be empty.
+    fn get_validation_context(&self,branch_mainheaders: &[Mainheader])-> Result<MainblockValidationContext,MaincoreInnerError> {
+        Ok(MainblockValidationContext {
+            height: branch_mainheaders.len(),
+            prev_mainheader: Some(branch_mainheaders.last().ok_or(MaincoreInnerError::NoMainheader)?.clone()),
+            expected_bits: Some(compute_branch_newbits(branch_mainheaders, &self.chain_params)?),
+            median_time_past: get_median_time_past(branch_mainheaders),
+            max_timestamp: Some(timestamp_now()+self.chain_params.max_future_block_time),
+        })
     }
     pub async fn get_mainblock(&mut self,block_height: usize)-> Result<Mainblock,MaincoreInnerError>{
-        match self.main_sd.get_chunk(block_height).await {
+        match self.main_cs.get_chunk(block_height).await {
             Ok(mb_rawbytes)=> {
-                println!("StorageDirectory get_chunk success");
-                println!("mb_rawbytes {:?}",mb_rawbytes);
                 let mb=unserialize_mainblock(mb_rawbytes)?;
                 Ok(mb)
             }
             Err(e)=> {
-                println!("StorageDirectory get_chunk error {:?}",e);
-                Err(MaincoreInnerError::StorageDirectoryError(e))
+                println!("ChunkStore get_chunk error {:?}",e);
+                Err(MaincoreInnerError::ChunkStoreError(e))
             }
         }
     }
-    //
+    /// Loads the mainheaders from the mainheader store. Mainheaders missing from the store, or not
+    /// chained to the previous one, are read back from their mainblocks and the store is repaired.
     pub async fn load_mainheaders(&mut self) -> Result<(),MaincoreInnerError> {
         let tmpblocks_count=self.get_mainblocks_count();
         println!("loading mainheaders - number of mainblocks {}",tmpblocks_count);
-        if tmpblocks_count==0 {
-            //Err(e) => {
-                //return Err(eprintln!("))
-                //self.check_genesis().await?;
-                println!("load_headers finished-no mainheaders");
-                return Ok(());
-                //return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, "Maincore ChunckStorage is empty get_blocks_count()==0")))
-           // }
-        }
-        for i in 0..tmpblocks_count {
-
-            match self.get_mainheader(i).await {
-                Ok(tmpheader)=> {
-                    self.header_vector.push(tmpheader);
-                    println!("loaded mainheader {}",i);
-                    //return Ok(());
-                }
-                Err(e)=> {
-                    println!("load_headers error {:?}",e);
-                    return Err(e);
-                }
+        let mut stored_mainheaders=self.header_store.load().await?;
+        stored_mainheaders.truncate(tmpblocks_count);
+        let mut valid_count=0;
+        for (i,mh) in stored_mainheaders.iter().enumerate() {
+            let expected_prev_hash=match i {
+                0 => Hash::new_empty(),
+                _ => stored_mainheaders[i-1].get_hash(),
+            };
+            if mh.get_prev_hash()!=expected_prev_hash {
+                break;
             }
+            valid_count+=1;
         }
-        println!("tmpblocks_count {}",tmpblocks_count);
-        let tmp_height=self.header_vector.len();
-        println!("load_headers finished with {} mainheaders loaded",tmp_height);
+        stored_mainheaders.truncate(valid_count);
+        self.header_store.truncate(valid_count).await?;
+        for mh in stored_mainheaders.into_iter() {
+            self.push_mainheader(mh);
+        }
+        if valid_count<tmpblocks_count {
+            println!("mainheader store has {} of {} mainheaders, reading the others from the mainblocks",valid_count,tmpblocks_count);
+            let mut missing_mainheaders=Vec::new();
+            for i in valid_count..tmpblocks_count {
+                let tmpheader=self.get_mainheader(i).await?;
+                self.push_mainheader(tmpheader.clone());
+                missing_mainheaders.push(tmpheader);
+            }
+            self.header_store.append_all(&missing_mainheaders).await?;
+        }
+        if let Some(first_mainheader)=self.header_vector.first() {
+            let genesis_hash=self.chain_params.get_genesis_hash();
+            if first_mainheader.get_hash()!=genesis_hash {
+                return Err(MaincoreInnerError::GenesisMismatch { expected: genesis_hash, actual: first_mainheader.get_hash() });
+            }
+        }
+        println!("load_headers finished with {} mainheaders loaded",self.header_vector.len());
+        self.load_side_mainblocks().await?;
         Ok(())
     }
     //
     pub async fn get_mainheader(&mut self,header_height: usize)-> Result<Mainheader, MaincoreInnerError> {
-        match self.main_sd.get_chunk(header_height).await {
+        match self.main_cs.get_chunk(header_height).await {
             Ok(mb_rawbytes)=> {
                 //println!("ChunksStorage get_chunk success");
                 let mb=unserialize_mainblock(mb_rawbytes)?;
@@ -212,12 +825,15 @@ impl MaincoreInner{
             }
             Err(e)=> {
                 println!("ChunksStorage get_chunk error {:?}",e);
-                Err(MaincoreInnerError::StorageDirectoryError(e))
+                Err(MaincoreInnerError::ChunkStoreError(e))
             }
         }
     }
     pub fn get_last_inmem_mainheader(&self)-> Result<Mainheader, MaincoreInnerError> {
-        let last_block_height=(self.header_vector.len())-1;
+        let last_block_height=match self.header_vector.len().checked_sub(1) {
+            Some(height) => height,
+            None => return Err(MaincoreInnerError::NoMainheader),
+        };
         //let last_block_height1=self.get_blocks_count()-1;
         //println!("*********** last_block_height {} {}",last_block_height1,last_block_height);
         Ok(self.header_vector[last_block_height].clone())
@@ -226,39 +842,162 @@ impl MaincoreInner{
     pub fn get_inmem_mainheader(&self,header_height: usize)-> Result<Mainheader, MaincoreInnerError> {
         Ok(self.header_vector[header_height].clone()) 
     }
-    pub fn get_newbits(&mut self)-> u32 {
-        //let newbits:u32;
-        
-        let tmp_height=self.header_vector.len();
-        let prev_mainheader=self.header_vector[tmp_height-1].clone();
-        if tmp_height % 4032 ==0 {
-            let mut summedtimestamp:i64=0;
-            for i in (tmp_height-4031)..(tmp_height) {
-                let deltatimestamp=self.header_vector[i].get_timestamp()-self.header_vector[i-1].get_timestamp();
-                if deltatimestamp==0 {
-                    println!("check deltatimestamp {} for i {}",deltatimestamp,i);
-                    println!("self.header_vector[i].get_timestamp() {} self.header_vector[i-1].get_timestamp() {}",self.header_vector[i].get_timestamp(),self.header_vector[i-1].get_timestamp());
-                    //process::exit(1);
+    pub fn get_mainstate(&self)-> &Mainstate {
+        &self.mainstate
+    }
+    pub fn get_balance(&self,address: &Hash)-> u64 {
+        self.mainstate.get_balance(address)
+    }
+    pub fn get_unspent_outputs(&self,address: &Hash)-> Vec<(MainstateOutpoint,MainstateOutput)> {
+        self.mainstate.get_outputs_by_address(address)
+    }
+    pub fn get_txspool(&self)-> &Maintxspool {
+        &self.txspool
+    }
+    /// Adds a signed transaction to the txspool if it spends unspent outputs without conflict.
+    pub fn add_maintx(&mut self,tx: Maintx)-> Result<Hash,MaincoreInnerError> {
+        let tmp_hash=self.txspool.add_maintx(tx, &self.mainstate)?;
+        let _ = self.events.send(MaincoreEvent::MaintxAdded(tmp_hash.clone()));
+        Ok(tmp_hash)
+    }
+    pub fn subscribe_events(&self)-> broadcast::Receiver<MaincoreEvent> {
+        self.events.subscribe()
+    }
+    /// Number of mainheaders of the main chain held in memory, the height of the next mainblock.
+    pub fn get_inmem_mainheaders_count(&self)-> usize {
+        self.header_vector.len()
+    }
+    /// Whether a mainheader is known, on the main chain or on a side chain.
+    pub fn has_mainheader(&self,hash: &Hash)-> bool {
+        self.mainheader_heights.contains_key(hash) || self.side_mainheaders.contains_key(hash)
+    }
+    /// Height of a mainheader of the main chain.
+    pub fn get_mainheader_height(&self,hash: &Hash)-> Option<usize> {
+        self.mainheader_heights.get(hash).copied()
+    }
+    /// Loads a mainblock of the main chain or of a side chain.
+    pub async fn get_mainblock_by_hash(&mut self,hash: &Hash)-> Result<Option<Mainblock>,MaincoreInnerError> {
+        if let Some(height)=self.get_mainheader_height(hash) {
+            return Ok(Some(self.get_mainblock(height).await?));
+        }
+        if self.side_mainheaders.contains_key(hash) {
+            return Ok(Some(self.load_side_mainblock(hash).await?));
+        }
+        Ok(None)
+    }
+    /// Hashes of main chain mainheaders from the tip backwards, dense at first then doubling the step,
+    /// always ending with the genesis mainheader.
+    pub fn get_mainheader_locator(&self)-> Vec<Hash> {
+        let mut locator=Vec::new();
+        let mut height=match self.header_vector.len().checked_sub(1) {
+            Some(height) => height,
+            None => return locator,
+        };
+        let mut step=1;
+        loop {
+            locator.push(self.header_vector[height].get_hash());
+            if height==0 {
+                break;
+            }
+            if locator.len()>=10 {
+                step*=2;
+            }
+            height=height.saturating_sub(step);
+        }
+        locator
+    }
+    /// Main chain mainheaders following the first hash of `locator` found on the main chain
+    /// (the genesis mainheader if none is), up to `stop_hash` included and at most `max_count`.
+    pub fn get_mainheaders_after_locator(&self,locator: &[Hash],stop_hash: &Hash,max_count: usize)-> Vec<Mainheader> {
+        let start_height=locator.iter()
+            .find_map(|hash| self.mainheader_heights.get(hash))
+            .map(|height| height+1)
+            .unwrap_or(0);
+        let mut mainheaders=Vec::new();
+        for mh in self.header_vector.iter().skip(start_height).take(max_count) {
+            let is_stop=mh.get_hash()==*stop_hash;
+            mainheaders.push(mh.clone());
+            if is_stop {
+                break;
+            }
+        }
+        mainheaders
+    }
+    pub fn get_syncpool(&self)-> &Syncpool {
+        &self.syncpool
+    }
+    pub fn get_syncpool_mut(&mut self)-> &mut Syncpool {
+        &mut self.syncpool
+    }
+    /// Validates mainheaders received from a peer and queues the unknown ones for download.
+    pub fn add_sync_mainheaders(&mut self,mainheaders: &[Mainheader])-> Result<usize,MaincoreInnerError> {
+        Ok(self.syncpool.add_mainheaders(mainheaders, &self.header_vector, &self.mainheader_heights, &self.chain_params, timestamp_now())?)
+    }
+    /// Connects, in chain order, the mainblocks the syncpool has downloaded. Returns the number connected.
+    /// The sync is abandoned if one of them is invalid.
+    pub async fn connect_synced_mainblocks(&mut self)-> Result<usize,MaincoreInnerError> {
+        let mut connected_count=0;
+        while let Some(mb)=self.syncpool.pop_next_mainblock() {
+            match self.add_confirmed_mainblock(mb).await {
+                Ok(_) => connected_count+=1,
+                Err(MaincoreInnerError::DuplicateMainblock(_)) => {}
+                Err(e) => {
+                    self.syncpool.reset();
+                    return Err(e);
                 }
-                summedtimestamp+=deltatimestamp;
-            }
-            let bitsbigint=bigint::bigint_from_compact(prev_mainheader.get_bits());
-            let newbitsbigint=bitsbigint.clone()*bigint::bigint_from_u64(summedtimestamp as u64)/bigint::bigint_from_u64((4031*300) as u64);
-            /*
-            println!("summed over interval from {} to {}",tmp_height-4031,tmp_height-1);
-            println!("summedtimestamp {} idealtime {}",summedtimestamp,4031*300);
-            println!("check deltatimestamp {}",self.header_vector[1].get_timestamp()-self.header_vector[0].get_timestamp());
-            println!("oldbits {} newbits {}",bitsbigint,newbitsbigint);
-            println!("oldbits compact {} newbits compact {}",bigint::compact_from_bigint(&bitsbigint),bigint::compact_from_bigint(&newbitsbigint));
-            println!("newbits {}",bigint::compact_from_bigint(&newbitsbigint));
-            println!("oldbits {}",bigint::compact_from_bigint(&bitsbigint));
-            process::exit(1);
-            */
-            return bigint::compact_from_bigint(&newbitsbigint);
-
-        } else {
-            return prev_mainheader.get_bits();
+            }
         }
+        Ok(connected_count)
+    }
+    pub fn get_sync_progress(&self)-> SyncpoolProgress {
+        self.syncpool.get_progress(self.header_vector.len())
+    }
+    pub fn get_miner(&self)-> &Miner {
+        &self.miner
+    }
+    pub fn get_miner_mut(&mut self)-> &mut Miner {
+        &mut self.miner
+    }
+    /// Assembles the next mainblock to mine on top of the tip: the reward transaction paying the miner
+    /// reward address followed by the best paying transactions of the txspool. The template replaces the
+    /// previous one, whose transactions are released first.
+    pub fn build_mainblock_template(&mut self)-> Result<MainblockTemplate,MaincoreInnerError> {
+        let reward_address=self.miner.get_reward_address().ok_or(MinerError::NoRewardAddress)?;
+        let prev_mainheader=self.get_last_inmem_mainheader()?;
+        let height=self.header_vector.len();
+        self.txspool.reset_tx_with_mainblock_height(height);
+        let bits=self.get_newbits()?;
+        let median_time_past=get_median_time_past(&self.header_vector).unwrap_or(prev_mainheader.get_timestamp());
+        let timestamp=timestamp_now().max(median_time_past+1);
+
+        let reward_value=self.chain_params.get_mainblock_subsidy(height);
+        let reward_size=new_reward_transaction(height as u32, reward_value, 0, reward_address.clone()).get_serialization_size();
+        let selected=self.txspool.select_maintxs(self.chain_params.max_mainblock_size-reward_size);
+        let selected_hashes: Vec<Hash>=selected.iter().map(|tx| tx.compute_hash()).collect();
+        let fee: u64=selected_hashes.iter()
+            .filter_map(|hash| self.txspool.get_entry(hash))
+            .map(|entry| entry.fee)
+            .sum();
+        self.txspool.freeze_with_mainblock_height(&selected_hashes, height);
+
+        let mut transactions=vec![new_reward_transaction(height as u32, reward_value, fee, reward_address)];
+        transactions.extend(selected);
+        Ok(MainblockTemplate::new(height, prev_mainheader.get_hash(), timestamp, bits, transactions))
+    }
+    /// Releases the transactions frozen for the template at `height`, which will not be mined:
+    /// it has been cancelled or the mainblock mined from it has been rejected.
+    pub fn release_mainblock_template(&mut self,height: usize) {
+        self.txspool.reset_tx_with_mainblock_height(height);
+    }
+    pub fn get_newbits(&self)-> Result<u32,MaincoreInnerError> {
+        compute_branch_newbits(&self.header_vector, &self.chain_params)
     }
 
-}
\ No newline at end of file
+}
+/// Computes the bits of the mainblock following the last of `branch_mainheaders`,
+/// which holds every mainheader of its branch from the genesis mainheader.
+pub fn compute_branch_newbits(branch_mainheaders: &[Mainheader],chain_params: &ChainParams)-> Result<u32,MaincoreInnerError> {
+    let next_height=branch_mainheaders.len();
+    let window_start=next_height.saturating_sub(chain_params.retarget_interval);
+    Ok(compute_next_bits(next_height, &branch_mainheaders[window_start..], chain_params)?)
+}
@@ -12,6 +12,10 @@ use utility::buffer::buffer_reader::BufferReader;
 use utility::buffer::buffer_reader::BufferReaderError;
 use utility::hash::bigint;
 use std::cmp::Ordering;
+use crate::miner::mining_engine::MiningEngine;
+
+/// Size of the nonce, the last of the bytes hashed by `Mainheader::compute_hash`.
+pub const MAINHEADER_NONCE_SIZE: usize = 4;
 
 #[derive(Debug, Error)]
 pub enum MainheaderError {
@@ -108,21 +112,20 @@ impl Mainheader {
         }
     }
     //
-    pub fn compute_hash(&self)-> Hash {
-        //a transaction hash is a signing hash, it does not include a signature
+    /// The bytes hashed by `compute_hash`: every field but the hash, the nonce last.
+    pub fn get_hashing_bytes(&self) -> Vec<u8> {
         //TODOLATER make it more efficient using pointer and not clone
         let mut bw = BufferWriter::new();
         bw.put_var_u32(self.version as u32);
-        //bw.put_var_u64(tmptx.vin.len() as u64);
         bw.put_hash(self.prev_hash.clone());
         bw.put_hash(self.root_hash.clone());
         bw.put_u64(self.timestamp as u64);
         bw.put_u32(self.bits);
         bw.put_u32(self.nonce);
-        let content = bw.get_bytes();
-
-        let tmphash=Hash::compute_hash(content.as_slice());
-        tmphash 
+        bw.get_bytes()
+    }
+    pub fn compute_hash(&self)-> Hash {
+        Hash::compute_hash(&self.get_hashing_bytes())
     }
     //
     pub fn serialize(&self) -> Vec<u8> {
@@ -179,44 +182,13 @@ impl fmt::Debug for Mainheader {
     }
 }
 
+/// Mines a mainheader on the calling thread. The timestamp is rolled forward when the nonce space is exhausted,
+/// so the returned mainheader may have a later timestamp than requested.
 pub fn mine_mainheader_with_cpu(version: u32,prev_hash: Hash,root_hash: Hash,timestamp: i64,bits: u32) -> Result<Mainheader, MainheaderError> {
-    let test: u64 =1;
-    let mut bw = BufferWriter::new();
-    bw.put_var_u32(version as u32);
-    //bw.put_var_u64(tmptx.vin.len() as u64);
-    bw.put_hash(prev_hash.clone());
-    bw.put_hash(root_hash.clone());
-    bw.put_u64(timestamp as u64);
-    bw.put_u32(bits);
-    
-    let mut nonce:u32=1;
- 
-    for num in 1..=42949000 {//4294967295//TODO use random number generator//TODO use search_iterations_count
-        //println!("Current number: {}", num);
-        nonce+=1;
-        let mut newbw=bw.clone();
-        newbw.put_u32(nonce);
-        let content = newbw.get_bytes();
-        let tmphash=Hash::compute_hash(content.as_slice());
-        
-        // compare hash with target
-        let bitsbigint=bigint::bigint_from_compact(bits);
-        let hashbigint=bigint::bigint_from_hash(&tmphash.clone());
-        //println!("genesis_block bigint      {:?}",bitsbigint);
-        //println!("genesis_block hash bigint {:?}",hashbigint);
-        // Compare a and b
-        
-        let comparison = hashbigint.cmp(&bitsbigint);
-        match comparison {
-            Ordering::Less => { let tmpmainheader=Mainheader::new(version, prev_hash.clone(),root_hash.clone(), timestamp, bits, nonce,tmphash.clone());
-                return Ok(tmpmainheader)},//{return true},//println!("hashbigint is less than bitsbigint"),
-            _ => continue,//println!("hashbigint is greater or equal to bitsbigint"),
-        }
-        /////////////////////////////////////////
-        //let tmpmainheader=Mainheader::new(version, prev_hash.clone(),root_hash.clone(), timestamp, bits, nonce,tmphash.clone());
-        //return Ok(tmpmainheader)
-        /////////////////////////////////////////
-        
+    let candidate=Mainheader::new(version, prev_hash, root_hash, timestamp, bits, 0, Hash::new_empty());
+    let engine=MiningEngine::new(1);
+    match engine.mine(&candidate)? {
+        Some(mh) => Ok(mh),
+        None => Err(MainheaderError::MiningUnsuccessful),
     }
-    return Err(MainheaderError::MiningUnsuccessful)//Err("mining unsuccessful".into())    
 }
@@ -0,0 +1,115 @@
+use std::path::Path;
+use std::path::PathBuf;
+use thiserror::Error;
+use tokio::fs;
+use tokio::fs::OpenOptions;
+use tokio::io;
+use tokio::io::AsyncWriteExt;
+use utility::hash::hash::Hash;
+use crate::mainheader::mainheader::unserialize_mainheader;
+use crate::mainheader::mainheader::Mainheader;
+use crate::mainheader::mainheader::MainheaderError;
+
+/// Largest `Mainheader::serialize` output, the version being a var_u32 of up to 5 bytes.
+pub const MAINHEADER_MAX_SERIALIZED_SIZE: usize = 5 + 32 + 32 + 8 + 4 + 4 + 32;
+/// A record is the serialized mainheader padded to the maximum size, followed by a 4 bytes checksum.
+pub const MAINHEADER_RECORD_SIZE: usize = MAINHEADER_MAX_SERIALIZED_SIZE + 4;
+
+#[derive(Debug, Error)]
+pub enum MainheaderStoreError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("Mainheader error: {0}")]
+    MainheaderError(#[from] MainheaderError),
+}
+
+/// Append-only file of fixed-size mainheader records, one per mainblock of the main chain,
+/// so that the mainheaders can be loaded without reading the mainblocks.
+pub struct MainheaderStore {
+    ms_path: PathBuf,
+    count: usize,
+}
+
+impl MainheaderStore {
+    pub fn new<P: AsRef<Path>>(ms_path: P) -> Self {
+        Self { ms_path: ms_path.as_ref().to_path_buf(), count: 0 }
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.count
+    }
+
+    /// Reads every record. A partial or corrupted record and everything after it is cut from the file.
+    pub async fn load(&mut self) -> Result<Vec<Mainheader>, MainheaderStoreError> {
+        let rawbytes = match fs::read(&self.ms_path).await {
+            Ok(rawbytes) => rawbytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(MainheaderStoreError::IoError(e)),
+        };
+        let mut mainheaders = Vec::new();
+        for record in rawbytes.chunks_exact(MAINHEADER_RECORD_SIZE) {
+            match unserialize_record(record) {
+                Some(mh) => mainheaders.push(mh),
+                None => break,
+            }
+        }
+        if mainheaders.len() * MAINHEADER_RECORD_SIZE != rawbytes.len() {
+            println!("MainheaderStore truncating a corrupted tail after {} mainheaders", mainheaders.len());
+            let file = OpenOptions::new().write(true).open(&self.ms_path).await?;
+            file.set_len((mainheaders.len() * MAINHEADER_RECORD_SIZE) as u64).await?;
+            file.sync_data().await?;
+        }
+        self.count = mainheaders.len();
+        Ok(mainheaders)
+    }
+
+    pub async fn append(&mut self, mh: &Mainheader) -> Result<(), MainheaderStoreError> {
+        self.append_all(std::slice::from_ref(mh)).await
+    }
+
+    pub async fn append_all(&mut self, mainheaders: &[Mainheader]) -> Result<(), MainheaderStoreError> {
+        let mut rawbytes = Vec::with_capacity(mainheaders.len() * MAINHEADER_RECORD_SIZE);
+        for mh in mainheaders.iter() {
+            rawbytes.extend_from_slice(&serialize_record(mh));
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.ms_path).await?;
+        file.write_all(&rawbytes).await?;
+        file.sync_data().await?;
+        self.count += mainheaders.len();
+        Ok(())
+    }
+
+    /// Keeps the first `count` records.
+    pub async fn truncate(&mut self, count: usize) -> Result<(), MainheaderStoreError> {
+        if count >= self.count {
+            return Ok(());
+        }
+        let file = OpenOptions::new().write(true).open(&self.ms_path).await?;
+        file.set_len((count * MAINHEADER_RECORD_SIZE) as u64).await?;
+        file.sync_data().await?;
+        self.count = count;
+        Ok(())
+    }
+}
+
+fn compute_record_checksum(content: &[u8]) -> [u8; 4] {
+    let hash = Hash::compute_hash(content);
+    let bytes = hash.as_bytes();
+    [bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
+fn serialize_record(mh: &Mainheader) -> Vec<u8> {
+    let mut record = mh.serialize();
+    record.resize(MAINHEADER_MAX_SERIALIZED_SIZE, 0);
+    let checksum = compute_record_checksum(&record);
+    record.extend_from_slice(&checksum);
+    record
+}
+
+fn unserialize_record(record: &[u8]) -> Option<Mainheader> {
+    let (content, checksum) = record.split_at(MAINHEADER_MAX_SERIALIZED_SIZE);
+    if compute_record_checksum(content) != checksum {
+        return None;
+    }
+    unserialize_mainheader(content.to_vec()).ok()
+}
@@ -1 +1,2 @@
-pub mod mainheader;
\ No newline at end of file
+pub mod mainheader;
+pub mod mainheader_store;
@@ -0,0 +1,567 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::path::PathBuf;
+use thiserror::Error;
+use utility::hash::hash::Hash;
+use utility::buffer::buffer_writer::BufferWriter;
+use utility::buffer::buffer_reader::BufferReader;
+use utility::buffer::buffer_reader::BufferReaderError;
+use utility::storage::async_file::{save_bytes_to_file_atomic, load_bytes_from_file, file_exists, AsyncFileError};
+use utility::storage::chunk_store::ChunkStore;
+use utility::storage::chunk_store::ChunkStoreError;
+use maintx::maintx::maintx::Maintx;
+use maintx::maintx_in::maintx_in::MaintxInError;
+use maintx::maintx_out::maintx_out::MaintxOutError;
+use crate::mainblock::mainblock::Mainblock;
+
+const MAINSTATE_VERSION: u32 = 1;
+/// Number of mainblocks connected between two checkpoints of the whole mainstate.
+pub const MAINSTATE_CHECKPOINT_INTERVAL: usize = 1000;
+
+#[derive(Debug, Error)]
+pub enum MainstateError {
+    #[error("Async file error: {0}")]
+    AsyncFileError(#[from] AsyncFileError),
+    #[error("Buffer reader error: {0}")]
+    BufferReaderError(#[from] BufferReaderError),
+    #[error("ChunkStore error: {0}")]
+    ChunkStoreError(#[from] ChunkStoreError),
+    #[error("MaintxIn error: {0}")]
+    MaintxInError(#[from] MaintxInError),
+    #[error("MaintxOut error: {0}")]
+    MaintxOutError(#[from] MaintxOutError),
+
+    #[error("Unknown mainstate version: {0}")]
+    UnknownVersion(u32),
+    #[error("Invalid mainstate path")]
+    InvalidPath,
+
+    #[error("Output {index} of {hash:?} does not exist")]
+    MissingOutput { hash: Hash, index: u32 },
+    #[error("Output {index} of {hash:?} is already spent")]
+    DoubleSpend { hash: Hash, index: u32 },
+    #[error("Reward output {index} of {hash:?} cannot be spent before mainblock {mature_height}")]
+    ImmatureReward { hash: Hash, index: u32, mature_height: u64 },
+    #[error("Public key does not match the address of output {index} of {hash:?}")]
+    AddressMismatch { hash: Hash, index: u32 },
+    #[error("Outputs exceed inputs in transaction {0:?}")]
+    OutputsExceedInputs(Hash),
+    #[error("Value overflow in transaction {0:?}")]
+    ValueOverflow(Hash),
+    #[error("Transaction {0:?} already has unspent outputs")]
+    DuplicateMaintx(Hash),
+    #[error("No undo data left to disconnect mainblock {0:?}")]
+    UndoUnavailable(Hash),
+    #[error("Undo data belongs to mainblock {expected:?}, not {actual:?}")]
+    UndoMismatch { expected: Hash, actual: Hash },
+}
+
+/// Reference to an output: the hash of its transaction and its index in `vout`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MainstateOutpoint {
+    pub hash: Hash,
+    pub index: u32,
+}
+
+/// An unspent output together with the height of the mainblock that created it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MainstateOutput {
+    pub value: u64,
+    pub address: Hash,
+    pub mainblock_height: u32,
+    /// Created by a reward transaction, spendable only once `reward_maturity` mainblocks are built on top.
+    pub is_reward: bool,
+}
+
+/// What a connected mainblock changed in the mainstate, so that it can be disconnected, or replayed on top
+/// of a checkpoint when the mainstate is loaded.
+#[derive(Debug, Clone)]
+pub struct MainstateUndo {
+    pub mainblock_hash: Hash,
+    pub created: Vec<(MainstateOutpoint, MainstateOutput)>,
+    pub spent: Vec<(MainstateOutpoint, MainstateOutput)>,
+}
+
+/// The set of unspent outputs ("mainstate") after the first `mainblocks_count` mainblocks.
+/// It is saved in its `ms_path` directory as the `MainstateUndo` of each mainblock, a record of a `ChunkStore`
+/// at its height, and as a checkpoint of the whole set written every `checkpoint_interval` mainblocks.
+/// Loading reads the checkpoint and replays the records above it.
+pub struct Mainstate {
+    ms_path: PathBuf,
+    unspent: HashMap<MainstateOutpoint, MainstateOutput>,
+    mainblocks_count: usize,
+    /// Hash of the last connected mainblock, empty when no mainblock is connected.
+    tip_hash: Hash,
+    undo_vector: VecDeque<MainstateUndo>,
+    undo_depth: usize,
+    reward_maturity: usize,
+    /// Opened by the first `load` or `save`.
+    ms_cs: Option<ChunkStore>,
+    /// Records of the first `stored_count` mainblocks are in the chunk store, those of the next ones are pending.
+    stored_count: usize,
+    pending_undos: VecDeque<MainstateUndo>,
+    /// Mainblocks count of the saved checkpoint, `None` if it does not belong to the connected mainblocks.
+    checkpoint_count: Option<usize>,
+    checkpoint_interval: usize,
+}
+
+/// Outputs created and spent by the transactions checked so far, on top of the mainstate.
+#[derive(Default)]
+struct MainstateOverlay {
+    created: HashMap<MainstateOutpoint, MainstateOutput>,
+    spent: HashSet<MainstateOutpoint>,
+}
+
+impl Mainstate {
+    pub fn new<P: AsRef<Path>>(ms_path: P) -> Self {
+        Self {
+            ms_path: ms_path.as_ref().to_path_buf(),
+            unspent: HashMap::new(),
+            mainblocks_count: 0,
+            tip_hash: Hash::new_empty(),
+            undo_vector: VecDeque::new(),
+            undo_depth: 0,
+            reward_maturity: 0,
+            ms_cs: None,
+            stored_count: 0,
+            pending_undos: VecDeque::new(),
+            checkpoint_count: None,
+            checkpoint_interval: MAINSTATE_CHECKPOINT_INTERVAL,
+        }
+    }
+
+    /// Sets how many mainblocks are connected between two checkpoints.
+    pub fn set_checkpoint_interval(&mut self, checkpoint_interval: usize) {
+        self.checkpoint_interval = checkpoint_interval.max(1);
+    }
+
+    /// Mainblocks count of the saved checkpoint, if it belongs to the connected mainblocks.
+    pub fn get_checkpoint_count(&self) -> Option<usize> {
+        self.checkpoint_count
+    }
+
+    /// Sets how many of the last connected mainblocks can be disconnected.
+    pub fn set_undo_depth(&mut self, undo_depth: usize) {
+        self.undo_depth = undo_depth;
+        while self.undo_vector.len() > self.undo_depth {
+            self.undo_vector.pop_front();
+        }
+    }
+
+    /// Sets how many mainblocks must follow a reward transaction before its outputs can be spent.
+    pub fn set_reward_maturity(&mut self, reward_maturity: usize) {
+        self.reward_maturity = reward_maturity;
+    }
+
+    /// Number of mainblocks that can currently be disconnected.
+    pub fn get_undo_count(&self) -> usize {
+        self.undo_vector.len()
+    }
+
+    /// Number of mainblocks whose transactions have been applied.
+    pub fn get_mainblocks_count(&self) -> usize {
+        self.mainblocks_count
+    }
+
+    /// Hash of the last mainblock whose transactions have been applied.
+    pub fn get_tip_hash(&self) -> Hash {
+        self.tip_hash.clone()
+    }
+
+    pub fn get_unspent_count(&self) -> usize {
+        self.unspent.len()
+    }
+
+    pub fn get_output(&self, hash: &Hash, index: u32) -> Option<&MainstateOutput> {
+        self.unspent.get(&MainstateOutpoint { hash: hash.clone(), index })
+    }
+
+    pub fn is_unspent(&self, hash: &Hash, index: u32) -> bool {
+        self.get_output(hash, index).is_some()
+    }
+
+    /// Lists the unspent outputs paying to `address`.
+    pub fn get_outputs_by_address(&self, address: &Hash) -> Vec<(MainstateOutpoint, MainstateOutput)> {
+        let mut outputs: Vec<(MainstateOutpoint, MainstateOutput)> = self
+            .unspent
+            .iter()
+            .filter(|(_, output)| output.address == *address)
+            .map(|(outpoint, output)| (outpoint.clone(), output.clone()))
+            .collect();
+        outputs.sort_by(|a, b| a.0.cmp(&b.0));
+        outputs
+    }
+
+    pub fn get_balance(&self, address: &Hash) -> u64 {
+        self.unspent
+            .values()
+            .filter(|output| output.address == *address)
+            .map(|output| output.value)
+            .sum()
+    }
+
+    /// Checks that all the inputs of a non-reward transaction spend existing unspent outputs
+    /// that can be spent in the next mainblock and returns its fee.
+    pub fn check_maintx(&self, tx: &Maintx) -> Result<u64, MainstateError> {
+        let mut overlay = MainstateOverlay::default();
+        self.check_maintx_with_overlay(tx, self.mainblocks_count as u32, &mut overlay)
+    }
+
+    /// Same as `check_maintx`, the inputs may also spend `pending_outputs`, created by transactions not confirmed yet.
+    pub fn check_maintx_with_pending_outputs(&self, tx: &Maintx, pending_outputs: &HashMap<MainstateOutpoint, MainstateOutput>) -> Result<u64, MainstateError> {
+        let mut overlay = MainstateOverlay::default();
+        for vin in tx.vin.iter() {
+            let outpoint = MainstateOutpoint { hash: vin.get_hash()?, index: vin.get_index()? };
+            if let Some(output) = pending_outputs.get(&outpoint) {
+                overlay.created.insert(outpoint, output.clone());
+            }
+        }
+        self.check_maintx_with_overlay(tx, self.mainblocks_count as u32, &mut overlay)
+    }
+
+    /// Checks every transaction of a mainblock in order and returns the sum of their fees.
+    /// Outputs created earlier in the mainblock may be spent by later transactions.
+    pub fn check_mainblock(&self, mb: &Mainblock) -> Result<u64, MainstateError> {
+        let mut overlay = MainstateOverlay::default();
+        self.check_mainblock_with_overlay(mb, &mut overlay)
+    }
+
+    /// Applies a mainblock to the mainstate after checking it.
+    pub fn connect_mainblock(&mut self, mb: &Mainblock) -> Result<(), MainstateError> {
+        let mut overlay = MainstateOverlay::default();
+        self.check_mainblock_with_overlay(mb, &mut overlay)?;
+        let mut undo = MainstateUndo {
+            mainblock_hash: mb.get_hash(),
+            created: Vec::new(),
+            spent: Vec::new(),
+        };
+        for outpoint in overlay.spent.iter() {
+            if let Some(output) = self.unspent.remove(outpoint) {
+                undo.spent.push((outpoint.clone(), output));
+            }
+        }
+        for (outpoint, output) in overlay.created.into_iter() {
+            if !overlay.spent.contains(&outpoint) {
+                undo.created.push((outpoint.clone(), output.clone()));
+                self.unspent.insert(outpoint, output);
+            }
+        }
+        undo.created.sort_by(|a, b| a.0.cmp(&b.0));
+        undo.spent.sort_by(|a, b| a.0.cmp(&b.0));
+        self.pending_undos.push_back(undo.clone());
+        self.push_undo(undo);
+        self.mainblocks_count += 1;
+        self.tip_hash = mb.get_hash();
+        Ok(())
+    }
+
+    fn push_undo(&mut self, undo: MainstateUndo) {
+        self.undo_vector.push_back(undo);
+        while self.undo_vector.len() > self.undo_depth {
+            self.undo_vector.pop_front();
+        }
+    }
+
+    /// Applies the record of the mainblock at height `mainblocks_count` again.
+    fn replay_undo(&mut self, undo: &MainstateUndo) {
+        for (outpoint, _) in undo.spent.iter() {
+            self.unspent.remove(outpoint);
+        }
+        for (outpoint, output) in undo.created.iter() {
+            self.unspent.insert(outpoint.clone(), output.clone());
+        }
+        self.mainblocks_count += 1;
+        self.tip_hash = undo.mainblock_hash.clone();
+    }
+
+    /// Reverts the last connected mainblock, which must be `mb`.
+    pub fn disconnect_mainblock(&mut self, mb: &Mainblock) -> Result<(), MainstateError> {
+        let mainblock_hash = mb.get_hash();
+        let undo = match self.undo_vector.back() {
+            Some(undo) => undo,
+            None => return Err(MainstateError::UndoUnavailable(mainblock_hash)),
+        };
+        if undo.mainblock_hash != mainblock_hash {
+            return Err(MainstateError::UndoMismatch {
+                expected: undo.mainblock_hash.clone(),
+                actual: mainblock_hash,
+            });
+        }
+        let undo = self.undo_vector.pop_back().ok_or(MainstateError::UndoUnavailable(mainblock_hash))?;
+        for (outpoint, _) in undo.created.iter() {
+            self.unspent.remove(outpoint);
+        }
+        for (outpoint, output) in undo.spent.into_iter() {
+            self.unspent.insert(outpoint, output);
+        }
+        if self.pending_undos.pop_back().is_none() {
+            self.stored_count -= 1;
+        }
+        self.mainblocks_count -= 1;
+        self.tip_hash = mb.header.get_prev_hash();
+        if self.checkpoint_count.is_some_and(|checkpoint_count| checkpoint_count > self.mainblocks_count) {
+            self.checkpoint_count = None;
+        }
+        Ok(())
+    }
+
+    /// Empties the mainstate so that it can be rebuilt from the first mainblock.
+    pub fn reset(&mut self) {
+        self.unspent.clear();
+        self.undo_vector.clear();
+        self.mainblocks_count = 0;
+        self.tip_hash = Hash::new_empty();
+        self.stored_count = 0;
+        self.pending_undos.clear();
+        self.checkpoint_count = None;
+    }
+
+    fn check_mainblock_with_overlay(&self, mb: &Mainblock, overlay: &mut MainstateOverlay) -> Result<u64, MainstateError> {
+        let mainblock_height = self.mainblocks_count as u32;
+        let mut total_fee: u64 = 0;
+        for (i, tx) in mb.transactions.iter().enumerate() {
+            if i > 0 {
+                let fee = self.check_maintx_with_overlay(tx, mainblock_height, overlay)?;
+                total_fee = total_fee
+                    .checked_add(fee)
+                    .ok_or_else(|| MainstateError::ValueOverflow(tx.compute_hash()))?;
+            }
+            self.add_maintx_outputs(tx, mainblock_height, i == 0, overlay)?;
+        }
+        Ok(total_fee)
+    }
+
+    fn check_maintx_with_overlay(&self, tx: &Maintx, mainblock_height: u32, overlay: &mut MainstateOverlay) -> Result<u64, MainstateError> {
+        let tx_hash = tx.compute_hash();
+        let mut inputs_value: u64 = 0;
+        for vin in tx.vin.iter() {
+            let outpoint = MainstateOutpoint { hash: vin.get_hash()?, index: vin.get_index()? };
+            if overlay.spent.contains(&outpoint) {
+                return Err(MainstateError::DoubleSpend { hash: outpoint.hash, index: outpoint.index });
+            }
+            let output = match overlay.created.get(&outpoint).or_else(|| self.unspent.get(&outpoint)) {
+                Some(output) => output,
+                None => return Err(MainstateError::MissingOutput { hash: outpoint.hash, index: outpoint.index }),
+            };
+            if Hash::compute_hash(&vin.get_publickey()?) != output.address {
+                return Err(MainstateError::AddressMismatch { hash: outpoint.hash, index: outpoint.index });
+            }
+            if output.is_reward {
+                let mature_height = output.mainblock_height as u64 + self.reward_maturity as u64;
+                if (mainblock_height as u64) < mature_height {
+                    return Err(MainstateError::ImmatureReward { hash: outpoint.hash, index: outpoint.index, mature_height });
+                }
+            }
+            inputs_value = inputs_value
+                .checked_add(output.value)
+                .ok_or_else(|| MainstateError::ValueOverflow(tx_hash.clone()))?;
+            overlay.spent.insert(outpoint);
+        }
+        let mut outputs_value: u64 = 0;
+        for vout in tx.vout.iter() {
+            outputs_value = outputs_value
+                .checked_add(vout.get_value()?)
+                .ok_or_else(|| MainstateError::ValueOverflow(tx_hash.clone()))?;
+        }
+        if outputs_value > inputs_value {
+            return Err(MainstateError::OutputsExceedInputs(tx_hash));
+        }
+        Ok(inputs_value - outputs_value)
+    }
+
+    fn add_maintx_outputs(&self, tx: &Maintx, mainblock_height: u32, is_reward: bool, overlay: &mut MainstateOverlay) -> Result<(), MainstateError> {
+        let tx_hash = tx.compute_hash();
+        for (index, vout) in tx.vout.iter().enumerate() {
+            let outpoint = MainstateOutpoint { hash: tx_hash.clone(), index: index as u32 };
+            if self.unspent.contains_key(&outpoint) || overlay.created.contains_key(&outpoint) {
+                return Err(MainstateError::DuplicateMaintx(tx_hash));
+            }
+            let output = MainstateOutput {
+                value: vout.get_value()?,
+                address: vout.get_address()?,
+                mainblock_height,
+                is_reward,
+            };
+            overlay.created.insert(outpoint, output);
+        }
+        Ok(())
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut outpoints: Vec<&MainstateOutpoint> = self.unspent.keys().collect();
+        outpoints.sort();
+        let mut bw = BufferWriter::new();
+        bw.put_var_u32(MAINSTATE_VERSION);
+        bw.put_var_u64(self.mainblocks_count as u64);
+        bw.put_hash(self.tip_hash.clone());
+        bw.put_var_u64(outpoints.len() as u64);
+        for outpoint in outpoints {
+            serialize_outpoint(&mut bw, outpoint);
+            serialize_output(&mut bw, &self.unspent[outpoint]);
+        }
+        bw.get_bytes()
+    }
+
+    pub fn unserialize(&mut self, rawbytes: Vec<u8>) -> Result<(), MainstateError> {
+        let mut br = BufferReader::new(rawbytes);
+        let version = br.get_var_u32()?;
+        if version != MAINSTATE_VERSION {
+            return Err(MainstateError::UnknownVersion(version));
+        }
+        let mainblocks_count = br.get_var_u64()? as usize;
+        let tip_hash = br.get_hash()?;
+        let unspent_count = br.get_var_u64()?;
+        let mut unspent = HashMap::new();
+        for _ in 0..unspent_count {
+            let outpoint = unserialize_outpoint(&mut br)?;
+            let output = unserialize_output(&mut br)?;
+            unspent.insert(outpoint, output);
+        }
+        self.unspent = unspent;
+        self.mainblocks_count = mainblocks_count;
+        self.tip_hash = tip_hash;
+        Ok(())
+    }
+
+    fn get_checkpoint_path(&self) -> Result<String, MainstateError> {
+        Ok(self.ms_path.join("Checkpoint.dat").to_str().ok_or(MainstateError::InvalidPath)?.to_string())
+    }
+
+    async fn open_chunk_store(&mut self) -> Result<(), MainstateError> {
+        if self.ms_cs.is_none() {
+            if self.ms_path.is_file() {
+                println!("removing the mainstate file of the former layout, the mainstate is rebuilt from the mainblocks");
+                tokio::fs::remove_file(&self.ms_path).await.map_err(AsyncFileError::from)?;
+            }
+            let mut ms_cs = ChunkStore::new(&self.ms_path, String::from("Mainstate")).await?;
+            ms_cs.init().await?;
+            self.ms_cs = Some(ms_cs);
+        }
+        Ok(())
+    }
+
+    async fn get_stored_undo(&mut self, height: usize) -> Result<MainstateUndo, MainstateError> {
+        self.open_chunk_store().await?;
+        let ms_cs = self.ms_cs.as_mut().ok_or(MainstateError::InvalidPath)?;
+        unserialize_undo(ms_cs.get_chunk(height).await?)
+    }
+
+    /// Loads the checkpoint and replays the records above it, starting from an empty mainstate if there is none.
+    /// The mainstate is left empty, to be rebuilt from the mainblocks, if the records do not follow the checkpoint.
+    pub async fn load(&mut self) -> Result<(), MainstateError> {
+        self.reset();
+        self.open_chunk_store().await?;
+        let records_count = self.ms_cs.as_ref().map_or(0, |ms_cs| ms_cs.chunk_count());
+        let checkpoint_path = self.get_checkpoint_path()?;
+        if file_exists(&checkpoint_path).await {
+            let rawbytes = load_bytes_from_file(&checkpoint_path).await?;
+            self.unserialize(rawbytes)?;
+            self.checkpoint_count = Some(self.mainblocks_count);
+        }
+        let checkpoint_count = self.mainblocks_count;
+        let follows_checkpoint = match checkpoint_count {
+            0 => true,
+            _ => checkpoint_count <= records_count && self.get_stored_undo(checkpoint_count - 1).await?.mainblock_hash == self.tip_hash,
+        };
+        if !follows_checkpoint {
+            println!("mainstate records do not follow the checkpoint of {} mainblocks", checkpoint_count);
+            self.reset();
+            return Ok(());
+        }
+        for height in checkpoint_count.min(records_count.saturating_sub(self.undo_depth))..records_count {
+            let undo = self.get_stored_undo(height).await?;
+            if height >= checkpoint_count {
+                self.replay_undo(&undo);
+            }
+            self.push_undo(undo);
+        }
+        self.stored_count = records_count;
+        Ok(())
+    }
+
+    /// Stores the records of the mainblocks connected since the last save, after removing those of the disconnected
+    /// ones, and writes a checkpoint if the last one is `checkpoint_interval` mainblocks old or was disconnected.
+    /// The checkpoint is written atomically, after the records it could be replayed with.
+    pub async fn save(&mut self) -> Result<(), MainstateError> {
+        self.open_chunk_store().await?;
+        let ms_cs = self.ms_cs.as_mut().ok_or(MainstateError::InvalidPath)?;
+        ms_cs.truncate_to(self.stored_count).await?;
+        while let Some(undo) = self.pending_undos.front() {
+            ms_cs.add_chunk(&serialize_undo(undo)).await?;
+            self.pending_undos.pop_front();
+            self.stored_count += 1;
+        }
+        let checkpoint_needed = match self.checkpoint_count {
+            Some(checkpoint_count) => self.mainblocks_count >= checkpoint_count + self.checkpoint_interval,
+            None => true,
+        };
+        if checkpoint_needed {
+            save_bytes_to_file_atomic(&self.serialize(), &self.get_checkpoint_path()?).await?;
+            self.checkpoint_count = Some(self.mainblocks_count);
+        }
+        Ok(())
+    }
+}
+
+fn serialize_undo(undo: &MainstateUndo) -> Vec<u8> {
+    let mut bw = BufferWriter::new();
+    bw.put_var_u32(MAINSTATE_VERSION);
+    bw.put_hash(undo.mainblock_hash.clone());
+    bw.put_var_u64(undo.created.len() as u64);
+    for (outpoint, output) in undo.created.iter() {
+        serialize_outpoint(&mut bw, outpoint);
+        serialize_output(&mut bw, output);
+    }
+    bw.put_var_u64(undo.spent.len() as u64);
+    for (outpoint, output) in undo.spent.iter() {
+        serialize_outpoint(&mut bw, outpoint);
+        serialize_output(&mut bw, output);
+    }
+    bw.get_bytes()
+}
+
+fn unserialize_undo(rawbytes: Vec<u8>) -> Result<MainstateUndo, MainstateError> {
+    let mut br = BufferReader::new(rawbytes);
+    let version = br.get_var_u32()?;
+    if version != MAINSTATE_VERSION {
+        return Err(MainstateError::UnknownVersion(version));
+    }
+    let mainblock_hash = br.get_hash()?;
+    let mut created = Vec::new();
+    for _ in 0..br.get_var_u64()? {
+        created.push((unserialize_outpoint(&mut br)?, unserialize_output(&mut br)?));
+    }
+    let mut spent = Vec::new();
+    for _ in 0..br.get_var_u64()? {
+        spent.push((unserialize_outpoint(&mut br)?, unserialize_output(&mut br)?));
+    }
+    Ok(MainstateUndo { mainblock_hash, created, spent })
+}
+
+fn serialize_outpoint(bw: &mut BufferWriter, outpoint: &MainstateOutpoint) {
+    bw.put_hash(outpoint.hash.clone());
+    bw.put_var_u32(outpoint.index);
+}
+
+fn serialize_output(bw: &mut BufferWriter, output: &MainstateOutput) {
+    bw.put_u64(output.value);
+    bw.put_hash(output.address.clone());
+    bw.put_u32(output.mainblock_height);
+    bw.put_u8(output.is_reward as u8);
+}
+
+fn unserialize_outpoint(br: &mut BufferReader) -> Result<MainstateOutpoint, MainstateError> {
+    let hash = br.get_hash()?;
+    let index = br.get_var_u32()?;
+    Ok(MainstateOutpoint { hash, index })
+}
+
+fn unserialize_output(br: &mut BufferReader) -> Result<MainstateOutput, MainstateError> {
+    let value = br.get_u64()?;
+    let address = br.get_hash()?;
+    let mainblock_height = br.get_u32()?;
+    let is_reward = br.get_u8()? != 0;
+    Ok(MainstateOutput { value, address, mainblock_height, is_reward })
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use thiserror::Error;
+use tokio::fs;
+use tokio::io;
+use utility::hash::hash::Hash;
+use utility::buffer::buffer_writer::BufferWriter;
+use utility::buffer::buffer_reader::BufferReader;
+use utility::buffer::buffer_reader::BufferReaderError;
+use utility::storage::chunk_store::ChunkStore;
+use utility::storage::chunk_store::ChunkStoreError;
+use crate::mainblock::mainblock::Mainblock;
+
+const MAINTX_INDEX_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum MaintxIndexError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("ChunkStore error: {0}")]
+    ChunkStoreError(#[from] ChunkStoreError),
+    #[error("Buffer reader error: {0}")]
+    BufferReaderError(#[from] BufferReaderError),
+
+    #[error("Unknown maintx index version: {0}")]
+    UnknownVersion(u32),
+    #[error("Mainblock {actual:?} is not the last indexed mainblock {expected:?}")]
+    TipMismatch { expected: Hash, actual: Hash },
+}
+
+/// Where a confirmed transaction is: the height of its mainblock and its position in the transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaintxLocation {
+    pub mainblock_height: u32,
+    pub position: u32,
+}
+
+/// Index from the hash of every confirmed transaction to its location. Each indexed mainblock is a record
+/// of the `ChunkStore` at its height, holding the mainblock hash and the hashes of its transactions, so that
+/// connecting or disconnecting a mainblock appends or removes a single record.
+pub struct MaintxIndex {
+    mi_path: PathBuf,
+    mi_cs: ChunkStore,
+    locations: HashMap<Hash, MaintxLocation>,
+    /// Hash of every indexed mainblock, to detect an index left behind by a reorganization.
+    mainblock_hashes: Vec<Hash>,
+}
+
+impl MaintxIndex {
+    /// Creates the index stored in the `mi_path` directory, `load` must be called before use.
+    pub async fn new<P: AsRef<Path>>(mi_path: P) -> Result<Self, MaintxIndexError> {
+        let mi_path = mi_path.as_ref().to_path_buf();
+        let mi_cs = ChunkStore::new(&mi_path, String::from("MaintxIndex")).await?;
+        Ok(Self {
+            mi_path,
+            mi_cs,
+            locations: HashMap::new(),
+            mainblock_hashes: Vec::new(),
+        })
+    }
+
+    /// Number of mainblocks whose transactions are indexed.
+    pub fn get_mainblocks_count(&self) -> usize {
+        self.mainblock_hashes.len()
+    }
+
+    /// Hash of the last indexed mainblock, empty when no mainblock is indexed.
+    pub fn get_tip_hash(&self) -> Hash {
+        self.mainblock_hashes.last().cloned().unwrap_or_else(Hash::new_empty)
+    }
+
+    pub fn get_maintxs_count(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn get_location(&self, hash: &Hash) -> Option<MaintxLocation> {
+        self.locations.get(hash).copied()
+    }
+
+    fn insert_record(&mut self, mainblock_hash: Hash, tx_hashes: Vec<Hash>) {
+        let mainblock_height = self.mainblock_hashes.len() as u32;
+        for (position, tx_hash) in tx_hashes.into_iter().enumerate() {
+            self.locations.insert(tx_hash, MaintxLocation { mainblock_height, position: position as u32 });
+        }
+        self.mainblock_hashes.push(mainblock_hash);
+    }
+
+    /// Indexes the transactions of the mainblock at height `get_mainblocks_count()` and stores its record.
+    pub async fn connect_mainblock(&mut self, mb: &Mainblock) -> Result<(), MaintxIndexError> {
+        let tx_hashes: Vec<Hash> = mb.transactions.iter().map(|tx| tx.compute_hash()).collect();
+        self.mi_cs.add_chunk(&serialize_record(&mb.get_hash(), &tx_hashes)).await?;
+        self.insert_record(mb.get_hash(), tx_hashes);
+        Ok(())
+    }
+
+    /// Removes the transactions of the last indexed mainblock, which must be `mb`.
+    pub async fn disconnect_mainblock(&mut self, mb: &Mainblock) -> Result<(), MaintxIndexError> {
+        if self.mainblock_hashes.last() != Some(&mb.get_hash()) {
+            return Err(MaintxIndexError::TipMismatch { expected: self.get_tip_hash(), actual: mb.get_hash() });
+        }
+        self.remove_last_mainblock().await
+    }
+
+    /// Removes the transactions of the last indexed mainblock, read back from its record.
+    pub async fn remove_last_mainblock(&mut self) -> Result<(), MaintxIndexError> {
+        let height = match self.mainblock_hashes.len().checked_sub(1) {
+            Some(height) => height,
+            None => return Ok(()),
+        };
+        let (_, tx_hashes) = unserialize_record(self.mi_cs.get_chunk(height).await?)?;
+        self.mi_cs.remove_chunk(height).await?;
+        for tx_hash in tx_hashes.iter() {
+            self.locations.remove(tx_hash);
+        }
+        self.mainblock_hashes.pop();
+        Ok(())
+    }
+
+    /// Removes every record.
+    pub async fn reset(&mut self) -> Result<(), MaintxIndexError> {
+        self.locations.clear();
+        self.mainblock_hashes.clear();
+        if self.mi_path.exists() {
+            fs::remove_dir_all(&self.mi_path).await?;
+        }
+        self.mi_cs = ChunkStore::new(&self.mi_path, String::from("MaintxIndex")).await?;
+        self.mi_cs.init().await?;
+        Ok(())
+    }
+
+    /// Loads the records, a record left incomplete by an interrupted write is dropped.
+    pub async fn load(&mut self) -> Result<(), MaintxIndexError> {
+        self.locations.clear();
+        self.mainblock_hashes.clear();
+        self.mi_cs.init().await?;
+        for height in 0..self.mi_cs.chunk_count() {
+            let (mainblock_hash, tx_hashes) = unserialize_record(self.mi_cs.get_chunk(height).await?)?;
+            self.insert_record(mainblock_hash, tx_hashes);
+        }
+        Ok(())
+    }
+}
+
+fn serialize_record(mainblock_hash: &Hash, tx_hashes: &[Hash]) -> Vec<u8> {
+    let mut bw = BufferWriter::new();
+    bw.put_var_u32(MAINTX_INDEX_VERSION);
+    bw.put_hash(mainblock_hash.clone());
+    bw.put_var_u64(tx_hashes.len() as u64);
+    for tx_hash in tx_hashes.iter() {
+        bw.put_hash(tx_hash.clone());
+    }
+    bw.get_bytes()
+}
+
+fn unserialize_record(rawbytes: Vec<u8>) -> Result<(Hash, Vec<Hash>), MaintxIndexError> {
+    let mut br = BufferReader::new(rawbytes);
+    let version = br.get_var_u32()?;
+    if version != MAINTX_INDEX_VERSION {
+        return Err(MaintxIndexError::UnknownVersion(version));
+    }
+    let mainblock_hash = br.get_hash()?;
+    let tx_hashes_count = br.get_var_u64()?;
+    let mut tx_hashes = Vec::new();
+    for _ in 0..tx_hashes_count {
+        tx_hashes.push(br.get_hash()?);
+    }
+    Ok((mainblock_hash, tx_hashes))
+}
@@ -0,0 +1 @@
+pub mod maintx_index;
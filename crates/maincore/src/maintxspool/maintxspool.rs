@@ -0,0 +1,276 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use thiserror::Error;
+use utility::hash::hash::Hash;
+use maintx::maintx::maintx::Maintx;
+use maintx::maintx_in::maintx_in::MaintxInError;
+use maintx::maintx_out::maintx_out::MaintxOutError;
+use crate::mainblock::mainblock::Mainblock;
+use crate::mainstate::mainstate::Mainstate;
+use crate::mainstate::mainstate::MainstateError;
+use crate::mainstate::mainstate::MainstateOutpoint;
+use crate::mainstate::mainstate::MainstateOutput;
+
+#[derive(Debug, Error)]
+pub enum MaintxspoolError {
+    #[error("Mainstate error: {0}")]
+    MainstateError(#[from] MainstateError),
+    #[error("MaintxIn error: {0}")]
+    MaintxInError(#[from] MaintxInError),
+    #[error("MaintxOut error: {0}")]
+    MaintxOutError(#[from] MaintxOutError),
+
+    #[error("Maintx {0:?} is already in the txspool")]
+    AlreadyInTxspool(Hash),
+    #[error("Maintx {0:?} has no inputs")]
+    NoInputs(Hash),
+    #[error("Maintx {0:?} has no outputs")]
+    NoOutputs(Hash),
+    #[error("Maintx {0:?} has a mainblock reward input")]
+    RewardInput(Hash),
+    #[error("Maintx {0:?} has an invalid signature")]
+    InvalidSignature(Hash),
+    #[error("Output {index} of {hash:?} is already spent by {spent_by:?} in the txspool")]
+    ConflictingSpend { hash: Hash, index: u32, spent_by: Hash },
+}
+
+/// A transaction waiting in the txspool with the fee it pays.
+#[derive(Debug, Clone)]
+pub struct MaintxspoolEntry {
+    pub maintx: Maintx,
+    pub hash: Hash,
+    pub fee: u64,
+    pub size: usize,
+    /// Height of the mainblock template the transaction has been included in, if any.
+    pub frozen_height: Option<usize>,
+}
+
+impl MaintxspoolEntry {
+    /// Compares the fee per serialized byte of two entries without rounding.
+    pub fn cmp_fee_rate(&self, other: &MaintxspoolEntry) -> Ordering {
+        let left = self.fee as u128 * other.size as u128;
+        let right = other.fee as u128 * self.size as u128;
+        left.cmp(&right)
+    }
+}
+
+/// Pool of signature-verified transactions that spend confirmed outputs, or outputs of other transactions
+/// of the pool, and do not conflict with each other.
+#[derive(Default)]
+pub struct Maintxspool {
+    entries: HashMap<Hash, MaintxspoolEntry>,
+    spent_outpoints: HashMap<MainstateOutpoint, Hash>,
+    pending_outputs: HashMap<MainstateOutpoint, MainstateOutput>,
+}
+
+impl Maintxspool {
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            spent_outpoints: HashMap::new(),
+            pending_outputs: HashMap::new(),
+        }
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    pub fn get_maintx(&self, hash: &Hash) -> Option<&Maintx> {
+        self.entries.get(hash).map(|entry| &entry.maintx)
+    }
+
+    pub fn get_entry(&self, hash: &Hash) -> Option<&MaintxspoolEntry> {
+        self.entries.get(hash)
+    }
+
+    /// Checks a transaction against the mainstate and the outputs of the txspool and returns its entry.
+    pub fn check_maintx(&self, tx: &Maintx, mainstate: &Mainstate) -> Result<MaintxspoolEntry, MaintxspoolError> {
+        let hash = tx.compute_hash();
+        if self.entries.contains_key(&hash) {
+            return Err(MaintxspoolError::AlreadyInTxspool(hash));
+        }
+        if tx.vin.is_empty() {
+            return Err(MaintxspoolError::NoInputs(hash));
+        }
+        if tx.vout.is_empty() {
+            return Err(MaintxspoolError::NoOutputs(hash));
+        }
+        if tx.vin.iter().any(|vin| !vin.is_ecdsa()) {
+            return Err(MaintxspoolError::RewardInput(hash));
+        }
+        if !tx.verify_signatures() {
+            return Err(MaintxspoolError::InvalidSignature(hash));
+        }
+        for vin in tx.vin.iter() {
+            let outpoint = MainstateOutpoint { hash: vin.get_hash()?, index: vin.get_index()? };
+            if let Some(spent_by) = self.spent_outpoints.get(&outpoint) {
+                return Err(MaintxspoolError::ConflictingSpend {
+                    hash: outpoint.hash,
+                    index: outpoint.index,
+                    spent_by: spent_by.clone(),
+                });
+            }
+        }
+        let fee = mainstate.check_maintx_with_pending_outputs(tx, &self.pending_outputs)?;
+        Ok(MaintxspoolEntry {
+            maintx: tx.clone(),
+            hash,
+            fee,
+            size: tx.get_serialization_size(),
+            frozen_height: None,
+        })
+    }
+
+    /// Adds a transaction to the txspool after checking it.
+    pub fn add_maintx(&mut self, tx: Maintx, mainstate: &Mainstate) -> Result<Hash, MaintxspoolError> {
+        let entry = self.check_maintx(&tx, mainstate)?;
+        let hash = entry.hash.clone();
+        for vin in tx.vin.iter() {
+            let outpoint = MainstateOutpoint { hash: vin.get_hash()?, index: vin.get_index()? };
+            self.spent_outpoints.insert(outpoint, hash.clone());
+        }
+        for (index, vout) in tx.vout.iter().enumerate() {
+            let outpoint = MainstateOutpoint { hash: hash.clone(), index: index as u32 };
+            let output = MainstateOutput {
+                value: vout.get_value()?,
+                address: vout.get_address()?,
+                mainblock_height: mainstate.get_mainblocks_count() as u32,
+                is_reward: false,
+            };
+            self.pending_outputs.insert(outpoint, output);
+        }
+        self.entries.insert(hash.clone(), entry);
+        Ok(hash)
+    }
+
+    /// Removes a transaction and the transactions of the txspool spending its outputs.
+    pub fn remove(&mut self, hash: &Hash) -> Option<Maintx> {
+        let entry = self.remove_entry(hash)?;
+        for index in 0..entry.maintx.vout.len() {
+            let spent_by = self.spent_outpoints.get(&MainstateOutpoint { hash: hash.clone(), index: index as u32 }).cloned();
+            if let Some(spent_by) = spent_by {
+                self.remove(&spent_by);
+            }
+        }
+        Some(entry.maintx)
+    }
+
+    /// Removes a transaction alone, the transactions spending its outputs stay.
+    fn remove_entry(&mut self, hash: &Hash) -> Option<MaintxspoolEntry> {
+        let entry = self.entries.remove(hash)?;
+        for vin in entry.maintx.vin.iter() {
+            if let (Ok(hash), Ok(index)) = (vin.get_hash(), vin.get_index()) {
+                self.spent_outpoints.remove(&MainstateOutpoint { hash, index });
+            }
+        }
+        for index in 0..entry.maintx.vout.len() {
+            self.pending_outputs.remove(&MainstateOutpoint { hash: hash.clone(), index: index as u32 });
+        }
+        Some(entry)
+    }
+
+    /// Removes the transactions confirmed in a mainblock, whose outputs are now in the mainstate,
+    /// and the ones spending the same outputs as the mainblock.
+    pub fn remove_confirmed_mainblock(&mut self, mb: &Mainblock) {
+        for tx in mb.transactions.iter() {
+            self.remove_entry(&tx.compute_hash());
+            for vin in tx.vin.iter() {
+                if let (Ok(hash), Ok(index)) = (vin.get_hash(), vin.get_index()) {
+                    let conflicting = self.spent_outpoints.get(&MainstateOutpoint { hash, index }).cloned();
+                    if let Some(conflicting) = conflicting {
+                        self.remove(&conflicting);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Puts back the transactions of a disconnected mainblock, the mainstate must already
+    /// be rolled back. The transactions are readmitted in mainblock order, so that a transaction can spend
+    /// the outputs of a transaction readmitted before it. Transactions that are no longer valid are dropped.
+    pub fn readmit_mainblock(&mut self, mb: &Mainblock, mainstate: &Mainstate) -> usize {
+        let mut readmitted = 0;
+        for tx in mb.transactions.iter().skip(1) {
+            match self.add_maintx(tx.clone(), mainstate) {
+                Ok(_) => readmitted += 1,
+                Err(e) => println!("txspool readmit_mainblock dropped maintx: {}", e),
+            }
+        }
+        readmitted
+    }
+
+    /// Drops the transactions whose inputs are no longer unspent in the mainstate or in the txspool,
+    /// with the transactions spending their outputs.
+    pub fn revalidate(&mut self, mainstate: &Mainstate) {
+        let invalid: Vec<Hash> = self
+            .entries
+            .values()
+            .filter(|entry| mainstate.check_maintx_with_pending_outputs(&entry.maintx, &self.pending_outputs).is_err())
+            .map(|entry| entry.hash.clone())
+            .collect();
+        for hash in invalid.iter() {
+            self.remove(hash);
+        }
+    }
+
+    /// Returns the entries from the highest to the lowest fee per byte.
+    pub fn get_sorted_entries(&self) -> Vec<&MaintxspoolEntry> {
+        let mut sorted: Vec<&MaintxspoolEntry> = self.entries.values().collect();
+        sorted.sort_by(|a, b| b.cmp_fee_rate(a).then_with(|| a.hash.cmp(&b.hash)));
+        sorted
+    }
+
+    /// Selects the best paying transactions that are not frozen and fit in `max_size` bytes.
+    /// A transaction spending outputs of the txspool only comes after the transactions creating them.
+    pub fn select_maintxs(&self, max_size: usize) -> Vec<Maintx> {
+        let sorted = self.get_sorted_entries();
+        let mut selected = Vec::new();
+        let mut selected_hashes = HashSet::new();
+        let mut size = 0;
+        loop {
+            let selected_count = selected.len();
+            for entry in sorted.iter() {
+                if selected_hashes.contains(&entry.hash) || entry.frozen_height.is_some() || size + entry.size > max_size {
+                    continue;
+                }
+                let parents_selected = entry.maintx.vin.iter().all(|vin| match vin.get_hash() {
+                    Ok(parent_hash) => !self.entries.contains_key(&parent_hash) || selected_hashes.contains(&parent_hash),
+                    Err(_) => false,
+                });
+                if !parents_selected {
+                    continue;
+                }
+                size += entry.size;
+                selected_hashes.insert(entry.hash.clone());
+                selected.push(entry.maintx.clone());
+            }
+            if selected.len() == selected_count {
+                return selected;
+            }
+        }
+    }
+
+    /// Marks transactions as included in the mainblock being built at `height`.
+    pub fn freeze_with_mainblock_height(&mut self, hashes: &[Hash], height: usize) {
+        for hash in hashes.iter() {
+            if let Some(entry) = self.entries.get_mut(hash) {
+                entry.frozen_height = Some(height);
+            }
+        }
+    }
+
+    /// Releases the transactions frozen for a mainblock height that has been filled by another mainblock.
+    pub fn reset_tx_with_mainblock_height(&mut self, height: usize) {
+        for entry in self.entries.values_mut() {
+            if entry.frozen_height.is_some_and(|frozen_height| frozen_height <= height) {
+                entry.frozen_height = None;
+            }
+        }
+    }
+}
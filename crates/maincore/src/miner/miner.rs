@@ -0,0 +1,197 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use thiserror::Error;
+use tokio::sync::Mutex;
+use utility::hash::hash::Hash;
+use maintx::maintx::maintx::Maintx;
+use crate::mainheader::mainheader::Mainheader;
+use crate::mainheader::mainheader::MainheaderError;
+use crate::mainblock::mainblock::Mainblock;
+use crate::mainblock::mainblock_validation::compute_mainblock_root_hash;
+use crate::maincore_inner::maincore_inner::MaincoreInner;
+use crate::miner::mining_engine::MiningEngine;
+use crate::miner::mining_engine::MiningStats;
+
+pub const MAINHEADER_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum MinerError {
+    #[error("Mainheader error: {0}")]
+    MainheaderError(#[from] MainheaderError),
+    #[error("Mining thread failed: {0}")]
+    MiningThreadFailed(String),
+    #[error("No reward address set")]
+    NoRewardAddress,
+    #[error("Maincore error: {0}")]
+    MaincoreInnerError(String),
+}
+
+/// Everything needed to mine a mainblock except the nonce.
+#[derive(Debug, Clone)]
+pub struct MainblockTemplate {
+    pub version: u32,
+    pub height: usize,
+    pub prev_hash: Hash,
+    pub root_hash: Hash,
+    pub timestamp: i64,
+    pub bits: u32,
+    pub transactions: Vec<Maintx>,
+}
+
+impl MainblockTemplate {
+    /// Creates a template whose first transaction must be the reward transaction.
+    pub fn new(height: usize, prev_hash: Hash, timestamp: i64, bits: u32, transactions: Vec<Maintx>) -> Self {
+        let root_hash = compute_mainblock_root_hash(&transactions);
+        Self {
+            version: MAINHEADER_VERSION,
+            height,
+            prev_hash,
+            root_hash,
+            timestamp,
+            bits,
+            transactions,
+        }
+    }
+
+    /// The mainheader of the template, with an empty nonce and hash.
+    pub fn get_candidate_mainheader(&self) -> Mainheader {
+        Mainheader::new(self.version, self.prev_hash.clone(), self.root_hash.clone(), self.timestamp, self.bits, 0, Hash::new_empty())
+    }
+}
+
+/// Mining settings, the cancellation flag of the template being mined and the hash counters.
+pub struct Miner {
+    threads_count: usize,
+    reward_address: Option<Hash>,
+    cancel_flag: Arc<AtomicBool>,
+    stopped: bool,
+    stats: Arc<MiningStats>,
+}
+
+impl Default for Miner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Miner {
+    pub fn new() -> Self {
+        Self {
+            threads_count: 1,
+            reward_address: None,
+            cancel_flag: Arc::new(AtomicBool::new(false)),
+            stopped: false,
+            stats: Arc::new(MiningStats::new()),
+        }
+    }
+
+    pub fn get_threads_count(&self) -> usize {
+        self.threads_count
+    }
+
+    pub fn set_threads_count(&mut self, threads_count: usize) {
+        self.threads_count = threads_count.max(1);
+    }
+
+    pub fn get_reward_address(&self) -> Option<Hash> {
+        self.reward_address.clone()
+    }
+
+    pub fn set_reward_address(&mut self, reward_address: Hash) {
+        self.reward_address = Some(reward_address);
+    }
+
+    /// Returns a fresh flag for a new template, the previous template is cancelled.
+    pub fn new_cancel_flag(&mut self) -> Arc<AtomicBool> {
+        self.cancel();
+        self.cancel_flag = Arc::new(AtomicBool::new(false));
+        self.cancel_flag.clone()
+    }
+
+    /// Cancels the template being mined, called whenever the tip changes.
+    pub fn cancel(&self) {
+        self.cancel_flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Starts mining, the hashrate is measured from this call.
+    pub fn start(&mut self) {
+        self.stopped = false;
+        self.stats = Arc::new(MiningStats::new());
+    }
+
+    pub fn get_stats(&self) -> Arc<MiningStats> {
+        self.stats.clone()
+    }
+
+    /// Hashes per second since the miner has been started.
+    pub fn get_hashrate(&self) -> f64 {
+        self.stats.get_hashrate()
+    }
+
+    pub fn stop(&mut self) {
+        self.stopped = true;
+        self.cancel();
+    }
+}
+
+/// Mines a template with a `MiningEngine` of `threads_count` threads.
+/// Returns `Ok(None)` if the template has been cancelled.
+pub async fn mine_mainblock_template(template: MainblockTemplate, threads_count: usize, cancel_flag: Arc<AtomicBool>, stats: Arc<MiningStats>) -> Result<Option<Mainblock>, MinerError> {
+    let candidate = template.get_candidate_mainheader();
+    let engine = MiningEngine::with_stop_flag(threads_count, cancel_flag, stats);
+    let result = tokio::task::spawn_blocking(move || engine.mine(&candidate))
+        .await
+        .map_err(|e| MinerError::MiningThreadFailed(e.to_string()))?;
+    match result? {
+        Some(mh) => Ok(Some(Mainblock::new(mh, template.transactions))),
+        None => Ok(None),
+    }
+}
+
+/// Mining service: builds a template from the tip, mines it without holding the lock
+/// and submits the solved mainblock, until the miner is stopped.
+pub async fn run_miner(maincore: Arc<Mutex<MaincoreInner>>) -> Result<(), MinerError> {
+    loop {
+        let (template, threads_count, cancel_flag, stats) = {
+            let mut mci = maincore.lock().await;
+            if mci.get_miner().is_stopped() {
+                return Ok(());
+            }
+            let template = match mci.build_mainblock_template() {
+                Ok(template) => template,
+                Err(e) => {
+                    println!("run_miner build_mainblock_template error: {}", e);
+                    return Err(MinerError::MaincoreInnerError(e.to_string()));
+                }
+            };
+            let threads_count = mci.get_miner().get_threads_count();
+            let cancel_flag = mci.get_miner_mut().new_cancel_flag();
+            let stats = mci.get_miner().get_stats();
+            (template, threads_count, cancel_flag, stats)
+        };
+        let height = template.height;
+        println!("run_miner mining mainblock at height {}", height);
+        let result = mine_mainblock_template(template, threads_count, cancel_flag, stats.clone()).await;
+        let mut mci = maincore.lock().await;
+        match result {
+            Ok(Some(mb)) => {
+                println!("run_miner hashrate {:.0} H/s", stats.get_hashrate());
+                if let Err(e) = mci.add_confirmed_mainblock(mb).await {
+                    println!("run_miner add_confirmed_mainblock error: {}", e);
+                    mci.release_mainblock_template(height);
+                }
+            }
+            // cancelled because the tip changed or the miner was stopped
+            Ok(None) => mci.release_mainblock_template(height),
+            Err(e) => {
+                mci.release_mainblock_template(height);
+                return Err(e);
+            }
+        }
+    }
+}
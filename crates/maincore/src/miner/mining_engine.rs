@@ -0,0 +1,222 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+use utility::hash::hash::Hash;
+use utility::hash::hash::HASH_SIZE;
+use utility::hash::bigint;
+use crate::mainheader::mainheader::Mainheader;
+use crate::mainheader::mainheader::MAINHEADER_NONCE_SIZE;
+use crate::mainheader::mainheader::MainheaderError;
+
+/// Number of hashes between two checks of the stop flag.
+const MINING_BATCH_SIZE: u64 = 4096;
+
+/// Hash counter shared by the mining threads, used to report the hashrate.
+pub struct MiningStats {
+    hashes_count: AtomicU64,
+    started_at: Instant,
+}
+
+impl Default for MiningStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MiningStats {
+    pub fn new() -> Self {
+        Self {
+            hashes_count: AtomicU64::new(0),
+            started_at: Instant::now(),
+        }
+    }
+
+    pub fn get_hashes_count(&self) -> u64 {
+        self.hashes_count.load(Ordering::Relaxed)
+    }
+
+    /// Hashes per second since the stats were created.
+    pub fn get_hashrate(&self) -> f64 {
+        let elapsed = self.started_at.elapsed().as_secs_f64();
+        if elapsed <= 0.0 {
+            return 0.0;
+        }
+        self.get_hashes_count() as f64 / elapsed
+    }
+
+    fn add_hashes(&self, count: u64) {
+        self.hashes_count.fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+/// Multi-threaded proof of work search. Each thread owns a contiguous slice of the 32-bit nonce space;
+/// when a slice is exhausted the timestamp is rolled forward by one second and the slice is mined again.
+pub struct MiningEngine {
+    threads_count: usize,
+    stop_flag: Arc<AtomicBool>,
+    stats: Arc<MiningStats>,
+}
+
+impl MiningEngine {
+    pub fn new(threads_count: usize) -> Self {
+        Self::with_stop_flag(threads_count, Arc::new(AtomicBool::new(false)), Arc::new(MiningStats::new()))
+    }
+
+    pub fn with_stop_flag(threads_count: usize, stop_flag: Arc<AtomicBool>, stats: Arc<MiningStats>) -> Self {
+        Self {
+            threads_count: threads_count.max(1),
+            stop_flag,
+            stats,
+        }
+    }
+
+    pub fn get_stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop_flag.clone()
+    }
+
+    pub fn stop(&self) {
+        self.stop_flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn get_stats(&self) -> Arc<MiningStats> {
+        self.stats.clone()
+    }
+
+    /// Searches a nonce, and if needed a later timestamp, for which the hash of `candidate` is below its target.
+    /// Blocks the calling thread until a solution is found or the engine is stopped (`Ok(None)`).
+    pub fn mine(&self, candidate: &Mainheader) -> Result<Option<Mainheader>, MainheaderError> {
+        self.mine_nonce_range(candidate, 0, u32::MAX)
+    }
+
+    /// Same as `mine`, searching only the nonces from `first_nonce` to `last_nonce` included for each timestamp.
+    pub fn mine_nonce_range(&self, candidate: &Mainheader, first_nonce: u32, last_nonce: u32) -> Result<Option<Mainheader>, MainheaderError> {
+        let target = target_from_compact(candidate.get_bits());
+        let found_flag = AtomicBool::new(false);
+        let solution: Mutex<Option<Mainheader>> = Mutex::new(None);
+
+        let results: Vec<Result<(), MainheaderError>> = std::thread::scope(|scope| {
+            let mut handles = Vec::new();
+            for (slice_first, slice_last) in split_nonce_range(self.threads_count, first_nonce, last_nonce) {
+                let target = &target;
+                let found_flag = &found_flag;
+                let solution = &solution;
+                handles.push(scope.spawn(move || {
+                    self.mine_slice(candidate, target, slice_first, slice_last, found_flag, solution)
+                }));
+            }
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or(Err(MainheaderError::MiningUnsuccessful)))
+                .collect()
+        });
+
+        if let Some(mh) = solution.into_inner().unwrap_or(None) {
+            return Ok(Some(mh));
+        }
+        for result in results {
+            result?;
+        }
+        Ok(None)
+    }
+
+    fn mine_slice(
+        &self,
+        candidate: &Mainheader,
+        target: &[u8; HASH_SIZE],
+        first_nonce: u32,
+        last_nonce: u32,
+        found_flag: &AtomicBool,
+        solution: &Mutex<Option<Mainheader>>,
+    ) -> Result<(), MainheaderError> {
+        let mut timestamp = candidate.get_timestamp();
+        // counted across timestamps, so that a small slice still checks the stop flag
+        let mut batch_count: u64 = 0;
+        loop {
+            let mut content = Mainheader::new(candidate.get_version(), candidate.get_prev_hash(), candidate.get_root_hash(), timestamp, candidate.get_bits(), 0, Hash::new_empty()).get_hashing_bytes();
+            let nonce_offset = content.len() - MAINHEADER_NONCE_SIZE;
+
+            let mut nonce = first_nonce;
+            loop {
+                if batch_count == MINING_BATCH_SIZE {
+                    self.stats.add_hashes(batch_count);
+                    batch_count = 0;
+                    if self.stop_flag.load(Ordering::Relaxed) || found_flag.load(Ordering::Relaxed) {
+                        return Ok(());
+                    }
+                }
+                content[nonce_offset..].copy_from_slice(&nonce.to_le_bytes());
+                let tmphash = Hash::compute_hash(&content);
+                batch_count += 1;
+                if hash_meets_target(&tmphash, target) {
+                    self.stats.add_hashes(batch_count);
+                    let mh = Mainheader::new(
+                        candidate.get_version(),
+                        candidate.get_prev_hash(),
+                        candidate.get_root_hash(),
+                        timestamp,
+                        candidate.get_bits(),
+                        nonce,
+                        tmphash,
+                    );
+                    if let Ok(mut solution) = solution.lock() {
+                        if solution.is_none() {
+                            *solution = Some(mh);
+                        }
+                    }
+                    found_flag.store(true, Ordering::Relaxed);
+                    return Ok(());
+                }
+                if nonce == last_nonce {
+                    break;
+                }
+                nonce += 1;
+            }
+            // the nonce slice is exhausted for this timestamp
+            timestamp = timestamp.checked_add(1).ok_or(MainheaderError::MiningUnsuccessful)?;
+        }
+    }
+}
+
+/// Splits the nonces from `first_nonce` to `last_nonce` included into contiguous slices, one per thread,
+/// the last slice taking the remainder. There are fewer slices than threads when the range is smaller.
+pub fn split_nonce_range(threads_count: usize, first_nonce: u32, last_nonce: u32) -> Vec<(u32, u32)> {
+    if last_nonce < first_nonce {
+        return Vec::new();
+    }
+    let range_size = last_nonce as u64 - first_nonce as u64 + 1;
+    let slices_count = (threads_count.max(1) as u64).min(range_size);
+    let slice_size = range_size / slices_count;
+    (0..slices_count)
+        .map(|slice_index| {
+            let slice_first = first_nonce as u64 + slice_index * slice_size;
+            let slice_last = if slice_index + 1 == slices_count { last_nonce as u64 } else { slice_first + slice_size - 1 };
+            (slice_first as u32, slice_last as u32)
+        })
+        .collect()
+}
+
+/// Target of `bits` as little-endian bytes, saturated to the largest 256-bit value.
+pub fn target_from_compact(bits: u32) -> [u8; HASH_SIZE] {
+    let target_bytes = bigint::bigint_from_compact(bits).to_bytes_le();
+    if target_bytes.len() > HASH_SIZE {
+        return [0xff; HASH_SIZE];
+    }
+    let mut target = [0u8; HASH_SIZE];
+    target[..target_bytes.len()].copy_from_slice(&target_bytes);
+    target
+}
+
+/// Same comparison as `Mainheader::check_target`: the hash, read as a little-endian integer,
+/// must be strictly below the target.
+pub fn hash_meets_target(hash: &Hash, target: &[u8; HASH_SIZE]) -> bool {
+    let hash_bytes = hash.as_bytes();
+    for i in (0..HASH_SIZE).rev() {
+        if hash_bytes[i] != target[i] {
+            return hash_bytes[i] < target[i];
+        }
+    }
+    false
+}
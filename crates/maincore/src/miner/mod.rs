@@ -0,0 +1,2 @@
+pub mod miner;
+pub mod mining_engine;
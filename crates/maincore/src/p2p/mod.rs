@@ -0,0 +1,4 @@
+pub mod p2p_message;
+pub mod p2p_addrman;
+pub mod p2p_node;
+pub mod p2p_light_connection;
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use utility::buffer::buffer_writer::BufferWriter;
+use utility::buffer::buffer_reader::BufferReader;
+use utility::storage::async_file::{save_bytes_to_file_atomic, load_bytes_from_file, file_exists};
+use crate::p2p::p2p_message::P2pAddress;
+use crate::p2p::p2p_message::P2pError;
+
+const P2P_ADDRMAN_VERSION: u32 = 1;
+pub const P2P_ADDRMAN_MAX_ADDRESSES: usize = 10_000;
+/// Connection attempts after which an address that never answered is forgotten.
+pub const P2P_ADDRMAN_MAX_FAILURES: u32 = 5;
+
+/// What is known about a peer address.
+#[derive(Debug, Clone)]
+pub struct P2pAddrmanEntry {
+    pub last_seen: i64,
+    pub failures: u32,
+    pub last_attempt: i64,
+}
+
+/// Known peer addresses, learned from `addr` messages and from successful connections.
+pub struct P2pAddrman {
+    addresses: HashMap<SocketAddr, P2pAddrmanEntry>,
+    am_path: Option<PathBuf>,
+}
+
+impl Default for P2pAddrman {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl P2pAddrman {
+    /// Creates an address manager kept in memory only.
+    pub fn new() -> Self {
+        Self { addresses: HashMap::new(), am_path: None }
+    }
+
+    /// Creates an address manager saved to `am_path`.
+    pub fn new_with_path<P: AsRef<Path>>(am_path: P) -> Self {
+        Self { addresses: HashMap::new(), am_path: Some(am_path.as_ref().to_path_buf()) }
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn contains(&self, socket_addr: &SocketAddr) -> bool {
+        self.addresses.contains_key(socket_addr)
+    }
+
+    /// Adds an address or refreshes its last seen time. Unroutable addresses are ignored.
+    pub fn add_address(&mut self, address: &P2pAddress) -> bool {
+        if address.socket_addr.port() == 0 || address.socket_addr.ip().is_unspecified() {
+            return false;
+        }
+        if let Some(entry) = self.addresses.get_mut(&address.socket_addr) {
+            entry.last_seen = entry.last_seen.max(address.last_seen);
+            return false;
+        }
+        if self.addresses.len() >= P2P_ADDRMAN_MAX_ADDRESSES {
+            self.evict_oldest();
+        }
+        self.addresses.insert(address.socket_addr, P2pAddrmanEntry { last_seen: address.last_seen, failures: 0, last_attempt: 0 });
+        true
+    }
+
+    pub fn add_addresses(&mut self, addresses: &[P2pAddress]) -> usize {
+        addresses.iter().filter(|address| self.add_address(address)).count()
+    }
+
+    /// Records a successful connection.
+    pub fn mark_good(&mut self, socket_addr: &SocketAddr, now: i64) {
+        let entry = self.addresses.entry(*socket_addr).or_insert(P2pAddrmanEntry { last_seen: now, failures: 0, last_attempt: now });
+        entry.last_seen = now;
+        entry.failures = 0;
+        entry.last_attempt = now;
+    }
+
+    /// Records a failed connection attempt, the address is forgotten after too many failures.
+    pub fn mark_failed(&mut self, socket_addr: &SocketAddr, now: i64) {
+        if let Some(entry) = self.addresses.get_mut(socket_addr) {
+            entry.failures += 1;
+            entry.last_attempt = now;
+            if entry.failures >= P2P_ADDRMAN_MAX_FAILURES {
+                self.addresses.remove(socket_addr);
+            }
+        }
+    }
+
+    pub fn remove_address(&mut self, socket_addr: &SocketAddr) {
+        self.addresses.remove(socket_addr);
+    }
+
+    /// Returns up to `max_count` addresses, the most recently seen first.
+    pub fn get_addresses(&self, max_count: usize) -> Vec<P2pAddress> {
+        let mut addresses: Vec<P2pAddress> = self
+            .addresses
+            .iter()
+            .map(|(socket_addr, entry)| P2pAddress { socket_addr: *socket_addr, last_seen: entry.last_seen })
+            .collect();
+        addresses.sort_by(|a, b| b.last_seen.cmp(&a.last_seen).then_with(|| a.socket_addr.cmp(&b.socket_addr)));
+        addresses.truncate(max_count);
+        addresses
+    }
+
+    /// Picks the address to connect to next: the fewest failures, then the oldest attempt.
+    pub fn select_address(&self, exclude: &HashSet<SocketAddr>) -> Option<SocketAddr> {
+        self.addresses
+            .iter()
+            .filter(|(socket_addr, _)| !exclude.contains(*socket_addr))
+            .min_by(|(a_addr, a), (b_addr, b)| {
+                a.failures
+                    .cmp(&b.failures)
+                    .then_with(|| a.last_attempt.cmp(&b.last_attempt))
+                    .then_with(|| b.last_seen.cmp(&a.last_seen))
+                    .then_with(|| a_addr.cmp(b_addr))
+            })
+            .map(|(socket_addr, _)| *socket_addr)
+    }
+
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .addresses
+            .iter()
+            .min_by(|(a_addr, a), (b_addr, b)| a.last_seen.cmp(&b.last_seen).then_with(|| a_addr.cmp(b_addr)))
+            .map(|(socket_addr, _)| *socket_addr);
+        if let Some(socket_addr) = oldest {
+            self.addresses.remove(&socket_addr);
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let addresses = self.get_addresses(self.addresses.len());
+        let mut bw = BufferWriter::new();
+        bw.put_var_u32(P2P_ADDRMAN_VERSION);
+        bw.put_var_u64(addresses.len() as u64);
+        for address in addresses.iter() {
+            bw.put_var_bytes(address.socket_addr.to_string().as_bytes());
+            bw.put_u64(address.last_seen as u64);
+        }
+        bw.get_bytes()
+    }
+
+    /// Replaces the addresses with the serialized ones, entries that cannot be parsed are skipped.
+    pub fn unserialize(&mut self, rawbytes: Vec<u8>) -> Result<(), P2pError> {
+        let mut br = BufferReader::new(rawbytes);
+        let version = br.get_var_u32()?;
+        if version != P2P_ADDRMAN_VERSION {
+            return Err(P2pError::UnknownAddrmanVersion(version));
+        }
+        self.addresses.clear();
+        let count = br.get_var_u64()?;
+        for _ in 0..count {
+            let socket_addr = String::from_utf8_lossy(&br.get_var_bytes()?).parse::<SocketAddr>();
+            let last_seen = br.get_u64()? as i64;
+            if let Ok(socket_addr) = socket_addr {
+                self.add_address(&P2pAddress { socket_addr, last_seen });
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the saved addresses, if any, keeping the addresses already known.
+    pub async fn load(&mut self) -> Result<(), P2pError> {
+        let path = match &self.am_path {
+            Some(path) => path.to_string_lossy().to_string(),
+            None => return Ok(()),
+        };
+        if !file_exists(&path).await {
+            return Ok(());
+        }
+        let rawbytes = load_bytes_from_file(&path).await?;
+        let known = self.get_addresses(self.addresses.len());
+        self.unserialize(rawbytes)?;
+        self.add_addresses(&known);
+        Ok(())
+    }
+
+    pub async fn save(&self) -> Result<(), P2pError> {
+        let path = match &self.am_path {
+            Some(path) => path.to_string_lossy().to_string(),
+            None => return Ok(()),
+        };
+        save_bytes_to_file_atomic(&self.serialize(), &path).await?;
+        Ok(())
+    }
+}
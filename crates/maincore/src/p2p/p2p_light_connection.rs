@@ -0,0 +1,109 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use utility::hash::hash::Hash;
+use utility::system::random::generate_secure_random_number;
+use utility::system::time::timestamp_now;
+use crate::mainheader::mainheader::Mainheader;
+use crate::mainblock::maintx_proof::MaintxProof;
+use crate::p2p::p2p_message::P2pError;
+use crate::p2p::p2p_message::P2pMessage;
+use crate::p2p::p2p_message::P2pVersion;
+use crate::p2p::p2p_message::P2P_MIN_PROTOCOL_VERSION;
+use crate::p2p::p2p_message::P2P_PROTOCOL_VERSION;
+use crate::p2p::p2p_message::read_p2p_message;
+use crate::p2p::p2p_message::write_p2p_message;
+
+/// Time a light connection waits for the handshake or for the answer to a request.
+const P2P_LIGHT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connection of a light client to a full node: it does not listen, relay or store mainblocks,
+/// it only asks for mainheaders and maintx proofs and waits for the answer.
+pub struct P2pLightConnection {
+    stream: TcpStream,
+    magic: u32,
+    remote_version: P2pVersion,
+}
+
+impl P2pLightConnection {
+    pub async fn connect(socket_addr: SocketAddr, magic: u32) -> Result<Self, P2pError> {
+        let mut stream = match tokio::time::timeout(P2P_LIGHT_TIMEOUT, TcpStream::connect(socket_addr)).await {
+            Ok(stream) => stream?,
+            Err(_) => return Err(P2pError::Timeout(format!("connection to {}", socket_addr))),
+        };
+        let remote_version = match tokio::time::timeout(P2P_LIGHT_TIMEOUT, run_light_handshake(&mut stream, magic)).await {
+            Ok(result) => result?,
+            Err(_) => return Err(P2pError::Timeout(format!("handshake with {}", socket_addr))),
+        };
+        Ok(Self { stream, magic, remote_version })
+    }
+
+    /// Number of mainheaders the node announced in its version.
+    pub fn get_remote_best_height(&self) -> u64 {
+        self.remote_version.best_height
+    }
+
+    /// Mainheaders following the first hash of `locator` the node knows.
+    pub async fn get_mainheaders(&mut self, locator: Vec<Hash>) -> Result<Vec<Mainheader>, P2pError> {
+        self.send(P2pMessage::GetHeaders { locator, stop_hash: Hash::new_empty() }).await?;
+        loop {
+            if let P2pMessage::Headers(mainheaders) = self.receive().await? {
+                return Ok(mainheaders);
+            }
+        }
+    }
+
+    /// Proofs of the maintxs of `addresses` from `from_height`, with the height of the last mainblock they cover.
+    pub async fn get_maintx_proofs(&mut self, addresses: Vec<Hash>, from_height: u64) -> Result<(u64, Vec<MaintxProof>), P2pError> {
+        self.send(P2pMessage::GetMaintxProofs { addresses, from_height }).await?;
+        loop {
+            if let P2pMessage::MaintxProofs { last_height, proofs } = self.receive().await? {
+                return Ok((last_height, proofs));
+            }
+        }
+    }
+
+    async fn send(&mut self, message: P2pMessage) -> Result<(), P2pError> {
+        write_p2p_message(&mut self.stream, self.magic, &message).await
+    }
+
+    /// Reads the next message, answering the requests a full node sends to any peer.
+    async fn receive(&mut self) -> Result<P2pMessage, P2pError> {
+        let message = match tokio::time::timeout(P2P_LIGHT_TIMEOUT, read_p2p_message(&mut self.stream, self.magic)).await {
+            Ok(message) => message?,
+            Err(_) => return Err(P2pError::Timeout(String::from("answer from the node"))),
+        };
+        match &message {
+            P2pMessage::Ping(nonce) => self.send(P2pMessage::Pong(*nonce)).await?,
+            P2pMessage::GetHeaders { .. } => self.send(P2pMessage::Headers(Vec::new())).await?,
+            P2pMessage::GetAddr => self.send(P2pMessage::Addr(Vec::new())).await?,
+            _ => {}
+        }
+        Ok(message)
+    }
+}
+
+/// Sends a version that announces no mainheader and no listening port, and acknowledges the version of the node.
+async fn run_light_handshake(stream: &mut TcpStream, magic: u32) -> Result<P2pVersion, P2pError> {
+    let nonce = generate_secure_random_number(0, usize::MAX - 1).map_err(|e| P2pError::HandshakeFailed(e.to_string()))? as u64;
+    let local_version = P2pVersion { version: P2P_PROTOCOL_VERSION, nonce, timestamp: timestamp_now(), best_height: 0, listen_port: 0 };
+    write_p2p_message(stream, magic, &P2pMessage::Version(local_version)).await?;
+    let mut remote_version = None;
+    let mut verack_received = false;
+    while remote_version.is_none() || !verack_received {
+        match read_p2p_message(stream, magic).await? {
+            P2pMessage::Version(version) => {
+                if version.version < P2P_MIN_PROTOCOL_VERSION {
+                    return Err(P2pError::HandshakeFailed(format!("protocol version {} is too old", version.version)));
+                }
+                write_p2p_message(stream, magic, &P2pMessage::Verack).await?;
+                remote_version = Some(version);
+            }
+            P2pMessage::Verack => verack_received = true,
+            message => {
+                return Err(P2pError::HandshakeFailed(format!("unexpected {} message", message.get_command_name())));
+            }
+        }
+    }
+    remote_version.ok_or_else(|| P2pError::HandshakeFailed(String::from("no version")))
+}
@@ -0,0 +1,440 @@
+use std::net::IpAddr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use thiserror::Error;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use utility::hash::hash::Hash;
+use utility::buffer::buffer_writer::BufferWriter;
+use utility::buffer::buffer_reader::BufferReader;
+use utility::buffer::buffer_reader::BufferReaderError;
+use utility::storage::async_file::AsyncFileError;
+use maintx::maintx::maintx::Maintx;
+use maintx::maintx::maintx::MaintxError;
+use maintx::maintx::maintx::unserialize_maintx;
+use crate::mainheader::mainheader::Mainheader;
+use crate::mainheader::mainheader::MainheaderError;
+use crate::mainheader::mainheader::unserialize_mainheader;
+use crate::mainblock::mainblock::Mainblock;
+use crate::mainblock::mainblock::MainblockError;
+use crate::mainblock::mainblock::unserialize_mainblock;
+use crate::mainblock::maintx_proof::MaintxProof;
+use crate::mainblock::maintx_proof::MaintxProofError;
+use crate::mainblock::maintx_proof::unserialize_maintx_proof;
+
+pub const P2P_PROTOCOL_VERSION: u32 = 1;
+pub const P2P_MIN_PROTOCOL_VERSION: u32 = 1;
+/// Magic, payload length and payload checksum.
+pub const P2P_FRAME_HEADER_SIZE: usize = 12;
+pub const P2P_MAX_PAYLOAD_SIZE: usize = 4 * 1024 * 1024;
+pub const P2P_MAX_INVENTORY_COUNT: usize = 50_000;
+pub const P2P_MAX_HEADERS_COUNT: usize = 2000;
+pub const P2P_MAX_ADDR_COUNT: usize = 1000;
+/// Addresses a light client may ask maintx proofs for in one request.
+pub const P2P_MAX_PROOF_ADDRESSES_COUNT: usize = 1000;
+/// Proofs a node gathers before answering, more are sent when the last mainblock covered has more matches.
+pub const P2P_MAINTX_PROOFS_BATCH_COUNT: usize = 500;
+
+const P2P_COMMAND_VERSION: u32 = 0;
+const P2P_COMMAND_VERACK: u32 = 1;
+const P2P_COMMAND_PING: u32 = 2;
+const P2P_COMMAND_PONG: u32 = 3;
+const P2P_COMMAND_INV: u32 = 4;
+const P2P_COMMAND_GETDATA: u32 = 5;
+const P2P_COMMAND_NOTFOUND: u32 = 6;
+const P2P_COMMAND_GETHEADERS: u32 = 7;
+const P2P_COMMAND_HEADERS: u32 = 8;
+const P2P_COMMAND_MAINBLOCK: u32 = 9;
+const P2P_COMMAND_MAINTX: u32 = 10;
+const P2P_COMMAND_GETADDR: u32 = 11;
+const P2P_COMMAND_ADDR: u32 = 12;
+const P2P_COMMAND_GETMAINTXPROOFS: u32 = 13;
+const P2P_COMMAND_MAINTXPROOFS: u32 = 14;
+
+const P2P_INVENTORY_MAINBLOCK: u32 = 1;
+const P2P_INVENTORY_MAINTX: u32 = 2;
+
+#[derive(Debug, Error)]
+pub enum P2pError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Buffer reader error: {0}")]
+    BufferReaderError(#[from] BufferReaderError),
+    #[error("Mainheader error: {0}")]
+    MainheaderError(#[from] MainheaderError),
+    #[error("Mainblock error: {0}")]
+    MainblockError(#[from] MainblockError),
+    #[error("Maintx error: {0}")]
+    MaintxError(#[from] MaintxError),
+    #[error("Maintx proof error: {0}")]
+    MaintxProofError(#[from] MaintxProofError),
+    #[error("Async file error: {0}")]
+    AsyncFileError(#[from] AsyncFileError),
+
+    #[error("Invalid magic: expected {expected:#x}, got {actual:#x}")]
+    InvalidMagic { expected: u32, actual: u32 },
+    #[error("Payload of {0} bytes exceeds the maximum size")]
+    PayloadTooLarge(usize),
+    #[error("Invalid payload checksum")]
+    InvalidChecksum,
+    #[error("Unknown command: {0}")]
+    UnknownCommand(u32),
+    #[error("Unknown inventory kind: {0}")]
+    UnknownInventoryKind(u32),
+    #[error("Too many items in message: {0}")]
+    TooManyItems(u64),
+    #[error("Unknown addrman version: {0}")]
+    UnknownAddrmanVersion(u32),
+    #[error("Handshake failed: {0}")]
+    HandshakeFailed(String),
+    #[error("Timeout: {0}")]
+    Timeout(String),
+    #[error("Peer {0} is not connected")]
+    PeerNotConnected(u64),
+    #[error("Send queue of peer {0} is full")]
+    SendQueueFull(u64),
+    #[error("Maincore error: {0}")]
+    MaincoreInnerError(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum P2pInventoryKind {
+    Mainblock,
+    Maintx,
+}
+
+/// Announces or requests a mainblock or a transaction by hash.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct P2pInventory {
+    pub kind: P2pInventoryKind,
+    pub hash: Hash,
+}
+
+impl P2pInventory {
+    pub fn new_mainblock(hash: Hash) -> Self {
+        Self { kind: P2pInventoryKind::Mainblock, hash }
+    }
+
+    pub fn new_maintx(hash: Hash) -> Self {
+        Self { kind: P2pInventoryKind::Maintx, hash }
+    }
+}
+
+/// The address a node listens on, with the last time it was known to be reachable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct P2pAddress {
+    pub socket_addr: SocketAddr,
+    pub last_seen: i64,
+}
+
+/// First message of the handshake, sent by both sides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct P2pVersion {
+    pub version: u32,
+    /// Random value identifying the node, used to detect connections to itself.
+    pub nonce: u64,
+    pub timestamp: i64,
+    pub best_height: u64,
+    /// Port the sender accepts connections on, 0 if it does not listen.
+    pub listen_port: u16,
+}
+
+#[derive(Debug, Clone)]
+pub enum P2pMessage {
+    Version(P2pVersion),
+    Verack,
+    Ping(u64),
+    Pong(u64),
+    Inv(Vec<P2pInventory>),
+    GetData(Vec<P2pInventory>),
+    NotFound(Vec<P2pInventory>),
+    /// Requests the mainheaders following the first known hash of `locator`, up to `stop_hash`.
+    GetHeaders { locator: Vec<Hash>, stop_hash: Hash },
+    Headers(Vec<Mainheader>),
+    Mainblock(Mainblock),
+    Maintx(Maintx),
+    GetAddr,
+    Addr(Vec<P2pAddress>),
+    /// Requests the proofs of the maintxs paying to or spending from `addresses`, from the mainblock at `from_height`.
+    GetMaintxProofs { addresses: Vec<Hash>, from_height: u64 },
+    /// Proofs covering the mainblocks from the requested height up to `last_height` included.
+    MaintxProofs { last_height: u64, proofs: Vec<MaintxProof> },
+}
+
+impl P2pMessage {
+    pub fn get_command_name(&self) -> &'static str {
+        match self {
+            P2pMessage::Version(_) => "version",
+            P2pMessage::Verack => "verack",
+            P2pMessage::Ping(_) => "ping",
+            P2pMessage::Pong(_) => "pong",
+            P2pMessage::Inv(_) => "inv",
+            P2pMessage::GetData(_) => "getdata",
+            P2pMessage::NotFound(_) => "notfound",
+            P2pMessage::GetHeaders { .. } => "getheaders",
+            P2pMessage::Headers(_) => "headers",
+            P2pMessage::Mainblock(_) => "mainblock",
+            P2pMessage::Maintx(_) => "maintx",
+            P2pMessage::GetAddr => "getaddr",
+            P2pMessage::Addr(_) => "addr",
+            P2pMessage::GetMaintxProofs { .. } => "getmaintxproofs",
+            P2pMessage::MaintxProofs { .. } => "maintxproofs",
+        }
+    }
+
+    /// Serializes the command followed by the fields of the message.
+    pub fn serialize_payload(&self) -> Vec<u8> {
+        let mut bw = BufferWriter::new();
+        match self {
+            P2pMessage::Version(version) => {
+                bw.put_var_u32(P2P_COMMAND_VERSION);
+                bw.put_var_u32(version.version);
+                bw.put_u64(version.nonce);
+                bw.put_u64(version.timestamp as u64);
+                bw.put_var_u64(version.best_height);
+                bw.put_u16(version.listen_port);
+            }
+            P2pMessage::Verack => bw.put_var_u32(P2P_COMMAND_VERACK),
+            P2pMessage::Ping(nonce) => {
+                bw.put_var_u32(P2P_COMMAND_PING);
+                bw.put_u64(*nonce);
+            }
+            P2pMessage::Pong(nonce) => {
+                bw.put_var_u32(P2P_COMMAND_PONG);
+                bw.put_u64(*nonce);
+            }
+            P2pMessage::Inv(inventories) => {
+                bw.put_var_u32(P2P_COMMAND_INV);
+                serialize_inventories(&mut bw, inventories);
+            }
+            P2pMessage::GetData(inventories) => {
+                bw.put_var_u32(P2P_COMMAND_GETDATA);
+                serialize_inventories(&mut bw, inventories);
+            }
+            P2pMessage::NotFound(inventories) => {
+                bw.put_var_u32(P2P_COMMAND_NOTFOUND);
+                serialize_inventories(&mut bw, inventories);
+            }
+            P2pMessage::GetHeaders { locator, stop_hash } => {
+                bw.put_var_u32(P2P_COMMAND_GETHEADERS);
+                bw.put_var_u64(locator.len() as u64);
+                for hash in locator.iter() {
+                    bw.put_hash(hash.clone());
+                }
+                bw.put_hash(stop_hash.clone());
+            }
+            P2pMessage::Headers(mainheaders) => {
+                bw.put_var_u32(P2P_COMMAND_HEADERS);
+                bw.put_var_u64(mainheaders.len() as u64);
+                for mh in mainheaders.iter() {
+                    bw.put_var_bytes(&mh.serialize());
+                }
+            }
+            P2pMessage::Mainblock(mb) => {
+                bw.put_var_u32(P2P_COMMAND_MAINBLOCK);
+                bw.put_var_bytes(&mb.serialize());
+            }
+            P2pMessage::Maintx(tx) => {
+                bw.put_var_u32(P2P_COMMAND_MAINTX);
+                bw.put_var_bytes(&tx.serialize());
+            }
+            P2pMessage::GetAddr => bw.put_var_u32(P2P_COMMAND_GETADDR),
+            P2pMessage::Addr(addresses) => {
+                bw.put_var_u32(P2P_COMMAND_ADDR);
+                bw.put_var_u64(addresses.len() as u64);
+                for address in addresses.iter() {
+                    serialize_address(&mut bw, address);
+                }
+            }
+            P2pMessage::GetMaintxProofs { addresses, from_height } => {
+                bw.put_var_u32(P2P_COMMAND_GETMAINTXPROOFS);
+                bw.put_var_u64(addresses.len() as u64);
+                for address in addresses.iter() {
+                    bw.put_hash(address.clone());
+                }
+                bw.put_var_u64(*from_height);
+            }
+            P2pMessage::MaintxProofs { last_height, proofs } => {
+                bw.put_var_u32(P2P_COMMAND_MAINTXPROOFS);
+                bw.put_var_u64(*last_height);
+                bw.put_var_u64(proofs.len() as u64);
+                for proof in proofs.iter() {
+                    proof.serialize(&mut bw);
+                }
+            }
+        }
+        bw.get_bytes()
+    }
+}
+
+pub fn unserialize_p2p_payload(rawbytes: Vec<u8>) -> Result<P2pMessage, P2pError> {
+    let mut br = BufferReader::new(rawbytes);
+    let command = br.get_var_u32()?;
+    let message = match command {
+        P2P_COMMAND_VERSION => P2pMessage::Version(P2pVersion {
+            version: br.get_var_u32()?,
+            nonce: br.get_u64()?,
+            timestamp: br.get_u64()? as i64,
+            best_height: br.get_var_u64()?,
+            listen_port: br.get_u16()?,
+        }),
+        P2P_COMMAND_VERACK => P2pMessage::Verack,
+        P2P_COMMAND_PING => P2pMessage::Ping(br.get_u64()?),
+        P2P_COMMAND_PONG => P2pMessage::Pong(br.get_u64()?),
+        P2P_COMMAND_INV => P2pMessage::Inv(unserialize_inventories(&mut br)?),
+        P2P_COMMAND_GETDATA => P2pMessage::GetData(unserialize_inventories(&mut br)?),
+        P2P_COMMAND_NOTFOUND => P2pMessage::NotFound(unserialize_inventories(&mut br)?),
+        P2P_COMMAND_GETHEADERS => {
+            let count = get_item_count(&mut br, P2P_MAX_HEADERS_COUNT)?;
+            let mut locator = Vec::with_capacity(count);
+            for _ in 0..count {
+                locator.push(br.get_hash()?);
+            }
+            let stop_hash = br.get_hash()?;
+            P2pMessage::GetHeaders { locator, stop_hash }
+        }
+        P2P_COMMAND_HEADERS => {
+            let count = get_item_count(&mut br, P2P_MAX_HEADERS_COUNT)?;
+            let mut mainheaders = Vec::with_capacity(count);
+            for _ in 0..count {
+                mainheaders.push(unserialize_mainheader(br.get_var_bytes()?)?);
+            }
+            P2pMessage::Headers(mainheaders)
+        }
+        P2P_COMMAND_MAINBLOCK => P2pMessage::Mainblock(unserialize_mainblock(br.get_var_bytes()?)?),
+        P2P_COMMAND_MAINTX => P2pMessage::Maintx(unserialize_maintx(br.get_var_bytes()?)?),
+        P2P_COMMAND_GETADDR => P2pMessage::GetAddr,
+        P2P_COMMAND_ADDR => {
+            let count = get_item_count(&mut br, P2P_MAX_ADDR_COUNT)?;
+            let mut addresses = Vec::with_capacity(count);
+            for _ in 0..count {
+                addresses.push(unserialize_address(&mut br)?);
+            }
+            P2pMessage::Addr(addresses)
+        }
+        P2P_COMMAND_GETMAINTXPROOFS => {
+            let count = get_item_count(&mut br, P2P_MAX_PROOF_ADDRESSES_COUNT)?;
+            let mut addresses = Vec::with_capacity(count);
+            for _ in 0..count {
+                addresses.push(br.get_hash()?);
+            }
+            let from_height = br.get_var_u64()?;
+            P2pMessage::GetMaintxProofs { addresses, from_height }
+        }
+        P2P_COMMAND_MAINTXPROOFS => {
+            let last_height = br.get_var_u64()?;
+            let count = get_item_count(&mut br, P2P_MAX_INVENTORY_COUNT)?;
+            let mut proofs = Vec::with_capacity(count);
+            for _ in 0..count {
+                proofs.push(unserialize_maintx_proof(&mut br)?);
+            }
+            P2pMessage::MaintxProofs { last_height, proofs }
+        }
+        _ => return Err(P2pError::UnknownCommand(command)),
+    };
+    Ok(message)
+}
+
+/// First 4 bytes of the hash of the payload.
+pub fn compute_payload_checksum(payload: &[u8]) -> u32 {
+    let hash = Hash::compute_hash(payload);
+    let bytes = hash.as_bytes();
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Frames a message: magic, payload length, payload checksum and payload.
+pub fn serialize_p2p_frame(magic: u32, message: &P2pMessage) -> Vec<u8> {
+    let payload = message.serialize_payload();
+    let mut bw = BufferWriter::new();
+    bw.put_u32(magic);
+    bw.put_u32(payload.len() as u32);
+    bw.put_u32(compute_payload_checksum(&payload));
+    bw.put_bytes(&payload);
+    bw.get_bytes()
+}
+
+pub async fn write_p2p_message<W: AsyncWrite + Unpin>(writer: &mut W, magic: u32, message: &P2pMessage) -> Result<(), P2pError> {
+    writer.write_all(&serialize_p2p_frame(magic, message)).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+pub async fn read_p2p_message<R: AsyncRead + Unpin>(reader: &mut R, magic: u32) -> Result<P2pMessage, P2pError> {
+    let mut header = [0u8; P2P_FRAME_HEADER_SIZE];
+    reader.read_exact(&mut header).await?;
+    let mut br = BufferReader::new(header.to_vec());
+    let actual_magic = br.get_u32()?;
+    if actual_magic != magic {
+        return Err(P2pError::InvalidMagic { expected: magic, actual: actual_magic });
+    }
+    let payload_size = br.get_u32()? as usize;
+    if payload_size > P2P_MAX_PAYLOAD_SIZE {
+        return Err(P2pError::PayloadTooLarge(payload_size));
+    }
+    let checksum = br.get_u32()?;
+    let mut payload = vec![0u8; payload_size];
+    reader.read_exact(&mut payload).await?;
+    if compute_payload_checksum(&payload) != checksum {
+        return Err(P2pError::InvalidChecksum);
+    }
+    unserialize_p2p_payload(payload)
+}
+
+fn get_item_count(br: &mut BufferReader, max_count: usize) -> Result<usize, P2pError> {
+    let count = br.get_var_u64()?;
+    if count > max_count as u64 {
+        return Err(P2pError::TooManyItems(count));
+    }
+    Ok(count as usize)
+}
+
+fn serialize_inventories(bw: &mut BufferWriter, inventories: &[P2pInventory]) {
+    bw.put_var_u64(inventories.len() as u64);
+    for inventory in inventories.iter() {
+        let kind = match inventory.kind {
+            P2pInventoryKind::Mainblock => P2P_INVENTORY_MAINBLOCK,
+            P2pInventoryKind::Maintx => P2P_INVENTORY_MAINTX,
+        };
+        bw.put_var_u32(kind);
+        bw.put_hash(inventory.hash.clone());
+    }
+}
+
+fn unserialize_inventories(br: &mut BufferReader) -> Result<Vec<P2pInventory>, P2pError> {
+    let count = get_item_count(br, P2P_MAX_INVENTORY_COUNT)?;
+    let mut inventories = Vec::with_capacity(count);
+    for _ in 0..count {
+        let kind = match br.get_var_u32()? {
+            P2P_INVENTORY_MAINBLOCK => P2pInventoryKind::Mainblock,
+            P2P_INVENTORY_MAINTX => P2pInventoryKind::Maintx,
+            kind => return Err(P2pError::UnknownInventoryKind(kind)),
+        };
+        inventories.push(P2pInventory { kind, hash: br.get_hash()? });
+    }
+    Ok(inventories)
+}
+
+/// Addresses are written as 16-byte IPv6 addresses, IPv4 addresses being mapped.
+fn serialize_address(bw: &mut BufferWriter, address: &P2pAddress) {
+    let ip = match address.socket_addr.ip() {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    };
+    bw.put_bytes(&ip.octets());
+    bw.put_u16(address.socket_addr.port());
+    bw.put_u64(address.last_seen as u64);
+}
+
+fn unserialize_address(br: &mut BufferReader) -> Result<P2pAddress, P2pError> {
+    let octets: [u8; 16] = br.get_bytes(16)?.try_into().map_err(|_| BufferReaderError::EndOfBuffer)?;
+    let ipv6 = Ipv6Addr::from(octets);
+    let ip = match ipv6.to_ipv4_mapped() {
+        Some(ipv4) => IpAddr::V4(ipv4),
+        None => IpAddr::V6(ipv6),
+    };
+    let port = br.get_u16()?;
+    let last_seen = br.get_u64()? as i64;
+    Ok(P2pAddress { socket_addr: SocketAddr::new(ip, port), last_seen })
+}
@@ -0,0 +1,681 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex as StdMutex;
+use std::sync::atomic::AtomicU16;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::sync::Mutex;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc;
+use tokio::sync::watch;
+use utility::hash::hash::Hash;
+use utility::system::random::generate_secure_random_number;
+use utility::system::time::timestamp_now;
+use crate::maincore_inner::maincore_inner::MaincoreEvent;
+use crate::maincore_inner::maincore_inner::MaincoreInner;
+use crate::maincore_inner::maincore_inner::MaincoreInnerError;
+use crate::p2p::p2p_addrman::P2pAddrman;
+use crate::p2p::p2p_message::P2pAddress;
+use crate::p2p::p2p_message::P2pError;
+use crate::p2p::p2p_message::P2pInventory;
+use crate::p2p::p2p_message::P2pInventoryKind;
+use crate::p2p::p2p_message::P2pMessage;
+use crate::p2p::p2p_message::P2pVersion;
+use crate::p2p::p2p_message::P2P_MAX_ADDR_COUNT;
+use crate::p2p::p2p_message::P2P_MAX_HEADERS_COUNT;
+use crate::p2p::p2p_message::P2P_MAINTX_PROOFS_BATCH_COUNT;
+use crate::p2p::p2p_message::P2P_MIN_PROTOCOL_VERSION;
+use crate::p2p::p2p_message::P2P_PROTOCOL_VERSION;
+use crate::p2p::p2p_message::read_p2p_message;
+use crate::p2p::p2p_message::write_p2p_message;
+use crate::syncpool::syncpool::SyncpoolError;
+
+/// Time after which a mainblock or transaction requested from a peer may be requested again.
+const P2P_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Interval at which the sync driver retries timed out mainblock requests.
+const P2P_SYNC_TICK: Duration = Duration::from_millis(250);
+/// Interval between two sync progress reports.
+const P2P_SYNC_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Settings of a p2p node.
+#[derive(Debug, Clone)]
+pub struct P2pConfig {
+    /// Address to accept connections on, port 0 picks a free port.
+    pub listen_addr: SocketAddr,
+    pub max_peers: usize,
+    pub handshake_timeout: Duration,
+    /// File the known peer addresses are saved to, kept in memory only when `None`.
+    pub addrman_path: Option<PathBuf>,
+    /// Minimum time between two maintx proofs requests served to the same peer. A request may read
+    /// many mainblocks, the requests of a peer coming faster are delayed.
+    pub maintx_proofs_interval: Duration,
+    /// Messages queued for a peer that is not reading them fast enough. A peer whose queue is full is disconnected,
+    /// except while it is sent the replies to its own requests, which wait for room in the queue.
+    pub send_queue_size: usize,
+}
+
+impl P2pConfig {
+    pub fn new(listen_addr: SocketAddr) -> Self {
+        Self {
+            listen_addr,
+            max_peers: 32,
+            handshake_timeout: Duration::from_secs(10),
+            addrman_path: None,
+            maintx_proofs_interval: Duration::from_millis(200),
+            send_queue_size: 256,
+        }
+    }
+}
+
+/// A connected peer, once the handshake is done.
+#[derive(Debug, Clone)]
+pub struct P2pPeerInfo {
+    pub peer_id: u64,
+    pub socket_addr: SocketAddr,
+    pub inbound: bool,
+    pub version: P2pVersion,
+}
+
+struct P2pPeer {
+    info: P2pPeerInfo,
+    sender: mpsc::Sender<P2pMessage>,
+    /// Earliest time the next maintx proofs request of the peer is served.
+    next_maintx_proofs_at: Instant,
+}
+
+struct P2pNodeShared {
+    maincore: Arc<Mutex<MaincoreInner>>,
+    config: P2pConfig,
+    magic: u32,
+    local_nonce: u64,
+    listen_port: AtomicU16,
+    next_peer_id: AtomicU64,
+    peers: StdMutex<HashMap<u64, P2pPeer>>,
+    addrman: StdMutex<P2pAddrman>,
+    requested: StdMutex<HashMap<Hash, Instant>>,
+    shutdown: watch::Sender<bool>,
+}
+
+/// Relays mainblocks, transactions and peer addresses between a `MaincoreInner` and its peers.
+/// Cloning gives another handle on the same node.
+#[derive(Clone)]
+pub struct P2pNode {
+    shared: Arc<P2pNodeShared>,
+}
+
+impl P2pNode {
+    pub async fn new(maincore: Arc<Mutex<MaincoreInner>>, config: P2pConfig) -> Self {
+        let magic = maincore.lock().await.get_chain_params().p2p_magic;
+        let local_nonce = match generate_secure_random_number(0, usize::MAX - 1) {
+            Ok(nonce) => nonce as u64,
+            Err(_) => timestamp_now() as u64,
+        };
+        let addrman = match &config.addrman_path {
+            Some(path) => P2pAddrman::new_with_path(path),
+            None => P2pAddrman::new(),
+        };
+        Self {
+            shared: Arc::new(P2pNodeShared {
+                maincore,
+                config,
+                magic,
+                local_nonce,
+                listen_port: AtomicU16::new(0),
+                next_peer_id: AtomicU64::new(1),
+                peers: StdMutex::new(HashMap::new()),
+                addrman: StdMutex::new(addrman),
+                requested: StdMutex::new(HashMap::new()),
+                shutdown: watch::channel(false).0,
+            }),
+        }
+    }
+
+    /// Loads the known addresses, starts accepting connections and relaying the maincore events.
+    /// Returns the address the node listens on.
+    pub async fn start(&self) -> Result<SocketAddr, P2pError> {
+        // the lock cannot be held across the load, the addrman built by `new` is taken out meanwhile
+        let mut addrman = std::mem::take(&mut *lock(&self.shared.addrman));
+        let loaded = addrman.load().await;
+        *lock(&self.shared.addrman) = addrman;
+        loaded?;
+
+        let listener = TcpListener::bind(self.shared.config.listen_addr).await?;
+        let local_addr = listener.local_addr()?;
+        self.shared.listen_port.store(local_addr.port(), Ordering::Relaxed);
+        println!("p2p node listening on {}", local_addr);
+
+        let node = self.clone();
+        tokio::spawn(async move { node.run_accept_loop(listener).await });
+        let node = self.clone();
+        let events = self.shared.maincore.lock().await.subscribe_events();
+        tokio::spawn(async move { node.run_event_relay(events).await });
+        let node = self.clone();
+        tokio::spawn(async move { node.run_sync_driver().await });
+        Ok(local_addr)
+    }
+
+    /// Stops accepting connections, disconnects every peer and saves the known addresses.
+    pub async fn shutdown(&self) -> Result<(), P2pError> {
+        let _ = self.shared.shutdown.send(true);
+        lock(&self.shared.peers).clear();
+        let addrman_bytes = {
+            let addrman = lock(&self.shared.addrman);
+            self.shared.config.addrman_path.as_ref().map(|_| addrman.serialize())
+        };
+        if let (Some(path), Some(rawbytes)) = (&self.shared.config.addrman_path, addrman_bytes) {
+            utility::storage::async_file::save_bytes_to_file_atomic(&rawbytes, &path.to_string_lossy()).await?;
+        }
+        Ok(())
+    }
+
+    pub fn get_local_nonce(&self) -> u64 {
+        self.shared.local_nonce
+    }
+
+    pub fn get_peers(&self) -> Vec<P2pPeerInfo> {
+        let mut peers: Vec<P2pPeerInfo> = lock(&self.shared.peers).values().map(|peer| peer.info.clone()).collect();
+        peers.sort_by_key(|peer| peer.peer_id);
+        peers
+    }
+
+    pub fn get_peers_count(&self) -> usize {
+        lock(&self.shared.peers).len()
+    }
+
+    pub fn get_known_addresses(&self) -> Vec<P2pAddress> {
+        lock(&self.shared.addrman).get_addresses(usize::MAX)
+    }
+
+    pub fn add_known_address(&self, socket_addr: SocketAddr) {
+        lock(&self.shared.addrman).add_address(&P2pAddress { socket_addr, last_seen: timestamp_now() });
+    }
+
+    pub fn disconnect(&self, peer_id: u64) {
+        lock(&self.shared.peers).remove(&peer_id);
+    }
+
+    /// Queues a message for a peer, the peer is disconnected if its queue is full.
+    pub fn send_to_peer(&self, peer_id: u64, message: P2pMessage) -> Result<(), P2pError> {
+        let mut peers = lock(&self.shared.peers);
+        let peer = peers.get(&peer_id).ok_or(P2pError::PeerNotConnected(peer_id))?;
+        match peer.sender.try_send(message) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                println!("p2p peer {} send queue is full, disconnecting", peer_id);
+                peers.remove(&peer_id);
+                Err(P2pError::SendQueueFull(peer_id))
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => Err(P2pError::PeerNotConnected(peer_id)),
+        }
+    }
+
+    /// Same as `send_to_peer`, but waits for room in the queue, for the replies to a request of the peer.
+    /// The peer is disconnected if there is still no room after `P2P_REQUEST_TIMEOUT`.
+    async fn send_to_peer_when_ready(&self, peer_id: u64, message: P2pMessage) -> Result<(), P2pError> {
+        let sender = lock(&self.shared.peers).get(&peer_id).map(|peer| peer.sender.clone()).ok_or(P2pError::PeerNotConnected(peer_id))?;
+        match tokio::time::timeout(P2P_REQUEST_TIMEOUT, sender.send(message)).await {
+            Ok(result) => result.map_err(|_| P2pError::PeerNotConnected(peer_id)),
+            Err(_) => {
+                self.disconnect(peer_id);
+                Err(P2pError::SendQueueFull(peer_id))
+            }
+        }
+    }
+
+    /// Sends a message to every connected peer. Peers whose queue is full are disconnected.
+    pub fn broadcast(&self, message: P2pMessage) {
+        lock(&self.shared.peers).retain(|peer_id, peer| match peer.sender.try_send(message.clone()) {
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                println!("p2p peer {} send queue is full, disconnecting", peer_id);
+                false
+            }
+            _ => true,
+        });
+    }
+
+    /// Opens an outbound connection and performs the handshake. Returns the id of the new peer.
+    pub async fn connect(&self, socket_addr: SocketAddr) -> Result<u64, P2pError> {
+        let connect_result = tokio::time::timeout(self.shared.config.handshake_timeout, TcpStream::connect(socket_addr)).await;
+        let stream = match connect_result {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(e)) => {
+                lock(&self.shared.addrman).mark_failed(&socket_addr, timestamp_now());
+                return Err(P2pError::IoError(e));
+            }
+            Err(_) => {
+                lock(&self.shared.addrman).mark_failed(&socket_addr, timestamp_now());
+                return Err(P2pError::Timeout(format!("connecting to {}", socket_addr)));
+            }
+        };
+        let result = self.start_peer(stream, socket_addr, false).await;
+        match &result {
+            Ok(_) => lock(&self.shared.addrman).mark_good(&socket_addr, timestamp_now()),
+            Err(_) => lock(&self.shared.addrman).mark_failed(&socket_addr, timestamp_now()),
+        }
+        result
+    }
+
+    /// Connects to known addresses until `target_count` outbound peers are connected or no address is left to try.
+    pub async fn connect_to_known_addresses(&self, target_count: usize) -> usize {
+        let mut tried: HashSet<SocketAddr> = lock(&self.shared.peers).values().map(|peer| peer.info.socket_addr).collect();
+        let mut connected = 0;
+        loop {
+            let outbound_count = lock(&self.shared.peers).values().filter(|peer| !peer.info.inbound).count();
+            if outbound_count >= target_count {
+                break;
+            }
+            let socket_addr = match lock(&self.shared.addrman).select_address(&tried) {
+                Some(socket_addr) => socket_addr,
+                None => break,
+            };
+            tried.insert(socket_addr);
+            match self.connect(socket_addr).await {
+                Ok(_) => connected += 1,
+                Err(e) => println!("p2p connect to {} failed: {}", socket_addr, e),
+            }
+        }
+        connected
+    }
+
+    async fn run_accept_loop(&self, listener: TcpListener) {
+        let mut shutdown = self.shared.shutdown.subscribe();
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => return,
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, socket_addr)) => {
+                        if self.get_peers_count() >= self.shared.config.max_peers {
+                            println!("p2p refusing {}: too many peers", socket_addr);
+                            continue;
+                        }
+                        let node = self.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = node.start_peer(stream, socket_addr, true).await {
+                                println!("p2p inbound peer {} failed: {}", socket_addr, e);
+                            }
+                        });
+                    }
+                    Err(e) => println!("p2p accept error: {}", e),
+                },
+            }
+        }
+    }
+
+    /// Announces the mainblocks connected and the transactions accepted by the maincore.
+    async fn run_event_relay(&self, mut events: broadcast::Receiver<MaincoreEvent>) {
+        let mut shutdown = self.shared.shutdown.subscribe();
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => return,
+                event = events.recv() => match event {
+                    Ok(MaincoreEvent::MainblockConnected { hash, .. }) => self.broadcast(P2pMessage::Inv(vec![P2pInventory::new_mainblock(hash)])),
+                    Ok(MaincoreEvent::MaintxAdded(hash)) => self.broadcast(P2pMessage::Inv(vec![P2pInventory::new_maintx(hash)])),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => println!("p2p event relay skipped {} events", skipped),
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+            }
+        }
+    }
+
+    /// Retries the mainblock requests of the syncpool that timed out and reports the sync progress.
+    async fn run_sync_driver(&self) {
+        let mut shutdown = self.shared.shutdown.subscribe();
+        let mut interval = tokio::time::interval(P2P_SYNC_TICK);
+        let mut last_report = Instant::now();
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => return,
+                _ = interval.tick() => {}
+            }
+            if let Err(e) = self.request_sync_mainblocks().await {
+                println!("p2p sync error: {}", e);
+            }
+            if last_report.elapsed() >= P2P_SYNC_REPORT_INTERVAL {
+                let mci = self.shared.maincore.lock().await;
+                if mci.get_syncpool().is_syncing() {
+                    let progress = mci.get_sync_progress();
+                    let eta = progress.eta.map(|eta| format!("{}s", eta.as_secs())).unwrap_or_else(|| String::from("unknown"));
+                    println!(
+                        "p2p sync {:.1}% ({}/{} mainblocks), eta {}",
+                        progress.percent, progress.mainblocks_count, progress.target_mainblocks_count, eta
+                    );
+                }
+                last_report = Instant::now();
+            }
+        }
+    }
+
+    /// Spreads the mainblocks the syncpool still needs over the connected peers.
+    async fn request_sync_mainblocks(&self) -> Result<(), P2pError> {
+        let mut mci = self.shared.maincore.lock().await;
+        if !mci.get_syncpool().is_syncing() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        if let Err(e) = mci.get_syncpool_mut().expire_requests(now) {
+            return Err(P2pError::MaincoreInnerError(e.to_string()));
+        }
+        let peer_ids: Vec<u64> = self.get_peers().iter().map(|peer| peer.peer_id).collect();
+        for peer_id in peer_ids.iter() {
+            let hashes = mci.get_syncpool_mut().request_mainblocks(*peer_id, peer_ids.len(), now);
+            if hashes.is_empty() {
+                continue;
+            }
+            let inventories = hashes.into_iter().map(P2pInventory::new_mainblock).collect();
+            if self.send_to_peer(*peer_id, P2pMessage::GetData(inventories)).is_err() {
+                mci.get_syncpool_mut().release_source(*peer_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Asks a peer for the mainheaders following our main chain.
+    async fn request_mainheaders(&self, peer_id: u64) -> Result<(), P2pError> {
+        let locator = self.shared.maincore.lock().await.get_mainheader_locator();
+        self.send_to_peer(peer_id, P2pMessage::GetHeaders { locator, stop_hash: Hash::new_empty() })
+    }
+
+    /// Runs the handshake on a new connection, registers the peer and spawns its reader and writer tasks.
+    async fn start_peer(&self, stream: TcpStream, socket_addr: SocketAddr, inbound: bool) -> Result<u64, P2pError> {
+        let (mut reader, mut writer) = stream.into_split();
+        let handshake = self.handshake(&mut reader, &mut writer);
+        let version = match tokio::time::timeout(self.shared.config.handshake_timeout, handshake).await {
+            Ok(result) => result?,
+            Err(_) => return Err(P2pError::Timeout(format!("handshake with {}", socket_addr))),
+        };
+
+        let peer_id = self.shared.next_peer_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::channel(self.shared.config.send_queue_size.max(1));
+        {
+            let mut peers = lock(&self.shared.peers);
+            if peers.values().any(|peer| peer.info.version.nonce == version.nonce) {
+                return Err(P2pError::HandshakeFailed(format!("already connected to node {}", version.nonce)));
+            }
+            if peers.len() >= self.shared.config.max_peers {
+                return Err(P2pError::HandshakeFailed(String::from("too many peers")));
+            }
+            let info = P2pPeerInfo { peer_id, socket_addr, inbound, version: version.clone() };
+            peers.insert(peer_id, P2pPeer { info, sender: sender.clone(), next_maintx_proofs_at: Instant::now() });
+        }
+        if inbound && version.listen_port != 0 {
+            // the address the peer accepts connections on
+            let listen_addr = SocketAddr::new(socket_addr.ip(), version.listen_port);
+            lock(&self.shared.addrman).add_address(&P2pAddress { socket_addr: listen_addr, last_seen: timestamp_now() });
+        }
+        println!("p2p peer {} connected to {} (inbound {}, height {})", peer_id, socket_addr, inbound, version.best_height);
+
+        tokio::spawn(run_peer_writer(writer, receiver, self.shared.magic));
+        let node = self.clone();
+        tokio::spawn(async move { node.run_peer_reader(peer_id, reader).await });
+
+        if !inbound {
+            let _ = sender.try_send(P2pMessage::GetAddr);
+        }
+        let locator = self.shared.maincore.lock().await.get_mainheader_locator();
+        let _ = sender.try_send(P2pMessage::GetHeaders { locator, stop_hash: Hash::new_empty() });
+        Ok(peer_id)
+    }
+
+    /// Both sides send their version and acknowledge the version of the other side.
+    async fn handshake(&self, reader: &mut OwnedReadHalf, writer: &mut OwnedWriteHalf) -> Result<P2pVersion, P2pError> {
+        let best_height = self.shared.maincore.lock().await.get_inmem_mainheaders_count() as u64;
+        let local_version = P2pVersion {
+            version: P2P_PROTOCOL_VERSION,
+            nonce: self.shared.local_nonce,
+            timestamp: timestamp_now(),
+            best_height,
+            listen_port: self.shared.listen_port.load(Ordering::Relaxed),
+        };
+        write_p2p_message(writer, self.shared.magic, &P2pMessage::Version(local_version)).await?;
+        let mut remote_version = None;
+        let mut verack_received = false;
+        while remote_version.is_none() || !verack_received {
+            match read_p2p_message(reader, self.shared.magic).await? {
+                P2pMessage::Version(version) => {
+                    if version.version < P2P_MIN_PROTOCOL_VERSION {
+                        return Err(P2pError::HandshakeFailed(format!("protocol version {} is too old", version.version)));
+                    }
+                    if version.nonce == self.shared.local_nonce {
+                        return Err(P2pError::HandshakeFailed(String::from("connected to self")));
+                    }
+                    write_p2p_message(writer, self.shared.magic, &P2pMessage::Verack).await?;
+                    remote_version = Some(version);
+                }
+                P2pMessage::Verack => verack_received = true,
+                message => {
+                    return Err(P2pError::HandshakeFailed(format!("unexpected {} message", message.get_command_name())));
+                }
+            }
+        }
+        remote_version.ok_or_else(|| P2pError::HandshakeFailed(String::from("no version")))
+    }
+
+    async fn run_peer_reader(&self, peer_id: u64, mut reader: OwnedReadHalf) {
+        let mut shutdown = self.shared.shutdown.subscribe();
+        loop {
+            let message = tokio::select! {
+                _ = shutdown.changed() => break,
+                message = read_p2p_message(&mut reader, self.shared.magic) => message,
+            };
+            let message = match message {
+                Ok(message) => message,
+                Err(e) => {
+                    println!("p2p peer {} read error: {}", peer_id, e);
+                    break;
+                }
+            };
+            if !lock(&self.shared.peers).contains_key(&peer_id) {
+                break;
+            }
+            if let Err(e) = self.handle_message(peer_id, message).await {
+                println!("p2p peer {} message error: {}", peer_id, e);
+                break;
+            }
+        }
+        lock(&self.shared.peers).remove(&peer_id);
+        self.shared.maincore.lock().await.get_syncpool_mut().release_source(peer_id);
+        println!("p2p peer {} disconnected", peer_id);
+    }
+
+    async fn handle_message(&self, peer_id: u64, message: P2pMessage) -> Result<(), P2pError> {
+        match message {
+            P2pMessage::Version(_) | P2pMessage::Verack => {}
+            P2pMessage::Ping(nonce) => self.send_to_peer(peer_id, P2pMessage::Pong(nonce))?,
+            P2pMessage::Pong(_) => {}
+            P2pMessage::Inv(inventories) => {
+                let (missing_maintxs, has_new_mainblock) = {
+                    let mci = self.shared.maincore.lock().await;
+                    let has_new_mainblock = inventories.iter().any(|inventory| {
+                        inventory.kind == P2pInventoryKind::Mainblock
+                            && !mci.has_mainheader(&inventory.hash)
+                            && !mci.get_syncpool().is_pending(&inventory.hash)
+                    });
+                    let missing_maintxs: Vec<P2pInventory> = inventories
+                        .into_iter()
+                        .filter(|inventory| inventory.kind == P2pInventoryKind::Maintx && !mci.get_txspool().contains(&inventory.hash))
+                        .collect();
+                    (missing_maintxs, has_new_mainblock)
+                };
+                // mainblocks are downloaded headers first, through the syncpool
+                if has_new_mainblock {
+                    self.request_mainheaders(peer_id).await?;
+                }
+                self.request_inventories(peer_id, missing_maintxs)?;
+            }
+            P2pMessage::GetData(inventories) => {
+                let mut not_found = Vec::new();
+                for inventory in inventories {
+                    let mut mci = self.shared.maincore.lock().await;
+                    let message = match inventory.kind {
+                        P2pInventoryKind::Mainblock => mci
+                            .get_mainblock_by_hash(&inventory.hash)
+                            .await
+                            .map_err(maincore_error)?
+                            .map(P2pMessage::Mainblock),
+                        P2pInventoryKind::Maintx => mci.get_txspool().get_maintx(&inventory.hash).cloned().map(P2pMessage::Maintx),
+                    };
+                    drop(mci);
+                    // the queue bounds what is read ahead of a peer that is slow to read its replies
+                    match message {
+                        Some(message) => self.send_to_peer_when_ready(peer_id, message).await?,
+                        None => not_found.push(inventory),
+                    }
+                }
+                if !not_found.is_empty() {
+                    self.send_to_peer_when_ready(peer_id, P2pMessage::NotFound(not_found)).await?;
+                }
+            }
+            P2pMessage::NotFound(inventories) => {
+                let mut mci = self.shared.maincore.lock().await;
+                let mut requested = lock(&self.shared.requested);
+                for inventory in inventories.iter() {
+                    requested.remove(&inventory.hash);
+                    if inventory.kind == P2pInventoryKind::Mainblock {
+                        mci.get_syncpool_mut().mark_not_found(peer_id, &inventory.hash);
+                    }
+                }
+            }
+            P2pMessage::GetHeaders { locator, stop_hash } => {
+                let mainheaders = self
+                    .shared
+                    .maincore
+                    .lock()
+                    .await
+                    .get_mainheaders_after_locator(&locator, &stop_hash, P2P_MAX_HEADERS_COUNT);
+                self.send_to_peer(peer_id, P2pMessage::Headers(mainheaders))?;
+            }
+            P2pMessage::Headers(mainheaders) => {
+                let (result, mut locator) = {
+                    let mut mci = self.shared.maincore.lock().await;
+                    (mci.add_sync_mainheaders(&mainheaders), mci.get_mainheader_locator())
+                };
+                match result {
+                    Ok(_) => {}
+                    Err(MaincoreInnerError::SyncpoolError(SyncpoolError::UnknownParent(_))) => {
+                        self.send_to_peer(peer_id, P2pMessage::GetHeaders { locator, stop_hash: Hash::new_empty() })?;
+                        return Ok(());
+                    }
+                    Err(e) => return Err(maincore_error(e)),
+                }
+                if mainheaders.len() >= P2P_MAX_HEADERS_COUNT {
+                    // the peer has more mainheaders, continue after the last one received
+                    if let Some(last) = mainheaders.last() {
+                        locator.insert(0, last.get_hash());
+                    }
+                    self.send_to_peer(peer_id, P2pMessage::GetHeaders { locator, stop_hash: Hash::new_empty() })?;
+                }
+                self.request_sync_mainblocks().await?;
+            }
+            P2pMessage::Mainblock(mb) => {
+                let mb_hash = mb.get_hash();
+                lock(&self.shared.requested).remove(&mb_hash);
+                let mut mci = self.shared.maincore.lock().await;
+                if mci.get_syncpool().is_pending(&mb_hash) {
+                    mci.get_syncpool_mut().add_mainblock(mb);
+                    let result = mci.connect_synced_mainblocks().await;
+                    drop(mci);
+                    if let Err(e) = result {
+                        println!("p2p peer {} sync failed on an invalid mainblock: {}", peer_id, e);
+                        return Err(maincore_error(e));
+                    }
+                    return self.request_sync_mainblocks().await;
+                }
+                match mci.add_confirmed_mainblock(mb).await {
+                    Ok(_) | Err(MaincoreInnerError::DuplicateMainblock(_)) => {}
+                    Err(MaincoreInnerError::OrphanMainblock(_)) => {
+                        let locator = mci.get_mainheader_locator();
+                        drop(mci);
+                        self.send_to_peer(peer_id, P2pMessage::GetHeaders { locator, stop_hash: Hash::new_empty() })?;
+                    }
+                    Err(e) => println!("p2p peer {} sent an invalid mainblock {:?}: {}", peer_id, mb_hash, e),
+                }
+            }
+            P2pMessage::Maintx(tx) => {
+                lock(&self.shared.requested).remove(&tx.compute_hash());
+                if let Err(e) = self.shared.maincore.lock().await.add_maintx(tx) {
+                    println!("p2p peer {} maintx rejected: {}", peer_id, e);
+                }
+            }
+            P2pMessage::GetAddr => {
+                let addresses = lock(&self.shared.addrman).get_addresses(P2P_MAX_ADDR_COUNT);
+                self.send_to_peer(peer_id, P2pMessage::Addr(addresses))?;
+            }
+            P2pMessage::Addr(addresses) => {
+                lock(&self.shared.addrman).add_addresses(&addresses);
+            }
+            P2pMessage::GetMaintxProofs { addresses, from_height } => {
+                // the messages of a peer are handled in order, waiting here delays only this peer
+                tokio::time::sleep(self.reserve_maintx_proofs_slot(peer_id)).await;
+                let (last_height, proofs) = self
+                    .shared
+                    .maincore
+                    .lock()
+                    .await
+                    .get_maintx_proofs(&addresses, from_height as usize, P2P_MAINTX_PROOFS_BATCH_COUNT)
+                    .await
+                    .map_err(maincore_error)?;
+                self.send_to_peer(peer_id, P2pMessage::MaintxProofs { last_height: last_height as u64, proofs })?;
+            }
+            // only light clients ask for maintx proofs
+            P2pMessage::MaintxProofs { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Reserves the next time `peer_id` may be served maintx proofs and returns how long to wait for it.
+    fn reserve_maintx_proofs_slot(&self, peer_id: u64) -> Duration {
+        let now = Instant::now();
+        let mut peers = lock(&self.shared.peers);
+        let peer = match peers.get_mut(&peer_id) {
+            Some(peer) => peer,
+            None => return Duration::ZERO,
+        };
+        let slot = peer.next_maintx_proofs_at.max(now);
+        peer.next_maintx_proofs_at = slot + self.shared.config.maintx_proofs_interval;
+        slot - now
+    }
+
+    /// Requests the inventories that are not already being downloaded from some peer.
+    fn request_inventories(&self, peer_id: u64, inventories: Vec<P2pInventory>) -> Result<(), P2pError> {
+        let now = Instant::now();
+        let to_request: Vec<P2pInventory> = {
+            let mut requested = lock(&self.shared.requested);
+            requested.retain(|_, requested_at| now.duration_since(*requested_at) < P2P_REQUEST_TIMEOUT);
+            inventories
+                .into_iter()
+                .filter(|inventory| requested.insert(inventory.hash.clone(), now).is_none())
+                .collect()
+        };
+        if to_request.is_empty() {
+            return Ok(());
+        }
+        self.send_to_peer(peer_id, P2pMessage::GetData(to_request))
+    }
+}
+
+async fn run_peer_writer(mut writer: OwnedWriteHalf, mut receiver: mpsc::Receiver<P2pMessage>, magic: u32) {
+    while let Some(message) = receiver.recv().await {
+        if let Err(e) = write_p2p_message(&mut writer, magic, &message).await {
+            println!("p2p write error: {}", e);
+            break;
+        }
+    }
+}
+
+fn maincore_error(e: MaincoreInnerError) -> P2pError {
+    P2pError::MaincoreInnerError(e.to_string())
+}
+
+/// Locks a mutex even if a thread panicked while holding it.
+fn lock<T>(mutex: &StdMutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
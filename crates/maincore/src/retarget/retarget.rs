@@ -0,0 +1,64 @@
+use num_bigint::BigUint;
+use thiserror::Error;
+use utility::hash::bigint;
+use crate::chain_params::chain_params::ChainParams;
+use crate::mainheader::mainheader::Mainheader;
+
+/// Number of previous mainheaders whose median timestamp a new mainheader must exceed.
+pub const MEDIAN_TIME_SPAN: usize = 11;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RetargetError {
+    #[error("Retarget window is empty")]
+    EmptyWindow,
+    #[error("Retarget window has {actual} mainheaders, {expected} are needed")]
+    WindowTooShort { expected: usize, actual: usize },
+}
+
+/// Computes the bits of the mainblock at `next_height`.
+/// `window` holds the mainheaders immediately preceding `next_height`, the last one being the tip;
+/// on a retarget height it must contain at least `retarget_interval` mainheaders.
+///
+/// The timespan of the last `retarget_interval` mainheaders is compared with the expected one,
+/// clamped to `max_adjustment_factor` in each direction, and the resulting target never exceeds
+/// the `pow_limit_bits` target.
+pub fn compute_next_bits(next_height: usize, window: &[Mainheader], chain_params: &ChainParams) -> Result<u32, RetargetError> {
+    let tip = window.last().ok_or(RetargetError::EmptyWindow)?;
+    let retarget_interval = chain_params.retarget_interval.max(2);
+    if chain_params.no_retargeting || next_height == 0 || !next_height.is_multiple_of(retarget_interval) {
+        return Ok(tip.get_bits());
+    }
+    if window.len() < retarget_interval {
+        return Err(RetargetError::WindowTooShort { expected: retarget_interval, actual: window.len() });
+    }
+    let first = &window[window.len() - retarget_interval];
+    let actual_timespan = tip.get_timestamp() - first.get_timestamp();
+    let expected_timespan = (retarget_interval as i64 - 1) * chain_params.target_spacing;
+    Ok(retarget_bits(tip.get_bits(), actual_timespan, expected_timespan, chain_params))
+}
+
+/// Scales the target of `bits` by `actual_timespan / expected_timespan` after clamping the timespan.
+pub fn retarget_bits(bits: u32, actual_timespan: i64, expected_timespan: i64, chain_params: &ChainParams) -> u32 {
+    let factor = chain_params.max_adjustment_factor.max(1) as i64;
+    let expected_timespan = expected_timespan.max(1);
+    let clamped_timespan = actual_timespan.clamp((expected_timespan / factor).max(1), expected_timespan * factor);
+
+    let target = bigint::bigint_from_compact(bits);
+    let new_target = target * BigUint::from(clamped_timespan as u64) / BigUint::from(expected_timespan as u64);
+    let pow_limit = bigint::bigint_from_compact(chain_params.pow_limit_bits);
+    if new_target > pow_limit {
+        return chain_params.pow_limit_bits;
+    }
+    bigint::compact_from_bigint(&new_target)
+}
+
+/// Median timestamp of the last `MEDIAN_TIME_SPAN` mainheaders of `headers`, `None` if there are none.
+pub fn get_median_time_past(headers: &[Mainheader]) -> Option<i64> {
+    let start = headers.len().saturating_sub(MEDIAN_TIME_SPAN);
+    let mut timestamps: Vec<i64> = headers[start..].iter().map(|mh| mh.get_timestamp()).collect();
+    if timestamps.is_empty() {
+        return None;
+    }
+    timestamps.sort_unstable();
+    Some(timestamps[timestamps.len() / 2])
+}
@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+use num_bigint::BigUint;
+use thiserror::Error;
+use utility::hash::bigint;
+use utility::hash::hash::Hash;
+use crate::chain_params::chain_params::ChainParams;
+use crate::mainblock::mainblock::Mainblock;
+use crate::mainblock::mainblock_validation::validate_mainheader;
+use crate::mainblock::mainblock_validation::MainblockValidationContext;
+use crate::mainblock::mainblock_validation::MainblockValidationError;
+use crate::mainheader::mainheader::Mainheader;
+use crate::retarget::retarget::compute_next_bits;
+use crate::retarget::retarget::get_median_time_past;
+use crate::retarget::retarget::RetargetError;
+use crate::retarget::retarget::MEDIAN_TIME_SPAN;
+
+/// Time a source has to deliver a requested mainblock before it is requested again.
+pub const SYNCPOOL_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Mainblocks requested from a single source at the same time.
+pub const SYNCPOOL_MAX_IN_FLIGHT_PER_SOURCE: usize = 16;
+/// Requests of a mainblock after which the sync is abandoned.
+pub const SYNCPOOL_MAX_ATTEMPTS: u32 = 5;
+/// Mainblocks ahead of the next one to connect that may be downloaded, bounds the memory used by the buffer.
+pub const SYNCPOOL_DOWNLOAD_WINDOW: usize = 1024;
+
+#[derive(Debug, Error)]
+pub enum SyncpoolError {
+    #[error("Mainblock validation error: {0}")]
+    MainblockValidationError(#[from] MainblockValidationError),
+    #[error("Retarget error: {0}")]
+    RetargetError(#[from] RetargetError),
+    #[error("Mainheader parent {0:?} is unknown")]
+    UnknownParent(Hash),
+    #[error("Mainblock {0:?} could not be downloaded")]
+    MainblockUnavailable(Hash),
+}
+
+/// Download state of a mainblock that has been requested at least once.
+struct SyncpoolDownload {
+    source_id: Option<u64>,
+    requested_at: Instant,
+    attempts: u32,
+    failed_sources: HashSet<u64>,
+}
+
+/// Mainheaders forking from the main chain or from the queued mainheaders, kept until their branch has
+/// more work than the synced one. A better branch may arrive over several batches of mainheaders.
+struct SyncpoolCandidate {
+    mainheaders: Vec<Mainheader>,
+    /// Last mainheaders of the candidate branch, used to validate the next ones.
+    window: Vec<Mainheader>,
+}
+
+/// Where the sync stands, the ETA is known once some mainblocks have been connected.
+#[derive(Debug, Clone)]
+pub struct SyncpoolProgress {
+    pub mainblocks_count: usize,
+    pub target_mainblocks_count: usize,
+    pub percent: f64,
+    pub eta: Option<Duration>,
+}
+
+/// Headers-first download of the mainblocks of a better chain.
+///
+/// Mainheaders are validated (proof of work, bits, timestamps) as soon as they are received and queued;
+/// their mainblocks are then requested from several sources, retried on timeout, buffered as they
+/// arrive in any order and handed out strictly in chain order to be connected.
+pub struct Syncpool {
+    /// Height of the first queued mainheader.
+    base_height: usize,
+    /// Validated mainheaders whose mainblocks have not been connected yet.
+    pending: VecDeque<Mainheader>,
+    pending_hashes: HashSet<Hash>,
+    /// Last mainheaders of the synced branch, up to the queue tail, used to validate the next ones.
+    window: Vec<Mainheader>,
+    downloads: HashMap<Hash, SyncpoolDownload>,
+    downloaded: HashMap<Hash, Mainblock>,
+    candidate: Option<SyncpoolCandidate>,
+    started_at: Option<Instant>,
+    connected_count: usize,
+}
+
+impl Default for Syncpool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Syncpool {
+    pub fn new() -> Self {
+        Self {
+            base_height: 0,
+            pending: VecDeque::new(),
+            pending_hashes: HashSet::new(),
+            window: Vec::new(),
+            downloads: HashMap::new(),
+            downloaded: HashMap::new(),
+            candidate: None,
+            started_at: None,
+            connected_count: 0,
+        }
+    }
+
+    pub fn is_syncing(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn get_pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn get_downloaded_count(&self) -> usize {
+        self.downloaded.len()
+    }
+
+    /// Number of mainblocks of the synced chain once every queued mainblock is connected.
+    pub fn get_target_mainblocks_count(&self) -> usize {
+        self.base_height + self.pending.len()
+    }
+
+    pub fn is_pending(&self, hash: &Hash) -> bool {
+        self.pending_hashes.contains(hash)
+    }
+
+    /// Forgets the queued mainheaders and the downloads.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.pending_hashes.clear();
+        self.window.clear();
+        self.downloads.clear();
+        self.downloaded.clear();
+        self.candidate = None;
+        self.started_at = None;
+        self.connected_count = 0;
+    }
+
+    /// Validates `mainheaders` and queues the ones that are not known yet. They must extend the queue tail,
+    /// or a main chain mainheader when the queue is empty. Mainheaders forking from the main chain or from
+    /// the queue build a candidate branch instead, which replaces the queued branch once it has more work.
+    /// Returns the number of mainheaders queued or added to the candidate branch.
+    pub fn add_mainheaders(
+        &mut self,
+        mainheaders: &[Mainheader],
+        main_mainheaders: &[Mainheader],
+        main_heights: &HashMap<Hash, usize>,
+        chain_params: &ChainParams,
+        now: i64,
+    ) -> Result<usize, SyncpoolError> {
+        let first_unknown = mainheaders.iter().position(|mh| {
+            let hash = mh.get_hash();
+            !main_heights.contains_key(&hash) && !self.pending_hashes.contains(&hash) && !self.is_candidate(&hash)
+        });
+        let new_mainheaders = match first_unknown {
+            Some(index) => &mainheaders[index..],
+            None => return Ok(0),
+        };
+        let prev_hash = new_mainheaders[0].get_prev_hash();
+        if self.pending.is_empty() {
+            let prev_height = *main_heights.get(&prev_hash).ok_or_else(|| SyncpoolError::UnknownParent(prev_hash.clone()))?;
+            self.start_branch(prev_height, main_mainheaders, chain_params);
+        } else if self.window.last().map(|mh| mh.get_hash()) != Some(prev_hash.clone()) {
+            return self.add_candidate_mainheaders(new_mainheaders, main_mainheaders, main_heights, chain_params, now);
+        }
+
+        let mut window = self.window.clone();
+        validate_mainheaders(new_mainheaders, self.get_target_mainblocks_count(), &mut window, chain_params, now)?;
+        self.window = window;
+        for mh in new_mainheaders.iter() {
+            self.pending_hashes.insert(mh.get_hash());
+            self.pending.push_back(mh.clone());
+        }
+        if self.started_at.is_none() {
+            self.started_at = Some(Instant::now());
+        }
+        Ok(new_mainheaders.len())
+    }
+
+    fn is_candidate(&self, hash: &Hash) -> bool {
+        self.candidate.as_ref().is_some_and(|candidate| candidate.mainheaders.iter().any(|mh| &mh.get_hash() == hash))
+    }
+
+    /// Height of a mainheader of the main chain or of the queue.
+    fn get_fork_height(&self, hash: &Hash, main_heights: &HashMap<Hash, usize>) -> Option<usize> {
+        match main_heights.get(hash) {
+            Some(height) => Some(*height),
+            None => self.pending.iter().position(|mh| &mh.get_hash() == hash).map(|index| self.base_height + index),
+        }
+    }
+
+    /// The mainheader at `height` of the synced branch, the main chain up to the queue and then the queue.
+    fn get_branch_mainheader<'a>(&'a self, height: usize, main_mainheaders: &'a [Mainheader]) -> &'a Mainheader {
+        if height < self.base_height {
+            &main_mainheaders[height]
+        } else {
+            &self.pending[height - self.base_height]
+        }
+    }
+
+    /// Extends the candidate branch, or starts a new one, with `mainheaders`, then switches to it if it has
+    /// more work than the synced branch after their fork.
+    fn add_candidate_mainheaders(
+        &mut self,
+        mainheaders: &[Mainheader],
+        main_mainheaders: &[Mainheader],
+        main_heights: &HashMap<Hash, usize>,
+        chain_params: &ChainParams,
+        now: i64,
+    ) -> Result<usize, SyncpoolError> {
+        let prev_hash = mainheaders[0].get_prev_hash();
+        let extends_candidate = self.candidate.as_ref().and_then(|candidate| candidate.mainheaders.last()).map(|mh| mh.get_hash()) == Some(prev_hash.clone());
+        let mut candidate = if extends_candidate {
+            self.candidate.take().unwrap()
+        } else {
+            let fork_height = self.get_fork_height(&prev_hash, main_heights).ok_or_else(|| SyncpoolError::UnknownParent(prev_hash.clone()))?;
+            let window_size = chain_params.retarget_interval.max(MEDIAN_TIME_SPAN);
+            let window = ((fork_height + 1).saturating_sub(window_size)..=fork_height)
+                .map(|height| self.get_branch_mainheader(height, main_mainheaders).clone())
+                .collect();
+            SyncpoolCandidate { mainheaders: Vec::new(), window }
+        };
+        let fork_hash = candidate.mainheaders.first().map(|mh| mh.get_prev_hash()).unwrap_or(prev_hash);
+        let fork_height = self.get_fork_height(&fork_hash, main_heights).ok_or_else(|| SyncpoolError::UnknownParent(fork_hash.clone()))?;
+        validate_mainheaders(mainheaders, fork_height + 1 + candidate.mainheaders.len(), &mut candidate.window, chain_params, now)?;
+        candidate.mainheaders.extend_from_slice(mainheaders);
+
+        let synced_work: BigUint = (fork_height + 1..self.get_target_mainblocks_count())
+            .map(|height| bigint::work_from_compact(self.get_branch_mainheader(height, main_mainheaders).get_bits()))
+            .sum();
+        let candidate_work: BigUint = candidate.mainheaders.iter().map(|mh| bigint::work_from_compact(mh.get_bits())).sum();
+        if candidate_work <= synced_work {
+            println!("syncpool candidate branch of {} mainheaders from height {} has less work", candidate.mainheaders.len(), fork_height);
+            self.candidate = Some(candidate);
+            return Ok(mainheaders.len());
+        }
+
+        println!("syncpool switching to the branch forking at height {}", fork_height);
+        if fork_height < self.base_height {
+            self.start_branch(fork_height, main_mainheaders, chain_params);
+        } else {
+            while self.get_target_mainblocks_count() > fork_height + 1 {
+                let hash = self.pending.pop_back().unwrap().get_hash();
+                self.pending_hashes.remove(&hash);
+                self.downloads.remove(&hash);
+                self.downloaded.remove(&hash);
+            }
+            self.candidate = None;
+        }
+        self.window = candidate.window;
+        for mh in candidate.mainheaders.into_iter() {
+            self.pending_hashes.insert(mh.get_hash());
+            self.pending.push_back(mh);
+        }
+        if self.started_at.is_none() {
+            self.started_at = Some(Instant::now());
+        }
+        Ok(mainheaders.len())
+    }
+
+    /// Starts a queue right after the main chain mainheader at `prev_height`.
+    fn start_branch(&mut self, prev_height: usize, main_mainheaders: &[Mainheader], chain_params: &ChainParams) {
+        self.reset();
+        let window_size = chain_params.retarget_interval.max(MEDIAN_TIME_SPAN);
+        let window_start = (prev_height + 1).saturating_sub(window_size);
+        self.window = main_mainheaders[window_start..=prev_height].to_vec();
+        self.base_height = prev_height + 1;
+    }
+
+    /// Releases the requests that timed out. Fails, and resets the sync, once a mainblock has been
+    /// requested `SYNCPOOL_MAX_ATTEMPTS` times.
+    pub fn expire_requests(&mut self, now: Instant) -> Result<usize, SyncpoolError> {
+        let mut expired_count = 0;
+        let mut unavailable = None;
+        for (hash, download) in self.downloads.iter_mut() {
+            let source_id = match download.source_id {
+                Some(source_id) => source_id,
+                None => continue,
+            };
+            if now.duration_since(download.requested_at) < SYNCPOOL_REQUEST_TIMEOUT {
+                continue;
+            }
+            download.source_id = None;
+            download.failed_sources.insert(source_id);
+            expired_count += 1;
+            if download.attempts >= SYNCPOOL_MAX_ATTEMPTS {
+                unavailable = Some(hash.clone());
+            }
+        }
+        if let Some(hash) = unavailable {
+            self.reset();
+            return Err(SyncpoolError::MainblockUnavailable(hash));
+        }
+        Ok(expired_count)
+    }
+
+    /// Picks the mainblocks to request from `source_id`, the earliest first. A mainblock a source failed
+    /// to deliver is only requested from it again when no other source is left to try.
+    pub fn request_mainblocks(&mut self, source_id: u64, sources_count: usize, now: Instant) -> Vec<Hash> {
+        let in_flight = self.downloads.values().filter(|download| download.source_id == Some(source_id)).count();
+        let mut available = SYNCPOOL_MAX_IN_FLIGHT_PER_SOURCE.saturating_sub(in_flight);
+        let mut requested = Vec::new();
+        for mh in self.pending.iter().take(SYNCPOOL_DOWNLOAD_WINDOW) {
+            if available == 0 {
+                break;
+            }
+            let hash = mh.get_hash();
+            if self.downloaded.contains_key(&hash) {
+                continue;
+            }
+            let download = self.downloads.entry(hash.clone()).or_insert(SyncpoolDownload {
+                source_id: None,
+                requested_at: now,
+                attempts: 0,
+                failed_sources: HashSet::new(),
+            });
+            if download.source_id.is_some() {
+                continue;
+            }
+            if download.failed_sources.contains(&source_id) && download.failed_sources.len() < sources_count {
+                continue;
+            }
+            download.source_id = Some(source_id);
+            download.requested_at = now;
+            download.attempts += 1;
+            requested.push(hash);
+            available -= 1;
+        }
+        requested
+    }
+
+    /// Buffers a downloaded mainblock. Returns `false` if it is not queued or already downloaded.
+    pub fn add_mainblock(&mut self, mb: Mainblock) -> bool {
+        let hash = mb.get_hash();
+        if !self.pending_hashes.contains(&hash) || self.downloaded.contains_key(&hash) {
+            return false;
+        }
+        self.downloads.remove(&hash);
+        self.downloaded.insert(hash, mb);
+        true
+    }
+
+    /// Records that `source_id` does not have a requested mainblock.
+    pub fn mark_not_found(&mut self, source_id: u64, hash: &Hash) {
+        if let Some(download) = self.downloads.get_mut(hash) {
+            if download.source_id == Some(source_id) {
+                download.source_id = None;
+            }
+            download.failed_sources.insert(source_id);
+        }
+    }
+
+    /// Releases the requests of a source that went away so that other sources can serve them.
+    pub fn release_source(&mut self, source_id: u64) {
+        for download in self.downloads.values_mut() {
+            if download.source_id == Some(source_id) {
+                download.source_id = None;
+                download.failed_sources.insert(source_id);
+            }
+        }
+    }
+
+    /// Returns the next mainblock to connect if it has been downloaded.
+    pub fn pop_next_mainblock(&mut self) -> Option<Mainblock> {
+        let hash = self.pending.front()?.get_hash();
+        let mb = self.downloaded.remove(&hash)?;
+        self.pending.pop_front();
+        self.pending_hashes.remove(&hash);
+        self.downloads.remove(&hash);
+        self.base_height += 1;
+        self.connected_count += 1;
+        if self.pending.is_empty() {
+            self.started_at = None;
+            self.connected_count = 0;
+        }
+        Some(mb)
+    }
+
+    /// Progress towards the target, `mainblocks_count` being the length of the main chain.
+    pub fn get_progress(&self, mainblocks_count: usize) -> SyncpoolProgress {
+        let target_mainblocks_count = if self.pending.is_empty() {
+            mainblocks_count
+        } else {
+            self.get_target_mainblocks_count().max(mainblocks_count)
+        };
+        let percent = if target_mainblocks_count == 0 {
+            100.0
+        } else {
+            mainblocks_count as f64 * 100.0 / target_mainblocks_count as f64
+        };
+        let eta = match self.started_at {
+            Some(started_at) if self.connected_count > 0 => {
+                let remaining = target_mainblocks_count - mainblocks_count;
+                let per_mainblock = started_at.elapsed().as_secs_f64() / self.connected_count as f64;
+                Some(Duration::from_secs_f64(per_mainblock * remaining as f64))
+            }
+            _ => None,
+        };
+        SyncpoolProgress { mainblocks_count, target_mainblocks_count, percent, eta }
+    }
+}
+
+/// Validates `mainheaders` as the mainheaders from `height` on top of `window`, which is kept to the last
+/// mainheaders needed to validate the next ones.
+fn validate_mainheaders(
+    mainheaders: &[Mainheader],
+    height: usize,
+    window: &mut Vec<Mainheader>,
+    chain_params: &ChainParams,
+    now: i64,
+) -> Result<(), SyncpoolError> {
+    let window_size = chain_params.retarget_interval.max(MEDIAN_TIME_SPAN);
+    for (offset, mh) in mainheaders.iter().enumerate() {
+        let height = height + offset;
+        let context = MainblockValidationContext {
+            height,
+            prev_mainheader: window.last().cloned(),
+            expected_bits: Some(compute_next_bits(height, window, chain_params)?),
+            median_time_past: get_median_time_past(window),
+            max_timestamp: Some(now + chain_params.max_future_block_time),
+        };
+        validate_mainheader(mh, &context)?;
+        window.push(mh.clone());
+        if window.len() > window_size {
+            window.remove(0);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use common::mine_on;
+use common::new_mainblock_on;
+use common::mine_tip;
+use common::open_maincore;
+use maincore::address_index::address_index::AddressIndex;
+use maintx::maintx::maintx::Maintx;
+use maintx::maintx_in::maintx_in::new_maintx_in_ecdsa;
+use maintx::maintx_out::maintx_out::new_ecdsa_maintx_out;
+use utility::ecdsa::ecdsa::derive_child_key_set;
+use utility::ecdsa::ecdsa::sign_messagehash;
+use utility::ecdsa::key_derivation_v1::derive_master_extended_secret_key;
+use utility::hash::hash::Hash;
+
+#[tokio::test]
+async fn test_address_index_history_and_balance() {
+    let dir = tempfile::tempdir().unwrap();
+    let keyset = derive_child_key_set(&derive_master_extended_secret_key("address index seed").unwrap(), 0, false).unwrap();
+    let address = keyset.get_address();
+    let recipient = Hash::compute_hash(b"recipient address");
+    let miner_address = Hash::compute_hash(b"miner address");
+    let mut mci = open_maincore(dir.path()).await;
+    mci.enable_address_index().await.unwrap();
+
+    let reward_maturity = mci.get_chain_params().reward_maturity;
+    for _ in 0..reward_maturity + 1 {
+        mine_tip(&mut mci, &address).await;
+    }
+    let (outpoint, output) = mci
+        .get_unspent_outputs(&address)
+        .into_iter()
+        .min_by_key(|(_, output)| output.mainblock_height)
+        .unwrap();
+    let mut tx = Maintx {
+        version: 1,
+        vin: vec![new_maintx_in_ecdsa(outpoint.hash.clone(), outpoint.index, keyset.get_public_key_compressed_bytes())],
+        vout: vec![new_ecdsa_maintx_out(output.value - 1000, recipient.clone())],
+    };
+    let signature = sign_messagehash(&keyset, tx.compute_hash()).unwrap();
+    tx.vin[0].set_signature(signature).unwrap();
+    let tx_hash = mci.add_maintx(tx).unwrap();
+    let unspent_count = mci.get_address_index().unwrap().get_unspent_count();
+    let spend_mb = mine_tip(&mut mci, &miner_address).await;
+    let spend_height = mci.get_mainblocks_count() as u32 - 1;
+
+    for tmp_address in [&address, &recipient, &miner_address] {
+        assert_eq!(mci.get_address_balance(tmp_address).unwrap(), mci.get_balance(tmp_address));
+    }
+    // the spent output is only kept in the history, the reward and the payment are new unspent outputs
+    assert_eq!(mci.get_address_index().unwrap().get_unspent_count(), unspent_count + 1);
+    let history = mci.get_address_history(&address).unwrap();
+    assert_eq!(history.len(), reward_maturity + 1);
+    let spent = history.iter().find(|entry| entry.tx_hash == outpoint.hash).unwrap();
+    assert_eq!(spent.spent_by.as_ref().map(|spend| (&spend.tx_hash, spend.mainblock_height)), Some((&tx_hash, spend_height)));
+    let received = mci.get_address_history(&recipient).unwrap();
+    assert_eq!(received.len(), 1);
+    assert_eq!((received[0].tx_hash.clone(), received[0].value), (tx_hash.clone(), output.value - 1000));
+
+    // a reorganization replacing the spending mainblock restores the spent output
+    let prev = mci.get_mainblock(spend_height as usize - 1).await.unwrap();
+    let fork1 = mine_on(&mut mci, &prev, spend_height as usize, &miner_address).await;
+    mine_on(&mut mci, &fork1, spend_height as usize + 1, &miner_address).await;
+    assert_ne!(mci.get_last_inmem_mainheader().unwrap().get_hash(), spend_mb.get_hash());
+    let history = mci.get_address_history(&address).unwrap();
+    assert!(history.iter().all(|entry| entry.is_unspent()));
+    assert!(mci.get_address_history(&recipient).unwrap().is_empty());
+    assert_eq!(mci.get_address_balance(&address).unwrap(), mci.get_balance(&address));
+    assert_eq!(mci.get_address_index().unwrap().get_unspent_count(), unspent_count + 2);
+    drop(mci);
+
+    // the stored records match an index rebuilt from the mainblocks
+    let mut mci = open_maincore(dir.path()).await;
+    mci.enable_address_index().await.unwrap();
+    let saved_history = mci.get_address_history(&address).unwrap();
+    let saved_balance = mci.get_address_balance(&miner_address).unwrap();
+    assert_eq!(mci.get_address_index().unwrap().get_unspent_count(), unspent_count + 2);
+    mci.reindex_address_index().await.unwrap();
+    assert_eq!(mci.get_address_history(&address).unwrap(), saved_history);
+    assert_eq!(mci.get_address_balance(&miner_address).unwrap(), saved_balance);
+    assert_eq!(mci.get_address_index().unwrap().get_unspent_count(), unspent_count + 2);
+}
+
+#[tokio::test]
+async fn test_address_index_rolls_back_records_left_by_a_crash() {
+    let dir = tempfile::tempdir().unwrap();
+    let address = Hash::compute_hash(b"address index address");
+    let mut mci = open_maincore(dir.path()).await;
+    mci.enable_address_index().await.unwrap();
+    mine_tip(&mut mci, &address).await;
+    let mb2 = mine_tip(&mut mci, &address).await;
+    let orphan = new_mainblock_on(&mci, &mb2, 3, &address);
+    let balance = mci.get_address_balance(&address).unwrap();
+    drop(mci);
+
+    // a record stored for a mainblock that a crash left out of the main chain
+    let mut address_index = AddressIndex::new(dir.path().join("AddressIndex")).await.unwrap();
+    address_index.load().await.unwrap();
+    address_index.connect_mainblock(&orphan).await.unwrap();
+    assert!(address_index.get_balance(&address) > balance);
+    drop(address_index);
+
+    let mut mci = open_maincore(dir.path()).await;
+    mci.enable_address_index().await.unwrap();
+    assert_eq!(mci.get_address_index().unwrap().get_tip_hash(), mb2.get_hash());
+    assert_eq!(mci.get_address_balance(&address).unwrap(), balance);
+    assert_eq!(mci.get_address_history(&address).unwrap().len(), 2);
+}
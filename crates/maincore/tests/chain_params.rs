@@ -0,0 +1,48 @@
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainblock::mainblock_validation::validate_mainblock;
+use maincore::mainblock::mainblock_validation::validate_mainheader;
+use maincore::mainblock::mainblock_validation::MainblockValidationContext;
+
+fn genesis_context(chain_params: &ChainParams) -> MainblockValidationContext {
+    MainblockValidationContext {
+        height: 0,
+        prev_mainheader: None,
+        expected_bits: Some(chain_params.genesis_bits),
+        median_time_past: None,
+        max_timestamp: None,
+    }
+}
+
+#[test]
+fn test_preset_genesis_mainblocks_are_valid() {
+    for chain_params in [ChainParams::mainnet(), ChainParams::testnet(), ChainParams::regtest()] {
+        let context = genesis_context(&chain_params);
+        let genesis_mainheader = chain_params.get_genesis_mainheader();
+        assert_eq!(genesis_mainheader.get_bits(), chain_params.genesis_bits);
+        assert!(validate_mainheader(&genesis_mainheader, &context).is_ok(), "{} genesis mainheader", chain_params.get_name());
+        assert!(validate_mainblock(&chain_params.get_genesis_mainblock(), &context).is_ok(), "{} genesis mainblock", chain_params.get_name());
+    }
+}
+
+#[test]
+fn test_preset_genesis_hashes_differ() {
+    let mainnet = ChainParams::mainnet().get_genesis_hash();
+    let testnet = ChainParams::testnet().get_genesis_hash();
+    let regtest = ChainParams::regtest().get_genesis_hash();
+    assert_ne!(mainnet, testnet);
+    assert_ne!(mainnet, regtest);
+    assert_ne!(testnet, regtest);
+}
+
+/// A change of these hashes is a change of the genesis mainblocks, and so of every chain.
+#[test]
+fn test_preset_genesis_hashes_are_pinned() {
+    let expected = [
+        (ChainParams::mainnet(), "604ff1a8e8442f06782be6d5c18905817367d49862037c16761f06a233030000"),
+        (ChainParams::testnet(), "3040c28bbc325baba969a7d25ae465b11960755bf89cb9a24deca6374b6c0800"),
+        (ChainParams::regtest(), "8800c48e7f3e65246cee7758fb6287da1676dd9e4fa19f1d4e541ef56f3c6f3c"),
+    ];
+    for (chain_params, genesis_hash) in expected {
+        assert_eq!(chain_params.get_genesis_hash().to_hex_string(), genesis_hash, "{} genesis hash", chain_params.get_name());
+    }
+}
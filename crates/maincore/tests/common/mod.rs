@@ -0,0 +1,49 @@
+//! Fixtures shared by the tests running a `MaincoreInner` on regtest.
+#![allow(dead_code)]
+
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainblock::mainblock::Mainblock;
+use maincore::maincore_inner::maincore_inner::MaincoreInner;
+use maincore::miner::miner::MainblockTemplate;
+use maincore::miner::mining_engine::MiningEngine;
+use maintx::maintx::maintx::new_reward_transaction;
+use std::path::Path;
+use utility::hash::hash::Hash;
+
+pub async fn open_maincore(path: &Path) -> MaincoreInner {
+    let mut mci = MaincoreInner::new(path, ChainParams::regtest()).await.unwrap();
+    mci.init().await.unwrap();
+    mci.load_mainheaders().await.unwrap();
+    mci
+}
+
+/// Mines the txspool on top of the tip.
+pub async fn mine_tip(mci: &mut MaincoreInner, reward_address: &Hash) -> Mainblock {
+    mci.get_miner_mut().set_reward_address(reward_address.clone());
+    let template = mci.build_mainblock_template().unwrap();
+    let mh = MiningEngine::new(1).mine(&template.get_candidate_mainheader()).unwrap().unwrap();
+    let mb = Mainblock::new(mh, template.transactions);
+    mci.add_confirmed_mainblock(mb.clone()).await.unwrap();
+    mb
+}
+
+/// Mines, without adding it, a mainblock at `height` holding only a reward transaction on top of `prev`.
+pub fn new_mainblock_on(mci: &MaincoreInner, prev: &Mainblock, height: usize, reward_address: &Hash) -> Mainblock {
+    let reward_value = mci.get_chain_params().get_mainblock_subsidy(height);
+    let template = MainblockTemplate::new(
+        height,
+        prev.get_hash(),
+        prev.header.get_timestamp() + 1,
+        prev.header.get_bits(),
+        vec![new_reward_transaction(height as u32, reward_value, 0, reward_address.clone())],
+    );
+    let mh = MiningEngine::new(1).mine(&template.get_candidate_mainheader()).unwrap().unwrap();
+    Mainblock::new(mh, template.transactions)
+}
+
+/// Mines a mainblock at `height` holding only a reward transaction on top of `prev` and adds it.
+pub async fn mine_on(mci: &mut MaincoreInner, prev: &Mainblock, height: usize, reward_address: &Hash) -> Mainblock {
+    let mb = new_mainblock_on(mci, prev, height, reward_address);
+    mci.add_confirmed_mainblock(mb.clone()).await.unwrap();
+    mb
+}
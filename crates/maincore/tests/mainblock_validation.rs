@@ -0,0 +1,155 @@
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainblock::mainblock::Mainblock;
+use maincore::mainblock::mainblock_validation::compute_mainblock_root_hash;
+use maincore::mainblock::mainblock_validation::validate_mainblock;
+use maincore::mainblock::mainblock_validation::validate_reward_value;
+use maincore::mainblock::mainblock_validation::MainblockValidationContext;
+use maincore::mainblock::mainblock_validation::MainblockValidationError;
+use maincore::mainheader::mainheader::mine_mainheader_with_cpu;
+use maincore::mainstate::mainstate::Mainstate;
+use maincore::mainstate::mainstate::MainstateError;
+use maintx::maintx::maintx::new_reward_transaction;
+use maintx::maintx::maintx::Maintx;
+use maintx::maintx_in::maintx_in::new_maintx_in_ecdsa;
+use maintx::maintx_out::maintx_out::new_ecdsa_maintx_out;
+use utility::ecdsa::ecdsa::derive_child_key_set;
+use utility::ecdsa::ecdsa::sign_messagehash;
+use utility::ecdsa::ecdsa::EcdsaKeySet;
+use utility::ecdsa::key_derivation_v1::derive_master_extended_secret_key;
+use utility::hash::hash::Hash;
+
+const REWARD_VALUE: u64 = 5_000;
+
+fn test_keyset() -> EcdsaKeySet {
+    derive_child_key_set(&derive_master_extended_secret_key("mainblock validation seed").unwrap(), 0, false).unwrap()
+}
+
+/// Context of the mainblock following the regtest genesis mainblock.
+fn next_context() -> MainblockValidationContext {
+    MainblockValidationContext {
+        height: 1,
+        prev_mainheader: Some(ChainParams::regtest().get_genesis_mainheader()),
+        expected_bits: None,
+        median_time_past: None,
+        max_timestamp: None,
+    }
+}
+
+fn mine_with_root_hash(transactions: Vec<Maintx>, root_hash: Hash) -> Mainblock {
+    let genesis_mainheader = ChainParams::regtest().get_genesis_mainheader();
+    let mh = mine_mainheader_with_cpu(1, genesis_mainheader.get_hash(), root_hash, genesis_mainheader.get_timestamp() + 1, 0x207fffff).unwrap();
+    Mainblock::new(mh, transactions)
+}
+
+fn mine(transactions: Vec<Maintx>) -> Mainblock {
+    let root_hash = compute_mainblock_root_hash(&transactions);
+    mine_with_root_hash(transactions, root_hash)
+}
+
+fn reward(height: u32) -> Maintx {
+    new_reward_transaction(height, REWARD_VALUE, 0, test_keyset().get_address())
+}
+
+/// A maintx spending the outputs `outpoints` of the test keyset, signed unless `signed` is false.
+fn spend(outpoints: &[(Hash, u32)], value: u64, signed: bool) -> Maintx {
+    let keyset = test_keyset();
+    let mut tx = Maintx {
+        version: 1,
+        vin: outpoints
+            .iter()
+            .map(|(hash, index)| new_maintx_in_ecdsa(hash.clone(), *index, keyset.get_public_key_compressed_bytes()))
+            .collect(),
+        vout: vec![new_ecdsa_maintx_out(value, Hash::compute_hash(b"recipient"))],
+    };
+    let signature = sign_messagehash(&keyset, tx.compute_hash()).unwrap();
+    for vin in tx.vin.iter_mut() {
+        vin.set_signature(if signed { signature.clone() } else { vec![0u8; signature.len()] }).unwrap();
+    }
+    tx
+}
+
+#[test]
+fn test_valid_mainblock() {
+    let mb = mine(vec![reward(1), spend(&[(Hash::compute_hash(b"funding"), 0)], 1000, true)]);
+    assert!(validate_mainblock(&mb, &next_context()).is_ok());
+}
+
+#[test]
+fn test_signed_maintx_verifies() {
+    // regression: check_signature used to reject every signature
+    let tx = spend(&[(Hash::compute_hash(b"funding"), 0), (Hash::compute_hash(b"funding"), 1)], 1000, true);
+    assert!(tx.verify_signatures());
+    let mut tampered = tx.clone();
+    tampered.vout[0] = new_ecdsa_maintx_out(2000, Hash::compute_hash(b"recipient"));
+    assert!(!tampered.verify_signatures());
+}
+
+#[test]
+fn test_invalid_signature() {
+    let mb = mine(vec![reward(1), spend(&[(Hash::compute_hash(b"funding"), 0)], 1000, false)]);
+    assert!(matches!(validate_mainblock(&mb, &next_context()), Err(MainblockValidationError::InvalidSignature(1))));
+}
+
+#[test]
+fn test_root_hash() {
+    let transactions = vec![reward(1), spend(&[(Hash::compute_hash(b"funding"), 0)], 1000, true)];
+    let mb = mine_with_root_hash(transactions, Hash::compute_hash(b"not the root"));
+    assert!(matches!(validate_mainblock(&mb, &next_context()), Err(MainblockValidationError::InvalidRootHash { .. })));
+
+    // [reward, a, b, b] has the root of [reward, a, b]
+    let a = spend(&[(Hash::compute_hash(b"funding"), 0)], 1000, true);
+    let b = spend(&[(Hash::compute_hash(b"funding"), 1)], 1000, true);
+    let root_hash = compute_mainblock_root_hash(&[reward(1), a.clone(), b.clone()]);
+    let mb = mine_with_root_hash(vec![reward(1), a, b.clone(), b], root_hash);
+    assert!(matches!(validate_mainblock(&mb, &next_context()), Err(MainblockValidationError::MutatedRootHash(_))));
+}
+
+#[test]
+fn test_reward_placement() {
+    let mb = mine(Vec::new());
+    assert!(matches!(validate_mainblock(&mb, &next_context()), Err(MainblockValidationError::NoTransactions)));
+
+    // the reward transaction must come first
+    let mb = mine(vec![spend(&[(Hash::compute_hash(b"funding"), 0)], 1000, true), reward(1)]);
+    assert!(matches!(validate_mainblock(&mb, &next_context()), Err(MainblockValidationError::InvalidRewardTransaction(_))));
+
+    // and only once
+    let mb = mine(vec![reward(1), new_reward_transaction(1, 1, 0, Hash::compute_hash(b"second reward"))]);
+    assert!(matches!(validate_mainblock(&mb, &next_context()), Err(MainblockValidationError::UnexpectedRewardInput(1))));
+
+    let mb = mine(vec![reward(2)]);
+    assert!(matches!(validate_mainblock(&mb, &next_context()), Err(MainblockValidationError::InvalidRewardHeight { expected: 1, actual: 2 })));
+}
+
+#[test]
+fn test_reward_value() {
+    let mb = mine(vec![new_reward_transaction(1, REWARD_VALUE, 300, test_keyset().get_address())]);
+    assert!(validate_reward_value(&mb, REWARD_VALUE, 300).is_ok());
+    assert!(matches!(
+        validate_reward_value(&mb, REWARD_VALUE, 299),
+        Err(MainblockValidationError::InvalidRewardValue { expected, actual }) if expected == REWARD_VALUE + 299 && actual == REWARD_VALUE + 300
+    ));
+    assert!(matches!(validate_reward_value(&mb, u64::MAX, 1), Err(MainblockValidationError::RewardValueOverflow)));
+}
+
+#[test]
+fn test_duplicate_inputs() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mainstate = Mainstate::new(dir.path().join("Mainstate"));
+    mainstate.connect_mainblock(&ChainParams::regtest().get_genesis_mainblock()).unwrap();
+    let funding = mine(vec![reward(1)]);
+    mainstate.connect_mainblock(&funding).unwrap();
+    let funding_hash = funding.transactions[0].compute_hash();
+
+    // the same output twice in one maintx
+    let mb = mine(vec![reward(2), spend(&[(funding_hash.clone(), 0), (funding_hash.clone(), 0)], 1000, true)]);
+    assert!(matches!(
+        validate_mainblock(&mb, &MainblockValidationContext { height: 2, ..next_context() }),
+        Err(MainblockValidationError::DuplicateInput(1))
+    ));
+    assert!(matches!(mainstate.check_mainblock(&mb), Err(MainstateError::DoubleSpend { index: 0, .. })));
+
+    // the same output in two maintxs of the mainblock
+    let mb = mine(vec![reward(2), spend(&[(funding_hash.clone(), 0)], 1000, true), spend(&[(funding_hash, 0)], 900, true)]);
+    assert!(matches!(mainstate.check_mainblock(&mb), Err(MainstateError::DoubleSpend { index: 0, .. })));
+}
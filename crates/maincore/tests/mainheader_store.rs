@@ -0,0 +1,121 @@
+mod common;
+
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use common::mine_tip;
+use common::open_maincore;
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainheader::mainheader::mine_mainheader_with_cpu;
+use maincore::mainheader::mainheader::Mainheader;
+use maincore::mainheader::mainheader_store::MainheaderStore;
+use maincore::mainheader::mainheader_store::MAINHEADER_RECORD_SIZE;
+use utility::hash::hash::Hash;
+
+/// `count` mainheaders on top of `prev`.
+fn mine_mainheaders(prev: &Mainheader, count: usize) -> Vec<Mainheader> {
+    let mut mainheaders: Vec<Mainheader> = Vec::new();
+    for i in 0..count {
+        let prev = mainheaders.last().unwrap_or(prev);
+        let mh = mine_mainheader_with_cpu(1, prev.get_hash(), Hash::compute_hash(&[i as u8]), prev.get_timestamp() + 1, prev.get_bits()).unwrap();
+        mainheaders.push(mh);
+    }
+    mainheaders
+}
+
+fn get_hashes(mainheaders: &[Mainheader]) -> Vec<Hash> {
+    mainheaders.iter().map(|mh| mh.get_hash()).collect()
+}
+
+#[tokio::test]
+async fn test_mainheader_store_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("Mainheaders.dat");
+    let mainheaders = mine_mainheaders(&ChainParams::regtest().get_genesis_mainheader(), 5);
+
+    let mut store = MainheaderStore::new(&path);
+    assert!(store.load().await.unwrap().is_empty());
+    store.append(&mainheaders[0]).await.unwrap();
+    store.append_all(&mainheaders[1..]).await.unwrap();
+    assert_eq!(store.get_count(), 5);
+    assert_eq!(fs::metadata(&path).unwrap().len(), (5 * MAINHEADER_RECORD_SIZE) as u64);
+
+    let mut loaded_store = MainheaderStore::new(&path);
+    assert_eq!(get_hashes(&loaded_store.load().await.unwrap()), get_hashes(&mainheaders));
+    assert_eq!(loaded_store.get_count(), 5);
+
+    loaded_store.truncate(3).await.unwrap();
+    assert_eq!(get_hashes(&MainheaderStore::new(&path).load().await.unwrap()), get_hashes(&mainheaders[..3]));
+}
+
+#[tokio::test]
+async fn test_mainheader_store_truncates_corrupted_tail() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("Mainheaders.dat");
+    let mainheaders = mine_mainheaders(&ChainParams::regtest().get_genesis_mainheader(), 4);
+    let mut store = MainheaderStore::new(&path);
+    store.append_all(&mainheaders[..3]).await.unwrap();
+
+    // a record cut by a crash in the middle of an append
+    let partial_record = &fs::read(&path).unwrap()[..MAINHEADER_RECORD_SIZE / 2];
+    OpenOptions::new().append(true).open(&path).unwrap().write_all(partial_record).unwrap();
+    let mut store = MainheaderStore::new(&path);
+    assert_eq!(get_hashes(&store.load().await.unwrap()), get_hashes(&mainheaders[..3]));
+    assert_eq!(fs::metadata(&path).unwrap().len(), (3 * MAINHEADER_RECORD_SIZE) as u64);
+
+    // a last record failing its checksum
+    let mut rawbytes = fs::read(&path).unwrap();
+    rawbytes[2 * MAINHEADER_RECORD_SIZE + 10] ^= 1;
+    fs::write(&path, &rawbytes).unwrap();
+    let mut store = MainheaderStore::new(&path);
+    assert_eq!(get_hashes(&store.load().await.unwrap()), get_hashes(&mainheaders[..2]));
+    assert_eq!(fs::metadata(&path).unwrap().len(), (2 * MAINHEADER_RECORD_SIZE) as u64);
+
+    // the store goes on after the cut
+    store.append_all(&mainheaders[2..]).await.unwrap();
+    assert_eq!(get_hashes(&MainheaderStore::new(&path).load().await.unwrap()), get_hashes(&mainheaders));
+}
+
+#[tokio::test]
+async fn test_load_mainheaders_repairs_the_store() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("Mainheaders.dat");
+    let miner_address = Hash::compute_hash(b"miner address");
+    let mut mci = open_maincore(dir.path()).await;
+    for _ in 0..3 {
+        mine_tip(&mut mci, &miner_address).await;
+    }
+    let tip_hash = mci.get_last_inmem_mainheader().unwrap().get_hash();
+    let mainheaders: Vec<Mainheader> = (0..4).map(|height| mci.get_inmem_mainheader(height).unwrap()).collect();
+    drop(mci);
+    assert_eq!(fs::metadata(&path).unwrap().len(), (4 * MAINHEADER_RECORD_SIZE) as u64);
+
+    // a store behind the mainblocks gets the missing mainheaders from the mainblocks
+    let mut store = MainheaderStore::new(&path);
+    store.load().await.unwrap();
+    store.truncate(1).await.unwrap();
+    let mci = open_maincore(dir.path()).await;
+    assert_eq!(mci.get_last_inmem_mainheader().unwrap().get_hash(), tip_hash);
+    drop(mci);
+    assert_eq!(get_hashes(&MainheaderStore::new(&path).load().await.unwrap()), get_hashes(&mainheaders));
+
+    // a store ahead of the mainblocks, e.g. after a crash before a mainblock was written, is cut back
+    let mut store = MainheaderStore::new(&path);
+    store.load().await.unwrap();
+    store.append_all(&mine_mainheaders(&mainheaders[3], 2)).await.unwrap();
+    let mci = open_maincore(dir.path()).await;
+    assert_eq!(mci.get_mainblocks_count(), 4);
+    assert_eq!(mci.get_last_inmem_mainheader().unwrap().get_hash(), tip_hash);
+    drop(mci);
+    assert_eq!(get_hashes(&MainheaderStore::new(&path).load().await.unwrap()), get_hashes(&mainheaders));
+
+    // a stored mainheader not chained to the previous one is read back from its mainblock
+    let mut store = MainheaderStore::new(&path);
+    store.load().await.unwrap();
+    store.truncate(2).await.unwrap();
+    store.append_all(&mine_mainheaders(&mainheaders[0], 2)).await.unwrap();
+    let mci = open_maincore(dir.path()).await;
+    assert_eq!(mci.get_last_inmem_mainheader().unwrap().get_hash(), tip_hash);
+    drop(mci);
+    assert_eq!(get_hashes(&MainheaderStore::new(&path).load().await.unwrap()), get_hashes(&mainheaders));
+}
@@ -0,0 +1,279 @@
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainblock::mainblock::Mainblock;
+use maincore::mainblock::mainblock_validation::compute_mainblock_root_hash;
+use maincore::mainheader::mainheader::mine_mainheader_with_cpu;
+use maincore::mainstate::mainstate::Mainstate;
+use maincore::mainstate::mainstate::MainstateError;
+use maintx::maintx::maintx::new_reward_transaction;
+use maintx::maintx::maintx::Maintx;
+use maintx::maintx_in::maintx_in::new_maintx_in_ecdsa;
+use maintx::maintx_out::maintx_out::new_ecdsa_maintx_out;
+use utility::ecdsa::ecdsa::derive_child_key_set;
+use utility::ecdsa::ecdsa::sign_messagehash;
+use utility::ecdsa::ecdsa::EcdsaKeySet;
+use utility::ecdsa::key_derivation_v1::derive_master_extended_secret_key;
+use utility::hash::hash::Hash;
+use utility::storage::chunk_store::ChunkStore;
+
+const REWARD_VALUE: u64 = 10_000;
+const UNDO_DEPTH: usize = 3;
+
+fn test_keyset() -> EcdsaKeySet {
+    derive_child_key_set(&derive_master_extended_secret_key("mainstate test seed").unwrap(), 0, false).unwrap()
+}
+
+fn new_mainstate(dir: &tempfile::TempDir) -> Mainstate {
+    let mut mainstate = Mainstate::new(dir.path().join("Mainstate"));
+    mainstate.set_undo_depth(UNDO_DEPTH);
+    mainstate
+}
+
+/// A mainblock at `height` whose reward pays the test keyset, followed by `transactions`.
+fn new_mainblock(height: u32, prev_hash: &Hash, transactions: Vec<Maintx>) -> Mainblock {
+    let mut all_transactions = vec![new_reward_transaction(height, REWARD_VALUE, 0, test_keyset().get_address())];
+    all_transactions.extend(transactions);
+    let root_hash = compute_mainblock_root_hash(&all_transactions);
+    let mh = mine_mainheader_with_cpu(1, prev_hash.clone(), root_hash, 1_000_000 + height as i64, 0x207fffff).unwrap();
+    Mainblock::new(mh, all_transactions)
+}
+
+/// A maintx spending the reward of `mb` to `recipient`.
+fn spend_reward(mb: &Mainblock, value: u64, recipient: &Hash) -> Maintx {
+    let keyset = test_keyset();
+    let mut tx = Maintx {
+        version: 1,
+        vin: vec![new_maintx_in_ecdsa(mb.transactions[0].compute_hash(), 0, keyset.get_public_key_compressed_bytes())],
+        vout: vec![new_ecdsa_maintx_out(value, recipient.clone())],
+    };
+    let signature = sign_messagehash(&keyset, tx.compute_hash()).unwrap();
+    tx.vin[0].set_signature(signature).unwrap();
+    tx
+}
+
+/// Connects the genesis mainblock and `count` mainblocks paying their reward to the test keyset.
+fn connect_chain(mainstate: &mut Mainstate, count: u32) -> Vec<Mainblock> {
+    let mut mainblocks = vec![ChainParams::regtest().get_genesis_mainblock()];
+    mainstate.connect_mainblock(&mainblocks[0]).unwrap();
+    for height in 1..=count {
+        let mb = new_mainblock(height, &mainblocks.last().unwrap().get_hash(), Vec::new());
+        mainstate.connect_mainblock(&mb).unwrap();
+        mainblocks.push(mb);
+    }
+    mainblocks
+}
+
+#[test]
+fn test_connect_and_disconnect() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mainstate = new_mainstate(&dir);
+    let mainblocks = connect_chain(&mut mainstate, 1);
+    let address = test_keyset().get_address();
+    let recipient = Hash::compute_hash(b"recipient");
+    let unspent_before = mainstate.get_outputs_by_address(&address);
+    assert_eq!(mainstate.get_balance(&address), REWARD_VALUE);
+
+    let tx = spend_reward(&mainblocks[1], REWARD_VALUE - 100, &recipient);
+    let mb = new_mainblock(2, &mainblocks[1].get_hash(), vec![tx.clone()]);
+    assert_eq!(mainstate.check_mainblock(&mb).unwrap(), 100);
+    mainstate.connect_mainblock(&mb).unwrap();
+    assert_eq!(mainstate.get_mainblocks_count(), 3);
+    assert!(!mainstate.is_unspent(&mainblocks[1].transactions[0].compute_hash(), 0));
+    assert_eq!(mainstate.get_balance(&recipient), REWARD_VALUE - 100);
+    assert_eq!(mainstate.get_balance(&address), REWARD_VALUE);
+    // the same mainblock cannot be connected twice
+    assert!(matches!(mainstate.check_mainblock(&mb), Err(MainstateError::DuplicateMaintx(_))));
+
+    mainstate.disconnect_mainblock(&mb).unwrap();
+    assert_eq!(mainstate.get_mainblocks_count(), 2);
+    assert_eq!(mainstate.get_balance(&recipient), 0);
+    assert_eq!(mainstate.get_outputs_by_address(&address), unspent_before);
+    assert!(mainstate.check_maintx(&tx).is_ok());
+}
+
+#[test]
+fn test_undo_depth() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mainstate = new_mainstate(&dir);
+    let mainblocks = connect_chain(&mut mainstate, 5);
+    assert_eq!(mainstate.get_undo_count(), UNDO_DEPTH);
+
+    // only the last connected mainblock can be disconnected
+    assert!(matches!(mainstate.disconnect_mainblock(&mainblocks[4]), Err(MainstateError::UndoMismatch { .. })));
+    for mb in mainblocks.iter().rev().take(UNDO_DEPTH) {
+        mainstate.disconnect_mainblock(mb).unwrap();
+    }
+    assert_eq!(mainstate.get_undo_count(), 0);
+    assert!(matches!(mainstate.disconnect_mainblock(&mainblocks[2]), Err(MainstateError::UndoUnavailable(_))));
+    assert_eq!(mainstate.get_mainblocks_count(), 3);
+    assert_eq!(mainstate.get_balance(&test_keyset().get_address()), 2 * REWARD_VALUE);
+
+    // lowering the undo depth drops the oldest undo data
+    let mb = new_mainblock(3, &mainblocks[2].get_hash(), Vec::new());
+    mainstate.connect_mainblock(&mb).unwrap();
+    mainstate.set_undo_depth(0);
+    assert!(matches!(mainstate.disconnect_mainblock(&mb), Err(MainstateError::UndoUnavailable(_))));
+}
+
+#[test]
+fn test_reward_maturity() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mainstate = new_mainstate(&dir);
+    mainstate.set_reward_maturity(2);
+    let mut mainblocks = connect_chain(&mut mainstate, 1);
+    let tx = spend_reward(&mainblocks[1], REWARD_VALUE, &Hash::compute_hash(b"recipient"));
+    assert!(matches!(mainstate.check_maintx(&tx), Err(MainstateError::ImmatureReward { mature_height: 3, .. })));
+    let mb = new_mainblock(2, &mainblocks[1].get_hash(), Vec::new());
+    mainstate.connect_mainblock(&mb).unwrap();
+    mainblocks.push(mb);
+    assert!(mainstate.check_maintx(&tx).is_ok());
+}
+
+#[tokio::test]
+async fn test_save_and_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mainstate = new_mainstate(&dir);
+    let mainblocks = connect_chain(&mut mainstate, 4);
+    mainstate.save().await.unwrap();
+    let saved = mainstate.serialize();
+
+    let mut loaded = new_mainstate(&dir);
+    loaded.load().await.unwrap();
+    assert_eq!(loaded.serialize(), saved);
+    // the undo data survives the reload
+    loaded.disconnect_mainblock(&mainblocks[4]).unwrap();
+    assert_eq!(loaded.get_mainblocks_count(), 4);
+
+    // nothing but the checkpoint and the records is left once saved
+    let mut files: Vec<String> = std::fs::read_dir(dir.path().join("Mainstate")).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().to_string()).collect();
+    files.sort();
+    assert_eq!(files, vec![String::from("Checkpoint.dat"), String::from("MainstateIndex.dat"), String::from("MainstateSegment0.dat")]);
+}
+
+#[tokio::test]
+async fn test_interrupted_save() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mainstate = new_mainstate(&dir);
+    connect_chain(&mut mainstate, 2);
+    mainstate.save().await.unwrap();
+    let saved = mainstate.serialize();
+
+    // a save interrupted before its rename leaves the previous mainstate readable
+    let leftover_path = dir.path().join("Mainstate").join("Checkpoint.dat.tmp");
+    std::fs::write(&leftover_path, &saved[..saved.len() / 2]).unwrap();
+    let mut loaded = new_mainstate(&dir);
+    loaded.load().await.unwrap();
+    assert_eq!(loaded.serialize(), saved);
+
+    // the next checkpoint replaces the leftover
+    mainstate.reset();
+    connect_chain(&mut mainstate, 2);
+    mainstate.save().await.unwrap();
+    assert!(!leftover_path.exists());
+}
+
+#[tokio::test]
+async fn test_records_replayed_on_the_checkpoint() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mainstate = new_mainstate(&dir);
+    mainstate.set_checkpoint_interval(4);
+    let mut mainblocks = connect_chain(&mut mainstate, 1);
+    mainstate.save().await.unwrap();
+    assert_eq!(mainstate.get_checkpoint_count(), Some(2));
+    let checkpoint = std::fs::read(dir.path().join("Mainstate").join("Checkpoint.dat")).unwrap();
+
+    // the mainblocks connected before the interval is reached are only stored as records
+    let recipient = Hash::compute_hash(b"recipient");
+    for height in 2..5 {
+        let tx = spend_reward(&mainblocks[height - 1], REWARD_VALUE - 100, &recipient);
+        let mb = new_mainblock(height as u32, &mainblocks.last().unwrap().get_hash(), vec![tx]);
+        mainstate.connect_mainblock(&mb).unwrap();
+        mainblocks.push(mb);
+        mainstate.save().await.unwrap();
+    }
+    assert_eq!(mainstate.get_checkpoint_count(), Some(2));
+    assert_eq!(std::fs::read(dir.path().join("Mainstate").join("Checkpoint.dat")).unwrap(), checkpoint);
+
+    let mut loaded = new_mainstate(&dir);
+    loaded.load().await.unwrap();
+    assert_eq!(loaded.serialize(), mainstate.serialize());
+    assert_eq!(loaded.get_balance(&recipient), 3 * (REWARD_VALUE - 100));
+    for height in (2..5).rev() {
+        loaded.disconnect_mainblock(&mainblocks[height]).unwrap();
+    }
+    assert_eq!(loaded.get_balance(&recipient), 0);
+
+    // a checkpoint is written once the interval is reached
+    let mb = new_mainblock(5, &mainblocks.last().unwrap().get_hash(), Vec::new());
+    mainstate.connect_mainblock(&mb).unwrap();
+    mainblocks.push(mb);
+    mainstate.save().await.unwrap();
+    assert_eq!(mainstate.get_checkpoint_count(), Some(6));
+    let mut loaded = new_mainstate(&dir);
+    loaded.load().await.unwrap();
+    assert_eq!(loaded.serialize(), mainstate.serialize());
+}
+
+#[tokio::test]
+async fn test_disconnect_below_the_checkpoint() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mainstate = new_mainstate(&dir);
+    mainstate.set_checkpoint_interval(4);
+    let mainblocks = connect_chain(&mut mainstate, 4);
+    mainstate.save().await.unwrap();
+    assert_eq!(mainstate.get_checkpoint_count(), Some(5));
+
+    // the disconnected mainblocks drop their records and the checkpoint above them
+    mainstate.disconnect_mainblock(&mainblocks[4]).unwrap();
+    mainstate.disconnect_mainblock(&mainblocks[3]).unwrap();
+    assert_eq!(mainstate.get_checkpoint_count(), None);
+    let mb = new_mainblock(3, &mainblocks[2].get_hash(), vec![spend_reward(&mainblocks[1], 500, &Hash::compute_hash(b"recipient"))]);
+    mainstate.connect_mainblock(&mb).unwrap();
+    mainstate.save().await.unwrap();
+    assert_eq!(mainstate.get_checkpoint_count(), Some(4));
+
+    let mut loaded = new_mainstate(&dir);
+    loaded.load().await.unwrap();
+    assert_eq!(loaded.serialize(), mainstate.serialize());
+    assert_eq!(loaded.get_tip_hash(), mb.get_hash());
+    loaded.disconnect_mainblock(&mb).unwrap();
+    assert_eq!(loaded.get_tip_hash(), mainblocks[2].get_hash());
+}
+
+#[tokio::test]
+async fn test_records_not_following_the_checkpoint() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mainstate = new_mainstate(&dir);
+    connect_chain(&mut mainstate, 3);
+    mainstate.save().await.unwrap();
+
+    // records lost behind the checkpoint leave an empty mainstate, to be rebuilt from the mainblocks
+    let mut records = ChunkStore::new(&dir.path().join("Mainstate"), String::from("Mainstate")).await.unwrap();
+    records.init().await.unwrap();
+    records.truncate_to(2).await.unwrap();
+    drop(records);
+    let mut loaded = new_mainstate(&dir);
+    loaded.load().await.unwrap();
+    assert_eq!(loaded.get_mainblocks_count(), 0);
+    assert_eq!(loaded.get_checkpoint_count(), None);
+
+    // rebuilding replaces both
+    connect_chain(&mut loaded, 3);
+    loaded.save().await.unwrap();
+    let mut reloaded = new_mainstate(&dir);
+    reloaded.load().await.unwrap();
+    assert_eq!(reloaded.serialize(), mainstate.serialize());
+}
+
+#[tokio::test]
+async fn test_mainstate_file_of_the_former_layout() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("Mainstate"), b"whole mainstate of the former layout").unwrap();
+    let mut mainstate = new_mainstate(&dir);
+    mainstate.load().await.unwrap();
+    assert_eq!(mainstate.get_mainblocks_count(), 0);
+    connect_chain(&mut mainstate, 2);
+    mainstate.save().await.unwrap();
+    let mut loaded = new_mainstate(&dir);
+    loaded.load().await.unwrap();
+    assert_eq!(loaded.serialize(), mainstate.serialize());
+}
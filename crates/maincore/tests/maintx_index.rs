@@ -0,0 +1,140 @@
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainblock::mainblock::Mainblock;
+use maincore::maincore_inner::maincore_inner::MaincoreInner;
+use maincore::maincore_inner::maincore_inner::MaincoreInnerError;
+use maincore::maintx_index::maintx_index::MaintxIndex;
+use maincore::miner::miner::MainblockTemplate;
+use maincore::miner::mining_engine::MiningEngine;
+use maintx::maintx::maintx::new_reward_transaction;
+use std::path::Path;
+use utility::hash::hash::Hash;
+
+async fn open_maincore(path: &Path) -> MaincoreInner {
+    let mut mci = MaincoreInner::new(path, ChainParams::regtest()).await.unwrap();
+    mci.init().await.unwrap();
+    mci.load_mainheaders().await.unwrap();
+    mci
+}
+
+/// Mines a mainblock holding only a reward transaction on top of the main chain mainheader `prev_height`.
+async fn mine_mainblock(mci: &mut MaincoreInner, prev_height: usize, reward_address: &Hash) -> Mainblock {
+    let prev_mainheader = mci.get_inmem_mainheader(prev_height).unwrap();
+    let height = prev_height + 1;
+    let reward_value = mci.get_chain_params().get_mainblock_subsidy(height);
+    let transactions = vec![new_reward_transaction(height as u32, reward_value, 0, reward_address.clone())];
+    let template = MainblockTemplate::new(
+        height,
+        prev_mainheader.get_hash(),
+        prev_mainheader.get_timestamp() + 1,
+        prev_mainheader.get_bits(),
+        transactions,
+    );
+    let mh = MiningEngine::new(1).mine(&template.get_candidate_mainheader()).unwrap().unwrap();
+    let mb = Mainblock::new(mh, template.transactions);
+    mci.add_confirmed_mainblock(mb.clone()).await.unwrap();
+    mb
+}
+
+#[tokio::test]
+async fn test_maintx_index_lookup_and_reindex() {
+    let dir = tempfile::tempdir().unwrap();
+    let address = Hash::compute_hash(b"maintx index address");
+    let mut mci = open_maincore(dir.path()).await;
+    assert!(matches!(mci.get_maintx_location(&Hash::new_empty()), Err(MaincoreInnerError::MaintxIndexDisabled)));
+
+    let mb1 = mine_mainblock(&mut mci, 0, &address).await;
+    mci.enable_maintx_index().await.unwrap();
+    let mb2 = mine_mainblock(&mut mci, 1, &address).await;
+
+    // mainblocks connected before and after the index was enabled are both indexed
+    for (height, mb) in [(1, &mb1), (2, &mb2)] {
+        let tx_hash = mb.transactions[0].compute_hash();
+        let (tx, location) = mci.get_confirmed_maintx(&tx_hash).await.unwrap().unwrap();
+        assert_eq!(tx.compute_hash(), tx_hash);
+        assert_eq!((location.mainblock_height, location.position), (height, 0));
+    }
+    assert!(mci.get_confirmed_maintx(&Hash::compute_hash(b"unknown")).await.unwrap().is_none());
+    drop(mci);
+
+    // the saved index is reloaded, and rebuilt from the mainblocks when it is lost
+    let mut mci = open_maincore(dir.path()).await;
+    mci.enable_maintx_index().await.unwrap();
+    assert_eq!(mci.get_maintx_index().unwrap().get_mainblocks_count(), 3);
+    std::fs::remove_dir_all(dir.path().join("MaintxIndex")).unwrap();
+    mci.reindex_maintx_index().await.unwrap();
+    let location = mci.get_maintx_location(&mb2.transactions[0].compute_hash()).unwrap().unwrap();
+    assert_eq!(location.mainblock_height, 2);
+    assert_eq!(mci.get_maintx_index().unwrap().get_maintxs_count(), 3);
+}
+
+#[tokio::test]
+async fn test_maintx_index_follows_reorganization() {
+    let dir = tempfile::tempdir().unwrap();
+    let address = Hash::compute_hash(b"main chain address");
+    let fork_address = Hash::compute_hash(b"side chain address");
+    let mut mci = open_maincore(dir.path()).await;
+    mci.enable_maintx_index().await.unwrap();
+    mine_mainblock(&mut mci, 0, &address).await;
+    let mb2 = mine_mainblock(&mut mci, 1, &address).await;
+
+    // a longer branch from height 1 disconnects mb2
+    let fork2 = mine_mainblock(&mut mci, 1, &fork_address).await;
+    assert!(mci.get_maintx_location(&fork2.transactions[0].compute_hash()).unwrap().is_none());
+    let fork2_hash = fork2.get_hash();
+    let prev_mainheader = fork2.get_mainheader();
+    let height = 3;
+    let reward_value = mci.get_chain_params().get_mainblock_subsidy(height);
+    let template = MainblockTemplate::new(
+        height,
+        fork2_hash,
+        prev_mainheader.get_timestamp() + 1,
+        prev_mainheader.get_bits(),
+        vec![new_reward_transaction(height as u32, reward_value, 0, fork_address.clone())],
+    );
+    let mh = MiningEngine::new(1).mine(&template.get_candidate_mainheader()).unwrap().unwrap();
+    let fork3 = Mainblock::new(mh, template.transactions);
+    mci.add_confirmed_mainblock(fork3.clone()).await.unwrap();
+    assert_eq!(mci.get_mainblocks_count(), 4);
+
+    assert!(mci.get_maintx_location(&mb2.transactions[0].compute_hash()).unwrap().is_none());
+    let location = mci.get_maintx_location(&fork2.transactions[0].compute_hash()).unwrap().unwrap();
+    assert_eq!(location.mainblock_height, 2);
+    let location = mci.get_maintx_location(&fork3.transactions[0].compute_hash()).unwrap().unwrap();
+    assert_eq!(location.mainblock_height, 3);
+    assert_eq!(mci.get_maintx_index().unwrap().get_tip_hash(), fork3.get_hash());
+}
+
+#[tokio::test]
+async fn test_maintx_index_records_survive_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let address = Hash::compute_hash(b"maintx index address");
+    let mut mci = open_maincore(dir.path()).await;
+    mci.enable_maintx_index().await.unwrap();
+    mine_mainblock(&mut mci, 0, &address).await;
+    let mb2 = mine_mainblock(&mut mci, 1, &address).await;
+    drop(mci);
+
+    // one record per mainblock is appended
+    let index_path = dir.path().join("MaintxIndex").join("MaintxIndexIndex.dat");
+    assert_eq!(std::fs::metadata(&index_path).unwrap().len(), 3 * 16);
+
+    // a record stored before a crash left the mainblock out of the main chain is removed on restart
+    let mut maintx_index = MaintxIndex::new(dir.path().join("MaintxIndex")).await.unwrap();
+    maintx_index.load().await.unwrap();
+    assert_eq!(maintx_index.get_tip_hash(), mb2.get_hash());
+    let orphan_tx = new_reward_transaction(3, 1, 0, address.clone());
+    let orphan_mh = MiningEngine::new(1)
+        .mine(&MainblockTemplate::new(3, mb2.get_hash(), mb2.header.get_timestamp() + 1, mb2.header.get_bits(), vec![orphan_tx.clone()]).get_candidate_mainheader())
+        .unwrap()
+        .unwrap();
+    maintx_index.connect_mainblock(&Mainblock::new(orphan_mh, vec![orphan_tx.clone()])).await.unwrap();
+    drop(maintx_index);
+
+    let mut mci = open_maincore(dir.path()).await;
+    mci.enable_maintx_index().await.unwrap();
+    assert_eq!(mci.get_maintx_index().unwrap().get_mainblocks_count(), 3);
+    assert_eq!(mci.get_maintx_index().unwrap().get_tip_hash(), mb2.get_hash());
+    assert!(mci.get_maintx_location(&orphan_tx.compute_hash()).unwrap().is_none());
+    assert!(mci.get_maintx_location(&mb2.transactions[0].compute_hash()).unwrap().is_some());
+    assert_eq!(std::fs::metadata(&index_path).unwrap().len(), 3 * 16);
+}
@@ -0,0 +1,214 @@
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainblock::mainblock::Mainblock;
+use maincore::mainblock::mainblock_validation::compute_mainblock_root_hash;
+use maincore::mainheader::mainheader::mine_mainheader_with_cpu;
+use maincore::mainstate::mainstate::Mainstate;
+use maincore::mainstate::mainstate::MainstateError;
+use maincore::maintxspool::maintxspool::Maintxspool;
+use maincore::maintxspool::maintxspool::MaintxspoolError;
+use maintx::maintx::maintx::new_reward_transaction;
+use maintx::maintx::maintx::Maintx;
+use maintx::maintx_in::maintx_in::new_maintx_in_ecdsa;
+use maintx::maintx_in::maintx_in::new_mainblockrewardtxin;
+use maintx::maintx_out::maintx_out::new_ecdsa_maintx_out;
+use utility::ecdsa::ecdsa::derive_child_key_set;
+use utility::ecdsa::ecdsa::sign_messagehash;
+use utility::ecdsa::ecdsa::EcdsaKeySet;
+use utility::ecdsa::key_derivation_v1::derive_master_extended_secret_key;
+use utility::hash::hash::Hash;
+
+const OUTPUT_VALUE: u64 = 10_000;
+
+fn test_keyset() -> EcdsaKeySet {
+    derive_child_key_set(&derive_master_extended_secret_key("txspool test seed").unwrap(), 0, false).unwrap()
+}
+
+/// A mainstate holding the genesis mainblock and a mainblock paying `outputs_count` outputs to the test keyset.
+/// Returns the hash of the funding transaction.
+fn funded_mainstate(dir: &tempfile::TempDir, outputs_count: usize) -> (Mainstate, Hash) {
+    let mut mainstate = Mainstate::new(dir.path().join("Mainstate"));
+    mainstate.set_undo_depth(10);
+    let genesis = ChainParams::regtest().get_genesis_mainblock();
+    mainstate.connect_mainblock(&genesis).unwrap();
+    let funding_tx = Maintx {
+        version: 1,
+        vin: vec![new_mainblockrewardtxin(1)],
+        vout: (0..outputs_count).map(|_| new_ecdsa_maintx_out(OUTPUT_VALUE, test_keyset().get_address())).collect(),
+    };
+    let funding_hash = funding_tx.compute_hash();
+    let mb = new_mainblock(&genesis.get_hash(), vec![funding_tx]);
+    mainstate.connect_mainblock(&mb).unwrap();
+    (mainstate, funding_hash)
+}
+
+fn new_mainblock(prev_hash: &Hash, transactions: Vec<Maintx>) -> Mainblock {
+    let mh = mine_mainheader_with_cpu(1, prev_hash.clone(), compute_mainblock_root_hash(&transactions), 1_000_000, 0x207fffff).unwrap();
+    Mainblock::new(mh, transactions)
+}
+
+/// A maintx spending `outpoints` of the test keyset and paying all but `fee` to `recipient`.
+fn spend(outpoints: &[(Hash, u32)], fee: u64, recipient: &Hash) -> Maintx {
+    let keyset = test_keyset();
+    let value = OUTPUT_VALUE * outpoints.len() as u64 - fee;
+    let mut tx = Maintx {
+        version: 1,
+        vin: outpoints
+            .iter()
+            .map(|(hash, index)| new_maintx_in_ecdsa(hash.clone(), *index, keyset.get_public_key_compressed_bytes()))
+            .collect(),
+        vout: vec![new_ecdsa_maintx_out(value, recipient.clone())],
+    };
+    let signature = sign_messagehash(&keyset, tx.compute_hash()).unwrap();
+    for vin in tx.vin.iter_mut() {
+        vin.set_signature(signature.clone()).unwrap();
+    }
+    tx
+}
+
+/// A maintx spending the single output of `parent`, paid to the test keyset.
+fn spend_child(parent: &Maintx, fee: u64) -> Maintx {
+    let keyset = test_keyset();
+    let value = parent.vout[0].get_value().unwrap() - fee;
+    let mut tx = Maintx {
+        version: 1,
+        vin: vec![new_maintx_in_ecdsa(parent.compute_hash(), 0, keyset.get_public_key_compressed_bytes())],
+        vout: vec![new_ecdsa_maintx_out(value, keyset.get_address())],
+    };
+    let signature = sign_messagehash(&keyset, tx.compute_hash()).unwrap();
+    tx.vin[0].set_signature(signature).unwrap();
+    tx
+}
+
+#[test]
+fn test_fee_rate_ordering() {
+    let dir = tempfile::tempdir().unwrap();
+    let (mainstate, funding_hash) = funded_mainstate(&dir, 4);
+    let recipient = Hash::compute_hash(b"recipient");
+    let mut txspool = Maintxspool::new();
+    let low = txspool.add_maintx(spend(&[(funding_hash.clone(), 0)], 100, &recipient), &mainstate).unwrap();
+    let high = txspool.add_maintx(spend(&[(funding_hash.clone(), 1)], 900, &recipient), &mainstate).unwrap();
+    // twice the fee for twice the size pays the same rate as `low`, the larger one is not the better
+    let double = txspool.add_maintx(spend(&[(funding_hash.clone(), 2), (funding_hash.clone(), 3)], 150, &recipient), &mainstate).unwrap();
+    assert_eq!(txspool.get_entry(&high).unwrap().fee, 900);
+
+    let sorted: Vec<Hash> = txspool.get_sorted_entries().iter().map(|entry| entry.hash.clone()).collect();
+    assert_eq!(sorted, vec![high.clone(), low.clone(), double.clone()]);
+
+    let selected: Vec<Hash> = txspool.select_maintxs(usize::MAX).iter().map(|tx| tx.compute_hash()).collect();
+    assert_eq!(selected, sorted);
+    // a transaction that does not fit is skipped for the next ones
+    let max_size = txspool.get_entry(&high).unwrap().size + txspool.get_entry(&low).unwrap().size;
+    let selected: Vec<Hash> = txspool.select_maintxs(max_size).iter().map(|tx| tx.compute_hash()).collect();
+    assert_eq!(selected, vec![high.clone(), low.clone()]);
+
+    // frozen transactions are not selected again
+    txspool.freeze_with_mainblock_height(std::slice::from_ref(&high), 2);
+    let selected: Vec<Hash> = txspool.select_maintxs(usize::MAX).iter().map(|tx| tx.compute_hash()).collect();
+    assert_eq!(selected, vec![low, double]);
+    txspool.reset_tx_with_mainblock_height(2);
+    assert!(txspool.get_entry(&high).unwrap().frozen_height.is_none());
+}
+
+#[test]
+fn test_conflict_rejection() {
+    let dir = tempfile::tempdir().unwrap();
+    let (mainstate, funding_hash) = funded_mainstate(&dir, 2);
+    let mut txspool = Maintxspool::new();
+    let tx = spend(&[(funding_hash.clone(), 0)], 100, &Hash::compute_hash(b"recipient"));
+    let hash = txspool.add_maintx(tx.clone(), &mainstate).unwrap();
+
+    assert!(matches!(txspool.add_maintx(tx, &mainstate), Err(MaintxspoolError::AlreadyInTxspool(_))));
+    let conflicting = spend(&[(funding_hash.clone(), 0)], 200, &Hash::compute_hash(b"other recipient"));
+    assert!(matches!(
+        txspool.add_maintx(conflicting, &mainstate),
+        Err(MaintxspoolError::ConflictingSpend { index: 0, spent_by, .. }) if spent_by == hash
+    ));
+    let missing = spend(&[(funding_hash.clone(), 7)], 100, &Hash::compute_hash(b"recipient"));
+    assert!(matches!(txspool.add_maintx(missing, &mainstate), Err(MaintxspoolError::MainstateError(MainstateError::MissingOutput { index: 7, .. }))));
+    let mut unsigned = spend(&[(funding_hash, 1)], 100, &Hash::compute_hash(b"recipient"));
+    unsigned.vout[0] = new_ecdsa_maintx_out(1, Hash::compute_hash(b"recipient"));
+    assert!(matches!(txspool.add_maintx(unsigned, &mainstate), Err(MaintxspoolError::InvalidSignature(_))));
+    assert_eq!(txspool.get_count(), 1);
+}
+
+#[test]
+fn test_eviction_on_confirmation() {
+    let dir = tempfile::tempdir().unwrap();
+    let (mut mainstate, funding_hash) = funded_mainstate(&dir, 3);
+    let recipient = Hash::compute_hash(b"recipient");
+    let mut txspool = Maintxspool::new();
+    let confirmed = spend(&[(funding_hash.clone(), 0)], 100, &test_keyset().get_address());
+    txspool.add_maintx(confirmed.clone(), &mainstate).unwrap();
+    let replaced = txspool.add_maintx(spend(&[(funding_hash.clone(), 1)], 100, &recipient), &mainstate).unwrap();
+    let child = txspool.add_maintx(spend_child(&confirmed, 100), &mainstate).unwrap();
+    let untouched = txspool.add_maintx(spend(&[(funding_hash.clone(), 2)], 100, &recipient), &mainstate).unwrap();
+    assert_eq!(txspool.get_count(), 4);
+
+    // the mainblock confirms `confirmed` and spends the output of `replaced` differently
+    let prev_hash = Hash::compute_hash(b"prev");
+    let mb = new_mainblock(&prev_hash, vec![
+        new_reward_transaction(2, OUTPUT_VALUE, 200, test_keyset().get_address()),
+        confirmed.clone(),
+        spend(&[(funding_hash, 1)], 100, &Hash::compute_hash(b"other recipient")),
+    ]);
+    mainstate.connect_mainblock(&mb).unwrap();
+    txspool.remove_confirmed_mainblock(&mb);
+
+    assert!(!txspool.contains(&confirmed.compute_hash()));
+    assert!(!txspool.contains(&replaced));
+    // the child of a confirmed transaction now spends a confirmed output
+    assert!(txspool.contains(&child));
+    assert!(txspool.contains(&untouched));
+    txspool.revalidate(&mainstate);
+    assert_eq!(txspool.get_count(), 2);
+}
+
+#[test]
+fn test_readmit_chained_maintxs() {
+    let dir = tempfile::tempdir().unwrap();
+    let (mut mainstate, funding_hash) = funded_mainstate(&dir, 1);
+    let parent = spend(&[(funding_hash.clone(), 0)], 100, &test_keyset().get_address());
+    let child = spend_child(&parent, 100);
+    let grandchild = spend_child(&child, 5_000);
+    let reward = |height: u32, fee: u64| new_reward_transaction(height, OUTPUT_VALUE, fee, Hash::compute_hash(b"miner"));
+    // the parent and the child in one mainblock, the grandchild in the next one
+    let mb2 = new_mainblock(&Hash::compute_hash(b"prev"), vec![reward(2, 200), parent.clone(), child.clone()]);
+    mainstate.connect_mainblock(&mb2).unwrap();
+    let mb3 = new_mainblock(&mb2.get_hash(), vec![reward(3, 5_000), grandchild.clone()]);
+    mainstate.connect_mainblock(&mb3).unwrap();
+
+    mainstate.disconnect_mainblock(&mb3).unwrap();
+    mainstate.disconnect_mainblock(&mb2).unwrap();
+    let mut txspool = Maintxspool::new();
+    assert_eq!(txspool.readmit_mainblock(&mb2, &mainstate), 2);
+    assert_eq!(txspool.readmit_mainblock(&mb3, &mainstate), 1);
+    assert_eq!(txspool.get_count(), 3);
+
+    // the grandchild pays the best rate but cannot come before its ancestors
+    let selected: Vec<Hash> = txspool.select_maintxs(usize::MAX).iter().map(|tx| tx.compute_hash()).collect();
+    assert_eq!(selected, vec![parent.compute_hash(), child.compute_hash(), grandchild.compute_hash()]);
+    // the ancestors are needed for the grandchild to be selected
+    txspool.freeze_with_mainblock_height(&[parent.compute_hash()], 2);
+    assert!(txspool.select_maintxs(usize::MAX).is_empty());
+    txspool.reset_tx_with_mainblock_height(2);
+
+    // the mainstate accepts the selected transactions in that order
+    let mb = new_mainblock(&Hash::compute_hash(b"prev"), [vec![reward(2, 5_200)], txspool.select_maintxs(usize::MAX)].concat());
+    assert_eq!(mainstate.check_mainblock(&mb).unwrap(), 5_200);
+
+    // removing a transaction removes its descendants
+    txspool.remove(&child.compute_hash());
+    assert!(txspool.contains(&parent.compute_hash()));
+    assert!(!txspool.contains(&grandchild.compute_hash()));
+
+    // a conflicting confirmation drops the whole chain
+    txspool.readmit_mainblock(&mb2, &mainstate);
+    txspool.readmit_mainblock(&mb3, &mainstate);
+    assert_eq!(txspool.get_count(), 3);
+    let double_spend = spend(&[(funding_hash, 0)], 300, &Hash::compute_hash(b"other recipient"));
+    let mb = new_mainblock(&Hash::compute_hash(b"prev"), vec![reward(2, 300), double_spend]);
+    mainstate.connect_mainblock(&mb).unwrap();
+    txspool.remove_confirmed_mainblock(&mb);
+    txspool.revalidate(&mainstate);
+    assert_eq!(txspool.get_count(), 0);
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::mine_tip;
+use common::open_maincore;
+use maintx::maintx::maintx::Maintx;
+use maintx::maintx_in::maintx_in::new_maintx_in_ecdsa;
+use maintx::maintx_out::maintx_out::new_ecdsa_maintx_out;
+use utility::ecdsa::ecdsa::derive_child_key_set;
+use utility::ecdsa::ecdsa::sign_messagehash;
+use utility::ecdsa::key_derivation_v1::derive_master_extended_secret_key;
+use utility::hash::hash::Hash;
+
+#[tokio::test]
+async fn test_template_maintxs_are_released() {
+    let dir = tempfile::tempdir().unwrap();
+    let keyset = derive_child_key_set(&derive_master_extended_secret_key("miner test seed").unwrap(), 0, false).unwrap();
+    let address = keyset.get_address();
+    let mut mci = open_maincore(dir.path()).await;
+    for _ in 0..mci.get_chain_params().reward_maturity + 1 {
+        mine_tip(&mut mci, &address).await;
+    }
+    let (outpoint, output) = mci.get_unspent_outputs(&address).into_iter().min_by_key(|(_, output)| output.mainblock_height).unwrap();
+    let mut tx = Maintx {
+        version: 1,
+        vin: vec![new_maintx_in_ecdsa(outpoint.hash, outpoint.index, keyset.get_public_key_compressed_bytes())],
+        vout: vec![new_ecdsa_maintx_out(output.value - 1000, Hash::compute_hash(b"recipient"))],
+    };
+    let signature = sign_messagehash(&keyset, tx.compute_hash()).unwrap();
+    tx.vin[0].set_signature(signature).unwrap();
+    let tx_hash = mci.add_maintx(tx).unwrap();
+
+    let template = mci.build_mainblock_template().unwrap();
+    assert_eq!(template.transactions[1].compute_hash(), tx_hash);
+    assert_eq!(mci.get_txspool().get_entry(&tx_hash).unwrap().frozen_height, Some(template.height));
+    // a new template replaces the previous one and takes its maintxs
+    let template = mci.build_mainblock_template().unwrap();
+    assert_eq!(template.transactions.len(), 2);
+
+    // a cancelled template gives its maintxs back to the txspool
+    mci.release_mainblock_template(template.height);
+    assert!(mci.get_txspool().get_entry(&tx_hash).unwrap().frozen_height.is_none());
+    let mb = mine_tip(&mut mci, &address).await;
+    assert_eq!(mb.transactions[1].compute_hash(), tx_hash);
+    assert!(!mci.get_txspool().contains(&tx_hash));
+}
@@ -0,0 +1,101 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use maincore::mainheader::mainheader::Mainheader;
+use maincore::miner::mining_engine::hash_meets_target;
+use maincore::miner::mining_engine::split_nonce_range;
+use maincore::miner::mining_engine::target_from_compact;
+use maincore::miner::mining_engine::MiningEngine;
+use maincore::miner::mining_engine::MiningStats;
+use utility::hash::hash::Hash;
+
+/// About one hash in 64 meets this target.
+const EASY_BITS: u32 = 0x20040000;
+/// No hash is expected to meet this target.
+const IMPOSSIBLE_BITS: u32 = 0x03000001;
+
+fn candidate(timestamp: i64, bits: u32) -> Mainheader {
+    Mainheader::new(1, Hash::compute_hash(b"prev"), Hash::compute_hash(b"root"), timestamp, bits, 0, Hash::new_empty())
+}
+
+/// The first nonce from `first_nonce` to `last_nonce` meeting the target at `timestamp`, searched one by one.
+fn find_nonce(timestamp: i64, bits: u32, first_nonce: u32, last_nonce: u32) -> Option<u32> {
+    let target = target_from_compact(bits);
+    (first_nonce..=last_nonce).find(|nonce| {
+        let mh = Mainheader::new(1, Hash::compute_hash(b"prev"), Hash::compute_hash(b"root"), timestamp, bits, *nonce, Hash::new_empty());
+        hash_meets_target(&mh.compute_hash(), &target)
+    })
+}
+
+#[test]
+fn test_split_nonce_range() {
+    assert_eq!(split_nonce_range(4, 0, 7), vec![(0, 1), (2, 3), (4, 5), (6, 7)]);
+    assert_eq!(split_nonce_range(3, 10, 20), vec![(10, 12), (13, 15), (16, 20)]);
+    assert_eq!(split_nonce_range(8, 5, 7), vec![(5, 5), (6, 6), (7, 7)]);
+    assert_eq!(split_nonce_range(0, 0, 3), vec![(0, 3)]);
+
+    for threads_count in [1, 3, 7, 16] {
+        let slices = split_nonce_range(threads_count, 0, u32::MAX);
+        assert_eq!(slices.len(), threads_count);
+        assert_eq!(slices.first().unwrap().0, 0);
+        assert_eq!(slices.last().unwrap().1, u32::MAX);
+        for pair in slices.windows(2) {
+            assert_eq!(pair[0].1 as u64 + 1, pair[1].0 as u64);
+        }
+    }
+}
+
+#[test]
+fn test_multi_thread_mining() {
+    for threads_count in [1, 2, 4] {
+        let engine = MiningEngine::new(threads_count);
+        let mh = engine.mine_nonce_range(&candidate(1000, EASY_BITS), 0, 4095).unwrap().unwrap();
+        assert!(mh.check_hash());
+        assert!(mh.check_target());
+        assert_eq!(mh.get_timestamp(), 1000);
+        assert!(engine.get_stats().get_hashes_count() > 0);
+    }
+    // a single thread finds the first nonce of its range
+    let mh = MiningEngine::new(1).mine_nonce_range(&candidate(1000, EASY_BITS), 0, 4095).unwrap().unwrap();
+    assert_eq!(Some(mh.get_nonce()), find_nonce(1000, EASY_BITS, 0, 4095));
+}
+
+#[test]
+fn test_timestamp_rollover() {
+    // a timestamp whose first nonces all miss the target
+    let timestamp = (1000..).find(|timestamp| find_nonce(*timestamp, EASY_BITS, 0, 3).is_none()).unwrap();
+    let expected_timestamp = (timestamp..).find(|timestamp| find_nonce(*timestamp, EASY_BITS, 0, 3).is_some()).unwrap();
+    let mh = MiningEngine::new(1).mine_nonce_range(&candidate(timestamp, EASY_BITS), 0, 3).unwrap().unwrap();
+    assert_eq!(mh.get_timestamp(), expected_timestamp);
+    assert!(mh.get_nonce() <= 3);
+    assert!(mh.check_hash());
+    assert!(mh.check_target());
+    // the threads roll their timestamps on their own, the first one finding its slice wins
+    let mh = MiningEngine::new(2).mine_nonce_range(&candidate(timestamp, EASY_BITS), 0, 3).unwrap().unwrap();
+    assert!(mh.get_timestamp() > timestamp);
+    assert!(mh.get_nonce() <= 3);
+    assert!(mh.check_target());
+}
+
+#[test]
+fn test_stop_and_hashrate() {
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let stats = Arc::new(MiningStats::new());
+    let engine = MiningEngine::with_stop_flag(2, stop_flag.clone(), stats.clone());
+    let stopper = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(200));
+        stop_flag.store(true, Ordering::Relaxed);
+    });
+    // a small range is mined again and again with later timestamps until the engine stops
+    assert!(engine.mine_nonce_range(&candidate(1000, IMPOSSIBLE_BITS), 0, 15).unwrap().is_none());
+    stopper.join().unwrap();
+    assert!(engine.get_stop_flag().load(Ordering::Relaxed));
+    assert!(stats.get_hashes_count() > 0);
+    assert!(stats.get_hashrate() > 0.0);
+
+    // a stopped engine gives up at once
+    let engine = MiningEngine::new(1);
+    engine.stop();
+    assert!(engine.mine(&candidate(1000, IMPOSSIBLE_BITS)).unwrap().is_none());
+}
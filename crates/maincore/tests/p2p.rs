@@ -0,0 +1,255 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainblock::mainblock::Mainblock;
+use maincore::maincore_inner::maincore_inner::MaincoreInner;
+use maincore::miner::mining_engine::MiningEngine;
+use maincore::p2p::p2p_addrman::P2pAddrman;
+use maincore::p2p::p2p_light_connection::P2pLightConnection;
+use maincore::p2p::p2p_message::P2pAddress;
+use maincore::p2p::p2p_message::P2pInventory;
+use maincore::p2p::p2p_message::P2pInventoryKind;
+use maincore::p2p::p2p_message::P2pMessage;
+use maincore::p2p::p2p_message::P2P_MAX_INVENTORY_COUNT;
+use maincore::p2p::p2p_node::P2pConfig;
+use maincore::p2p::p2p_node::P2pNode;
+use maintx::maintx::maintx::Maintx;
+use maintx::maintx_in::maintx_in::new_maintx_in_ecdsa;
+use maintx::maintx_out::maintx_out::new_ecdsa_maintx_out;
+use utility::ecdsa::ecdsa::derive_child_key_set;
+use utility::ecdsa::ecdsa::sign_messagehash;
+use utility::ecdsa::ecdsa::EcdsaKeySet;
+use utility::ecdsa::key_derivation_v1::derive_master_extended_secret_key;
+use utility::hash::hash::Hash;
+
+const WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct TestNode {
+    _dir: tempfile::TempDir,
+    maincore: Arc<Mutex<MaincoreInner>>,
+    p2p: P2pNode,
+    addr: SocketAddr,
+}
+
+async fn start_node() -> TestNode {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mci = MaincoreInner::new(dir.path(), ChainParams::regtest()).await.unwrap();
+    mci.init().await.unwrap();
+    mci.load_mainheaders().await.unwrap();
+    let maincore = Arc::new(Mutex::new(mci));
+    let p2p = P2pNode::new(maincore.clone(), P2pConfig::new("127.0.0.1:0".parse().unwrap())).await;
+    let addr = p2p.start().await.unwrap();
+    TestNode { _dir: dir, maincore, p2p, addr }
+}
+
+fn test_keyset() -> EcdsaKeySet {
+    derive_child_key_set(&derive_master_extended_secret_key("p2p test seed").unwrap(), 0, false).unwrap()
+}
+
+async fn mine_mainblocks(node: &TestNode, reward_address: &Hash, count: usize) {
+    for _ in 0..count {
+        let mut mci = node.maincore.lock().await;
+        mci.get_miner_mut().set_reward_address(reward_address.clone());
+        let template = mci.build_mainblock_template().unwrap();
+        let mh = MiningEngine::new(1).mine(&template.get_candidate_mainheader()).unwrap().unwrap();
+        mci.add_confirmed_mainblock(Mainblock::new(mh, template.transactions)).await.unwrap();
+    }
+}
+
+async fn wait_for_height(node: &TestNode, count: usize) {
+    let result = tokio::time::timeout(WAIT_TIMEOUT, async {
+        while node.maincore.lock().await.get_mainblocks_count() < count {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+    assert!(result.is_ok(), "node did not reach {} mainblocks", count);
+}
+
+async fn get_tip_hash(node: &TestNode) -> Hash {
+    node.maincore.lock().await.get_last_inmem_mainheader().unwrap().get_hash()
+}
+
+#[tokio::test]
+async fn test_three_nodes_converge() {
+    let keyset = test_keyset();
+    let node_a = start_node().await;
+    let node_b = start_node().await;
+    let node_c = start_node().await;
+    mine_mainblocks(&node_a, &keyset.get_address(), 5).await;
+
+    node_b.p2p.connect(node_a.addr).await.unwrap();
+    node_c.p2p.connect(node_b.addr).await.unwrap();
+    wait_for_height(&node_b, 6).await;
+    wait_for_height(&node_c, 6).await;
+
+    // new mainblocks are announced along the line a -> b -> c
+    mine_mainblocks(&node_a, &keyset.get_address(), 3).await;
+    wait_for_height(&node_c, 9).await;
+    let tip_hash = get_tip_hash(&node_a).await;
+    assert_eq!(get_tip_hash(&node_b).await, tip_hash);
+    assert_eq!(get_tip_hash(&node_c).await, tip_hash);
+}
+
+#[tokio::test]
+async fn test_maintx_relay() {
+    let keyset = test_keyset();
+    let address = keyset.get_address();
+    let node_a = start_node().await;
+    let node_b = start_node().await;
+    let reward_maturity = ChainParams::regtest().reward_maturity;
+    mine_mainblocks(&node_a, &address, reward_maturity + 1).await;
+    node_b.p2p.connect(node_a.addr).await.unwrap();
+    wait_for_height(&node_b, reward_maturity + 2).await;
+
+    let tx_hash = {
+        let mut mci = node_a.maincore.lock().await;
+        let (outpoint, output) = mci
+            .get_unspent_outputs(&address)
+            .into_iter()
+            .min_by_key(|(_, output)| output.mainblock_height)
+            .unwrap();
+        let mut tx = Maintx {
+            version: 1,
+            vin: vec![new_maintx_in_ecdsa(outpoint.hash, outpoint.index, keyset.get_public_key_compressed_bytes())],
+            vout: vec![new_ecdsa_maintx_out(output.value - 1000, address.clone())],
+        };
+        let signature = sign_messagehash(&keyset, tx.compute_hash()).unwrap();
+        tx.vin[0].set_signature(signature).unwrap();
+        mci.add_maintx(tx).unwrap()
+    };
+
+    let result = tokio::time::timeout(WAIT_TIMEOUT, async {
+        while !node_b.maincore.lock().await.get_txspool().contains(&tx_hash) {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+    assert!(result.is_ok(), "maintx was not relayed");
+}
+
+#[tokio::test]
+async fn test_address_propagation() {
+    let node_a = start_node().await;
+    let node_b = start_node().await;
+    let node_c = start_node().await;
+
+    // a learns the listening address of b from its inbound connection
+    node_b.p2p.connect(node_a.addr).await.unwrap();
+    // c asks a for addresses when it connects
+    node_c.p2p.connect(node_a.addr).await.unwrap();
+
+    let result = tokio::time::timeout(WAIT_TIMEOUT, async {
+        while !node_c.p2p.get_known_addresses().iter().any(|address| address.socket_addr == node_b.addr) {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+    assert!(result.is_ok(), "address of b did not reach c");
+
+    let connected = node_c.p2p.connect_to_known_addresses(2).await;
+    assert_eq!(connected, 1);
+    assert_eq!(node_c.p2p.get_peers_count(), 2);
+}
+
+#[tokio::test]
+async fn test_headers_first_sync_from_two_sources() {
+    let keyset = test_keyset();
+    let node_a = start_node().await;
+    let node_b = start_node().await;
+    let node_c = start_node().await;
+    mine_mainblocks(&node_a, &keyset.get_address(), 40).await;
+    node_b.p2p.connect(node_a.addr).await.unwrap();
+    wait_for_height(&node_b, 41).await;
+
+    node_c.p2p.connect(node_a.addr).await.unwrap();
+    node_c.p2p.connect(node_b.addr).await.unwrap();
+    wait_for_height(&node_c, 41).await;
+    assert_eq!(get_tip_hash(&node_c).await, get_tip_hash(&node_a).await);
+
+    let mci = node_c.maincore.lock().await;
+    assert!(!mci.get_syncpool().is_syncing());
+    let progress = mci.get_sync_progress();
+    assert_eq!(progress.target_mainblocks_count, 41);
+    assert_eq!(progress.percent, 100.0);
+}
+
+#[tokio::test]
+async fn test_addrman_keeps_addresses_added_before_start() {
+    let dir = tempfile::tempdir().unwrap();
+    let addrman_path = dir.path().join("Addrman");
+    let saved_addr: SocketAddr = "10.0.0.1:9000".parse().unwrap();
+    let mut saved = P2pAddrman::new_with_path(&addrman_path);
+    saved.add_address(&P2pAddress { socket_addr: saved_addr, last_seen: 1 });
+    saved.save().await.unwrap();
+
+    let mut mci = MaincoreInner::new(dir.path().join("Maincore"), ChainParams::regtest()).await.unwrap();
+    mci.init().await.unwrap();
+    mci.load_mainheaders().await.unwrap();
+    let mut config = P2pConfig::new("127.0.0.1:0".parse().unwrap());
+    config.addrman_path = Some(addrman_path);
+    let p2p = P2pNode::new(Arc::new(Mutex::new(mci)), config).await;
+    let added_addr: SocketAddr = "10.0.0.2:9000".parse().unwrap();
+    p2p.add_known_address(added_addr);
+    p2p.start().await.unwrap();
+
+    let known: Vec<SocketAddr> = p2p.get_known_addresses().iter().map(|address| address.socket_addr).collect();
+    assert!(known.contains(&saved_addr));
+    assert!(known.contains(&added_addr));
+}
+
+#[tokio::test]
+async fn test_maintx_proofs_requests_are_rate_limited() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mci = MaincoreInner::new(dir.path(), ChainParams::regtest()).await.unwrap();
+    mci.init().await.unwrap();
+    mci.load_mainheaders().await.unwrap();
+    let magic = mci.get_chain_params().p2p_magic;
+    let mut config = P2pConfig::new("127.0.0.1:0".parse().unwrap());
+    config.maintx_proofs_interval = Duration::from_millis(300);
+    let p2p = P2pNode::new(Arc::new(Mutex::new(mci)), config).await;
+    let addr = p2p.start().await.unwrap();
+
+    let mut connection = P2pLightConnection::connect(addr, magic).await.unwrap();
+    let started_at = Instant::now();
+    for _ in 0..3 {
+        let (last_height, proofs) = connection.get_maintx_proofs(vec![test_keyset().get_address()], 0).await.unwrap();
+        assert_eq!((last_height, proofs.len()), (0, 0));
+    }
+    // the first request is served at once, the next ones one interval apart
+    assert!(started_at.elapsed() >= Duration::from_millis(600));
+}
+
+#[tokio::test]
+async fn test_peer_not_reading_is_disconnected_when_its_queue_is_full() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mci = MaincoreInner::new(dir.path(), ChainParams::regtest()).await.unwrap();
+    mci.init().await.unwrap();
+    mci.load_mainheaders().await.unwrap();
+    let magic = mci.get_chain_params().p2p_magic;
+    let mut config = P2pConfig::new("127.0.0.1:0".parse().unwrap());
+    config.send_queue_size = 4;
+    let p2p = P2pNode::new(Arc::new(Mutex::new(mci)), config).await;
+    let addr = p2p.start().await.unwrap();
+
+    // the light connection only reads the replies to its own requests
+    let _connection = P2pLightConnection::connect(addr, magic).await.unwrap();
+    while p2p.get_peers_count() == 0 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let inventories: Vec<P2pInventory> = (0..P2P_MAX_INVENTORY_COUNT)
+        .map(|i| P2pInventory { kind: P2pInventoryKind::Maintx, hash: Hash::compute_hash(&i.to_le_bytes()) })
+        .collect();
+    // once the socket buffers are full the queue fills up
+    for _ in 0..100 {
+        if p2p.get_peers_count() == 0 {
+            break;
+        }
+        p2p.broadcast(P2pMessage::Inv(inventories.clone()));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(p2p.get_peers_count(), 0);
+}
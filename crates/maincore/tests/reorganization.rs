@@ -0,0 +1,165 @@
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainblock::mainblock::Mainblock;
+use maincore::maincore_inner::maincore_inner::MaincoreInner;
+use maincore::maincore_inner::maincore_inner::MaincoreInnerError;
+use maincore::miner::miner::MainblockTemplate;
+use maincore::miner::mining_engine::MiningEngine;
+use maintx::maintx::maintx::new_reward_transaction;
+use std::path::Path;
+use utility::hash::hash::Hash;
+
+async fn open_maincore(path: &Path) -> MaincoreInner {
+    let mut mci = MaincoreInner::new(path, ChainParams::regtest()).await.unwrap();
+    mci.init().await.unwrap();
+    mci.load_mainheaders().await.unwrap();
+    mci
+}
+
+/// Mines, without adding it, a mainblock at `height` holding only a reward transaction on top of `prev`.
+fn new_mainblock_on(mci: &MaincoreInner, prev: &Mainblock, height: usize, reward_address: &Hash) -> Mainblock {
+    let reward_value = mci.get_chain_params().get_mainblock_subsidy(height);
+    let template = MainblockTemplate::new(
+        height,
+        prev.get_hash(),
+        prev.header.get_timestamp() + 1,
+        prev.header.get_bits(),
+        vec![new_reward_transaction(height as u32, reward_value, 0, reward_address.clone())],
+    );
+    let mh = MiningEngine::new(1).mine(&template.get_candidate_mainheader()).unwrap().unwrap();
+    Mainblock::new(mh, template.transactions)
+}
+
+/// Mines `count` mainblocks on top of `prev` at `prev_height` and adds them. Returns them, `prev` first.
+async fn mine_branch(mci: &mut MaincoreInner, prev: &Mainblock, prev_height: usize, count: usize, reward_address: &Hash) -> Vec<Mainblock> {
+    let mut branch = vec![prev.clone()];
+    for height in prev_height + 1..=prev_height + count {
+        let mb = new_mainblock_on(mci, branch.last().unwrap(), height, reward_address);
+        mci.add_confirmed_mainblock(mb.clone()).await.unwrap();
+        branch.push(mb);
+    }
+    branch
+}
+
+fn get_tip_hash(mci: &MaincoreInner) -> Hash {
+    mci.get_last_inmem_mainheader().unwrap().get_hash()
+}
+
+#[tokio::test]
+async fn test_side_branch_overtakes_main_chain() {
+    let dir = tempfile::tempdir().unwrap();
+    let main_address = Hash::compute_hash(b"main chain address");
+    let side_address = Hash::compute_hash(b"side chain address");
+    let mut mci = open_maincore(dir.path()).await;
+    let genesis = mci.get_mainblock(0).await.unwrap();
+    let main_branch = mine_branch(&mut mci, &genesis, 0, 3, &main_address).await;
+    let main_tip_hash = get_tip_hash(&mci);
+
+    // as much work as the main chain is not enough to switch
+    let side_branch = mine_branch(&mut mci, &main_branch[1], 1, 2, &side_address).await;
+    assert_eq!(get_tip_hash(&mci), main_tip_hash);
+    assert_eq!(mci.get_side_mainheaders_count(), 2);
+
+    let side_tip = new_mainblock_on(&mci, side_branch.last().unwrap(), 4, &side_address);
+    mci.add_confirmed_mainblock(side_tip.clone()).await.unwrap();
+    assert_eq!(get_tip_hash(&mci), side_tip.get_hash());
+    assert_eq!(mci.get_mainblocks_count(), 5);
+    assert_eq!(mci.get_mainheader_height(&side_tip.get_hash()), Some(4));
+    // the two disconnected mainblocks are kept as side mainblocks
+    assert_eq!(mci.get_side_mainheaders_count(), 2);
+    assert!(mci.has_mainheader(&main_tip_hash));
+    assert_eq!(mci.get_mainstate().get_tip_hash(), side_tip.get_hash());
+    let subsidy = mci.get_chain_params().get_mainblock_subsidy(1);
+    assert_eq!(mci.get_balance(&main_address), subsidy);
+    assert_eq!(mci.get_balance(&side_address), 3 * subsidy);
+    drop(mci);
+
+    // the switch survives a restart
+    let mci = open_maincore(dir.path()).await;
+    assert_eq!(get_tip_hash(&mci), side_tip.get_hash());
+    assert_eq!(mci.get_mainstate().get_tip_hash(), side_tip.get_hash());
+    assert_eq!(mci.get_balance(&side_address), 3 * subsidy);
+}
+
+#[tokio::test]
+async fn test_side_branch_with_less_work() {
+    let dir = tempfile::tempdir().unwrap();
+    let main_address = Hash::compute_hash(b"main chain address");
+    let side_address = Hash::compute_hash(b"side chain address");
+    let mut mci = open_maincore(dir.path()).await;
+    let genesis = mci.get_mainblock(0).await.unwrap();
+    let main_branch = mine_branch(&mut mci, &genesis, 0, 3, &main_address).await;
+    let chainwork = mci.get_chainwork();
+    let mainstate_bytes = mci.get_mainstate().serialize();
+
+    mine_branch(&mut mci, &main_branch[1], 1, 1, &side_address).await;
+    assert_eq!(get_tip_hash(&mci), main_branch[3].get_hash());
+    assert_eq!(mci.get_chainwork(), chainwork);
+    assert_eq!(mci.get_side_mainheaders_count(), 1);
+    assert_eq!(mci.get_mainstate().serialize(), mainstate_bytes);
+    assert_eq!(mci.get_balance(&side_address), 0);
+}
+
+#[tokio::test]
+async fn test_reorganization_deeper_than_undo_depth() {
+    let dir = tempfile::tempdir().unwrap();
+    let main_address = Hash::compute_hash(b"main chain address");
+    let side_address = Hash::compute_hash(b"side chain address");
+    let mut mci = open_maincore(dir.path()).await;
+    let confirmation_depth = mci.get_confirmation_depth();
+    assert_eq!(mci.get_mainstate().get_undo_count(), 1);
+    let genesis = mci.get_mainblock(0).await.unwrap();
+    let main_branch = mine_branch(&mut mci, &genesis, 0, confirmation_depth + 2, &main_address).await;
+    let tip_hash = get_tip_hash(&mci);
+    assert_eq!(mci.get_mainstate().get_undo_count(), confirmation_depth);
+
+    // replacing a final mainblock would disconnect more mainblocks than the undo data covers
+    let fork_height = main_branch.len() - 2 - confirmation_depth;
+    let too_deep = new_mainblock_on(&mci, &main_branch[fork_height], fork_height + 1, &side_address);
+    assert!(matches!(
+        mci.add_confirmed_mainblock(too_deep).await,
+        Err(MaincoreInnerError::FinalMainblockConflict(height)) if height == fork_height + 1
+    ));
+    assert_eq!(get_tip_hash(&mci), tip_hash);
+    assert_eq!(mci.get_side_mainheaders_count(), 0);
+
+    // the deepest reorganization disconnects every mainblock the undo data covers
+    let side_branch = mine_branch(&mut mci, &main_branch[fork_height + 1], fork_height + 1, confirmation_depth, &side_address).await;
+    assert_eq!(get_tip_hash(&mci), tip_hash);
+    let side_tip = new_mainblock_on(&mci, side_branch.last().unwrap(), main_branch.len(), &side_address);
+    mci.add_confirmed_mainblock(side_tip.clone()).await.unwrap();
+    assert_eq!(get_tip_hash(&mci), side_tip.get_hash());
+    let subsidy = mci.get_chain_params().get_mainblock_subsidy(1);
+    assert_eq!(mci.get_balance(&side_address), (confirmation_depth as u64 + 1) * subsidy);
+}
+
+fn read_dir_files(path: &std::path::Path) -> Vec<(std::ffi::OsString, Vec<u8>)> {
+    std::fs::read_dir(path).unwrap().map(|entry| entry.unwrap()).map(|entry| (entry.file_name(), std::fs::read(entry.path()).unwrap())).collect()
+}
+
+#[tokio::test]
+async fn test_mainstate_left_on_the_old_branch_is_rebuilt() {
+    let dir = tempfile::tempdir().unwrap();
+    let main_address = Hash::compute_hash(b"main chain address");
+    let side_address = Hash::compute_hash(b"side chain address");
+    let mut mci = open_maincore(dir.path()).await;
+    let genesis = mci.get_mainblock(0).await.unwrap();
+    let main_branch = mine_branch(&mut mci, &genesis, 0, 2, &main_address).await;
+    let old_mainstate = read_dir_files(&dir.path().join("Mainstate"));
+
+    // the side branch replaces the last mainblock with two mainblocks
+    mine_branch(&mut mci, &main_branch[1], 1, 2, &side_address).await;
+    let tip_hash = get_tip_hash(&mci);
+    let main_balance = mci.get_balance(&main_address);
+    let side_balance = mci.get_balance(&side_address);
+    drop(mci);
+
+    // a crash before the mainstate was saved leaves it on the old branch with fewer mainblocks
+    for (file_name, rawbytes) in old_mainstate {
+        std::fs::write(dir.path().join("Mainstate").join(file_name), rawbytes).unwrap();
+    }
+    let mci = open_maincore(dir.path()).await;
+    assert_eq!(mci.get_mainstate().get_tip_hash(), tip_hash);
+    assert_eq!(mci.get_mainstate().get_mainblocks_count(), 4);
+    assert_eq!(mci.get_balance(&main_address), main_balance);
+    assert_eq!(mci.get_balance(&side_address), side_balance);
+}
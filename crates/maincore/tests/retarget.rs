@@ -0,0 +1,146 @@
+use num_bigint::BigUint;
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainblock::mainblock_validation::validate_mainheader;
+use maincore::mainblock::mainblock_validation::MainblockValidationContext;
+use maincore::mainblock::mainblock_validation::MainblockValidationError;
+use maincore::mainheader::mainheader::mine_mainheader_with_cpu;
+use maincore::mainheader::mainheader::Mainheader;
+use maincore::retarget::retarget::compute_next_bits;
+use maincore::retarget::retarget::get_median_time_past;
+use maincore::retarget::retarget::retarget_bits;
+use maincore::retarget::retarget::RetargetError;
+use utility::hash::bigint;
+use utility::hash::hash::Hash;
+
+const INTERVAL: usize = 10;
+const SPACING: i64 = 60;
+const EXPECTED_TIMESPAN: i64 = (INTERVAL as i64 - 1) * SPACING;
+
+fn test_params() -> ChainParams {
+    ChainParams {
+        retarget_interval: INTERVAL,
+        target_spacing: SPACING,
+        no_retargeting: false,
+        pow_limit_bits: 0x1f7fffff,
+        max_adjustment_factor: 4,
+        ..ChainParams::mainnet()
+    }
+}
+
+fn headers_with_timestamps(bits: u32, timestamps: &[i64]) -> Vec<Mainheader> {
+    timestamps
+        .iter()
+        .map(|timestamp| Mainheader::new(1, Hash::new_empty(), Hash::new_empty(), *timestamp, bits, 0, Hash::new_empty()))
+        .collect()
+}
+
+fn headers_with_spacing(bits: u32, count: usize, spacing: i64) -> Vec<Mainheader> {
+    let timestamps: Vec<i64> = (0..count as i64).map(|i| 1_000_000 + i * spacing).collect();
+    headers_with_timestamps(bits, &timestamps)
+}
+
+#[test]
+fn compact_roundtrip() {
+    for bits in [0x1e0fffffu32, 0x1f7fffff, 0x207fffff, 0x1d00ffff, 0x03123456] {
+        assert_eq!(bigint::compact_from_bigint(&bigint::bigint_from_compact(bits)), bits);
+    }
+    // a value whose top byte would set the sign bit of the mantissa takes one more byte
+    assert_eq!(bigint::compact_from_bigint(&BigUint::from(0x7fffffu32)), 0x037fffff);
+    assert_eq!(bigint::compact_from_bigint(&BigUint::from(0x800000u32)), 0x04008000);
+    assert_eq!(bigint::compact_from_bigint(&BigUint::from(0xffffffu32)), 0x0400ffff);
+}
+
+#[test]
+fn bits_unchanged_between_retargets() {
+    let params = test_params();
+    let headers = headers_with_spacing(0x1e0fffff, 7, SPACING * 100);
+    assert_eq!(compute_next_bits(7, &headers, &params), Ok(0x1e0fffff));
+}
+
+#[test]
+fn bits_unchanged_without_retargeting() {
+    let params = ChainParams::regtest();
+    let headers = headers_with_spacing(0x207fffff, params.retarget_interval, 1000);
+    assert_eq!(compute_next_bits(params.retarget_interval, &headers, &params), Ok(0x207fffff));
+}
+
+#[test]
+fn retarget_vectors() {
+    let params = test_params();
+    let vectors: [(u32, i64, u32); 8] = [
+        // (bits, actual timespan, expected bits)
+        (0x1e0fffff, EXPECTED_TIMESPAN, 0x1e0fffff),
+        (0x1e0fffff, EXPECTED_TIMESPAN * 2, 0x1e1ffffe),
+        (0x1e0fffff, EXPECTED_TIMESPAN / 2, 0x1e07ffff),
+        // clamped to 4x easier
+        (0x1e0fffff, EXPECTED_TIMESPAN * 10, 0x1e3ffffc),
+        // clamped to 4x harder
+        (0x1e0fffff, EXPECTED_TIMESPAN / 10, 0x1e03ffff),
+        // zero and negative timespans are clamped as well
+        (0x1e0fffff, 0, 0x1e03ffff),
+        (0x1e0fffff, -500, 0x1e03ffff),
+        // never easier than the pow limit
+        (0x1f3fffff, EXPECTED_TIMESPAN * 4, 0x1f7fffff),
+    ];
+    for (bits, actual_timespan, expected_bits) in vectors {
+        assert_eq!(retarget_bits(bits, actual_timespan, EXPECTED_TIMESPAN, &params), expected_bits, "bits {:#x} timespan {}", bits, actual_timespan);
+    }
+}
+
+#[test]
+fn retarget_over_header_window() {
+    let params = test_params();
+    // mainblocks twice slower than expected
+    let headers = headers_with_spacing(0x1e0fffff, 2 * INTERVAL, SPACING * 2);
+    assert_eq!(compute_next_bits(2 * INTERVAL, &headers, &params), Ok(0x1e1ffffe));
+    // only the last retarget_interval mainheaders matter
+    let mut timestamps: Vec<i64> = vec![0, 5, 10];
+    timestamps.extend((0..INTERVAL as i64).map(|i| 1_000_000 + i * SPACING));
+    let headers = headers_with_timestamps(0x1e0fffff, &timestamps);
+    assert_eq!(compute_next_bits(INTERVAL, &headers, &params), Ok(0x1e0fffff));
+    // mainheaders at the pow limit stay there when mainblocks are slow
+    let headers = headers_with_spacing(0x1f7fffff, INTERVAL, SPACING * 10);
+    assert_eq!(compute_next_bits(INTERVAL, &headers, &params), Ok(0x1f7fffff));
+}
+
+#[test]
+fn retarget_window_errors() {
+    let params = test_params();
+    assert_eq!(compute_next_bits(INTERVAL, &[], &params), Err(RetargetError::EmptyWindow));
+    let headers = headers_with_spacing(0x1e0fffff, INTERVAL - 1, SPACING);
+    assert_eq!(
+        compute_next_bits(INTERVAL, &headers, &params),
+        Err(RetargetError::WindowTooShort { expected: INTERVAL, actual: INTERVAL - 1 })
+    );
+}
+
+#[test]
+fn median_time_past_vectors() {
+    assert_eq!(get_median_time_past(&[]), None);
+    assert_eq!(get_median_time_past(&headers_with_timestamps(0x1e0fffff, &[42])), Some(42));
+    assert_eq!(get_median_time_past(&headers_with_timestamps(0x1e0fffff, &[5, 1, 3])), Some(3));
+    // only the last 11 timestamps count, unordered
+    let timestamps = [1000, 1, 2, 11, 10, 9, 8, 7, 6, 5, 4, 3];
+    assert_eq!(get_median_time_past(&headers_with_timestamps(0x1e0fffff, &timestamps)), Some(6));
+}
+
+#[test]
+fn timestamp_bounds() {
+    let prev = headers_with_timestamps(0x207fffff, &[1000])[0].clone();
+    let context = MainblockValidationContext {
+        height: 1,
+        prev_mainheader: Some(prev.clone()),
+        expected_bits: Some(0x207fffff),
+        median_time_past: Some(1000),
+        max_timestamp: Some(2000),
+    };
+    let mine = |timestamp: i64| {
+        let mh = mine_mainheader_with_cpu(1, prev.get_hash(), Hash::new_empty(), timestamp, 0x207fffff).unwrap();
+        assert_eq!(mh.get_timestamp(), timestamp);
+        validate_mainheader(&mh, &context)
+    };
+    assert!(mine(1001).is_ok());
+    assert!(mine(2000).is_ok());
+    assert!(matches!(mine(1000), Err(MainblockValidationError::TimestampTooOld { timestamp: 1000, median_time_past: 1000 })));
+    assert!(matches!(mine(2001), Err(MainblockValidationError::TimestampTooFarInFuture { timestamp: 2001, max_timestamp: 2000 })));
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use common::mine_tip;
+use common::open_maincore;
+use utility::hash::hash::Hash;
+use utility::storage::storage_directory::StorageDirectory;
+
+#[tokio::test]
+async fn test_mainblocks_of_the_old_layout_are_migrated() {
+    let dir = tempfile::tempdir().unwrap();
+    let miner_address = Hash::compute_hash(b"miner address");
+    let mut mci = open_maincore(&dir.path().join("node")).await;
+    for _ in 0..3 {
+        mine_tip(&mut mci, &miner_address).await;
+    }
+    let mut mainblocks = Vec::new();
+    for height in 0..mci.get_mainblocks_count() {
+        mainblocks.push(mci.get_mainblock(height).await.unwrap());
+    }
+
+    // one Mainblock<n> file per mainblock, the first ones written before chunk records existed
+    let old_path = dir.path().join("old_node");
+    let mut old_sd = StorageDirectory::new(old_path.join("Mainblocks"), String::from("Mainblock")).await.unwrap();
+    for (height, mb) in mainblocks[..2].iter().enumerate() {
+        std::fs::write(old_path.join("Mainblocks").join(format!("Mainblock{}", height)), mb.serialize()).unwrap();
+    }
+    old_sd.init().await.unwrap();
+    for mb in mainblocks[2..].iter() {
+        old_sd.add_chunk(&mb.serialize()).await.unwrap();
+    }
+
+    let mut migrated = open_maincore(&old_path).await;
+    assert_eq!(migrated.get_mainblocks_count(), mainblocks.len());
+    assert_eq!(migrated.get_last_inmem_mainheader().unwrap().get_hash(), mainblocks.last().unwrap().get_hash());
+    for (height, mb) in mainblocks.iter().enumerate() {
+        assert_eq!(migrated.get_mainblock(height).await.unwrap().get_hash(), mb.get_hash());
+    }
+    assert_eq!(migrated.get_balance(&miner_address), mci.get_balance(&miner_address));
+    drop(migrated);
+
+    // the old files are gone and the node opens again from the chunk store alone
+    let mut old_sd = StorageDirectory::new(old_path.join("Mainblocks"), String::from("Mainblock")).await.unwrap();
+    old_sd.init().await.unwrap();
+    assert_eq!(old_sd.chunk_count(), 0);
+    let reopened = open_maincore(&old_path).await;
+    assert_eq!(reopened.get_last_inmem_mainheader().unwrap().get_hash(), mainblocks.last().unwrap().get_hash());
+}
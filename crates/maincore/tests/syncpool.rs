@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainblock::mainblock::Mainblock;
+use maincore::mainheader::mainheader::mine_mainheader_with_cpu;
+use maincore::mainheader::mainheader::Mainheader;
+use maincore::syncpool::syncpool::Syncpool;
+use maincore::syncpool::syncpool::SyncpoolError;
+use utility::hash::hash::Hash;
+use utility::system::time::timestamp_now;
+
+/// `count` mainheaders on top of `prev`, `branch` tells the branches apart.
+fn mine_mainheaders(prev: &Mainheader, count: usize, branch: &[u8]) -> Vec<Mainheader> {
+    let mut mainheaders: Vec<Mainheader> = Vec::new();
+    for _ in 0..count {
+        let prev = mainheaders.last().unwrap_or(prev);
+        let mh = mine_mainheader_with_cpu(1, prev.get_hash(), Hash::compute_hash(branch), prev.get_timestamp() + 1, prev.get_bits()).unwrap();
+        mainheaders.push(mh);
+    }
+    mainheaders
+}
+
+struct TestChain {
+    chain_params: ChainParams,
+    main_mainheaders: Vec<Mainheader>,
+    main_heights: HashMap<Hash, usize>,
+}
+
+impl TestChain {
+    fn new() -> Self {
+        let chain_params = ChainParams::regtest();
+        let genesis = chain_params.get_genesis_mainblock().get_mainheader();
+        let main_heights = HashMap::from([(genesis.get_hash(), 0)]);
+        Self { chain_params, main_mainheaders: vec![genesis], main_heights }
+    }
+
+    fn add(&self, syncpool: &mut Syncpool, mainheaders: &[Mainheader]) -> Result<usize, SyncpoolError> {
+        syncpool.add_mainheaders(mainheaders, &self.main_mainheaders, &self.main_heights, &self.chain_params, timestamp_now())
+    }
+}
+
+#[test]
+fn test_better_branch_replaces_the_synced_branch() {
+    let chain = TestChain::new();
+    let mut syncpool = Syncpool::new();
+    let synced = mine_mainheaders(&chain.main_mainheaders[0], 3, b"synced");
+    assert_eq!(chain.add(&mut syncpool, &synced).unwrap(), 3);
+
+    // a branch with less work is kept aside, its next batch makes it better
+    let better = mine_mainheaders(&chain.main_mainheaders[0], 4, b"better");
+    assert_eq!(chain.add(&mut syncpool, &better[..2]).unwrap(), 2);
+    assert!(syncpool.is_pending(&synced[2].get_hash()));
+    assert!(!syncpool.is_pending(&better[0].get_hash()));
+    // mainheaders sent again are skipped
+    assert_eq!(chain.add(&mut syncpool, &better[..2]).unwrap(), 0);
+    assert_eq!(chain.add(&mut syncpool, &better[2..]).unwrap(), 2);
+    assert_eq!(syncpool.get_target_mainblocks_count(), 5);
+    assert!(better.iter().all(|mh| syncpool.is_pending(&mh.get_hash())));
+    assert!(synced.iter().all(|mh| !syncpool.is_pending(&mh.get_hash())));
+
+    // the new branch is extended as usual
+    let next = mine_mainheaders(&better[3], 1, b"better");
+    assert_eq!(chain.add(&mut syncpool, &next).unwrap(), 1);
+    assert_eq!(syncpool.get_target_mainblocks_count(), 6);
+}
+
+#[test]
+fn test_better_branch_forking_from_the_queue() {
+    let chain = TestChain::new();
+    let mut syncpool = Syncpool::new();
+    let synced = mine_mainheaders(&chain.main_mainheaders[0], 3, b"synced");
+    chain.add(&mut syncpool, &synced).unwrap();
+    assert!(syncpool.add_mainblock(Mainblock::new(synced[2].clone(), Vec::new())));
+    assert_eq!(syncpool.get_downloaded_count(), 1);
+
+    // as much work after the fork is not enough
+    let fork = mine_mainheaders(&synced[0], 3, b"fork");
+    chain.add(&mut syncpool, &fork[..2]).unwrap();
+    assert!(syncpool.is_pending(&synced[2].get_hash()));
+    chain.add(&mut syncpool, &fork[2..]).unwrap();
+    assert_eq!(syncpool.get_pending_count(), 4);
+    assert!(syncpool.is_pending(&synced[0].get_hash()));
+    assert!(!syncpool.is_pending(&synced[1].get_hash()));
+    assert!(fork.iter().all(|mh| syncpool.is_pending(&mh.get_hash())));
+    // the mainblock of the replaced branch is dropped
+    assert_eq!(syncpool.get_downloaded_count(), 0);
+}
+
+#[test]
+fn test_invalid_or_unknown_branch_is_rejected() {
+    let chain = TestChain::new();
+    let mut syncpool = Syncpool::new();
+    let synced = mine_mainheaders(&chain.main_mainheaders[0], 2, b"synced");
+    chain.add(&mut syncpool, &synced).unwrap();
+
+    let unknown = Mainheader::new(1, Hash::compute_hash(b"unknown"), Hash::compute_hash(b"root"), 0, 0x207fffff, 0, Hash::new_empty());
+    assert!(matches!(chain.add(&mut syncpool, &[unknown]), Err(SyncpoolError::UnknownParent(_))));
+
+    let mut invalid = mine_mainheaders(&chain.main_mainheaders[0], 3, b"invalid");
+    invalid[2] = Mainheader::new(1, invalid[1].get_hash(), Hash::compute_hash(b"root"), invalid[1].get_timestamp() + 1, 0x1d00ffff, 0, Hash::new_empty());
+    assert!(matches!(chain.add(&mut syncpool, &invalid), Err(SyncpoolError::MainblockValidationError(_))));
+    assert_eq!(syncpool.get_pending_count(), 2);
+    assert!(synced.iter().all(|mh| syncpool.is_pending(&mh.get_hash())));
+}
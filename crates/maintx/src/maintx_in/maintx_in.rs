@@ -66,7 +66,7 @@ impl MaintxInEcdsa {
     pub fn check_signature(&self, hash: Hash) -> bool {
         match verify_signature(&self.publickey, hash, &self.signature) {
             Ok(value) => {
-               return false;
+               return value;
             }
             Err(_) => {
                 return false;
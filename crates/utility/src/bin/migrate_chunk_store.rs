@@ -0,0 +1,31 @@
+//! Moves the chunks of a one-file-per-chunk storage directory into a chunk store.
+//!
+//! usage: migrate_chunk_store <directory> <category> [--keep-old]
+use utility::storage::chunk_store::migrate_storage_directory;
+use utility::storage::chunk_store::ChunkStore;
+use utility::storage::storage_directory::StorageDirectory;
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        eprintln!("usage: {} <directory> <category> [--keep-old]", args[0]);
+        std::process::exit(2);
+    }
+    let remove_old = !args[3..].iter().any(|arg| arg == "--keep-old");
+    let result = async {
+        let mut storage_directory = StorageDirectory::new(&args[1], args[2].clone()).await?;
+        let mut chunk_store = ChunkStore::new(&args[1], args[2].clone()).await?;
+        chunk_store.init().await?;
+        let copied_count = migrate_storage_directory(&mut storage_directory, &mut chunk_store, remove_old).await?;
+        Ok::<(usize, usize), utility::storage::chunk_store::ChunkStoreError>((copied_count, chunk_store.chunk_count()))
+    }
+    .await;
+    match result {
+        Ok((copied_count, chunk_count)) => println!("migrated {} chunks, the chunk store holds {} chunks", copied_count, chunk_count),
+        Err(e) => {
+            eprintln!("migration failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
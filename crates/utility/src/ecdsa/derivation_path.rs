@@ -0,0 +1,112 @@
+use std::fmt;
+use thiserror::Error;
+use crate::buffer::buffer_writer::BufferWriter;
+use crate::buffer::buffer_reader::BufferReader;
+use crate::buffer::buffer_reader::BufferReaderError;
+use crate::ecdsa::key_derivation_v1::HARDENED_OFFSET;
+
+/// Chain of the receiving addresses of an account: m/account'/0/index.
+pub const EXTERNAL_CHAIN: u32 = 0;
+/// Chain of the change addresses of an account: m/account'/1/index.
+pub const INTERNAL_CHAIN: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum DerivationPathError {
+    #[error("Derivation path must start with m: {0}")]
+    MissingMaster(String),
+    #[error("Invalid derivation path index: {0}")]
+    InvalidIndex(String),
+    #[error("Buffer reader error: {0}")]
+    BufferReaderError(#[from] BufferReaderError),
+}
+
+/// Indexes leading from the master key to a key, e.g. m/0'/1/5. Hardened indexes include `HARDENED_OFFSET`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DerivationPath {
+    indexes: Vec<u32>,
+}
+
+impl DerivationPath {
+    /// The path of the master key itself, m.
+    pub fn master() -> Self {
+        DerivationPath { indexes: Vec::new() }
+    }
+    /// The path of an account key, m/account'.
+    pub fn account(account: u32) -> Self {
+        DerivationPath::master().hardened_child(account)
+    }
+    pub fn child(&self, index: u32) -> Self {
+        let mut indexes = self.indexes.clone();
+        indexes.push(index);
+        DerivationPath { indexes }
+    }
+    pub fn hardened_child(&self, index: u32) -> Self {
+        self.child(index | HARDENED_OFFSET)
+    }
+    pub fn get_indexes(&self) -> &[u32] {
+        &self.indexes
+    }
+    pub fn get_last_index(&self) -> Option<u32> {
+        self.indexes.last().copied()
+    }
+    pub fn get_depth(&self) -> usize {
+        self.indexes.len()
+    }
+    /// The indexes following `parent`, if this path goes through it.
+    pub fn strip_prefix(&self, parent: &DerivationPath) -> Option<&[u32]> {
+        self.indexes.strip_prefix(parent.indexes.as_slice())
+    }
+    pub fn serialize(&self, bw: &mut BufferWriter) {
+        bw.put_var_u32(self.indexes.len() as u32);
+        for index in self.indexes.iter() {
+            bw.put_u32(*index);
+        }
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for index in self.indexes.iter() {
+            if *index >= HARDENED_OFFSET {
+                write!(f, "/{}'", index - HARDENED_OFFSET)?;
+            } else {
+                write!(f, "/{}", index)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a path such as m/0'/1/5, where `'` or `h` marks a hardened index.
+pub fn parse_derivation_path(path: &str) -> Result<DerivationPath, DerivationPathError> {
+    let mut parts = path.trim().split('/');
+    if parts.next() != Some("m") {
+        return Err(DerivationPathError::MissingMaster(path.to_string()));
+    }
+    let mut derivation_path = DerivationPath::master();
+    for part in parts {
+        let (number, hardened) = match part.strip_suffix('\'').or_else(|| part.strip_suffix('h')) {
+            Some(number) => (number, true),
+            None => (part, false),
+        };
+        if number.is_empty() || !number.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(DerivationPathError::InvalidIndex(part.to_string()));
+        }
+        let index: u32 = number.parse().map_err(|_| DerivationPathError::InvalidIndex(part.to_string()))?;
+        if index >= HARDENED_OFFSET {
+            return Err(DerivationPathError::InvalidIndex(part.to_string()));
+        }
+        derivation_path = if hardened { derivation_path.hardened_child(index) } else { derivation_path.child(index) };
+    }
+    Ok(derivation_path)
+}
+
+pub fn unserialize_derivation_path(br: &mut BufferReader) -> Result<DerivationPath, DerivationPathError> {
+    let count = br.get_var_u32()?;
+    let mut indexes = Vec::new();
+    for _ in 0..count {
+        indexes.push(br.get_u32()?);
+    }
+    Ok(DerivationPath { indexes })
+}
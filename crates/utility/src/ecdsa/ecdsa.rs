@@ -5,6 +5,11 @@ use thiserror::Error;
 use crate::ecdsa::key_derivation_v1::KeyDerivationError;
 use crate::ecdsa::key_derivation_v1::ExtendedSecretKey;
 use crate::ecdsa::key_derivation_v1::derive_child_extended_secret_key;
+use crate::ecdsa::key_derivation_v1::derive_child_extended_public_key;
+use crate::ecdsa::key_derivation_v1::ExtendedPublicKey;
+use crate::ecdsa::key_derivation_v1::derive_extended_secret_key_from_path;
+use crate::ecdsa::key_derivation_v1::derive_extended_public_key_from_indexes;
+use crate::ecdsa::derivation_path::DerivationPath;
 
 use crate::hash::hash;
 use crate::hash::hash::Hash;
@@ -40,6 +45,7 @@ pub struct EcdsaKeySet {
 pub struct EcdsaKeySet {
     extended_secret_key: ExtendedSecretKey,
     derivation_index:u32,
+    derivation_path: DerivationPath,
     secret_key: SecretKey,
     public_key: PublicKey,
     address: Hash,
@@ -65,6 +71,44 @@ impl EcdsaKeySet {
     pub fn get_derivation_index(&self)->u32{
         self.derivation_index
     }
+    pub fn get_derivation_path(&self)->&DerivationPath{
+        &self.derivation_path
+    }
+    pub fn get_public_key_set(&self)->EcdsaPublicKeySet{
+        EcdsaPublicKeySet {
+            derivation_index: self.derivation_index,
+            derivation_path: self.derivation_path.clone(),
+            public_key: self.public_key,
+            address: self.address.clone(),
+        }
+    }
+}
+
+/// Public part of an `EcdsaKeySet`: enough to receive and to watch an address, not to sign.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EcdsaPublicKeySet {
+    derivation_index:u32,
+    derivation_path: DerivationPath,
+    public_key: PublicKey,
+    address: Hash,
+}
+
+impl EcdsaPublicKeySet {
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+    pub fn get_public_key_compressed_bytes(&self) -> Vec<u8> {
+        self.public_key.serialize().to_vec()
+    }
+    pub fn get_address(&self)->Hash{
+        self.address.clone()
+    }
+    pub fn get_derivation_index(&self)->u32{
+        self.derivation_index
+    }
+    pub fn get_derivation_path(&self)->&DerivationPath{
+        &self.derivation_path
+    }
 }
 /*
 /// Generates a keypair from a given secret key byte slice.
@@ -82,8 +126,20 @@ pub fn generate_keyset(secret_key_bytes: &[u8]) -> Result<EcdsaKeySet, EcdsaKeyS
 }
 */
 //////////////
+/// Derives the key set of the child `derivation_index` of the master key, whose path is m/derivation_index.
 pub fn derive_child_key_set(master_extended_secret_key: &ExtendedSecretKey, derivation_index: u32,hardened:bool) -> Result<EcdsaKeySet, EcdsaKeySetError> {
     let child_extended_secret_key = derive_child_extended_secret_key(master_extended_secret_key, derivation_index,hardened)?;
+    new_key_set(child_extended_secret_key, DerivationPath::master().child(derivation_index))
+}
+
+/// Derives the key set at `derivation_path` from the master key.
+pub fn derive_key_set_from_path(master_extended_secret_key: &ExtendedSecretKey, derivation_path: &DerivationPath) -> Result<EcdsaKeySet, EcdsaKeySetError> {
+    let child_extended_secret_key = derive_extended_secret_key_from_path(master_extended_secret_key, derivation_path)?;
+    new_key_set(child_extended_secret_key, derivation_path.clone())
+}
+
+fn new_key_set(child_extended_secret_key: ExtendedSecretKey, derivation_path: DerivationPath) -> Result<EcdsaKeySet, EcdsaKeySetError> {
+    let derivation_index = derivation_path.get_last_index().unwrap_or(0);
     let secp = Secp256k1::new();
     let secret_key = SecretKey::from_slice(&child_extended_secret_key.secret_key())
         .map_err(|e| EcdsaKeySetError::SecretKeyParseError(e.to_string()))?;
@@ -93,12 +149,42 @@ pub fn derive_child_key_set(master_extended_secret_key: &ExtendedSecretKey, deri
     Ok(EcdsaKeySet {
         extended_secret_key: child_extended_secret_key,
         derivation_index,
+        derivation_path,
         secret_key,
         public_key,
         address,
     })
 }
 /////////////
+/// Derives the public key set of the non-hardened child `derivation_index` of an extended public key,
+/// whose path is m/derivation_index relative to that key.
+pub fn derive_child_public_key_set(extended_public_key: &ExtendedPublicKey, derivation_index: u32) -> Result<EcdsaPublicKeySet, EcdsaKeySetError> {
+    let public_key = derive_child_extended_public_key(extended_public_key, derivation_index)?.get_public_key();
+    Ok(new_public_key_set(public_key, DerivationPath::master().child(derivation_index)))
+}
+
+/// Derives the public key set at `derivation_path` from the extended public key at `extended_public_key_path`.
+/// The path must go through that key and continue with non-hardened indexes only.
+pub fn derive_public_key_set_from_path(
+    extended_public_key: &ExtendedPublicKey,
+    extended_public_key_path: &DerivationPath,
+    derivation_path: &DerivationPath,
+) -> Result<EcdsaPublicKeySet, EcdsaKeySetError> {
+    let indexes = derivation_path
+        .strip_prefix(extended_public_key_path)
+        .ok_or(KeyDerivationError::DerivationPathOutsideExtendedPublicKey)?;
+    let public_key = derive_extended_public_key_from_indexes(extended_public_key, indexes)?.get_public_key();
+    Ok(new_public_key_set(public_key, derivation_path.clone()))
+}
+
+fn new_public_key_set(public_key: PublicKey, derivation_path: DerivationPath) -> EcdsaPublicKeySet {
+    EcdsaPublicKeySet {
+        derivation_index: derivation_path.get_last_index().unwrap_or(0),
+        derivation_path,
+        address: Hash::compute_hash(&public_key.serialize()),
+        public_key,
+    }
+}
 
 /// Signs a message hash with the provided keypair.
 pub fn sign_messagehash(kp: &EcdsaKeySet, message_hash: Hash) -> Result<Vec<u8>, EcdsaKeySetError> {
@@ -6,16 +6,29 @@ use secp256k1::{SecretKey, PublicKey, Secp256k1, Scalar};
 use secp256k1::constants::SECRET_KEY_SIZE;
 use zeroize::Zeroize;
 use thiserror::Error;
+use crate::hash::hash::Hash;
+use crate::ecdsa::derivation_path::DerivationPath;
 
 pub const HARDENED_OFFSET: u32 = 0x80000000;
+/// Size of a serialized `ExtendedSecretKey`: the secret key and the chain code.
+pub const EXTENDED_SECRET_KEY_SIZE: usize = SECRET_KEY_SIZE + 32;
+/// Marks a serialized `ExtendedPublicKey`: the magic, the compressed public key, the chain code, then a checksum.
+const EXTENDED_PUBLIC_KEY_MAGIC: [u8; 4] = *b"GXP1";
+pub const EXTENDED_PUBLIC_KEY_SIZE: usize = 4 + 33 + 32 + 4;
 const PBKDF2_ITERATIONS: u32 = 2000;//310_000;
 const SALT: &[u8] = b"crypto_wallet_salt";
+/// Size of the seed a BIP-39 mnemonic is stretched into.
+pub const BIP39_SEED_SIZE: usize = 64;
+const BIP39_PBKDF2_ITERATIONS: u32 = 2048;
+const BIP39_SALT_PREFIX: &str = "mnemonic";
 
 /// Custom error type for key derivation
 #[derive(Debug, Error)]
 pub enum KeyDerivationError {
     #[error("Seed must be exactly 64 bytes")]
     InvalidSeedLength,
+    #[error("BIP-39 mnemonic and passphrase must be ASCII")]
+    NonAsciiBip39Input,
     #[error("HMAC initialization failed")]
     HmacInitFailed,
     #[error("Invalid secret key derived")]
@@ -32,6 +45,12 @@ pub enum KeyDerivationError {
     InvalidTweakSize,
     #[error("Invalid resulting public key")]
     InvalidResultingPublicKey,
+    #[error("Invalid extended public key")]
+    InvalidExtendedPublicKey,
+    #[error("Extended public key checksum mismatch")]
+    ExtendedPublicKeyChecksumMismatch,
+    #[error("Derivation path does not go through the extended public key")]
+    DerivationPathOutsideExtendedPublicKey,
 }
 
 /// Represents an extended secret key with a chain code.
@@ -49,6 +68,96 @@ impl ExtendedSecretKey {
     pub fn secret_key(&self) -> [u8; SECRET_KEY_SIZE] {
         self.secret_key
     }
+    /// The extended public key of this key: it derives the public keys of the non-hardened children.
+    pub fn get_extended_public_key(&self) -> Result<ExtendedPublicKey, KeyDerivationError> {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&self.secret_key).map_err(|_| KeyDerivationError::InvalidSecretKey)?;
+        Ok(ExtendedPublicKey { public_key: PublicKey::from_secret_key(&secp, &secret_key), chain_code: self.chain_code })
+    }
+    /// The secret key followed by the chain code. The caller must zeroize the bytes once used.
+    pub fn serialize(&self) -> [u8; EXTENDED_SECRET_KEY_SIZE] {
+        let mut bytes = [0u8; EXTENDED_SECRET_KEY_SIZE];
+        bytes[..SECRET_KEY_SIZE].copy_from_slice(&self.secret_key);
+        bytes[SECRET_KEY_SIZE..].copy_from_slice(&self.chain_code);
+        bytes
+    }
+}
+
+/// Represents an extended public key with a chain code, holding no secret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedPublicKey {
+    public_key: PublicKey,
+    chain_code: [u8; 32],
+}
+
+impl ExtendedPublicKey {
+    pub fn get_public_key(&self) -> PublicKey {
+        self.public_key
+    }
+    pub fn get_chain_code(&self) -> [u8; 32] {
+        self.chain_code
+    }
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(EXTENDED_PUBLIC_KEY_SIZE);
+        bytes.extend_from_slice(&EXTENDED_PUBLIC_KEY_MAGIC);
+        bytes.extend_from_slice(&self.public_key.serialize());
+        bytes.extend_from_slice(&self.chain_code);
+        let checksum = compute_extended_public_key_checksum(&bytes);
+        bytes.extend_from_slice(&checksum);
+        bytes
+    }
+    /// Hexadecimal form of `serialize`, to be shared with a watch-only wallet.
+    pub fn to_hex_string(&self) -> String {
+        self.serialize().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+/// Restores an `ExtendedPublicKey` from the bytes of `ExtendedPublicKey::serialize`, checking its checksum.
+pub fn unserialize_extended_public_key(bytes: &[u8]) -> Result<ExtendedPublicKey, KeyDerivationError> {
+    if bytes.len() != EXTENDED_PUBLIC_KEY_SIZE || bytes[..4] != EXTENDED_PUBLIC_KEY_MAGIC {
+        return Err(KeyDerivationError::InvalidExtendedPublicKey);
+    }
+    let (content, checksum) = bytes.split_at(EXTENDED_PUBLIC_KEY_SIZE - 4);
+    if checksum != compute_extended_public_key_checksum(content) {
+        return Err(KeyDerivationError::ExtendedPublicKeyChecksumMismatch);
+    }
+    let public_key = PublicKey::from_slice(&content[4..37]).map_err(|_| KeyDerivationError::InvalidExtendedPublicKey)?;
+    let mut chain_code = [0u8; 32];
+    chain_code.copy_from_slice(&content[37..]);
+    Ok(ExtendedPublicKey { public_key, chain_code })
+}
+
+/// Restores an `ExtendedPublicKey` from the string of `ExtendedPublicKey::to_hex_string`.
+pub fn unserialize_extended_public_key_hex(hex_string: &str) -> Result<ExtendedPublicKey, KeyDerivationError> {
+    let hex_string = hex_string.trim();
+    if hex_string.len() != EXTENDED_PUBLIC_KEY_SIZE * 2 || !hex_string.is_ascii() {
+        return Err(KeyDerivationError::InvalidExtendedPublicKey);
+    }
+    let mut bytes = Vec::with_capacity(EXTENDED_PUBLIC_KEY_SIZE);
+    for i in (0..hex_string.len()).step_by(2) {
+        let byte = u8::from_str_radix(&hex_string[i..i + 2], 16).map_err(|_| KeyDerivationError::InvalidExtendedPublicKey)?;
+        bytes.push(byte);
+    }
+    unserialize_extended_public_key(&bytes)
+}
+
+fn compute_extended_public_key_checksum(content: &[u8]) -> [u8; 4] {
+    let hash = Hash::compute_hash(content);
+    let bytes = hash.as_bytes();
+    [bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
+/// Restores an `ExtendedSecretKey` from the bytes of `ExtendedSecretKey::serialize`.
+pub fn unserialize_extended_secret_key(bytes: &[u8]) -> Result<ExtendedSecretKey, KeyDerivationError> {
+    if bytes.len() != EXTENDED_SECRET_KEY_SIZE {
+        return Err(KeyDerivationError::InvalidSecretKey);
+    }
+    SecretKey::from_slice(&bytes[..SECRET_KEY_SIZE]).map_err(|_| KeyDerivationError::InvalidSecretKey)?;
+    let mut secret_key = [0u8; SECRET_KEY_SIZE];
+    let mut chain_code = [0u8; 32];
+    secret_key.copy_from_slice(&bytes[..SECRET_KEY_SIZE]);
+    chain_code.copy_from_slice(&bytes[SECRET_KEY_SIZE..]);
+    Ok(ExtendedSecretKey::new(secret_key, chain_code))
 }
 
 impl Zeroize for ExtendedSecretKey {
@@ -86,10 +195,20 @@ pub fn derive_master_extended_secret_key(seed: &str) -> Result<ExtendedSecretKey
 
 
 
+    let master_extended_secret_key = derive_master_extended_secret_key_from_seed_bytes(&master_seed_bytes);
+    master_seed_bytes.zeroize();
+    master_extended_secret_key
+}
+
+/// Derives the master extended secret key from the bytes of a stretched seed, e.g. a BIP-39 seed.
+pub fn derive_master_extended_secret_key_from_seed_bytes(master_seed_bytes: &[u8]) -> Result<ExtendedSecretKey, KeyDerivationError> {
+    if master_seed_bytes.len() < 16 || master_seed_bytes.len() > 64 {
+        return Err(KeyDerivationError::InvalidSeedLength);
+    }
     let mut mac = Hmac::<Sha512>::new_from_slice(b"Crypto seed")
         //.map_err(|_| "HMAC initialization failed")?;
         .map_err(|_| KeyDerivationError::HmacInitFailed)?;
-    mac.update(&master_seed_bytes);
+    mac.update(master_seed_bytes);
     let result = mac.finalize().into_bytes();
 
     let (secret_key_bytes, chain_code_bytes) = result.split_at(SECRET_KEY_SIZE);
@@ -111,6 +230,24 @@ pub fn derive_master_extended_secret_key(seed: &str) -> Result<ExtendedSecretKey
     Ok(ExtendedSecretKey::new(secret_key, chain_code))
 }
 
+/// Stretches a BIP-39 mnemonic and its optional passphrase into the seed of the standard: PBKDF2-HMAC-SHA512
+/// with 2048 iterations, salted with "mnemonic" followed by the passphrase. The standard normalizes both to
+/// NFKD first, which only leaves ASCII unchanged, so other characters are rejected rather than giving a seed
+/// other tools would not find. The caller must zeroize the seed once used.
+pub fn derive_bip39_seed(mnemonic: &str, passphrase: &str) -> Result<[u8; BIP39_SEED_SIZE], KeyDerivationError> {
+    if !mnemonic.is_ascii() || !passphrase.is_ascii() {
+        return Err(KeyDerivationError::NonAsciiBip39Input);
+    }
+    let mut mnemonic = mnemonic.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let mut salt = format!("{}{}", BIP39_SALT_PREFIX, passphrase);
+    let mut seed_bytes = [0u8; BIP39_SEED_SIZE];
+    let result = pbkdf2::<Hmac<Sha512>>(mnemonic.as_bytes(), salt.as_bytes(), BIP39_PBKDF2_ITERATIONS, &mut seed_bytes);
+    salt.zeroize();
+    mnemonic.zeroize();
+    result.map_err(|_| KeyDerivationError::HmacInitFailed)?;
+    Ok(seed_bytes)
+}
+
 /// Derives a child extended secret key.
 /// 
 /// # Arguments
@@ -169,17 +306,61 @@ pub fn derive_child_extended_secret_key(
     Ok(ExtendedSecretKey::new(child_sk.secret_bytes(), new_chain_code))
 }
 
+/// Derives the extended secret key at `path` from the master extended secret key, hardening the
+/// indexes from `HARDENED_OFFSET`.
+pub fn derive_extended_secret_key_from_path(
+    master: &ExtendedSecretKey,
+    path: &DerivationPath,
+) -> Result<ExtendedSecretKey, KeyDerivationError> {
+    let mut extended_secret_key = master.clone();
+    for index in path.get_indexes().iter() {
+        extended_secret_key = derive_child_extended_secret_key(&extended_secret_key, *index, *index >= HARDENED_OFFSET)?;
+    }
+    Ok(extended_secret_key)
+}
+
+/// Derives the extended public key following the non-hardened `indexes` from `parent`.
+pub fn derive_extended_public_key_from_indexes(
+    parent: &ExtendedPublicKey,
+    indexes: &[u32],
+) -> Result<ExtendedPublicKey, KeyDerivationError> {
+    let mut extended_public_key = parent.clone();
+    for index in indexes.iter() {
+        extended_public_key = derive_child_extended_public_key(&extended_public_key, *index)?;
+    }
+    Ok(extended_public_key)
+}
+
 /// Derives a child public key from an extended public key.
 /// 
 /// # Arguments
 /// - `parent_pubkey`: The parent public key.
 /// - `chain_code`: The chain code associated with the parent.
 /// - `index`: The index of the child key.
-fn derive_child_public_key(
+pub fn derive_child_public_key(
     parent_pubkey: &PublicKey,
     chain_code: &[u8],
     index: u32,
 ) -> Result<PublicKey, KeyDerivationError> {
+    let (child_pubkey, _new_chain_code) = derive_child_public_key_and_chain_code(parent_pubkey, chain_code, index)?;
+    Ok(child_pubkey)
+}
+
+/// Derives a child extended public key. It has the public key of the child extended secret key
+/// derived with the same non-hardened `index`.
+pub fn derive_child_extended_public_key(
+    parent: &ExtendedPublicKey,
+    index: u32,
+) -> Result<ExtendedPublicKey, KeyDerivationError> {
+    let (public_key, chain_code) = derive_child_public_key_and_chain_code(&parent.public_key, &parent.chain_code, index)?;
+    Ok(ExtendedPublicKey { public_key, chain_code })
+}
+
+fn derive_child_public_key_and_chain_code(
+    parent_pubkey: &PublicKey,
+    chain_code: &[u8],
+    index: u32,
+) -> Result<(PublicKey, [u8; 32]), KeyDerivationError> {
     if index >= HARDENED_OFFSET {
         //return Err("Cannot derive hardened key from public key");
         return Err(KeyDerivationError::PublicKeyHardenedDerivationError);
@@ -192,7 +373,7 @@ fn derive_child_public_key(
     mac.update(&index.to_be_bytes());
     let result = mac.finalize().into_bytes();
 
-    let (key_tweak_bytes, _new_chain_code) = result.split_at(SECRET_KEY_SIZE);
+    let (key_tweak_bytes, chain_code_bytes) = result.split_at(SECRET_KEY_SIZE);
 
     let key_tweak_array: [u8; 32] = key_tweak_bytes
         .try_into()
@@ -206,5 +387,7 @@ fn derive_child_public_key(
         //.map_err(|_| "Invalid resulting public key")?;
         .map_err(|_| KeyDerivationError::InvalidResultingPublicKey)?;
 
-    Ok(child_pubkey)
+    let mut new_chain_code = [0u8; 32];
+    new_chain_code.copy_from_slice(chain_code_bytes);
+    Ok((child_pubkey, new_chain_code))
 }
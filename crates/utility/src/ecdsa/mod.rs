@@ -1,2 +1,3 @@
 pub mod ecdsa;
-pub mod key_derivation_v1;
\ No newline at end of file
+pub mod key_derivation_v1;
+pub mod derivation_path;
@@ -11,7 +11,8 @@ pub fn bigint_from_hash(h: &Hash) -> BigUint {
 pub fn compact_from_bigint(value: &BigUint) -> u32 {
     let big_lim = BigUint::from(0x00800000u32); // 24-bit boundary
     let mut tvalue = value.clone();
-    let mut exponent: u32 = 0;
+    // the exponent is the size in bytes, a mantissa alone already fills 3 bytes
+    let mut exponent: u32 = 3;
 
     // Shift right until the value fits within 24 bits
     while tvalue >= big_lim {
@@ -19,13 +20,8 @@ pub fn compact_from_bigint(value: &BigUint) -> u32 {
         exponent += 1;
     }
 
-    // Extract the mantissa and shift the value back if necessary
+    // The value now fits within 23 bits, the sign bit of the mantissa is never set
     let mantissa = tvalue.to_u32().unwrap_or(0);
-    if mantissa & 0x00800000 != 0 {
-        // If mantissa overflows 24 bits, adjust and increment exponent
-        tvalue >>= 8;
-        exponent += 1;
-    }
 
     // Compact format: [exponent (8 bits)][mantissa (24 bits)]
     (exponent << 24) | (mantissa & 0x007fffff)
@@ -50,3 +46,10 @@ pub fn bigint_from_compact(compact: u32) -> BigUint {
 pub fn bigint_from_u64(value: u64) -> BigUint {
     BigUint::from(value)
 }
+
+/// Expected number of hashes needed to find a hash below the target of `compact`,
+/// computed as 2^256 / (target + 1).
+pub fn work_from_compact(compact: u32) -> BigUint {
+    let target = bigint_from_compact(compact);
+    (BigUint::from(1u32) << 256) / (target + BigUint::from(1u32))
+}
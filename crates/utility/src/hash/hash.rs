@@ -13,7 +13,7 @@ pub enum HashError {
 }
 
 /// A wrapper around a 32-byte hash.
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
 pub struct Hash([u8; HASH_SIZE]);
 
 impl Hash {
@@ -1,15 +1,47 @@
 
+use thiserror::Error;
 use super::hash::Hash;
 use super::hash::HASH_SIZE;
 
+/// Prefix of a leaf hash, so that a leaf can never be taken for a branch.
+const LEAF_PREFIX: u8 = 0x00;
+/// Prefix of a branch hash.
+const BRANCH_PREFIX: u8 = 0x01;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TreeError {
+    #[error("Leaf index {index} is out of range for {leaves_count} leaves")]
+    IndexOutOfRange { index: usize, leaves_count: usize },
+
+    #[error("Proof has {actual} hashes, {expected} expected")]
+    InvalidProofLength { expected: usize, actual: usize },
+
+    /// Two sibling nodes are equal: the tree cannot be told apart from the one where the odd last node was duplicated.
+    #[error("Sibling nodes {index} and {} of level {level} are equal", index + 1)]
+    DuplicatedNodes { level: usize, index: usize },
+
+    #[error("Proof does not lead to the root")]
+    RootMismatch,
+}
+
+/// Path from a leaf to the root: the sibling of the node at each level, from the leaves up.
+/// The sibling of the last node of an odd level is the node itself and is left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub index: u32,
+    pub leaves_count: u32,
+    pub siblings: Vec<Hash>,
+}
+
 /// Compute the Merkle root for a blockchain-like structure using in-place memory updates.
+/// Leaves and branches are hashed with different prefixes, the last node of an odd level is paired with itself.
 pub fn compute_root(hashes: &[Hash]) -> Hash {
         if hashes.is_empty() {
             return Hash::new_empty();
         }
 
-        // Create a mutable vector from the input hashes.
-        let mut nodes = hashes.to_vec();
+        // Create a mutable vector from the leaf hashes.
+        let mut nodes: Vec<Hash> = hashes.iter().map(compute_leaf).collect();
         let mut length = nodes.len();
 
         while length > 1 {
@@ -35,11 +67,119 @@ pub fn compute_root(hashes: &[Hash]) -> Hash {
         nodes[0].clone()
 }
 
-/// Compute a branch hash with strict ordering and double SHA-256.
+/// Same as `compute_root`, but fails if two sibling nodes are equal. Such a list of hashes has the root of
+/// another list (e.g. `[a,b,c]` and `[a,b,c,c]`), so it must be rejected rather than trusted.
+pub fn compute_root_checked(hashes: &[Hash]) -> Result<Hash, TreeError> {
+    let mut level = 0;
+    let mut nodes: Vec<Hash> = hashes.iter().map(compute_leaf).collect();
+    while nodes.len() > 1 {
+        for i in (0..nodes.len() - 1).step_by(2) {
+            if nodes[i] == nodes[i + 1] {
+                return Err(TreeError::DuplicatedNodes { level, index: i });
+            }
+        }
+        nodes = compute_next_level(&nodes);
+        level += 1;
+    }
+    Ok(nodes.first().cloned().unwrap_or_else(Hash::new_empty))
+}
+
+/// Compute the proof that `hashes[index]` is a leaf of `compute_root(hashes)`.
+pub fn compute_proof(hashes: &[Hash], index: usize) -> Result<MerkleProof, TreeError> {
+    if index >= hashes.len() {
+        return Err(TreeError::IndexOutOfRange { index, leaves_count: hashes.len() });
+    }
+    let mut siblings = Vec::new();
+    let mut nodes: Vec<Hash> = hashes.iter().map(compute_leaf).collect();
+    let mut position = index;
+    while nodes.len() > 1 {
+        let sibling_position = position ^ 1;
+        if sibling_position < nodes.len() {
+            siblings.push(nodes[sibling_position].clone());
+        }
+        nodes = compute_next_level(&nodes);
+        position /= 2;
+    }
+    Ok(MerkleProof { index: index as u32, leaves_count: hashes.len() as u32, siblings })
+}
+
+/// Checks that `leaf` is the leaf `proof.index` of the tree of `proof.leaves_count` leaves whose root is `root`.
+/// A proof whose sibling equals the node it is paired with is rejected, see `compute_root_checked`.
+pub fn verify_proof(leaf: &Hash, proof: &MerkleProof, root: &Hash) -> Result<(), TreeError> {
+    let index = proof.index as usize;
+    let mut length = proof.leaves_count as usize;
+    if index >= length {
+        return Err(TreeError::IndexOutOfRange { index, leaves_count: length });
+    }
+    let expected_length = get_proof_length(index, length);
+    if proof.siblings.len() != expected_length {
+        return Err(TreeError::InvalidProofLength { expected: expected_length, actual: proof.siblings.len() });
+    }
+
+    let mut node = compute_leaf(leaf);
+    let mut position = index;
+    let mut siblings = proof.siblings.iter();
+    let mut level = 0;
+    while length > 1 {
+        node = if position % 2 == 1 {
+            let sibling = siblings.next().ok_or(TreeError::RootMismatch)?;
+            if *sibling == node {
+                return Err(TreeError::DuplicatedNodes { level, index: position - 1 });
+            }
+            compute_branch(sibling, &node)
+        } else if position + 1 < length {
+            let sibling = siblings.next().ok_or(TreeError::RootMismatch)?;
+            if *sibling == node {
+                return Err(TreeError::DuplicatedNodes { level, index: position });
+            }
+            compute_branch(&node, sibling)
+        } else {
+            compute_branch(&node, &node)
+        };
+        position /= 2;
+        length = length.div_ceil(2);
+        level += 1;
+    }
+    if node != *root {
+        return Err(TreeError::RootMismatch);
+    }
+    Ok(())
+}
+
+/// Number of siblings in the proof of leaf `index` among `leaves_count` leaves.
+fn get_proof_length(mut index: usize, mut leaves_count: usize) -> usize {
+    let mut proof_length = 0;
+    while leaves_count > 1 {
+        if index ^ 1 < leaves_count {
+            proof_length += 1;
+        }
+        index /= 2;
+        leaves_count = leaves_count.div_ceil(2);
+    }
+    proof_length
+}
+
+fn compute_next_level(nodes: &[Hash]) -> Vec<Hash> {
+    nodes
+        .chunks(2)
+        .map(|pair| compute_branch(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+        .collect()
+}
+
+/// Compute a leaf hash: the prefixed hash of an element.
+fn compute_leaf(hash: &Hash) -> Hash {
+    let mut leaf_concat = [0u8; 1 + HASH_SIZE];
+    leaf_concat[0] = LEAF_PREFIX;
+    leaf_concat[1..].copy_from_slice(hash.as_bytes());
+    Hash::compute_hash(&leaf_concat)
+}
+
+/// Compute a branch hash with strict ordering: the prefixed hash of both children.
 fn compute_branch(left: &Hash, right: &Hash) -> Hash {
-    let mut hash_concat = [0u8; HASH_SIZE * 2];
-    hash_concat[..HASH_SIZE].copy_from_slice(left.as_bytes());
-    hash_concat[HASH_SIZE..].copy_from_slice(right.as_bytes());
+    let mut hash_concat = [0u8; 1 + HASH_SIZE * 2];
+    hash_concat[0] = BRANCH_PREFIX;
+    hash_concat[1..1 + HASH_SIZE].copy_from_slice(left.as_bytes());
+    hash_concat[1 + HASH_SIZE..].copy_from_slice(right.as_bytes());
     Hash::compute_hash(&hash_concat)
 }
 /*
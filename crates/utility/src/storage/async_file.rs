@@ -2,7 +2,9 @@
 use std::io::{self};
 use tokio::fs::{self, File, OpenOptions};
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use std::path::Path;
 use thiserror::Error;
+use crate::storage::storage_directory::TEMPORARY_FILE_SUFFIX;
 
 #[derive(Error, Debug)]
 pub enum AsyncFileError {
@@ -25,6 +27,24 @@ pub async fn save_bytes_to_file(data: &[u8], input_path: &str) -> Result<(), Asy
     Ok(())
 }
 
+/// Same as `save_bytes_to_file`, but the data is written to a temporary file, synced, then renamed over
+/// the file, so a crash leaves either the previous content or the new one.
+pub async fn save_bytes_to_file_atomic(data: &[u8], input_path: &str) -> Result<(), AsyncFileError> {
+    let tmp_path = format!("{}{}", input_path, TEMPORARY_FILE_SUFFIX);
+    let mut file = File::create(&tmp_path).await.map_err(|e| AsyncFileError::FileCreationError(e.to_string()))?;
+    file.write_all(data).await.map_err(AsyncFileError::Io)?;
+    file.sync_all().await?;
+    drop(file);
+    fs::rename(&tmp_path, input_path).await?;
+    // makes the rename durable
+    let parent = match Path::new(input_path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent).await?.sync_all().await?;
+    Ok(())
+}
+
 pub async fn load_bytes_from_file(input_path: &str) -> Result<Vec<u8>, AsyncFileError> {
     let mut file = File::open(input_path).await.map_err(|e| match e.kind() {
         io::ErrorKind::NotFound => AsyncFileError::FileNotFound(input_path.to_string()),
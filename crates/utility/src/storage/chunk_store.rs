@@ -0,0 +1,250 @@
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{self, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use crate::storage::storage_directory::compute_chunk_checksum;
+use crate::storage::storage_directory::StorageDirectory;
+use crate::storage::storage_directory::StorageDirectoryError;
+
+/// Size above which a new segment file is started.
+pub const CHUNK_STORE_SEGMENT_SIZE: u64 = 128 * 1024 * 1024;
+/// Index entry: segment number (u32), offset in the segment (u64) and chunk length (u32).
+const CHUNK_INDEX_ENTRY_SIZE: usize = 16;
+/// Each chunk is preceded in the segment file by its length (u32) and a checksum of the chunk.
+const CHUNK_RECORD_HEADER_SIZE: u64 = 8;
+
+#[derive(Debug, Error)]
+pub enum ChunkStoreError {
+    #[error("I/O error occurred: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Storage directory error: {0}")]
+    StorageDirectoryError(#[from] StorageDirectoryError),
+
+    #[error("Chunk not found: {0}")]
+    ChunkNotFound(usize),
+
+    #[error("Chunk {0} is corrupted")]
+    CorruptedChunk(usize),
+
+    #[error("Invalid operation: {0}")]
+    InvalidOperation(String),
+
+    #[error("Chunk too large: {0} bytes")]
+    ChunkTooLarge(usize),
+}
+
+/// Where a chunk is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkLocation {
+    pub segment: u32,
+    pub offset: u64,
+    pub length: u32,
+}
+
+/// Chunks appended to rolling segment files (`<category>Segment<n>.dat`) of about `CHUNK_STORE_SEGMENT_SIZE` bytes,
+/// located through an index file (`<category>Index.dat`) of fixed-size entries that is kept in memory.
+pub struct ChunkStore {
+    path: PathBuf,
+    category: String,
+    segment_size: u64,
+    index: Vec<ChunkLocation>,
+}
+
+impl ChunkStore {
+    /// Creates a new `ChunkStore` instance, `init` must be called before use.
+    pub async fn new<P: AsRef<Path>>(path: P, category: String) -> Result<Self, ChunkStoreError> {
+        Self::with_segment_size(path, category, CHUNK_STORE_SEGMENT_SIZE).await
+    }
+
+    pub async fn with_segment_size<P: AsRef<Path>>(path: P, category: String, segment_size: u64) -> Result<Self, ChunkStoreError> {
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            fs::create_dir_all(&path).await?;
+        }
+        println!("ChunkStore - path:{:?} category {:?}", path, category);
+        Ok(Self { path, category, segment_size: segment_size.max(1), index: Vec::new() })
+    }
+
+    fn get_segment_path(&self, segment: u32) -> PathBuf {
+        self.path.join(format!("{}Segment{}.dat", self.category, segment))
+    }
+
+    fn get_index_path(&self) -> PathBuf {
+        self.path.join(format!("{}Index.dat", self.category))
+    }
+
+    /// Loads the index. The corrupt tail left by an interrupted write is dropped: the entries pointing past the end
+    /// of their segment and the last chunks whose record does not match their entry or their checksum.
+    pub async fn init(&mut self) -> Result<(), ChunkStoreError> {
+        let index_bytes = match fs::read(self.get_index_path()).await {
+            Ok(index_bytes) => index_bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(ChunkStoreError::Io(e)),
+        };
+        self.index = index_bytes.chunks_exact(CHUNK_INDEX_ENTRY_SIZE).map(unserialize_location).collect();
+
+        let mut valid_count = 0;
+        let mut segment_sizes: Vec<u64> = Vec::new();
+        for location in self.index.iter() {
+            while segment_sizes.len() <= location.segment as usize {
+                let segment_path = self.get_segment_path(segment_sizes.len() as u32);
+                segment_sizes.push(fs::metadata(segment_path).await.map(|metadata| metadata.len()).unwrap_or(0));
+            }
+            let end = location.offset + CHUNK_RECORD_HEADER_SIZE + location.length as u64;
+            if end > segment_sizes[location.segment as usize] {
+                break;
+            }
+            valid_count += 1;
+        }
+        while valid_count > 0 {
+            match self.get_chunk(valid_count - 1).await {
+                Ok(_) => break,
+                Err(ChunkStoreError::CorruptedChunk(index)) => {
+                    println!("ChunkStore dropping corrupted chunk {}", index);
+                    valid_count -= 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if valid_count < self.index.len() || index_bytes.len() % CHUNK_INDEX_ENTRY_SIZE != 0 {
+            println!("ChunkStore dropping {} index entries", self.index.len() - valid_count);
+            self.truncate_index(valid_count).await?;
+        }
+        Ok(())
+    }
+
+    /// Number of chunks stored, the index of the next chunk.
+    pub fn chunk_count(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn get_chunk_location(&self, chunk_index: usize) -> Option<ChunkLocation> {
+        self.index.get(chunk_index).copied()
+    }
+
+    /// Appends a chunk at index `chunk_count()`.
+    pub async fn add_chunk(&mut self, chunk_bytes: &[u8]) -> Result<(), ChunkStoreError> {
+        let length = u32::try_from(chunk_bytes.len()).map_err(|_| ChunkStoreError::ChunkTooLarge(chunk_bytes.len()))?;
+        let (mut segment, mut offset) = match self.index.last() {
+            Some(last) => (last.segment, last.offset + CHUNK_RECORD_HEADER_SIZE + last.length as u64),
+            None => (0, 0),
+        };
+        if offset > 0 && offset + CHUNK_RECORD_HEADER_SIZE + length as u64 > self.segment_size {
+            segment += 1;
+            offset = 0;
+        }
+
+        let mut file = OpenOptions::new().create(true).write(true).truncate(false).open(self.get_segment_path(segment)).await?;
+        // anything after the last indexed chunk is the leftover of an interrupted write
+        file.set_len(offset).await?;
+        file.seek(io::SeekFrom::Start(offset)).await?;
+        file.write_all(&serialize_chunk_record(chunk_bytes, length)).await?;
+        file.sync_data().await?;
+
+        let location = ChunkLocation { segment, offset, length };
+        let mut index_file = OpenOptions::new().create(true).append(true).open(self.get_index_path()).await?;
+        index_file.write_all(&serialize_location(&location)).await?;
+        index_file.sync_data().await?;
+        self.index.push(location);
+        Ok(())
+    }
+
+    /// Reads a chunk, checking its record against the length of its index entry and its checksum.
+    pub async fn get_chunk(&mut self, chunk_index: usize) -> Result<Vec<u8>, ChunkStoreError> {
+        let location = self.get_chunk_location(chunk_index).ok_or(ChunkStoreError::ChunkNotFound(chunk_index))?;
+        let mut file = File::open(self.get_segment_path(location.segment)).await?;
+        file.seek(io::SeekFrom::Start(location.offset)).await?;
+        let mut record = vec![0u8; CHUNK_RECORD_HEADER_SIZE as usize + location.length as usize];
+        file.read_exact(&mut record).await?;
+        unserialize_chunk_record(record, location.length).ok_or(ChunkStoreError::CorruptedChunk(chunk_index))
+    }
+
+    /// Removes chunk `chunk_index`, which must be the last one so that the numbering keeps no gap.
+    pub async fn remove_chunk(&mut self, chunk_index: usize) -> Result<(), ChunkStoreError> {
+        let last = match self.index.last() {
+            Some(last) if chunk_index + 1 == self.index.len() => *last,
+            _ => return Err(ChunkStoreError::InvalidOperation(format!("chunk {} is not the last of {} chunks", chunk_index, self.index.len()))),
+        };
+        self.truncate_index(chunk_index).await?;
+        if last.offset == 0 && last.segment > 0 {
+            fs::remove_file(self.get_segment_path(last.segment)).await?;
+        } else {
+            let file = OpenOptions::new().write(true).open(self.get_segment_path(last.segment)).await?;
+            file.set_len(last.offset).await?;
+            file.sync_data().await?;
+        }
+        Ok(())
+    }
+
+    /// Keeps the first `chunk_count` chunks, the chunks from index `chunk_count` are removed from the last one.
+    pub async fn truncate_to(&mut self, chunk_count: usize) -> Result<(), ChunkStoreError> {
+        while self.index.len() > chunk_count {
+            self.remove_chunk(self.index.len() - 1).await?;
+        }
+        Ok(())
+    }
+
+    async fn truncate_index(&mut self, count: usize) -> Result<(), ChunkStoreError> {
+        self.index.truncate(count);
+        let index_file = OpenOptions::new().create(true).write(true).truncate(false).open(self.get_index_path()).await?;
+        index_file.set_len((count * CHUNK_INDEX_ENTRY_SIZE) as u64).await?;
+        index_file.sync_data().await?;
+        Ok(())
+    }
+}
+
+fn serialize_chunk_record(chunk_bytes: &[u8], length: u32) -> Vec<u8> {
+    let mut record = Vec::with_capacity(CHUNK_RECORD_HEADER_SIZE as usize + chunk_bytes.len());
+    record.extend_from_slice(&length.to_le_bytes());
+    record.extend_from_slice(&compute_chunk_checksum(chunk_bytes));
+    record.extend_from_slice(chunk_bytes);
+    record
+}
+
+/// Returns the chunk of a record, `None` if its length prefix is not `length` or its checksum does not match.
+fn unserialize_chunk_record(mut record: Vec<u8>, length: u32) -> Option<Vec<u8>> {
+    let chunk_bytes = record.split_off(CHUNK_RECORD_HEADER_SIZE as usize);
+    if record[0..4] != length.to_le_bytes() || record[4..8] != compute_chunk_checksum(&chunk_bytes) {
+        return None;
+    }
+    Some(chunk_bytes)
+}
+
+fn serialize_location(location: &ChunkLocation) -> [u8; CHUNK_INDEX_ENTRY_SIZE] {
+    let mut entry = [0u8; CHUNK_INDEX_ENTRY_SIZE];
+    entry[0..4].copy_from_slice(&location.segment.to_le_bytes());
+    entry[4..12].copy_from_slice(&location.offset.to_le_bytes());
+    entry[12..16].copy_from_slice(&location.length.to_le_bytes());
+    entry
+}
+
+fn unserialize_location(entry: &[u8]) -> ChunkLocation {
+    ChunkLocation {
+        segment: u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]),
+        offset: u64::from_le_bytes([entry[4], entry[5], entry[6], entry[7], entry[8], entry[9], entry[10], entry[11]]),
+        length: u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]),
+    }
+}
+
+/// Copies the chunks of a one-file-per-chunk `StorageDirectory` that are not yet in `chunk_store`,
+/// in order, then removes the old chunk files if `remove_old` is set. Returns the number of chunks copied.
+pub async fn migrate_storage_directory(
+    storage_directory: &mut StorageDirectory,
+    chunk_store: &mut ChunkStore,
+    remove_old: bool,
+) -> Result<usize, ChunkStoreError> {
+    storage_directory.init().await?;
+    let old_count = storage_directory.chunk_count();
+    let mut copied_count = 0;
+    for chunk_index in chunk_store.chunk_count()..old_count {
+        let chunk_bytes = storage_directory.get_chunk(chunk_index).await?;
+        chunk_store.add_chunk(&chunk_bytes).await?;
+        copied_count += 1;
+    }
+    if remove_old {
+        storage_directory.truncate_to(0).await?;
+    }
+    println!("ChunkStore migrated {} chunks", copied_count);
+    Ok(copied_count)
+}
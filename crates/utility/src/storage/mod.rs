@@ -1,2 +1,3 @@
 pub mod async_file;
-pub mod storage_directory;
\ No newline at end of file
+pub mod storage_directory;
+pub mod chunk_store;
@@ -3,6 +3,14 @@ use tokio::fs::{self, File};//, OpenOptions};
 use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
 use std::path::{Path, PathBuf};
 use thiserror::Error;
+use crate::hash::hash::Hash;
+
+/// Suffix of the file a write goes to before it is renamed over its destination.
+pub const TEMPORARY_FILE_SUFFIX: &str = ".tmp";
+/// Marks a chunk record: the magic, a checksum of the chunk, then the chunk.
+/// Chunks written before records existed have no magic and are returned unchecked.
+const CHUNK_RECORD_MAGIC: [u8; 4] = *b"GCK1";
+const CHUNK_RECORD_HEADER_SIZE: usize = 8;
 
 
 #[derive(Debug, Error)]
@@ -21,12 +29,20 @@ pub enum StorageDirectoryError {
 
     #[error("File creation failed: {0}")]
     FileCreationError(String),
+
+    #[error("Chunk {0} is corrupted")]
+    CorruptedChunk(usize),
+
+    #[error("Chunk not found: {0}")]
+    ChunkNotFound(usize),
 }
 
+/// Files of a directory. Chunks are numbered from 0 to `chunk_count()-1` without gap,
+/// chunk `i` being stored in the file `<category><i>`.
 pub struct StorageDirectory{
     path: PathBuf,
     category:String,
-    storage_files_last_index:Option<usize>,
+    chunk_count:usize,
 }
 
 //const MAX_STORAGE_FILE_INDEX:u32=50000000;
@@ -40,7 +56,7 @@ impl StorageDirectory{
         }
         println!("StorageDirectory - path:{:?} category {:?}", path,category);
 
-        Ok(Self { path, category,storage_files_last_index:None})
+        Ok(Self { path, category,chunk_count:0})
     }
 
     /// Lists all files in the directory.
@@ -49,7 +65,7 @@ impl StorageDirectory{
         let mut entries = fs::read_dir(&self.path).await?;
         while let Some(entry) = entries.next_entry().await? {
             let path = entry.path();
-            if path.is_file() {
+            if path.is_file() && !path.to_string_lossy().ends_with(TEMPORARY_FILE_SUFFIX) {
                 files.push(path);
             }
         }
@@ -71,27 +87,65 @@ impl StorageDirectory{
     }
 
     /// save data to a file in the directory (creates the file if it doesn't exist).
+    /// The data is written to a temporary file, synced, then renamed over the file, so a crash
+    /// leaves either the previous content or the new one.
     pub async fn save_bytes_to_file(&self, filename: &str, data: &[u8]) -> Result<(),StorageDirectoryError> {
         println!("StorageDirectory save_bytes_to_file {:?}",filename);
         let file_path = self.path.join(filename);
-        let mut file = File::create(file_path).await.map_err(|e| StorageDirectoryError::FileCreationError(e.to_string()))?;
+        let tmp_path = self.path.join(format!("{}{}", filename, TEMPORARY_FILE_SUFFIX));
+        let mut file = File::create(&tmp_path).await.map_err(|e| StorageDirectoryError::FileCreationError(e.to_string()))?;
         file.write_all(data).await.map_err(StorageDirectoryError::Io)?;
+        file.sync_all().await?;
+        drop(file);
+        fs::rename(&tmp_path, &file_path).await?;
+        self.sync_directory().await
+    }
+
+    /// Makes the creation, renaming and removal of files durable.
+    async fn sync_directory(&self) -> Result<(),StorageDirectoryError> {
+        File::open(&self.path).await?.sync_all().await?;
         Ok(())
     }
+
+    /// Removes the temporary files left by writes interrupted before their rename.
+    pub async fn remove_temporary_files(&self) -> Result<usize,StorageDirectoryError> {
+        let mut removed_count = 0;
+        let mut entries = fs::read_dir(&self.path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_string_lossy().ends_with(TEMPORARY_FILE_SUFFIX) {
+                fs::remove_file(entry.path()).await?;
+                removed_count += 1;
+            }
+        }
+        if removed_count > 0 {
+            println!("StorageDirectory removed {} temporary files", removed_count);
+        }
+        Ok(removed_count)
+    }
     
     // TODO empty file - it does not delete the file it just empty it to save space, file should always be kept 
 
+    /// Removes a file from the directory.
+    pub async fn remove_file(&self, filename: &str) -> Result<(),StorageDirectoryError> {
+        let file_path = self.path.join(filename);
+        fs::remove_file(file_path.clone()).await.map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => StorageDirectoryError::FileNotFound((file_path.clone()).to_str().expect("Failed to convert path to string").to_string()),
+            _ => StorageDirectoryError::Io(e),
+        })
+    }
+
     /// Checks if a file exists in the directory.
     pub async fn file_exists(&self, filename: &str) -> bool {
         println!("does {:?} file_exists",filename);
         self.path.join(filename).exists()
     }
+    /// Index of the last chunk, `None` if there is no chunk.
     pub fn get_storage_files_last_index(&self) -> Option<usize> {
-        //match self.storage_files_last_index {
-        //    Some(count) => println!("self.storage_files_last_index: {}", count),
-        //    None => println!("self.storage_files_last_index is None"),
-        //}
-        self.storage_files_last_index
+        self.chunk_count.checked_sub(1)
+    }
+    /// Number of chunks, the index the next chunk is added at.
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_count
     }
     pub async fn init_storage_files_last_index(&mut self) {
         if let Ok(mut entries) = fs::read_dir(self.path.clone()).await {
@@ -109,35 +163,111 @@ impl StorageDirectory{
                 }
             }
             //println!("max_index {:?}", max_index);
-            self.storage_files_last_index= max_index;
+            self.chunk_count= max_index.map_or(0, |index| index+1);
         }
     }
+    /// Scans the chunks. Temporary files are removed, and so is the corrupt tail: the chunks after
+    /// a missing one and the last chunks whose checksum does not match.
     pub async fn init(&mut self) -> Result<(),StorageDirectoryError> {
-        //TODO better error handling
+        self.remove_temporary_files().await?;
         self.init_storage_files_last_index().await;
-        Ok(())
+        self.truncate_corrupt_tail().await
+    }
+    async fn truncate_corrupt_tail(&mut self) -> Result<(),StorageDirectoryError> {
+        let last_index = match self.get_storage_files_last_index() {
+            Some(last_index) => last_index,
+            None => return Ok(()),
+        };
+        let mut valid_count = 0;
+        while valid_count <= last_index && self.path.join(self.get_chunk_filename(valid_count)).exists() {
+            valid_count += 1;
+        }
+        for index in (valid_count..=last_index).rev() {
+            if self.path.join(self.get_chunk_filename(index)).exists() {
+                println!("StorageDirectory removing chunk {} after a missing chunk", index);
+                self.remove_file(&self.get_chunk_filename(index)).await?;
+            }
+        }
+        while valid_count > 0 {
+            match self.get_chunk(valid_count - 1).await {
+                Ok(_) => break,
+                Err(StorageDirectoryError::CorruptedChunk(index)) => {
+                    println!("StorageDirectory removing corrupted chunk {}", index);
+                    self.remove_file(&self.get_chunk_filename(index)).await?;
+                    valid_count -= 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        self.chunk_count = valid_count;
+        self.sync_directory().await
+    }
+    fn get_chunk_filename(&self, chunk_index: usize) -> String {
+        format!("{}{}", self.category, chunk_index)
     }
+    /// Adds a chunk at index `chunk_count()`.
     pub async fn add_chunk(&mut self,chunk_bytes: &[u8])->  Result<(),StorageDirectoryError> {
-        let mut i=0;
-        let index_result=self.get_storage_files_last_index();
-        match index_result {
-            Some(index) => i=index,//println!("get_storage_files_last_index: {}", index),
-            None => i=0,//println!("storage files last index not initialized"), 
+        let file_path=self.get_chunk_filename(self.chunk_count);
+        self.save_bytes_to_file(&file_path, &serialize_chunk_record(chunk_bytes)).await?;
+        self.chunk_count+=1;
+        Ok(())
+    }
+    /// Removes chunk `chunk_index`, which must be the last one so that the numbering keeps no gap.
+    pub async fn remove_chunk(&mut self,chunk_index: usize)->  Result<(),StorageDirectoryError> {
+        if self.get_storage_files_last_index()!=Some(chunk_index) {
+            return Err(StorageDirectoryError::InvalidOperation(format!("chunk {} is not the last of {} chunks", chunk_index, self.chunk_count)));
         }
-        let file_path=format!("{}{}",self.category, i);
-        self.save_bytes_to_file(&file_path, chunk_bytes).await?;
-        
-        if let Some(ref mut count) = self.storage_files_last_index {
-            *count += 1;
-        } else if self.storage_files_last_index .is_none() {
-            self.storage_files_last_index  = Some(0);
+        self.remove_file(&self.get_chunk_filename(chunk_index)).await?;
+        self.chunk_count=chunk_index;
+        self.sync_directory().await
+    }
+    /// Keeps the first `chunk_count` chunks, the chunks from index `chunk_count` are removed from the last one.
+    pub async fn truncate_to(&mut self,chunk_count: usize)->  Result<(),StorageDirectoryError> {
+        while self.chunk_count>chunk_count {
+            self.remove_chunk(self.chunk_count-1).await?;
         }
-
         Ok(())
     }
     pub async fn get_chunk(&mut self,chunk_height:usize)->  Result<Vec<u8>,StorageDirectoryError> {
-        let file_path=format!("{}{}",self.category, chunk_height);
-        self.load_bytes_from_file(&file_path).await
+        if chunk_height>=self.chunk_count {
+            return Err(StorageDirectoryError::ChunkNotFound(chunk_height));
+        }
+        let file_path=self.get_chunk_filename(chunk_height);
+        let record=self.load_bytes_from_file(&file_path).await?;
+        unserialize_chunk_record(record).ok_or(StorageDirectoryError::CorruptedChunk(chunk_height))
     }
 
 }
+
+pub(crate) fn compute_chunk_checksum(chunk_bytes: &[u8]) -> [u8; 4] {
+    let hash = Hash::compute_hash(chunk_bytes);
+    let bytes = hash.as_bytes();
+    [bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
+fn serialize_chunk_record(chunk_bytes: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(CHUNK_RECORD_HEADER_SIZE + chunk_bytes.len());
+    record.extend_from_slice(&CHUNK_RECORD_MAGIC);
+    record.extend_from_slice(&compute_chunk_checksum(chunk_bytes));
+    record.extend_from_slice(chunk_bytes);
+    record
+}
+
+/// Returns the chunk of a record, `None` if its checksum does not match.
+fn unserialize_chunk_record(mut record: Vec<u8>) -> Option<Vec<u8>> {
+    if !record.starts_with(&CHUNK_RECORD_MAGIC) {
+        // an empty file or a record cut within its magic is not a legacy chunk
+        if CHUNK_RECORD_MAGIC.starts_with(&record) {
+            return None;
+        }
+        return Some(record);
+    }
+    if record.len() < CHUNK_RECORD_HEADER_SIZE {
+        return None;
+    }
+    let chunk_bytes = record.split_off(CHUNK_RECORD_HEADER_SIZE);
+    if record[4..CHUNK_RECORD_HEADER_SIZE] != compute_chunk_checksum(&chunk_bytes) {
+        return None;
+    }
+    Some(chunk_bytes)
+}
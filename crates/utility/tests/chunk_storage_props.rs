@@ -0,0 +1,118 @@
+//! Any sequence of add, remove, truncate and reopen keeps `StorageDirectory` and `ChunkStore`
+//! equal to a plain vector of chunks.
+use proptest::prelude::*;
+use utility::storage::chunk_store::ChunkStore;
+use utility::storage::storage_directory::StorageDirectory;
+
+#[derive(Debug, Clone)]
+enum ChunkOperation {
+    Add(Vec<u8>),
+    RemoveLast,
+    TruncateTo(usize),
+    Reopen,
+}
+
+fn chunk_operation() -> impl Strategy<Value = ChunkOperation> {
+    prop_oneof![
+        4 => proptest::collection::vec(any::<u8>(), 0..64).prop_map(ChunkOperation::Add),
+        1 => Just(ChunkOperation::RemoveLast),
+        1 => (0usize..8).prop_map(ChunkOperation::TruncateTo),
+        1 => Just(ChunkOperation::Reopen),
+    ]
+}
+
+async fn open_storage_directory(path: &std::path::Path) -> StorageDirectory {
+    let mut sd = StorageDirectory::new(path, String::from("Chunk")).await.unwrap();
+    sd.init().await.unwrap();
+    sd
+}
+
+async fn open_chunk_store(path: &std::path::Path) -> ChunkStore {
+    // small segments so that the sequences cross segment boundaries
+    let mut cs = ChunkStore::with_segment_size(path, String::from("Chunk"), 128).await.unwrap();
+    cs.init().await.unwrap();
+    cs
+}
+
+async fn run_storage_directory(operations: &[ChunkOperation]) -> Result<(), TestCaseError> {
+    let dir = tempfile::tempdir().unwrap();
+    let mut sd = open_storage_directory(dir.path()).await;
+    let mut model: Vec<Vec<u8>> = Vec::new();
+    for operation in operations.iter() {
+        match operation {
+            ChunkOperation::Add(chunk) => {
+                sd.add_chunk(chunk).await.unwrap();
+                model.push(chunk.clone());
+            }
+            ChunkOperation::RemoveLast => {
+                let result = sd.remove_chunk(model.len().wrapping_sub(1)).await;
+                prop_assert_eq!(result.is_ok(), model.pop().is_some());
+            }
+            ChunkOperation::TruncateTo(count) => {
+                sd.truncate_to(*count).await.unwrap();
+                model.truncate(*count);
+            }
+            ChunkOperation::Reopen => sd = open_storage_directory(dir.path()).await,
+        }
+        prop_assert_eq!(sd.chunk_count(), model.len());
+        prop_assert_eq!(sd.get_storage_files_last_index(), model.len().checked_sub(1));
+    }
+    prop_assert!(sd.remove_chunk(model.len()).await.is_err());
+    prop_assert!(sd.get_chunk(model.len()).await.is_err());
+    let mut sd = open_storage_directory(dir.path()).await;
+    prop_assert_eq!(sd.chunk_count(), model.len());
+    for (index, chunk) in model.iter().enumerate() {
+        prop_assert_eq!(&sd.get_chunk(index).await.unwrap(), chunk);
+    }
+    Ok(())
+}
+
+async fn run_chunk_store(operations: &[ChunkOperation]) -> Result<(), TestCaseError> {
+    let dir = tempfile::tempdir().unwrap();
+    let mut cs = open_chunk_store(dir.path()).await;
+    let mut model: Vec<Vec<u8>> = Vec::new();
+    for operation in operations.iter() {
+        match operation {
+            ChunkOperation::Add(chunk) => {
+                cs.add_chunk(chunk).await.unwrap();
+                model.push(chunk.clone());
+            }
+            ChunkOperation::RemoveLast => {
+                let result = cs.remove_chunk(model.len().wrapping_sub(1)).await;
+                prop_assert_eq!(result.is_ok(), model.pop().is_some());
+            }
+            ChunkOperation::TruncateTo(count) => {
+                cs.truncate_to(*count).await.unwrap();
+                model.truncate(*count);
+            }
+            ChunkOperation::Reopen => cs = open_chunk_store(dir.path()).await,
+        }
+        prop_assert_eq!(cs.chunk_count(), model.len());
+    }
+    prop_assert!(cs.remove_chunk(model.len()).await.is_err());
+    prop_assert!(cs.get_chunk(model.len()).await.is_err());
+    let mut cs = open_chunk_store(dir.path()).await;
+    prop_assert_eq!(cs.chunk_count(), model.len());
+    for (index, chunk) in model.iter().enumerate() {
+        prop_assert_eq!(&cs.get_chunk(index).await.unwrap(), chunk);
+    }
+    Ok(())
+}
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    #[test]
+    fn prop_storage_directory_matches_model(operations in proptest::collection::vec(chunk_operation(), 0..24)) {
+        block_on(run_storage_directory(&operations))?;
+    }
+
+    #[test]
+    fn prop_chunk_store_matches_model(operations in proptest::collection::vec(chunk_operation(), 0..24)) {
+        block_on(run_chunk_store(&operations))?;
+    }
+}
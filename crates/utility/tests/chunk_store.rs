@@ -0,0 +1,102 @@
+//! Record checks of `ChunkStore` and the migration from the one-file-per-chunk `StorageDirectory` layout.
+use std::path::Path;
+use std::process::Command;
+use utility::storage::chunk_store::migrate_storage_directory;
+use utility::storage::chunk_store::ChunkStore;
+use utility::storage::chunk_store::ChunkStoreError;
+use utility::storage::storage_directory::StorageDirectory;
+
+const CATEGORY: &str = "Chunk";
+
+async fn open_chunk_store(path: &Path) -> ChunkStore {
+    let mut cs = ChunkStore::new(path, String::from(CATEGORY)).await.unwrap();
+    cs.init().await.unwrap();
+    cs
+}
+
+async fn open_storage_directory(path: &Path) -> StorageDirectory {
+    let mut sd = StorageDirectory::new(path, String::from(CATEGORY)).await.unwrap();
+    sd.init().await.unwrap();
+    sd
+}
+
+fn chunk_data(index: usize) -> Vec<u8> {
+    (0..100 + index).map(|i| (i * 7 + index) as u8).collect()
+}
+
+fn segment_path(path: &Path) -> std::path::PathBuf {
+    path.join(format!("{}Segment0.dat", CATEGORY))
+}
+
+async fn assert_chunks(cs: &mut ChunkStore, count: usize) {
+    assert_eq!(cs.chunk_count(), count);
+    for index in 0..count {
+        assert_eq!(cs.get_chunk(index).await.unwrap(), chunk_data(index));
+    }
+}
+
+#[tokio::test]
+async fn test_get_chunk_checks_the_record() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut cs = open_chunk_store(dir.path()).await;
+    for index in 0..3 {
+        cs.add_chunk(&chunk_data(index)).await.unwrap();
+    }
+
+    // a flipped bit in the chunk fails the checksum
+    let location = cs.get_chunk_location(1).unwrap();
+    let mut rawbytes = std::fs::read(segment_path(dir.path())).unwrap();
+    rawbytes[location.offset as usize + 8 + 5] ^= 1;
+    // a length prefix that does not match the index entry
+    rawbytes[0] ^= 1;
+    std::fs::write(segment_path(dir.path()), &rawbytes).unwrap();
+    assert!(matches!(cs.get_chunk(0).await, Err(ChunkStoreError::CorruptedChunk(0))));
+    assert!(matches!(cs.get_chunk(1).await, Err(ChunkStoreError::CorruptedChunk(1))));
+    assert_eq!(cs.get_chunk(2).await.unwrap(), chunk_data(2));
+}
+
+#[tokio::test]
+async fn test_migrate_storage_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut sd = open_storage_directory(dir.path()).await;
+    for index in 0..3 {
+        sd.add_chunk(&chunk_data(index)).await.unwrap();
+    }
+    // a chunk of the layout before records, without magic nor checksum
+    std::fs::write(dir.path().join(format!("{}3", CATEGORY)), chunk_data(3)).unwrap();
+    let mut sd = open_storage_directory(dir.path()).await;
+    let mut cs = open_chunk_store(dir.path()).await;
+    assert_eq!(migrate_storage_directory(&mut sd, &mut cs, false).await.unwrap(), 4);
+    assert_chunks(&mut cs, 4).await;
+    assert_eq!(open_storage_directory(dir.path()).await.chunk_count(), 4);
+
+    // an interrupted migration goes on from the chunks already copied
+    sd.add_chunk(&chunk_data(4)).await.unwrap();
+    assert_eq!(migrate_storage_directory(&mut sd, &mut cs, true).await.unwrap(), 1);
+    assert_chunks(&mut cs, 5).await;
+    assert_eq!(open_storage_directory(dir.path()).await.chunk_count(), 0);
+    assert_chunks(&mut open_chunk_store(dir.path()).await, 5).await;
+}
+
+#[tokio::test]
+async fn test_migrate_chunk_store_bin() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut sd = open_storage_directory(dir.path()).await;
+    for index in 0..3 {
+        sd.add_chunk(&chunk_data(index)).await.unwrap();
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_migrate_chunk_store")).arg(dir.path()).arg(CATEGORY).arg("--keep-old").output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("migrated 3 chunks, the chunk store holds 3 chunks"));
+    assert_eq!(open_storage_directory(dir.path()).await.chunk_count(), 3);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_migrate_chunk_store")).arg(dir.path()).arg(CATEGORY).output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("migrated 0 chunks, the chunk store holds 3 chunks"));
+    assert_eq!(open_storage_directory(dir.path()).await.chunk_count(), 0);
+    assert_chunks(&mut open_chunk_store(dir.path()).await, 3).await;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_migrate_chunk_store")).arg(dir.path()).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
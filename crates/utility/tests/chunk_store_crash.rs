@@ -0,0 +1,114 @@
+//! Rebuilds on disk the states a crash can leave a `ChunkStore` in, and checks that `init`
+//! recovers a consistent prefix of the chunks.
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use utility::storage::chunk_store::ChunkStore;
+
+const CATEGORY: &str = "Chunk";
+/// Small enough for a few chunks to spread over several segments.
+const SEGMENT_SIZE: u64 = 300;
+
+async fn open(path: &Path) -> ChunkStore {
+    let mut cs = ChunkStore::with_segment_size(path, String::from(CATEGORY), SEGMENT_SIZE).await.unwrap();
+    cs.init().await.unwrap();
+    cs
+}
+
+fn chunk_data(index: usize) -> Vec<u8> {
+    (0..100 + index).map(|i| (i * 7 + index) as u8).collect()
+}
+
+async fn write_chunks(path: &Path, count: usize) {
+    let mut cs = open(path).await;
+    for index in 0..count {
+        cs.add_chunk(&chunk_data(index)).await.unwrap();
+    }
+}
+
+fn segment_path(path: &Path, segment: u32) -> std::path::PathBuf {
+    path.join(format!("{}Segment{}.dat", CATEGORY, segment))
+}
+
+fn index_path(path: &Path) -> std::path::PathBuf {
+    path.join(format!("{}Index.dat", CATEGORY))
+}
+
+async fn assert_chunks(cs: &mut ChunkStore, count: usize) {
+    assert_eq!(cs.chunk_count(), count);
+    for index in 0..count {
+        assert_eq!(cs.get_chunk(index).await.unwrap(), chunk_data(index));
+    }
+}
+
+#[tokio::test]
+async fn test_torn_last_record_at_every_length() {
+    let dir = tempfile::tempdir().unwrap();
+    write_chunks(dir.path(), 4).await;
+    let location = open(dir.path()).await.get_chunk_location(3).unwrap();
+    let segment = std::fs::read(segment_path(dir.path(), location.segment)).unwrap();
+    let index = std::fs::read(index_path(dir.path())).unwrap();
+
+    for cut in location.offset as usize..segment.len() {
+        std::fs::write(segment_path(dir.path(), location.segment), &segment[..cut]).unwrap();
+        std::fs::write(index_path(dir.path()), &index).unwrap();
+        let mut cs = open(dir.path()).await;
+        assert_chunks(&mut cs, 3).await;
+    }
+    std::fs::write(segment_path(dir.path(), location.segment), &segment).unwrap();
+    std::fs::write(index_path(dir.path()), &index).unwrap();
+    assert_chunks(&mut open(dir.path()).await, 4).await;
+}
+
+#[tokio::test]
+async fn test_corrupted_tail_records_are_dropped() {
+    let dir = tempfile::tempdir().unwrap();
+    write_chunks(dir.path(), 5).await;
+    let cs = open(dir.path()).await;
+    // the record was given its full length but not all of its bytes reached the disk
+    for chunk_index in [3, 4] {
+        let location = cs.get_chunk_location(chunk_index).unwrap();
+        let mut segment = std::fs::read(segment_path(dir.path(), location.segment)).unwrap();
+        let last = (location.offset + 8 + location.length as u64 - 1) as usize;
+        segment[last] = !segment[last];
+        std::fs::write(segment_path(dir.path(), location.segment), segment).unwrap();
+    }
+    drop(cs);
+
+    let mut cs = open(dir.path()).await;
+    assert_chunks(&mut cs, 3).await;
+    cs.add_chunk(&chunk_data(3)).await.unwrap();
+    cs.add_chunk(&chunk_data(4)).await.unwrap();
+    assert_chunks(&mut open(dir.path()).await, 5).await;
+}
+
+#[tokio::test]
+async fn test_add_chunk_interrupted_before_the_index_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    write_chunks(dir.path(), 3).await;
+    // the record reached the segment but its index entry did not
+    let location = open(dir.path()).await.get_chunk_location(2).unwrap();
+    let mut segment = OpenOptions::new().append(true).open(segment_path(dir.path(), location.segment)).unwrap();
+    segment.write_all(&[0xab; 50]).unwrap();
+    drop(segment);
+
+    let mut cs = open(dir.path()).await;
+    assert_chunks(&mut cs, 3).await;
+    cs.add_chunk(&chunk_data(3)).await.unwrap();
+    assert_chunks(&mut open(dir.path()).await, 4).await;
+}
+
+#[tokio::test]
+async fn test_partial_index_entry_is_dropped() {
+    let dir = tempfile::tempdir().unwrap();
+    write_chunks(dir.path(), 3).await;
+    let mut index = OpenOptions::new().append(true).open(index_path(dir.path())).unwrap();
+    index.write_all(&[0u8; 7]).unwrap();
+    drop(index);
+
+    let mut cs = open(dir.path()).await;
+    assert_chunks(&mut cs, 3).await;
+    assert_eq!(std::fs::metadata(index_path(dir.path())).unwrap().len(), 3 * 16);
+    cs.add_chunk(&chunk_data(3)).await.unwrap();
+    assert_chunks(&mut open(dir.path()).await, 4).await;
+}
@@ -0,0 +1,54 @@
+use utility::buffer::buffer_reader::BufferReader;
+use utility::buffer::buffer_writer::BufferWriter;
+use utility::ecdsa::derivation_path::parse_derivation_path;
+use utility::ecdsa::derivation_path::unserialize_derivation_path;
+use utility::ecdsa::derivation_path::DerivationPath;
+use utility::ecdsa::derivation_path::DerivationPathError;
+use utility::ecdsa::ecdsa::derive_child_key_set;
+use utility::ecdsa::ecdsa::derive_key_set_from_path;
+use utility::ecdsa::ecdsa::derive_public_key_set_from_path;
+use utility::ecdsa::key_derivation_v1::derive_extended_secret_key_from_path;
+use utility::ecdsa::key_derivation_v1::derive_master_extended_secret_key;
+use utility::ecdsa::key_derivation_v1::HARDENED_OFFSET;
+
+#[test]
+fn parse_and_format() {
+    let path = parse_derivation_path("m/3'/1/25").unwrap();
+    assert_eq!(path.get_indexes(), &[HARDENED_OFFSET + 3, 1, 25]);
+    assert_eq!(path, DerivationPath::account(3).child(1).child(25));
+    assert_eq!(path.to_string(), "m/3'/1/25");
+    assert_eq!(parse_derivation_path("m/3h/1/25").unwrap(), path);
+    assert_eq!(parse_derivation_path("m").unwrap(), DerivationPath::master());
+    assert_eq!(DerivationPath::master().to_string(), "m");
+
+    assert!(matches!(parse_derivation_path("3'/1"), Err(DerivationPathError::MissingMaster(_))));
+    for invalid in ["m/", "m/a", "m/-1", "m/+1", "m/1''", "m/2147483648", "m/4294967296"] {
+        assert!(matches!(parse_derivation_path(invalid), Err(DerivationPathError::InvalidIndex(_))), "{}", invalid);
+    }
+
+    let mut bw = BufferWriter::new();
+    path.serialize(&mut bw);
+    let mut br = BufferReader::new(bw.get_bytes());
+    assert_eq!(unserialize_derivation_path(&mut br).unwrap(), path);
+}
+
+#[test]
+fn derive_from_path() {
+    let master = derive_master_extended_secret_key("derivation path seed").unwrap();
+    let account_path = DerivationPath::account(2);
+    let path = parse_derivation_path("m/2'/1/7").unwrap();
+    let keyset = derive_key_set_from_path(&master, &path).unwrap();
+    assert_eq!(keyset.get_derivation_path(), &path);
+    assert_eq!(keyset.get_derivation_index(), 7);
+
+    // the path of the legacy derivation from the master key
+    let legacy = derive_child_key_set(&master, HARDENED_OFFSET + 1, true).unwrap();
+    assert_eq!(legacy.get_derivation_path().to_string(), "m/1'");
+    assert_eq!(derive_key_set_from_path(&master, legacy.get_derivation_path()).unwrap().get_address(), legacy.get_address());
+
+    // the account key derives the public key sets of its non-hardened paths only
+    let xpub = derive_extended_secret_key_from_path(&master, &account_path).unwrap().get_extended_public_key().unwrap();
+    assert_eq!(derive_public_key_set_from_path(&xpub, &account_path, &path).unwrap(), keyset.get_public_key_set());
+    assert!(derive_public_key_set_from_path(&xpub, &account_path, &account_path.hardened_child(0)).is_err());
+    assert!(derive_public_key_set_from_path(&xpub, &account_path, &parse_derivation_path("m/1'/1/7").unwrap()).is_err());
+}
@@ -0,0 +1,143 @@
+//! Rebuilds on disk the states a crash can leave a `StorageDirectory` in, and checks that `init`
+//! recovers a consistent prefix of the chunks.
+use std::path::Path;
+use utility::storage::storage_directory::StorageDirectory;
+use utility::storage::storage_directory::StorageDirectoryError;
+use utility::storage::storage_directory::TEMPORARY_FILE_SUFFIX;
+
+const CATEGORY: &str = "Chunk";
+
+async fn open(path: &Path) -> StorageDirectory {
+    let mut sd = StorageDirectory::new(path, String::from(CATEGORY)).await.unwrap();
+    sd.init().await.unwrap();
+    sd
+}
+
+fn chunk_data(index: usize) -> Vec<u8> {
+    (0..100 + index).map(|i| (i * 7 + index) as u8).collect()
+}
+
+async fn write_chunks(path: &Path, count: usize) {
+    let mut sd = open(path).await;
+    for index in 0..count {
+        sd.add_chunk(&chunk_data(index)).await.unwrap();
+    }
+}
+
+fn chunk_path(path: &Path, index: usize) -> std::path::PathBuf {
+    path.join(format!("{}{}", CATEGORY, index))
+}
+
+async fn assert_chunks(sd: &mut StorageDirectory, count: usize) {
+    assert_eq!(sd.chunk_count(), count);
+    for index in 0..count {
+        assert_eq!(sd.get_chunk(index).await.unwrap(), chunk_data(index));
+    }
+}
+
+#[tokio::test]
+async fn test_save_interrupted_before_rename_keeps_previous_content() {
+    let dir = tempfile::tempdir().unwrap();
+    let sd = open(dir.path()).await;
+    sd.save_bytes_to_file("state", b"previous").await.unwrap();
+    std::fs::write(dir.path().join(format!("state{}", TEMPORARY_FILE_SUFFIX)), b"new but cut").unwrap();
+
+    let sd = open(dir.path()).await;
+    assert_eq!(sd.load_bytes_from_file("state").await.unwrap(), b"previous");
+    assert!(!dir.path().join(format!("state{}", TEMPORARY_FILE_SUFFIX)).exists());
+    assert_eq!(sd.list_files().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_add_chunk_interrupted_before_rename() {
+    let dir = tempfile::tempdir().unwrap();
+    write_chunks(dir.path(), 3).await;
+    std::fs::write(dir.path().join(format!("{}3{}", CATEGORY, TEMPORARY_FILE_SUFFIX)), &chunk_data(3)[..10]).unwrap();
+
+    let mut sd = open(dir.path()).await;
+    assert_chunks(&mut sd, 3).await;
+    sd.add_chunk(&chunk_data(3)).await.unwrap();
+    assert_chunks(&mut sd, 4).await;
+}
+
+#[tokio::test]
+async fn test_add_chunk_interrupted_after_rename() {
+    let dir = tempfile::tempdir().unwrap();
+    write_chunks(dir.path(), 3).await;
+    // the chunk reached its final name but the process died before updating its counter
+    let mut sd = open(dir.path()).await;
+    sd.add_chunk(&chunk_data(3)).await.unwrap();
+    drop(sd);
+
+    let mut sd = open(dir.path()).await;
+    assert_chunks(&mut sd, 4).await;
+}
+
+#[tokio::test]
+async fn test_torn_last_chunk_at_every_length() {
+    let dir = tempfile::tempdir().unwrap();
+    write_chunks(dir.path(), 3).await;
+    let record = std::fs::read(chunk_path(dir.path(), 2)).unwrap();
+
+    for cut in 0..record.len() {
+        std::fs::write(chunk_path(dir.path(), 2), &record[..cut]).unwrap();
+        let mut sd = open(dir.path()).await;
+        assert_chunks(&mut sd, 2).await;
+        assert!(!chunk_path(dir.path(), 2).exists(), "torn chunk cut at {} was kept", cut);
+        std::fs::write(chunk_path(dir.path(), 2), &record).unwrap();
+    }
+    let mut sd = open(dir.path()).await;
+    assert_chunks(&mut sd, 3).await;
+}
+
+#[tokio::test]
+async fn test_corrupted_tail_chunks_are_removed() {
+    let dir = tempfile::tempdir().unwrap();
+    write_chunks(dir.path(), 5).await;
+    for index in [3, 4] {
+        let mut record = std::fs::read(chunk_path(dir.path(), index)).unwrap();
+        let last = record.len() - 1;
+        record[last] ^= 0x01;
+        std::fs::write(chunk_path(dir.path(), index), record).unwrap();
+    }
+
+    let mut sd = open(dir.path()).await;
+    assert_chunks(&mut sd, 3).await;
+    sd.add_chunk(&chunk_data(3)).await.unwrap();
+    assert_chunks(&mut sd, 4).await;
+}
+
+#[tokio::test]
+async fn test_chunks_after_a_missing_chunk_are_removed() {
+    let dir = tempfile::tempdir().unwrap();
+    write_chunks(dir.path(), 6).await;
+    std::fs::remove_file(chunk_path(dir.path(), 3)).unwrap();
+
+    let mut sd = open(dir.path()).await;
+    assert_chunks(&mut sd, 3).await;
+    assert!(!chunk_path(dir.path(), 4).exists());
+    assert!(!chunk_path(dir.path(), 5).exists());
+}
+
+#[tokio::test]
+async fn test_corrupted_chunk_is_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    write_chunks(dir.path(), 3).await;
+    let mut sd = open(dir.path()).await;
+    let mut record = std::fs::read(chunk_path(dir.path(), 1)).unwrap();
+    record[20] ^= 0xff;
+    std::fs::write(chunk_path(dir.path(), 1), record).unwrap();
+
+    assert!(matches!(sd.get_chunk(1).await, Err(StorageDirectoryError::CorruptedChunk(1))));
+}
+
+#[tokio::test]
+async fn test_legacy_chunks_without_record_are_readable() {
+    let dir = tempfile::tempdir().unwrap();
+    for index in 0..3 {
+        std::fs::write(chunk_path(dir.path(), index), chunk_data(index)).unwrap();
+    }
+
+    let mut sd = open(dir.path()).await;
+    assert_chunks(&mut sd, 3).await;
+}
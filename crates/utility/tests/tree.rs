@@ -0,0 +1,71 @@
+use utility::hash::hash::Hash;
+use utility::hash::tree::compute_proof;
+use utility::hash::tree::compute_root;
+use utility::hash::tree::compute_root_checked;
+use utility::hash::tree::verify_proof;
+use utility::hash::tree::TreeError;
+
+fn leaves(count: usize) -> Vec<Hash> {
+    (0..count).map(|i| Hash::compute_hash(&(i as u64).to_le_bytes())).collect()
+}
+
+#[test]
+fn proofs_verify_for_every_leaf() {
+    for count in 1..=17 {
+        let hashes = leaves(count);
+        let root = compute_root(&hashes);
+        assert_eq!(compute_root_checked(&hashes), Ok(root.clone()));
+        for (index, leaf) in hashes.iter().enumerate() {
+            let proof = compute_proof(&hashes, index).unwrap();
+            assert_eq!(verify_proof(leaf, &proof, &root), Ok(()), "leaf {} of {}", index, count);
+            assert_eq!(verify_proof(&Hash::compute_hash(b"not a leaf"), &proof, &root), Err(TreeError::RootMismatch));
+        }
+    }
+}
+
+#[test]
+fn proof_errors() {
+    let hashes = leaves(5);
+    let root = compute_root(&hashes);
+    assert_eq!(compute_proof(&hashes, 5), Err(TreeError::IndexOutOfRange { index: 5, leaves_count: 5 }));
+
+    let mut proof = compute_proof(&hashes, 1).unwrap();
+    proof.siblings.pop();
+    assert!(matches!(verify_proof(&hashes[1], &proof, &root), Err(TreeError::InvalidProofLength { .. })));
+
+    // claiming another position in the tree fails
+    let mut proof = compute_proof(&hashes, 1).unwrap();
+    proof.index = 0;
+    assert!(verify_proof(&hashes[1], &proof, &root).is_err());
+}
+
+#[test]
+fn leaves_and_branches_are_domain_separated() {
+    // a single leaf is not its own root, and a branch cannot be presented as a leaf
+    let hashes = leaves(4);
+    assert_ne!(compute_root(&hashes[..1]), hashes[0]);
+    let upper = vec![compute_root(&hashes[..2]), compute_root(&hashes[2..])];
+    assert_ne!(compute_root(&upper), compute_root(&hashes));
+}
+
+#[test]
+fn duplicated_last_node_is_detected() {
+    let hashes = leaves(3);
+    let mut mutated = hashes.clone();
+    mutated.push(hashes[2].clone());
+    // both lists have the same root
+    assert_eq!(compute_root(&mutated), compute_root(&hashes));
+    assert_eq!(compute_root_checked(&mutated), Err(TreeError::DuplicatedNodes { level: 0, index: 2 }));
+
+    // the duplication of a whole subtree is detected at the level it happens
+    let hashes = leaves(6);
+    let mut mutated = hashes.clone();
+    mutated.extend_from_slice(&hashes[4..6]);
+    assert_eq!(compute_root(&mutated), compute_root(&hashes));
+    assert_eq!(compute_root_checked(&mutated), Err(TreeError::DuplicatedNodes { level: 1, index: 2 }));
+
+    // a proof for the duplicated position of the mutated list is rejected
+    let root = compute_root(&hashes);
+    let proof = compute_proof(&mutated, 7).unwrap();
+    assert!(matches!(verify_proof(&mutated[7], &proof, &root), Err(TreeError::DuplicatedNodes { .. })));
+}
@@ -0,0 +1,96 @@
+use thiserror::Error;
+use zeroize::Zeroize;
+use sha2::Digest;
+use sha2::Sha256;
+use chacha20poly1305::aead::OsRng;
+use chacha20poly1305::aead::rand_core::RngCore;
+use crate::wallet_v1::bip39_wordlist::BIP39_WORDLIST;
+
+/// Bits of entropy or checksum a word encodes.
+const BITS_PER_WORD: usize = 11;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum Bip39Error {
+    #[error("Invalid entropy size: {0} bytes")]
+    InvalidEntropySize(usize),
+    #[error("Invalid mnemonic word count: {0}")]
+    InvalidWordCount(usize),
+    #[error("Unknown mnemonic word: {0}")]
+    UnknownWord(String),
+    #[error("Mnemonic checksum mismatch")]
+    ChecksumMismatch,
+}
+
+/// Generates a mnemonic of `words_count` words (12, 15, 18, 21 or 24) from fresh random entropy.
+pub fn generate_bip39_mnemonic(words_count: usize) -> Result<String, Bip39Error> {
+    let entropy_size = get_entropy_size(words_count)?;
+    let mut entropy = vec![0u8; entropy_size];
+    OsRng.fill_bytes(&mut entropy);
+    let mnemonic = bip39_mnemonic_from_entropy(&entropy);
+    entropy.zeroize();
+    mnemonic
+}
+
+/// Encodes 16 to 32 bytes of entropy (a multiple of 4) followed by the first bits of its SHA-256 as words of 11 bits.
+pub fn bip39_mnemonic_from_entropy(entropy: &[u8]) -> Result<String, Bip39Error> {
+    if !(16..=32).contains(&entropy.len()) || !entropy.len().is_multiple_of(4) {
+        return Err(Bip39Error::InvalidEntropySize(entropy.len()));
+    }
+    let checksum = Sha256::digest(entropy);
+    let mut bits = get_bits(entropy);
+    bits.extend(get_bits(&checksum).into_iter().take(entropy.len() / 4));
+    let words: Vec<&str> = bits
+        .chunks(BITS_PER_WORD)
+        .map(|chunk| BIP39_WORDLIST[chunk.iter().fold(0usize, |index, bit| (index << 1) | *bit as usize)])
+        .collect();
+    bits.zeroize();
+    Ok(words.join(" "))
+}
+
+/// Decodes the entropy of a mnemonic, checking its words and checksum.
+pub fn bip39_mnemonic_to_entropy(mnemonic: &str) -> Result<Vec<u8>, Bip39Error> {
+    let words: Vec<&str> = mnemonic.split_whitespace().collect();
+    let entropy_size = get_entropy_size(words.len())?;
+    let mut bits = Vec::with_capacity(words.len() * BITS_PER_WORD);
+    for word in words.iter() {
+        let index = BIP39_WORDLIST
+            .binary_search(word)
+            .map_err(|_| Bip39Error::UnknownWord(word.to_string()))?;
+        bits.extend((0..BITS_PER_WORD).rev().map(|shift| ((index >> shift) & 1) as u8));
+    }
+    let mut entropy: Vec<u8> = bits[..entropy_size * 8]
+        .chunks(8)
+        .map(|chunk| chunk.iter().fold(0u8, |byte, bit| (byte << 1) | bit))
+        .collect();
+    let checksum = Sha256::digest(&entropy);
+    let matches = get_bits(&checksum).into_iter().take(entropy_size / 4).eq(bits[entropy_size * 8..].iter().copied());
+    bits.zeroize();
+    if !matches {
+        entropy.zeroize();
+        return Err(Bip39Error::ChecksumMismatch);
+    }
+    Ok(entropy)
+}
+
+pub fn check_bip39_mnemonic(mnemonic: &str) -> bool {
+    match bip39_mnemonic_to_entropy(mnemonic) {
+        Ok(mut entropy) => {
+            entropy.zeroize();
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Each word encodes 11 bits, 1 of checksum for every 32 of entropy.
+fn get_entropy_size(words_count: usize) -> Result<usize, Bip39Error> {
+    if !(12..=24).contains(&words_count) || !words_count.is_multiple_of(3) {
+        return Err(Bip39Error::InvalidWordCount(words_count));
+    }
+    Ok(words_count * BITS_PER_WORD * 32 / 33 / 8)
+}
+
+/// The bits of `bytes`, most significant first.
+fn get_bits(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1)).collect()
+}
@@ -0,0 +1,100 @@
+/// Branch-and-bound gives up after this many visited nodes and the largest-first selection is used instead.
+pub const BNB_MAX_TRIES: usize = 100_000;
+
+/// Outcome of a coin selection: the chosen candidates and whether a change output is needed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoinSelection {
+    pub indexes: Vec<usize>,
+    pub selected_value: u64,
+    pub with_change: bool,
+}
+
+/// Branch-and-bound search for candidates whose effective values (value minus the fee of spending them) sum
+/// to at least `target` and at most `target + cost_of_change`, so that no change output is worth creating.
+/// The subset wasting the least is kept. Returns `None` if there is none or the search gave up.
+pub fn select_coins_bnb(effective_values: &[u64], target: u64, cost_of_change: u64) -> Option<CoinSelection> {
+    let mut order: Vec<usize> = (0..effective_values.len()).collect();
+    order.sort_by(|a, b| effective_values[*b].cmp(&effective_values[*a]));
+    let sorted_values: Vec<u64> = order.iter().map(|index| effective_values[*index]).collect();
+    let available: u64 = sorted_values.iter().sum();
+    if available < target {
+        return None;
+    }
+
+    let mut search = BnbSearch {
+        values: &sorted_values,
+        target,
+        upper_bound: target.saturating_add(cost_of_change),
+        tries: BNB_MAX_TRIES,
+        chosen: Vec::new(),
+        best: None,
+    };
+    search.run(0, 0, available);
+    let (waste, positions) = search.best?;
+    Some(CoinSelection {
+        indexes: positions.iter().map(|position| order[*position]).collect(),
+        selected_value: target + waste,
+        with_change: false,
+    })
+}
+
+/// Adds the candidates from the largest effective value down until `target` is reached. A change output
+/// is needed when the excess is above `cost_of_change`, otherwise it is left to the fee.
+pub fn select_coins_largest_first(effective_values: &[u64], target: u64, cost_of_change: u64) -> Option<CoinSelection> {
+    let mut order: Vec<usize> = (0..effective_values.len()).collect();
+    order.sort_by(|a, b| effective_values[*b].cmp(&effective_values[*a]));
+    let mut indexes = Vec::new();
+    let mut selected_value: u64 = 0;
+    for index in order.into_iter() {
+        indexes.push(index);
+        selected_value += effective_values[index];
+        if selected_value >= target {
+            return Some(CoinSelection {
+                indexes,
+                selected_value,
+                with_change: selected_value - target > cost_of_change,
+            });
+        }
+    }
+    None
+}
+
+struct BnbSearch<'a> {
+    /// Effective values sorted from the largest.
+    values: &'a [u64],
+    target: u64,
+    upper_bound: u64,
+    tries: usize,
+    chosen: Vec<usize>,
+    /// Waste and positions of the best selection found.
+    best: Option<(u64, Vec<usize>)>,
+}
+
+impl BnbSearch<'_> {
+    /// Explores the inclusion, then the omission, of the candidate at `position`. `remaining` is the sum of the
+    /// values from `position`, used to cut the branches that cannot reach the target.
+    fn run(&mut self, position: usize, current: u64, remaining: u64) {
+        if self.tries == 0 || current > self.upper_bound {
+            return;
+        }
+        self.tries -= 1;
+        if current >= self.target {
+            let waste = current - self.target;
+            if self.best.as_ref().map(|(best_waste, _)| waste < *best_waste).unwrap_or(true) {
+                self.best = Some((waste, self.chosen.clone()));
+            }
+            return;
+        }
+        if position == self.values.len() || current + remaining < self.target {
+            return;
+        }
+        let value = self.values[position];
+        self.chosen.push(position);
+        self.run(position + 1, current + value, remaining - value);
+        self.chosen.pop();
+        if matches!(self.best, Some((0, _))) {
+            return;
+        }
+        self.run(position + 1, current, remaining - value);
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use thiserror::Error;
+use num_bigint::BigUint;
+use utility::hash::hash::Hash;
+use utility::hash::bigint::work_from_compact;
+use utility::buffer::buffer_writer::BufferWriter;
+use utility::buffer::buffer_reader::BufferReader;
+use utility::buffer::buffer_reader::BufferReaderError;
+use utility::storage::async_file::{save_bytes_to_file_atomic, load_bytes_from_file, file_exists, AsyncFileError};
+use utility::system::time::timestamp_now;
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainheader::mainheader::Mainheader;
+use maincore::mainheader::mainheader::MainheaderError;
+use maincore::mainheader::mainheader::unserialize_mainheader;
+use maincore::mainblock::mainblock_validation::validate_mainheader;
+use maincore::mainblock::mainblock_validation::MainblockValidationContext;
+use maincore::mainblock::mainblock_validation::MainblockValidationError;
+use maincore::mainblock::maintx_proof::MaintxProof;
+use maincore::mainblock::maintx_proof::MaintxProofError;
+use maincore::mainblock::maintx_proof::unserialize_maintx_proof;
+use maincore::retarget::retarget::compute_next_bits;
+use maincore::retarget::retarget::get_median_time_past;
+use maincore::retarget::retarget::RetargetError;
+use maincore::retarget::retarget::MEDIAN_TIME_SPAN;
+use maincore::p2p::p2p_message::P2pError;
+use maincore::p2p::p2p_message::P2P_MAX_HEADERS_COUNT;
+use maincore::p2p::p2p_light_connection::P2pLightConnection;
+
+use crate::wallet_v1::wallet_inner::WalletInner;
+use crate::wallet_v1::wallet_inner::WalletInnerError;
+
+const LIGHT_CLIENT_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum LightClientError {
+    #[error("Async file error: {0}")]
+    AsyncFileError(#[from] AsyncFileError),
+    #[error("Buffer reader error: {0}")]
+    BufferReaderError(#[from] BufferReaderError),
+    #[error("Mainheader error: {0}")]
+    MainheaderError(#[from] MainheaderError),
+    #[error("Mainblock validation error: {0}")]
+    MainblockValidationError(#[from] MainblockValidationError),
+    #[error("Retarget error: {0}")]
+    RetargetError(#[from] RetargetError),
+    #[error("Maintx proof error: {0}")]
+    MaintxProofError(#[from] MaintxProofError),
+    #[error("Wallet error: {0}")]
+    WalletInnerError(#[from] WalletInnerError),
+    #[error("P2p error: {0}")]
+    P2pError(#[from] P2pError),
+
+    #[error("Unknown light client version: {0}")]
+    UnknownVersion(u32),
+    #[error("Invalid light client path")]
+    InvalidPath,
+    #[error("Genesis mainheader mismatch, expected {expected:?}, got {actual:?}")]
+    GenesisMismatch { expected: Hash, actual: Hash },
+    #[error("Mainheader {0:?} does not extend a known mainheader")]
+    UnknownParent(Hash),
+    #[error("Maintx proof at height {height} is invalid: {source}")]
+    InvalidMaintxProof { height: u32, source: MaintxProofError },
+}
+
+/// Mainheaders forking from the chain, kept until their branch has more work than the mainheaders they replace.
+/// A better branch may arrive over several batches of mainheaders.
+struct LightClientCandidate {
+    fork_height: usize,
+    mainheaders: Vec<Mainheader>,
+    /// Last mainheaders of the candidate branch, used to validate the next ones.
+    window: Vec<Mainheader>,
+}
+
+/// SPV mode of a wallet: keeps the validated mainheader chain and the maintxs of the wallet addresses
+/// with their Merkle proofs, instead of the whole mainblocks. The node serving the proofs can hide maintxs,
+/// it cannot make up a maintx that is not in a mainblock of the chain with the most work.
+pub struct LightClient {
+    lc_path: PathBuf,
+    chain_params: ChainParams,
+    header_vector: Vec<Mainheader>,
+    mainheader_heights: HashMap<Hash, usize>,
+    /// Verified proofs by height and position, replayed into the wallet after a reorganization.
+    proofs: BTreeMap<(u32, u32), MaintxProof>,
+    candidate: Option<LightClientCandidate>,
+}
+
+impl LightClient {
+    pub fn new<P: AsRef<Path>>(lc_path: P, chain_params: ChainParams) -> Self {
+        let mut light_client = LightClient {
+            lc_path: lc_path.as_ref().to_path_buf(),
+            chain_params,
+            header_vector: Vec::new(),
+            mainheader_heights: HashMap::new(),
+            proofs: BTreeMap::new(),
+            candidate: None,
+        };
+        light_client.reset();
+        light_client
+    }
+
+    pub fn get_chain_params(&self) -> &ChainParams {
+        &self.chain_params
+    }
+
+    pub fn get_mainheaders_count(&self) -> usize {
+        self.header_vector.len()
+    }
+
+    pub fn get_mainheader(&self, height: usize) -> Option<&Mainheader> {
+        self.header_vector.get(height)
+    }
+
+    pub fn get_last_mainheader(&self) -> &Mainheader {
+        // the genesis mainheader is never removed
+        &self.header_vector[self.header_vector.len() - 1]
+    }
+
+    pub fn get_maintx_proofs(&self) -> Vec<&MaintxProof> {
+        self.proofs.values().collect()
+    }
+
+    /// Keeps only the genesis mainheader.
+    pub fn reset(&mut self) {
+        let genesis_mainheader = self.chain_params.get_genesis_mainheader();
+        self.mainheader_heights.clear();
+        self.mainheader_heights.insert(genesis_mainheader.get_hash(), 0);
+        self.header_vector = vec![genesis_mainheader];
+        self.proofs.clear();
+        self.candidate = None;
+    }
+
+    /// Hashes of mainheaders from the tip backwards, dense at first then doubling the step,
+    /// always ending with the genesis mainheader. The tip of the candidate branch comes first, so that
+    /// the node sends the mainheaders following it.
+    pub fn get_mainheader_locator(&self) -> Vec<Hash> {
+        let mut locator: Vec<Hash> = self.candidate.iter().filter_map(|candidate| candidate.mainheaders.last()).map(|mh| mh.get_hash()).collect();
+        let mut height = self.header_vector.len() - 1;
+        let mut step = 1;
+        loop {
+            locator.push(self.header_vector[height].get_hash());
+            if height == 0 {
+                break;
+            }
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            height = height.saturating_sub(step);
+        }
+        locator
+    }
+
+    /// Validates mainheaders that follow a known mainheader. A branch replacing some of the chain is kept as
+    /// a candidate branch, extended by the next batches, and only taken once it has more work: the proofs above
+    /// the fork are dropped, the wallet resources are rebuilt from the remaining proofs and `last_known_height`
+    /// goes back to the fork. Returns the number of mainheaders added to the chain or to the candidate branch.
+    pub fn add_mainheaders(&mut self, wallet: &mut WalletInner, mainheaders: &[Mainheader]) -> Result<usize, LightClientError> {
+        // skip the mainheaders already on the chain or in the candidate branch
+        let known_count = mainheaders
+            .iter()
+            .take_while(|mh| self.mainheader_heights.contains_key(&mh.get_hash()) || self.is_candidate(&mh.get_hash()))
+            .count();
+        let new_mainheaders = &mainheaders[known_count..];
+        let first = match new_mainheaders.first() {
+            Some(first) => first,
+            None => return Ok(0),
+        };
+        let prev_hash = first.get_prev_hash();
+        let extends_candidate = self.candidate.as_ref().and_then(|candidate| candidate.mainheaders.last()).map(|mh| mh.get_hash()) == Some(prev_hash.clone());
+        let mut candidate = if extends_candidate {
+            self.candidate.take().unwrap()
+        } else {
+            let fork_height = *self
+                .mainheader_heights
+                .get(&prev_hash)
+                .ok_or_else(|| LightClientError::UnknownParent(first.get_hash()))?;
+            let window_size = self.chain_params.retarget_interval.max(MEDIAN_TIME_SPAN);
+            let window = self.header_vector[(fork_height + 1).saturating_sub(window_size)..=fork_height].to_vec();
+            LightClientCandidate { fork_height, mainheaders: Vec::new(), window }
+        };
+
+        let window_size = self.chain_params.retarget_interval.max(MEDIAN_TIME_SPAN);
+        let max_timestamp = timestamp_now() + self.chain_params.max_future_block_time;
+        for mh in new_mainheaders.iter() {
+            let height = candidate.fork_height + 1 + candidate.mainheaders.len();
+            let context = MainblockValidationContext {
+                height,
+                prev_mainheader: candidate.window.last().cloned(),
+                expected_bits: Some(compute_next_bits(height, &candidate.window, &self.chain_params)?),
+                median_time_past: get_median_time_past(&candidate.window),
+                max_timestamp: Some(max_timestamp),
+            };
+            validate_mainheader(mh, &context)?;
+            candidate.window.push(mh.clone());
+            if candidate.window.len() > window_size {
+                candidate.window.remove(0);
+            }
+            candidate.mainheaders.push(mh.clone());
+        }
+
+        let fork_height = candidate.fork_height;
+        if fork_height + 1 < self.header_vector.len() {
+            let branch_work: BigUint = candidate.mainheaders.iter().map(|mh| work_from_compact(mh.get_bits())).sum();
+            let replaced_work: BigUint = self.header_vector[fork_height + 1..].iter().map(|mh| work_from_compact(mh.get_bits())).sum();
+            if branch_work <= replaced_work {
+                println!("light client candidate branch of {} mainheaders from height {} has less work", candidate.mainheaders.len(), fork_height);
+                self.candidate = Some(candidate);
+                return Ok(new_mainheaders.len());
+            }
+            println!("light client reorganization at height {}", fork_height);
+            for mh in self.header_vector.drain(fork_height + 1..) {
+                self.mainheader_heights.remove(&mh.get_hash());
+            }
+            self.proofs.retain(|(height, _), _| (*height as usize) <= fork_height);
+            self.replay_maintx_proofs(wallet)?;
+            wallet.set_last_known_height(wallet.get_last_known_height().min(fork_height));
+            self.candidate = None;
+        }
+        for mh in candidate.mainheaders.into_iter() {
+            self.mainheader_heights.insert(mh.get_hash(), self.header_vector.len());
+            self.header_vector.push(mh);
+        }
+        Ok(new_mainheaders.len())
+    }
+
+    fn is_candidate(&self, hash: &Hash) -> bool {
+        self.candidate.as_ref().is_some_and(|candidate| candidate.mainheaders.iter().any(|mh| &mh.get_hash() == hash))
+    }
+
+    /// Verifies maintx proofs answering a request from `wallet.get_last_known_height()` and pushes their maintxs
+    /// into the wallet in chain order. Proofs above the local tip are left for a later request. Any invalid
+    /// proof rejects the whole answer. The wallet then knows the mainblocks up to `last_height`.
+    pub fn add_maintx_proofs(&mut self, wallet: &mut WalletInner, last_height: usize, proofs: Vec<MaintxProof>) -> Result<usize, LightClientError> {
+        let tip_height = self.header_vector.len() - 1;
+        let mut verified = Vec::new();
+        for proof in proofs.into_iter() {
+            let height = proof.mainblock_height;
+            let mh = match self.header_vector.get(height as usize) {
+                Some(mh) => mh,
+                None => continue,
+            };
+            proof.verify(mh).map_err(|source| LightClientError::InvalidMaintxProof { height, source })?;
+            verified.push(proof);
+        }
+        verified.sort_by_key(|proof| (proof.mainblock_height, proof.get_position()));
+        wallet.set_reward_maturity(self.chain_params.reward_maturity);
+        let mut added_count = 0;
+        for proof in verified.into_iter() {
+            // a known maintx is pushed again, the wallet may have been restored since it was received
+            wallet.update_resources(proof.maintx.clone(), proof.mainblock_height)?;
+            if self.proofs.insert((proof.mainblock_height, proof.get_position()), proof).is_none() {
+                added_count += 1;
+            }
+        }
+        wallet.set_last_known_height(wallet.get_last_known_height().max(last_height.min(tip_height)));
+        Ok(added_count)
+    }
+
+    /// Rebuilds the wallet resources from the known proofs, e.g. after `load`. The resources spent by a maintx
+    /// built but not confirmed yet stay unavailable.
+    pub fn replay_maintx_proofs(&self, wallet: &mut WalletInner) -> Result<(), LightClientError> {
+        let pending: Vec<(Hash, u32)> = wallet
+            .get_resources()
+            .iter()
+            .filter(|resource| !resource.available)
+            .map(|resource| (resource.hash.clone(), resource.index))
+            .collect();
+        wallet.clear_resources();
+        wallet.set_reward_maturity(self.chain_params.reward_maturity);
+        for proof in self.proofs.values() {
+            wallet.update_resources(proof.maintx.clone(), proof.mainblock_height)?;
+        }
+        for (hash, index) in pending.iter() {
+            wallet.set_resource_unavailable(hash, *index);
+        }
+        Ok(())
+    }
+
+    /// Downloads the mainheaders of the node, then the proofs of the wallet maintxs up to the tip.
+    pub async fn sync(&mut self, wallet: &mut WalletInner, connection: &mut P2pLightConnection) -> Result<(), LightClientError> {
+        loop {
+            let mainheaders = connection.get_mainheaders(self.get_mainheader_locator()).await?;
+            let received_count = mainheaders.len();
+            let added_count = self.add_mainheaders(wallet, &mainheaders)?;
+            println!("light client received {} mainheaders, {} added, tip height {}", received_count, added_count, self.header_vector.len() - 1);
+            if received_count < P2P_MAX_HEADERS_COUNT || added_count == 0 {
+                break;
+            }
+        }
+        let tip_height = self.header_vector.len() - 1;
+        let addresses = wallet.get_addresses();
+        // the last known mainblock is asked again, its maintxs are already known and skipped
+        while wallet.get_last_known_height() < tip_height {
+            let from_height = wallet.get_last_known_height();
+            let (last_height, proofs) = connection.get_maintx_proofs(addresses.clone(), from_height as u64).await?;
+            let added_count = self.add_maintx_proofs(wallet, last_height as usize, proofs)?;
+            println!("light client scanned mainblocks {} to {}, {} maintxs added", from_height, last_height, added_count);
+            if wallet.get_last_known_height() <= from_height {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bw = BufferWriter::new();
+        bw.put_var_u32(LIGHT_CLIENT_VERSION);
+        bw.put_var_u64(self.header_vector.len() as u64);
+        for mh in self.header_vector.iter() {
+            bw.put_var_bytes(&mh.serialize());
+        }
+        bw.put_var_u64(self.proofs.len() as u64);
+        for proof in self.proofs.values() {
+            proof.serialize(&mut bw);
+        }
+        bw.get_bytes()
+    }
+
+    /// Restores the chain and proofs saved by `serialize`. The mainheaders are trusted, they were validated before being saved.
+    pub fn unserialize(&mut self, rawbytes: Vec<u8>) -> Result<(), LightClientError> {
+        let mut br = BufferReader::new(rawbytes);
+        let version = br.get_var_u32()?;
+        if version != LIGHT_CLIENT_VERSION {
+            return Err(LightClientError::UnknownVersion(version));
+        }
+        let mainheaders_count = br.get_var_u64()?;
+        let mut header_vector = Vec::new();
+        let mut mainheader_heights = HashMap::new();
+        for height in 0..mainheaders_count as usize {
+            let mh = unserialize_mainheader(br.get_var_bytes()?)?;
+            mainheader_heights.insert(mh.get_hash(), height);
+            header_vector.push(mh);
+        }
+        let genesis_hash = self.chain_params.get_genesis_hash();
+        let first_hash = header_vector.first().map(|mh| mh.get_hash()).unwrap_or_else(Hash::new_empty);
+        if first_hash != genesis_hash {
+            return Err(LightClientError::GenesisMismatch { expected: genesis_hash, actual: first_hash });
+        }
+        let proofs_count = br.get_var_u64()?;
+        let mut proofs = BTreeMap::new();
+        for _ in 0..proofs_count {
+            let proof = unserialize_maintx_proof(&mut br)?;
+            proofs.insert((proof.mainblock_height, proof.get_position()), proof);
+        }
+        self.header_vector = header_vector;
+        self.mainheader_heights = mainheader_heights;
+        self.proofs = proofs;
+        self.candidate = None;
+        Ok(())
+    }
+
+    /// Loads the saved state if there is one. Returns whether it was loaded.
+    pub async fn load(&mut self) -> Result<bool, LightClientError> {
+        let path = self.lc_path.to_str().ok_or(LightClientError::InvalidPath)?;
+        if !file_exists(path).await {
+            return Ok(false);
+        }
+        let rawbytes = load_bytes_from_file(path).await?;
+        self.unserialize(rawbytes)?;
+        Ok(true)
+    }
+
+    pub async fn save(&self) -> Result<(), LightClientError> {
+        let path = self.lc_path.to_str().ok_or(LightClientError::InvalidPath)?;
+        save_bytes_to_file_atomic(&self.serialize(), path).await?;
+        Ok(())
+    }
+}
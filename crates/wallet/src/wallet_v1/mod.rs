@@ -1,5 +1,12 @@
 pub mod seed_wordlist;
+pub mod bip39_wordlist;
 pub mod seed_generation;
+pub mod bip39;
+pub mod seed_scheme;
 pub mod resource_info;
 pub mod resource;
-pub mod wallet_inner;
\ No newline at end of file
+pub mod coin_selection;
+pub mod wallet_inner;
+pub mod light_client;
+pub mod wallet_file;
+pub mod wallet_restore;
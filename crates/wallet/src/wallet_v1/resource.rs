@@ -1,9 +1,22 @@
+use thiserror::Error;
 use utility::hash::hash::Hash;
 use utility::buffer::buffer_writer::BufferWriter;
+use utility::buffer::buffer_reader::BufferReader;
+use utility::buffer::buffer_reader::BufferReaderError;
 
 use crate::wallet_v1::resource_info::ResourceInfo;
 use crate::wallet_v1::resource_info::UnspentResourceInfo;
 use crate::wallet_v1::resource_info::SpentResourceInfo;
+use crate::wallet_v1::resource_info::RESOURCE_INFO_IDENTIFIER_UNSPENT;
+use crate::wallet_v1::resource_info::RESOURCE_INFO_IDENTIFIER_SPENT;
+
+#[derive(Debug, Error)]
+pub enum ResourceError {
+    #[error("Buffer reader error: {0}")]
+    BufferReaderError(#[from] BufferReaderError),
+    #[error("Unknown resource info identifier: {0}")]
+    UnknownInfoIdentifier(u32),
+}
 //
 #[derive(Debug)]
 #[derive(Clone)] 
@@ -12,6 +25,11 @@ pub struct Resource {
     pub index: u32,
     pub value:u64,
     pub key_index:usize,
+    /// Height of the mainblock of the maintx, a reward can only be spent `reward_maturity` mainblocks later.
+    pub mainblock_height:u32,
+    pub is_reward:bool,
+    /// False while a built maintx spends the resource. Only kept in memory: a maintx that was never
+    /// broadcast does not lock the resource once the wallet is loaded again.
     pub available:bool,
     //pub status: Vec<u8>,
     pub info:ResourceInfo,
@@ -36,16 +54,35 @@ impl Resource {
     pub fn is_unspent_resource(&self)-> bool{
         self.info.is_unspent_resource_info()
     }
+    /// Whether a maintx in the mainblock at `mainblock_height` may spend the resource.
+    pub fn is_mature(&self, mainblock_height: usize, reward_maturity: usize) -> bool {
+        !self.is_reward || mainblock_height >= self.mainblock_height as usize + reward_maturity
+    }
+    pub fn serialize(&self, bw: &mut BufferWriter) {
+        bw.put_hash(self.hash.clone());
+        bw.put_var_u32(self.index);
+        bw.put_u64(self.value);
+        bw.put_var_u64(self.key_index as u64);
+        bw.put_var_u32(self.mainblock_height);
+        bw.put_u8(self.is_reward as u8);
+        if self.is_unspent_resource() {
+            bw.put_var_u32(RESOURCE_INFO_IDENTIFIER_UNSPENT);
+        } else {
+            bw.put_var_u32(RESOURCE_INFO_IDENTIFIER_SPENT);
+        }
+    }
 }
 
 //
-pub fn new_unspent_resource(h:Hash,tmpindex:u32,value:u64,key_index: usize) ->Resource {
+pub fn new_unspent_resource(h:Hash,tmpindex:u32,value:u64,key_index: usize,mainblock_height:u32,is_reward:bool) ->Resource {
 
     let mut new_resource= Resource {
         hash: h,
         index: tmpindex,
         value,
         key_index,
+        mainblock_height,
+        is_reward,
         available:true,
         //status: Vec::new(),
         info:ResourceInfo::UnspentResourceInfoVariant(UnspentResourceInfo{}),
@@ -58,4 +95,19 @@ pub fn new_unspent_resource(h:Hash,tmpindex:u32,value:u64,key_index: usize) ->Re
     //
     ////new_resource.status.extend_from_slice(&tmpbw.get_bytes());
     return new_resource
-}
\ No newline at end of file
+}
+
+pub fn unserialize_resource(br: &mut BufferReader) -> Result<Resource, ResourceError> {
+    let hash = br.get_hash()?;
+    let index = br.get_var_u32()?;
+    let value = br.get_u64()?;
+    let key_index = br.get_var_u64()? as usize;
+    let mainblock_height = br.get_var_u32()?;
+    let is_reward = br.get_u8()? != 0;
+    let info = match br.get_var_u32()? {
+        RESOURCE_INFO_IDENTIFIER_UNSPENT => ResourceInfo::UnspentResourceInfoVariant(UnspentResourceInfo{}),
+        RESOURCE_INFO_IDENTIFIER_SPENT => ResourceInfo::SpentResourceInfoVariant(SpentResourceInfo{}),
+        identifier => return Err(ResourceError::UnknownInfoIdentifier(identifier)),
+    };
+    Ok(Resource { hash, index, value, key_index, mainblock_height, is_reward, available: true, info })
+}
@@ -0,0 +1,139 @@
+use thiserror::Error;
+use zeroize::Zeroize;
+use utility::buffer::buffer_writer::BufferWriter;
+use utility::buffer::buffer_reader::BufferReader;
+use utility::buffer::buffer_reader::BufferReaderError;
+use utility::ecdsa::key_derivation_v1::ExtendedSecretKey;
+use utility::ecdsa::key_derivation_v1::KeyDerivationError;
+use utility::ecdsa::key_derivation_v1::derive_bip39_seed;
+use utility::ecdsa::key_derivation_v1::derive_master_extended_secret_key;
+use utility::ecdsa::key_derivation_v1::derive_master_extended_secret_key_from_seed_bytes;
+
+use crate::wallet_v1::bip39::check_bip39_mnemonic;
+use crate::wallet_v1::seed_generation::check_seed;
+
+const SEED_SCHEME_LEGACY_TAG: u8 = 0;
+const SEED_SCHEME_BIP39_TAG: u8 = 1;
+
+#[derive(Debug, Error)]
+pub enum SeedSchemeError {
+    #[error("Unknown seed scheme: {0}")]
+    UnknownSeedScheme(u8),
+    #[error("Invalid seed phrase")]
+    InvalidSeed,
+    #[error("KeyDerivationError error: {0}")]
+    KeyDerivationError(#[from] KeyDerivationError),
+    #[error("Buffer reader error: {0}")]
+    BufferReaderError(#[from] BufferReaderError),
+}
+
+/// How a seed phrase becomes the master key. The tag is saved with the phrase, so that a phrase keeps the
+/// master key it was created with whatever the default scheme becomes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeedScheme {
+    /// The 24 words of `generate_seed` and their checksum word, stretched by `derive_master_extended_secret_key`.
+    Legacy,
+    /// A BIP-39 mnemonic and its optional passphrase, stretched into the seed of the standard.
+    Bip39,
+}
+
+impl SeedScheme {
+    pub fn get_tag(&self) -> u8 {
+        match self {
+            SeedScheme::Legacy => SEED_SCHEME_LEGACY_TAG,
+            SeedScheme::Bip39 => SEED_SCHEME_BIP39_TAG,
+        }
+    }
+}
+
+pub fn new_seed_scheme_from_tag(tag: u8) -> Result<SeedScheme, SeedSchemeError> {
+    match tag {
+        SEED_SCHEME_LEGACY_TAG => Ok(SeedScheme::Legacy),
+        SEED_SCHEME_BIP39_TAG => Ok(SeedScheme::Bip39),
+        _ => Err(SeedSchemeError::UnknownSeedScheme(tag)),
+    }
+}
+
+/// A seed phrase with its scheme. The BIP-39 passphrase is empty for the legacy scheme.
+pub struct WalletSeed {
+    scheme: SeedScheme,
+    phrase: String,
+    passphrase: String,
+}
+
+impl WalletSeed {
+    pub fn get_scheme(&self) -> SeedScheme {
+        self.scheme
+    }
+
+    pub fn get_phrase(&self) -> &str {
+        &self.phrase
+    }
+
+    pub fn get_passphrase(&self) -> &str {
+        &self.passphrase
+    }
+
+    /// Whether the checksum of the phrase matches, for the scheme of the phrase.
+    pub fn is_valid(&self) -> bool {
+        match self.scheme {
+            SeedScheme::Legacy => check_seed(&self.phrase),
+            SeedScheme::Bip39 => check_bip39_mnemonic(&self.phrase) && self.passphrase.is_ascii(),
+        }
+    }
+
+    pub fn derive_master_extended_secret_key(&self) -> Result<ExtendedSecretKey, SeedSchemeError> {
+        match self.scheme {
+            SeedScheme::Legacy => Ok(derive_master_extended_secret_key(&self.phrase)?),
+            SeedScheme::Bip39 => {
+                if !check_bip39_mnemonic(&self.phrase) {
+                    return Err(SeedSchemeError::InvalidSeed);
+                }
+                let mut seed_bytes = derive_bip39_seed(&self.phrase, &self.passphrase)?;
+                let master_extended_secret_key = derive_master_extended_secret_key_from_seed_bytes(&seed_bytes);
+                seed_bytes.zeroize();
+                Ok(master_extended_secret_key?)
+            }
+        }
+    }
+
+    /// The caller must zeroize the bytes once used.
+    pub fn serialize(&self, bw: &mut BufferWriter) {
+        bw.put_u8(self.scheme.get_tag());
+        bw.put_var_bytes(self.phrase.as_bytes());
+        bw.put_var_bytes(self.passphrase.as_bytes());
+    }
+}
+
+impl Drop for WalletSeed {
+    fn drop(&mut self) {
+        self.phrase.zeroize();
+        self.passphrase.zeroize();
+    }
+}
+
+pub fn new_legacy_wallet_seed(phrase: String) -> WalletSeed {
+    WalletSeed { scheme: SeedScheme::Legacy, phrase, passphrase: String::new() }
+}
+
+/// The passphrase must be ASCII: the standard normalizes it to NFKD, which is not implemented here, so
+/// `derive_bip39_seed` rejects other characters rather than deriving keys other wallets would not find.
+/// Such a seed is not valid. The English wordlist keeps every valid mnemonic ASCII.
+pub fn new_bip39_wallet_seed(mnemonic: String, passphrase: String) -> WalletSeed {
+    WalletSeed { scheme: SeedScheme::Bip39, phrase: mnemonic, passphrase }
+}
+
+pub fn unserialize_wallet_seed(br: &mut BufferReader) -> Result<WalletSeed, SeedSchemeError> {
+    let scheme = new_seed_scheme_from_tag(br.get_u8()?)?;
+    let mut wallet_seed = WalletSeed { scheme, phrase: String::new(), passphrase: String::new() };
+    wallet_seed.phrase = new_string_from_secret_bytes(br.get_var_bytes()?)?;
+    wallet_seed.passphrase = new_string_from_secret_bytes(br.get_var_bytes()?)?;
+    Ok(wallet_seed)
+}
+
+fn new_string_from_secret_bytes(bytes: Vec<u8>) -> Result<String, SeedSchemeError> {
+    String::from_utf8(bytes).map_err(|e| {
+        e.into_bytes().zeroize();
+        SeedSchemeError::InvalidSeed
+    })
+}
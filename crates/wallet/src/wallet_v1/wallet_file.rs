@@ -0,0 +1,581 @@
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::TryLockError;
+use std::time::Duration;
+use std::time::Instant;
+use thiserror::Error;
+use zeroize::Zeroize;
+use argon2::Algorithm;
+use argon2::Argon2;
+use argon2::Params;
+use argon2::Version;
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::aead::KeyInit;
+use chacha20poly1305::aead::OsRng;
+use chacha20poly1305::aead::Payload;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::XChaCha20Poly1305;
+use chacha20poly1305::XNonce;
+use utility::hash::hash::Hash;
+use utility::buffer::buffer_writer::BufferWriter;
+use utility::buffer::buffer_reader::BufferReader;
+use utility::buffer::buffer_reader::BufferReaderError;
+use utility::ecdsa::key_derivation_v1::KeyDerivationError;
+use utility::ecdsa::key_derivation_v1::unserialize_extended_secret_key;
+use utility::ecdsa::derivation_path::DerivationPath;
+use utility::ecdsa::derivation_path::DerivationPathError;
+use utility::ecdsa::derivation_path::unserialize_derivation_path;
+use utility::storage::storage_directory::StorageDirectory;
+use utility::storage::storage_directory::StorageDirectoryError;
+use utility::system::time::timestamp_now;
+
+use crate::wallet_v1::resource::Resource;
+use crate::wallet_v1::resource::ResourceError;
+use crate::wallet_v1::resource::unserialize_resource;
+use crate::wallet_v1::wallet_inner::WalletInner;
+use crate::wallet_v1::wallet_inner::WalletInnerError;
+use crate::wallet_v1::wallet_inner::new_walletinner_from_master_key;
+use crate::wallet_v1::seed_scheme::WalletSeed;
+use crate::wallet_v1::seed_scheme::SeedSchemeError;
+use crate::wallet_v1::seed_scheme::new_legacy_wallet_seed;
+use crate::wallet_v1::seed_scheme::unserialize_wallet_seed;
+use crate::wallet_v1::wallet_inner::restore_walletinner;
+
+const WALLET_FILE_VERSION: u32 = 1;
+const WALLET_FILE_NAME: &str = "Wallet";
+const WALLET_SALT_SIZE: usize = 16;
+const WALLET_NONCE_SIZE: usize = 24;
+const WALLET_KEY_SIZE: usize = 32;
+/// How often an expired unlock tries again to lock a wallet that is in use.
+const WALLET_LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Error)]
+pub enum WalletFileError {
+    #[error("Storage directory error: {0}")]
+    StorageDirectoryError(#[from] StorageDirectoryError),
+    #[error("Buffer reader error: {0}")]
+    BufferReaderError(#[from] BufferReaderError),
+    #[error("Resource error: {0}")]
+    ResourceError(#[from] ResourceError),
+    #[error("KeyDerivationError error: {0}")]
+    KeyDerivationError(#[from] KeyDerivationError),
+    #[error("Derivation path error: {0}")]
+    DerivationPathError(#[from] DerivationPathError),
+    #[error("Seed scheme error: {0}")]
+    SeedSchemeError(#[from] SeedSchemeError),
+    #[error("Wallet error: {0}")]
+    WalletInnerError(#[from] WalletInnerError),
+
+    #[error("Unknown wallet file version: {0}")]
+    UnknownVersion(u32),
+    #[error("Invalid key derivation parameters: {0}")]
+    InvalidKdfParams(String),
+    #[error("Wrong passphrase or corrupted wallet file")]
+    DecryptionFailed,
+    #[error("Encryption failed")]
+    EncryptionFailed,
+    #[error("Wallet is locked")]
+    Locked,
+    #[error("A wallet already exists in this directory")]
+    AlreadyExists,
+    #[error("Wallet file has {keysets_count} key sets and {addresses_count} addresses")]
+    AddressesMismatch { keysets_count: usize, addresses_count: usize },
+}
+
+/// Argon2id parameters deriving the encryption key from the passphrase, stored in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalletKdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for WalletKdfParams {
+    fn default() -> Self {
+        WalletKdfParams { memory_kib: 64 * 1024, iterations: 3, parallelism: 1 }
+    }
+}
+
+/// Information about the wallet kept in clear in the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletMetadata {
+    pub label: String,
+    pub created_at: i64,
+}
+
+/// Seed and master key of the wallet, only in memory while it is unlocked. Both zeroize themselves.
+struct WalletSecret {
+    seed: WalletSeed,
+    inner: WalletInner,
+}
+
+/// The part of the wallet shared with the task locking it when the unlock timeout expires.
+struct WalletFileState {
+    derivation_paths: Vec<DerivationPath>,
+    addresses: Vec<Hash>,
+    vresource: Vec<Resource>,
+    last_known_height: usize,
+    secret: Option<WalletSecret>,
+    unlocked_until: Option<Instant>,
+    /// Counts the unlocks, so that the timeout of a previous unlock leaves a newer one alone.
+    unlock_count: u64,
+}
+
+impl WalletFileState {
+    fn copy_public_data(&mut self, inner: &WalletInner) -> Result<(), WalletFileError> {
+        let mut derivation_paths = Vec::new();
+        for i in 0..inner.get_keysets_count() {
+            derivation_paths.push(inner.get_derivation_path(i)?);
+        }
+        self.derivation_paths = derivation_paths;
+        self.addresses = inner.get_addresses();
+        self.vresource = inner.get_resources().to_vec();
+        self.last_known_height = inner.get_last_known_height();
+        Ok(())
+    }
+
+    /// Keeps what the wallet learned while unlocked and drops the secret, which zeroizes itself.
+    fn lock(&mut self) {
+        if let Some(secret) = self.secret.take() {
+            if let Err(e) = self.copy_public_data(&secret.inner) {
+                println!("Error lock wallet file: {}", e);
+            }
+        }
+        self.unlocked_until = None;
+    }
+
+    fn expire_unlock(&mut self) {
+        if self.unlocked_until.is_some_and(|unlocked_until| Instant::now() >= unlocked_until) {
+            println!("wallet unlock timeout expired, locking");
+            self.lock();
+        }
+    }
+
+    /// Locks the wallet if it is still unlocked by unlock number `unlock_count`.
+    fn expire_unlock_count(&mut self, unlock_count: u64) {
+        if self.unlock_count == unlock_count && self.secret.is_some() {
+            println!("wallet unlock timeout expired, locking");
+            self.lock();
+        }
+    }
+}
+
+/// The unlocked wallet, to sign or to follow the chain. An expired unlock locks the wallet once it is released.
+pub struct UnlockedWallet<'a> {
+    state: MutexGuard<'a, WalletFileState>,
+}
+
+impl UnlockedWallet<'_> {
+    fn get_secret(&self) -> &WalletSecret {
+        // only built while there is a secret, which the guard keeps
+        self.state.secret.as_ref().expect("unlocked wallet")
+    }
+
+    pub fn get_seed(&self) -> &str {
+        self.get_secret().seed.get_phrase()
+    }
+
+    pub fn get_wallet_seed(&self) -> &WalletSeed {
+        &self.get_secret().seed
+    }
+}
+
+impl Deref for UnlockedWallet<'_> {
+    type Target = WalletInner;
+
+    fn deref(&self) -> &WalletInner {
+        &self.get_secret().inner
+    }
+}
+
+impl DerefMut for UnlockedWallet<'_> {
+    fn deref_mut(&mut self) -> &mut WalletInner {
+        &mut self.state.secret.as_mut().expect("unlocked wallet").inner
+    }
+}
+
+/// A wallet saved in a directory. The account, the key set derivation paths, addresses, resources and `last_known_height`
+/// are stored in clear, the seed with its scheme and the master `ExtendedSecretKey` are encrypted with XChaCha20-Poly1305
+/// under a key derived from the passphrase by Argon2id. The secret is only kept in memory between `unlock`
+/// and `lock`, or until a timer locks the wallet when the unlock timeout expires.
+pub struct WalletFile {
+    sd: StorageDirectory,
+    metadata: WalletMetadata,
+    hardened: bool,
+    account: u32,
+    kdf_params: WalletKdfParams,
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+    state: Arc<Mutex<WalletFileState>>,
+}
+
+impl WalletFile {
+    /// Creates the hardened wallet of the legacy `seed`, encrypted under `passphrase`, and saves it. The wallet is left locked.
+    pub async fn create<P: AsRef<Path>>(
+        path: P,
+        label: String,
+        seed: String,
+        passphrase: &str,
+        kdf_params: WalletKdfParams,
+    ) -> Result<Self, WalletFileError> {
+        Self::create_from_wallet_seed(path, label, new_legacy_wallet_seed(seed), None, passphrase, kdf_params).await
+    }
+
+    /// Same as `create` for a seed of any scheme, with the wallet of `account` if there is one.
+    pub async fn create_from_wallet_seed<P: AsRef<Path>>(
+        path: P,
+        label: String,
+        seed: WalletSeed,
+        account: Option<u32>,
+        passphrase: &str,
+        kdf_params: WalletKdfParams,
+    ) -> Result<Self, WalletFileError> {
+        let inner = new_walletinner_from_master_key(seed.derive_master_extended_secret_key()?, account)?;
+        Self::create_with_walletinner(path, label, seed, inner, passphrase, kdf_params).await
+    }
+
+    /// Same as `create_from_wallet_seed` for a wallet already derived from `seed`, e.g. restored with its resources.
+    pub async fn create_with_walletinner<P: AsRef<Path>>(
+        path: P,
+        label: String,
+        seed: WalletSeed,
+        inner: WalletInner,
+        passphrase: &str,
+        kdf_params: WalletKdfParams,
+    ) -> Result<Self, WalletFileError> {
+        if Self::exists(&path).await {
+            return Err(WalletFileError::AlreadyExists);
+        }
+        let mut state = new_wallet_file_state(Vec::new(), Vec::new(), Vec::new(), 0);
+        state.copy_public_data(&inner)?;
+        let mut wallet_file = WalletFile {
+            sd: StorageDirectory::new(path, String::from(WALLET_FILE_NAME)).await?,
+            metadata: WalletMetadata { label, created_at: timestamp_now() },
+            hardened: inner.is_hardened(),
+            account: inner.get_account().unwrap_or(0),
+            kdf_params,
+            salt: Vec::new(),
+            nonce: Vec::new(),
+            ciphertext: Vec::new(),
+            state: Arc::new(Mutex::new(state)),
+        };
+        wallet_file.encrypt_secret(&WalletSecret { seed, inner }, passphrase)?;
+        wallet_file.save().await?;
+        Ok(wallet_file)
+    }
+
+    /// Opens a saved wallet, locked.
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, WalletFileError> {
+        let sd = StorageDirectory::new(path, String::from(WALLET_FILE_NAME)).await?;
+        let rawbytes = sd.load_bytes_from_file(WALLET_FILE_NAME).await?;
+        unserialize_wallet_file(sd, rawbytes)
+    }
+
+    pub async fn exists<P: AsRef<Path>>(path: P) -> bool {
+        tokio::fs::metadata(path.as_ref().join(WALLET_FILE_NAME)).await.is_ok()
+    }
+
+    /// Writes the wallet, with what the unlocked wallet learned since it was unlocked.
+    pub async fn save(&mut self) -> Result<(), WalletFileError> {
+        {
+            let mut state = self.get_state();
+            if let Some(secret) = state.secret.take() {
+                let result = state.copy_public_data(&secret.inner);
+                state.secret = Some(secret);
+                result?;
+            }
+        }
+        self.sd.save_bytes_to_file(WALLET_FILE_NAME, &self.serialize()).await?;
+        Ok(())
+    }
+
+    pub fn get_metadata(&self) -> &WalletMetadata {
+        &self.metadata
+    }
+
+    pub fn get_kdf_params(&self) -> WalletKdfParams {
+        self.kdf_params
+    }
+
+    /// Decrypts the secret and keeps the wallet unlocked for `timeout`. Within a tokio runtime a timer locks the
+    /// wallet when the timeout expires, otherwise it is locked by the next access.
+    pub fn unlock(&mut self, passphrase: &str, timeout: Duration) -> Result<(), WalletFileError> {
+        let mut plaintext = self.decrypt_secret(passphrase)?;
+        let mut state = self.get_state();
+        let result = restore_secret(&state, self.hardened, self.account, &plaintext);
+        plaintext.zeroize();
+        let secret = result?;
+        state.lock();
+        state.secret = Some(secret);
+        state.unlocked_until = Some(Instant::now() + timeout);
+        state.unlock_count += 1;
+        let unlock_count = state.unlock_count;
+        drop(state);
+        self.spawn_unlock_timeout(unlock_count, timeout);
+        Ok(())
+    }
+
+    /// Keeps what the wallet learned while unlocked and forgets the secret.
+    pub fn lock(&mut self) {
+        self.get_state().lock();
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.get_state().secret.is_none()
+    }
+
+    /// The unlocked wallet, to sign or to follow the chain.
+    pub fn get_unlocked_wallet(&self) -> Result<UnlockedWallet<'_>, WalletFileError> {
+        let state = self.get_state();
+        if state.secret.is_none() {
+            return Err(WalletFileError::Locked);
+        }
+        Ok(UnlockedWallet { state })
+    }
+
+    /// Encrypts the secret under a new passphrase and saves the wallet.
+    pub async fn change_passphrase(&mut self, passphrase: &str, new_passphrase: &str) -> Result<(), WalletFileError> {
+        let mut plaintext = self.decrypt_secret(passphrase)?;
+        let result = restore_secret(&self.get_state(), self.hardened, self.account, &plaintext);
+        plaintext.zeroize();
+        self.encrypt_secret(&result?, new_passphrase)?;
+        self.save().await
+    }
+
+    pub fn get_addresses(&self) -> Vec<Hash> {
+        let state = self.get_state();
+        match &state.secret {
+            Some(secret) => secret.inner.get_addresses(),
+            None => state.addresses.clone(),
+        }
+    }
+
+    pub fn get_last_known_height(&self) -> usize {
+        let state = self.get_state();
+        match &state.secret {
+            Some(secret) => secret.inner.get_last_known_height(),
+            None => state.last_known_height,
+        }
+    }
+
+    pub fn get_balance(&self) -> u64 {
+        let state = self.get_state();
+        match &state.secret {
+            Some(secret) => secret.inner.get_balance(),
+            None => state.vresource.iter().filter(|r| r.is_unspent_resource()).map(|r| r.value).sum(),
+        }
+    }
+
+    /// The shared state, locked first if the unlock timeout expired before its timer ran.
+    fn get_state(&self) -> MutexGuard<'_, WalletFileState> {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.expire_unlock();
+        state
+    }
+
+    /// Locks the wallet when the timeout of unlock number `unlock_count` expires. A wallet in use at that time
+    /// is locked as soon as it is released.
+    fn spawn_unlock_timeout(&self, unlock_count: u64, timeout: Duration) {
+        let handle = match tokio::runtime::Handle::try_current() {
+            Ok(handle) => handle,
+            Err(_) => return,
+        };
+        let weak_state = Arc::downgrade(&self.state);
+        handle.spawn(async move {
+            tokio::time::sleep(timeout).await;
+            loop {
+                // the secret went with the dropped wallet file
+                let shared_state = match weak_state.upgrade() {
+                    Some(shared_state) => shared_state,
+                    None => return,
+                };
+                let expired = match shared_state.try_lock() {
+                    Ok(mut state) => {
+                        state.expire_unlock_count(unlock_count);
+                        true
+                    }
+                    Err(TryLockError::Poisoned(poisoned)) => {
+                        poisoned.into_inner().expire_unlock_count(unlock_count);
+                        true
+                    }
+                    Err(TryLockError::WouldBlock) => false,
+                };
+                if expired {
+                    return;
+                }
+                drop(shared_state);
+                tokio::time::sleep(WALLET_LOCK_RETRY_INTERVAL).await;
+            }
+        });
+    }
+
+    fn encrypt_secret(&mut self, secret: &WalletSecret, passphrase: &str) -> Result<(), WalletFileError> {
+        let mut salt = vec![0u8; WALLET_SALT_SIZE];
+        let mut nonce = vec![0u8; WALLET_NONCE_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let mut master_bytes = secret.inner.get_master_extended_secret_key()?.serialize();
+        let mut bw = BufferWriter::new();
+        secret.seed.serialize(&mut bw);
+        bw.put_var_bytes(&master_bytes);
+        master_bytes.zeroize();
+        let mut plaintext = bw.get_bytes();
+
+        let aad = get_associated_data(&self.kdf_params, &salt);
+        let mut key = derive_encryption_key(passphrase, &salt, &self.kdf_params)?;
+        let cipher = XChaCha20Poly1305::new_from_slice(&key).map_err(|_| WalletFileError::EncryptionFailed);
+        key.zeroize();
+        let ciphertext = cipher?
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: &plaintext, aad: &aad })
+            .map_err(|_| WalletFileError::EncryptionFailed);
+        plaintext.zeroize();
+        self.ciphertext = ciphertext?;
+        self.salt = salt;
+        self.nonce = nonce;
+        Ok(())
+    }
+
+    fn decrypt_secret(&self, passphrase: &str) -> Result<Vec<u8>, WalletFileError> {
+        if self.nonce.len() != WALLET_NONCE_SIZE {
+            return Err(WalletFileError::DecryptionFailed);
+        }
+        let aad = get_associated_data(&self.kdf_params, &self.salt);
+        let mut key = derive_encryption_key(passphrase, &self.salt, &self.kdf_params)?;
+        let cipher = XChaCha20Poly1305::new_from_slice(&key).map_err(|_| WalletFileError::DecryptionFailed);
+        key.zeroize();
+        cipher?
+            .decrypt(XNonce::from_slice(&self.nonce), Payload { msg: &self.ciphertext, aad: &aad })
+            .map_err(|_| WalletFileError::DecryptionFailed)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let state = self.get_state();
+        let mut bw = BufferWriter::new();
+        bw.put_var_u32(WALLET_FILE_VERSION);
+        bw.put_var_bytes(self.metadata.label.as_bytes());
+        bw.put_u64(self.metadata.created_at as u64);
+        bw.put_u8(self.hardened as u8);
+        bw.put_var_u32(self.account);
+        bw.put_var_u64(state.last_known_height as u64);
+        bw.put_var_u64(state.derivation_paths.len() as u64);
+        for (derivation_path, address) in state.derivation_paths.iter().zip(state.addresses.iter()) {
+            derivation_path.serialize(&mut bw);
+            bw.put_hash(address.clone());
+        }
+        bw.put_var_u64(state.vresource.len() as u64);
+        for resource in state.vresource.iter() {
+            resource.serialize(&mut bw);
+        }
+        bw.put_var_u32(self.kdf_params.memory_kib);
+        bw.put_var_u32(self.kdf_params.iterations);
+        bw.put_var_u32(self.kdf_params.parallelism);
+        bw.put_var_bytes(&self.salt);
+        bw.put_var_bytes(&self.nonce);
+        bw.put_var_bytes(&self.ciphertext);
+        bw.get_bytes()
+    }
+}
+
+fn unserialize_wallet_file(sd: StorageDirectory, rawbytes: Vec<u8>) -> Result<WalletFile, WalletFileError> {
+    let mut br = BufferReader::new(rawbytes);
+    let version = br.get_var_u32()?;
+    if version != WALLET_FILE_VERSION {
+        return Err(WalletFileError::UnknownVersion(version));
+    }
+    let label = String::from_utf8_lossy(&br.get_var_bytes()?).into_owned();
+    let created_at = br.get_u64()? as i64;
+    let hardened = br.get_u8()? != 0;
+    let account = br.get_var_u32()?;
+    let last_known_height = br.get_var_u64()? as usize;
+    let keysets_count = br.get_var_u64()?;
+    let mut derivation_paths = Vec::new();
+    let mut addresses = Vec::new();
+    for _ in 0..keysets_count {
+        derivation_paths.push(unserialize_derivation_path(&mut br)?);
+        addresses.push(br.get_hash()?);
+    }
+    let resources_count = br.get_var_u64()?;
+    let mut vresource = Vec::new();
+    for _ in 0..resources_count {
+        vresource.push(unserialize_resource(&mut br)?);
+    }
+    let kdf_params = WalletKdfParams {
+        memory_kib: br.get_var_u32()?,
+        iterations: br.get_var_u32()?,
+        parallelism: br.get_var_u32()?,
+    };
+    let salt = br.get_var_bytes()?;
+    let nonce = br.get_var_bytes()?;
+    let ciphertext = br.get_var_bytes()?;
+    Ok(WalletFile {
+        sd,
+        metadata: WalletMetadata { label, created_at },
+        hardened,
+        account,
+        kdf_params,
+        salt,
+        nonce,
+        ciphertext,
+        state: Arc::new(Mutex::new(new_wallet_file_state(derivation_paths, addresses, vresource, last_known_height))),
+    })
+}
+
+fn new_wallet_file_state(derivation_paths: Vec<DerivationPath>, addresses: Vec<Hash>, vresource: Vec<Resource>, last_known_height: usize) -> WalletFileState {
+    WalletFileState {
+        derivation_paths,
+        addresses,
+        vresource,
+        last_known_height,
+        secret: None,
+        unlocked_until: None,
+        unlock_count: 0,
+    }
+}
+
+/// Rebuilds the wallet from the decrypted seed and master key and the public data of the file.
+fn restore_secret(state: &WalletFileState, hardened: bool, account: u32, plaintext: &[u8]) -> Result<WalletSecret, WalletFileError> {
+    let mut br = BufferReader::new(plaintext.to_vec());
+    let seed = unserialize_wallet_seed(&mut br)?;
+    let mut master_bytes = br.get_var_bytes()?;
+    let master_extended_secret_key = unserialize_extended_secret_key(&master_bytes);
+    master_bytes.zeroize();
+    let inner = restore_walletinner(
+        master_extended_secret_key?,
+        hardened,
+        account,
+        &state.derivation_paths,
+        state.vresource.clone(),
+        state.last_known_height,
+    )?;
+    if inner.get_addresses() != state.addresses {
+        return Err(WalletFileError::AddressesMismatch { keysets_count: inner.get_keysets_count(), addresses_count: state.addresses.len() });
+    }
+    Ok(WalletSecret { seed, inner })
+}
+
+/// The parameters the key is derived with are authenticated with the secret, so that they cannot be swapped.
+fn get_associated_data(kdf_params: &WalletKdfParams, salt: &[u8]) -> Vec<u8> {
+    let mut bw = BufferWriter::new();
+    bw.put_var_u32(WALLET_FILE_VERSION);
+    bw.put_var_u32(kdf_params.memory_kib);
+    bw.put_var_u32(kdf_params.iterations);
+    bw.put_var_u32(kdf_params.parallelism);
+    bw.put_var_bytes(salt);
+    bw.get_bytes()
+}
+
+fn derive_encryption_key(passphrase: &str, salt: &[u8], kdf_params: &WalletKdfParams) -> Result<[u8; WALLET_KEY_SIZE], WalletFileError> {
+    let params = Params::new(kdf_params.memory_kib, kdf_params.iterations, kdf_params.parallelism, Some(WALLET_KEY_SIZE))
+        .map_err(|e| WalletFileError::InvalidKdfParams(e.to_string()))?;
+    let mut key = [0u8; WALLET_KEY_SIZE];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| WalletFileError::InvalidKdfParams(e.to_string()))?;
+    Ok(key)
+}
@@ -2,11 +2,19 @@ use std::error::Error;
 use thiserror::Error;
 use utility::ecdsa::key_derivation_v1::HARDENED_OFFSET;
 use utility::ecdsa::key_derivation_v1::ExtendedSecretKey;
+use utility::ecdsa::key_derivation_v1::ExtendedPublicKey;
+use utility::ecdsa::key_derivation_v1::derive_extended_secret_key_from_path;
 use utility::ecdsa::ecdsa::derive_child_key_set;
+use utility::ecdsa::ecdsa::derive_key_set_from_path;
+use utility::ecdsa::ecdsa::derive_public_key_set_from_path;
+use utility::ecdsa::derivation_path::DerivationPath;
+use utility::ecdsa::derivation_path::EXTERNAL_CHAIN;
+use utility::ecdsa::derivation_path::INTERNAL_CHAIN;
 
 use utility::ecdsa::key_derivation_v1::derive_master_extended_secret_key;
 use utility::ecdsa::key_derivation_v1::KeyDerivationError;
 use utility::ecdsa::ecdsa::EcdsaKeySet;
+use utility::ecdsa::ecdsa::EcdsaPublicKeySet;
 use utility::ecdsa::ecdsa::EcdsaKeySetError;
 use utility::ecdsa::ecdsa::{sign_messagehash, verify_signature};
 
@@ -18,11 +26,20 @@ use utility::buffer::buffer_reader::BufferReader;
 use std::fs;
 use maintx::maintx::maintx::Maintx;
 use maintx::maintx_out::maintx_out::MaintxOutError;
+use maintx::maintx_out::maintx_out::new_ecdsa_maintx_out;
 
 use maintx::maintx_in::maintx_in::MaintxInError;
+use maintx::maintx_in::maintx_in::new_maintx_in_ecdsa;
+use maincore::chain_params::chain_params::ChainParams;
 
 use crate::wallet_v1::resource::Resource;
 use crate::wallet_v1::resource::new_unspent_resource;
+use crate::wallet_v1::coin_selection::select_coins_bnb;
+use crate::wallet_v1::coin_selection::select_coins_largest_first;
+
+/// Size of a compact ECDSA signature, used to know the size of an input before it is signed.
+const SIGNATURE_SIZE: usize = 64;
+
 #[derive(Debug, Error)]
 pub enum WalletInnerError {
     #[error("Key pair vector is empty")]
@@ -48,15 +65,39 @@ pub enum WalletInnerError {
     MaintxOutError(#[from] MaintxOutError),
     #[error("MaintxInError error: {0}")]
     MaintxInError(#[from] MaintxInError),
+
+    #[error("Transaction has no recipient")]
+    NoRecipient,
+    #[error("Recipient value cannot be zero")]
+    ZeroRecipientValue,
+    #[error("Transaction value overflow")]
+    ValueOverflow,
+    #[error("Insufficient funds: {available} available, {required} required")]
+    InsufficientFunds { available: u64, required: u64 },
+    #[error("Watch-only wallet holds no secret key")]
+    WatchOnly,
+    #[error("No key set for the public key of input {0}")]
+    UnknownInputPublicKey(usize),
+    #[error("Invalid account: {0}")]
+    InvalidAccount(u32),
 }
 
 #[derive(Debug, Clone)]
 pub struct WalletInner {
     version: u64,
+    /// A hardened wallet derives its key sets at m/index', the others at m/account'/chain/index.
     hardened:bool,
-    master_extended_secret_key:ExtendedSecretKey,
+    account: u32,
+    /// None for a watch-only wallet.
+    master_extended_secret_key:Option<ExtendedSecretKey>,
+    /// The account key m/account' of a non-hardened or watch-only wallet.
+    extended_public_key:Option<ExtendedPublicKey>,
+    /// Empty for a watch-only wallet.
     vks: Vec<EcdsaKeySet>,
+    vpks: Vec<EcdsaPublicKeySet>,
     last_known_height: usize,
+    /// Mainblocks a reward waits before it can be spent, the one of mainnet until the chain is known.
+    reward_maturity: usize,
     vresource: Vec<Resource>,
 }
 
@@ -76,6 +117,36 @@ impl WalletInner {
         self.version
     }
 
+    pub fn is_hardened(&self) -> bool {
+        self.hardened
+    }
+
+    /// The account of a non-hardened wallet.
+    pub fn get_account(&self) -> Option<u32> {
+        if self.hardened {
+            None
+        } else {
+            Some(self.account)
+        }
+    }
+
+    pub fn is_watch_only(&self) -> bool {
+        self.master_extended_secret_key.is_none()
+    }
+
+    pub fn get_master_extended_secret_key(&self) -> Result<&ExtendedSecretKey, WalletInnerError> {
+        self.master_extended_secret_key.as_ref().ok_or(WalletInnerError::WatchOnly)
+    }
+
+    /// The account key to share with a watch-only wallet. None for a hardened wallet, whose public keys cannot be derived without the secret.
+    pub fn get_extended_public_key(&self) -> Option<&ExtendedPublicKey> {
+        self.extended_public_key.as_ref()
+    }
+
+    pub fn get_resources(&self) -> &[Resource] {
+        &self.vresource
+    }
+
     pub fn get_last_known_height(&self) -> usize {
         self.last_known_height
     }
@@ -84,7 +155,18 @@ impl WalletInner {
         self.last_known_height = height;
     }
 
+    pub fn get_reward_maturity(&self) -> usize {
+        self.reward_maturity
+    }
+
+    pub fn set_reward_maturity(&mut self, reward_maturity: usize) {
+        self.reward_maturity = reward_maturity;
+    }
+
     pub fn get_secret_key_bytes(&self, index: usize) -> Result<Vec<u8>, WalletInnerError> {
+        if self.is_watch_only() {
+            return Err(WalletInnerError::WatchOnly);
+        }
         self.vks
             .get(index)
             .map(|ks| ks.get_secret_key_bytes().clone())
@@ -92,34 +174,44 @@ impl WalletInner {
     }
 
     pub fn get_public_key_compressed_bytes(&self, index: usize) -> Result<Vec<u8>, WalletInnerError> {
-        self.vks
+        self.vpks
             .get(index)
             .map(|ks| ks.get_public_key_compressed_bytes().clone())
             .ok_or(WalletInnerError::InvalidIndex(index))
     }
 
     pub fn get_address(&self, index: usize) -> Result<Hash, WalletInnerError> {
-        self.vks
+        self.vpks
             .get(index)
             .map(|ks| ks.get_address())
             .ok_or(WalletInnerError::InvalidIndex(index))
     }
 
     pub fn get_addresses(&self) -> Vec<Hash> {
-        self.vks.iter().map(|ks| ks.get_address()).collect()
+        self.vpks.iter().map(|pks| pks.get_address()).collect()
     }
     pub fn get_derivation_index(&self, index: usize) -> Result<u32, WalletInnerError> {
-        self.vks
+        self.vpks
             .get(index)
             .map(|ks| ks.get_derivation_index())
             .ok_or(WalletInnerError::InvalidIndex(index))
     }
 
+    pub fn get_derivation_path(&self, index: usize) -> Result<DerivationPath, WalletInnerError> {
+        self.vpks
+            .get(index)
+            .map(|ks| ks.get_derivation_path().clone())
+            .ok_or(WalletInnerError::InvalidIndex(index))
+    }
+
     pub fn get_keysets_count(&self) -> usize {
-        self.vks.len()
+        self.vpks.len()
     }
 
     pub fn get_keypair(&self, index: usize) -> Result<EcdsaKeySet, WalletInnerError> {
+        if self.is_watch_only() {
+            return Err(WalletInnerError::WatchOnly);
+        }
         self.vks
             .get(index)
             .cloned()
@@ -127,6 +219,9 @@ impl WalletInner {
     }
 
     pub fn get_random_keyset(&self) -> Result<EcdsaKeySet, WalletInnerError> {
+        if self.is_watch_only() {
+            return Err(WalletInnerError::WatchOnly);
+        }
         let count = self.vks.len();
         if count == 0 {
             return Err(WalletInnerError::EmptyEcdsaKeySet);
@@ -148,21 +243,40 @@ impl WalletInner {
         Ok(())
     }
     */
+    /// Generates the next receiving key set.
     pub fn generate_key_set(&mut self) -> Result<(), WalletInnerError> {
-        if self.vks.is_empty() {
-            return Err(WalletInnerError::EmptyEcdsaKeySet);
-        }
+        self.generate_chain_key_set(EXTERNAL_CHAIN)
+    }
 
-        let last_derivation_index=self.get_derivation_index(self.vks.len()-1)?;//
+    /// Generates the next change key set. A hardened wallet has a single chain for both.
+    pub fn generate_change_key_set(&mut self) -> Result<(), WalletInnerError> {
+        self.generate_chain_key_set(INTERNAL_CHAIN)
+    }
+
+    fn generate_chain_key_set(&mut self, chain: u32) -> Result<(), WalletInnerError> {
+        let (parent_path, first_index) = if self.hardened {
+            if self.vpks.is_empty() {
+                return Err(WalletInnerError::EmptyEcdsaKeySet);
+            }
+            let last_derivation_index=self.get_derivation_index(self.vpks.len()-1)?;//
+            (DerivationPath::master(), last_derivation_index + 1)
+        } else {
+            let chain_path = DerivationPath::account(self.account).child(chain);
+            let next_index = self
+                .vpks
+                .iter()
+                .filter_map(|pks| pks.get_derivation_path().strip_prefix(&chain_path))
+                .filter_map(|indexes| indexes.first())
+                .max()
+                .map(|index| index + 1)
+                .unwrap_or(0);
+            (chain_path, next_index)
+        };
         
-        for count in 1..=5000 { 
-
-            let new_ks_result = derive_child_key_set(&self.master_extended_secret_key, last_derivation_index+count, true);
-            match new_ks_result {
-                Ok(new_ks) => {
-                    self.vks.push(new_ks);
-                    return Ok(())
-                },
+        for count in 0..5000 { 
+
+            match self.push_key_set(parent_path.child(first_index+count)) {
+                Ok(()) => return Ok(()),
                 Err(error) => println!("Error generate_key_set: {}", error),
             }
             
@@ -170,14 +284,85 @@ impl WalletInner {
 
         return Err(WalletInnerError::GenerateKeySetFailedAfterTooManyAttempt);
     }
-    pub fn update_resources(&mut self, tmpmaintx: Maintx) -> Result<(), WalletInnerError> {
+
+    /// Derives the key set at `derivation_path` from the master key if there is one, otherwise only its public
+    /// part from the account key.
+    fn push_key_set(&mut self, derivation_path: DerivationPath) -> Result<(), WalletInnerError> {
+        match (&self.master_extended_secret_key, &self.extended_public_key) {
+            (Some(master_extended_secret_key), _) => {
+                let new_ks = derive_key_set_from_path(master_extended_secret_key, &derivation_path)?;
+                self.vpks.push(new_ks.get_public_key_set());
+                self.vks.push(new_ks);
+            }
+            (None, Some(extended_public_key)) => {
+                let account_path = DerivationPath::account(self.account);
+                let new_pks = derive_public_key_set_from_path(extended_public_key, &account_path, &derivation_path)?;
+                self.vpks.push(new_pks);
+            }
+            (None, None) => return Err(WalletInnerError::WatchOnly),
+        }
+        Ok(())
+    }
+    /// Index of the last key set an output was received on.
+    pub fn get_last_used_keyset_index(&self) -> Option<usize> {
+        self.vresource.iter().map(|r| r.key_index).max()
+    }
+
+    /// Generates key sets until there are `count`.
+    pub fn extend_key_sets(&mut self, count: usize) -> Result<(), WalletInnerError> {
+        while self.vpks.len() < count {
+            self.generate_key_set()?;
+        }
+        Ok(())
+    }
+
+    /// Indexes on `chain` of the key sets derived on it. A hardened wallet has a single chain, its key sets
+    /// are indexed in the order they were generated.
+    fn get_chain_indexes(&self, chain: u32) -> Vec<Option<usize>> {
+        if self.hardened {
+            return (0..self.vpks.len()).map(Some).collect();
+        }
+        let chain_path = DerivationPath::account(self.account).child(chain);
+        self.vpks
+            .iter()
+            .map(|pks| pks.get_derivation_path().strip_prefix(&chain_path).and_then(|indexes| indexes.first().map(|index| *index as usize)))
+            .collect()
+    }
+
+    /// Number of key sets derived on `chain`.
+    pub fn get_chain_key_sets_count(&self, chain: u32) -> usize {
+        self.get_chain_indexes(chain).iter().flatten().count()
+    }
+
+    /// Index on `chain` of the last key set of that chain an output was received on.
+    pub fn get_last_used_chain_index(&self, chain: u32) -> Option<usize> {
+        let chain_indexes = self.get_chain_indexes(chain);
+        self.vresource.iter().filter_map(|r| chain_indexes.get(r.key_index).copied().flatten()).max()
+    }
+
+    /// Generates key sets on `chain` until it has `count`.
+    pub fn extend_chain_key_sets(&mut self, chain: u32, count: usize) -> Result<(), WalletInnerError> {
+        while self.get_chain_key_sets_count(chain) < count {
+            self.generate_chain_key_set(chain)?;
+        }
+        Ok(())
+    }
+
+    /// Pushes the maintx of the mainblock at `mainblock_height` into the resources.
+    pub fn update_resources(&mut self, tmpmaintx: Maintx, mainblock_height: u32) -> Result<(), WalletInnerError> {
+        self.update_key_set_resources(tmpmaintx, mainblock_height, 0)
+    }
+
+    /// Same as `update_resources`, only looking for outputs paying to the key sets from `first_key_index`.
+    pub fn update_key_set_resources(&mut self, tmpmaintx: Maintx, mainblock_height: u32, first_key_index: usize) -> Result<(), WalletInnerError> {
         let addresses = self.get_addresses();
+        let is_reward = tmpmaintx.vin.iter().any(|vin| !vin.is_ecdsa());
 
         for (i, vout) in tmpmaintx.vout.iter().enumerate() {
-            for (j, address) in addresses.iter().enumerate() {
+            for (j, address) in addresses.iter().enumerate().skip(first_key_index) {
                 if vout.matches_address(address) {
                     let hash = tmpmaintx.compute_hash();
-                    self.add_unspent_resource(hash, i as u32, vout.get_value()?, j);
+                    self.add_unspent_resource(hash, i as u32, vout.get_value()?, j, mainblock_height, is_reward);
                 }
             }
         }
@@ -191,11 +376,11 @@ impl WalletInner {
         Ok(())
     }
 
-    pub fn add_unspent_resource(&mut self, h: Hash, tmpindex: u32, value: u64, key_index: usize) {
+    pub fn add_unspent_resource(&mut self, h: Hash, tmpindex: u32, value: u64, key_index: usize, mainblock_height: u32, is_reward: bool) {
         if self.vresource.iter().any(|r| r.hash==h && r.index == tmpindex) {
             return;
         }
-        let new_resource = new_unspent_resource(h, tmpindex, value, key_index);
+        let new_resource = new_unspent_resource(h, tmpindex, value, key_index, mainblock_height, is_reward);
         self.vresource.push(new_resource);
     }
 
@@ -205,6 +390,135 @@ impl WalletInner {
         }
     }
 
+    /// Marks the resource as spent by a maintx not confirmed yet.
+    pub fn set_resource_unavailable(&mut self, h: &Hash, tmpindex: u32) {
+        if let Some(resource) = self.vresource.iter_mut().find(|r| &r.hash == h && r.index == tmpindex) {
+            resource.available = false;
+        }
+    }
+
+    /// Forgets every resource, before they are pushed again from the maintxs of the chain.
+    pub fn clear_resources(&mut self) {
+        self.vresource.clear();
+    }
+
+    /// Builds and signs a maintx paying `recipients` (address, value), with a fee of `fee_rate` per byte.
+    /// See `build_unsigned_transaction`.
+    pub fn build_transaction(&mut self, recipients: &[(Hash, u64)], fee_rate: u64) -> Result<Maintx, WalletInnerError> {
+        if self.is_watch_only() {
+            return Err(WalletInnerError::WatchOnly);
+        }
+        let mut maintx = self.build_unsigned_transaction(recipients, fee_rate)?;
+        if let Err(error) = self.sign_transaction(&mut maintx) {
+            self.release_transaction(&maintx)?;
+            return Err(error);
+        }
+        Ok(maintx)
+    }
+
+    /// Makes the resources spent by a built maintx available again, when it is abandoned before being confirmed.
+    pub fn release_transaction(&mut self, maintx: &Maintx) -> Result<(), WalletInnerError> {
+        for vin in maintx.vin.iter().filter(|vin| vin.is_ecdsa()) {
+            let (hash, index) = (vin.get_hash()?, vin.get_index()?);
+            if let Some(resource) = self.vresource.iter_mut().find(|r| r.hash == hash && r.index == index) {
+                resource.available = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds a maintx paying `recipients` (address, value), with a fee of `fee_rate` per byte, leaving the
+    /// signatures to the wallet holding the secret keys (`sign_transaction`).
+    /// The resources are selected by branch-and-bound to avoid a change output, falling back to largest-first,
+    /// in which case the change goes to a newly generated key set. Rewards are only selected once they can be spent
+    /// in the next mainblock. The selected resources become unavailable until the maintx is confirmed or released
+    /// (`release_transaction`).
+    pub fn build_unsigned_transaction(&mut self, recipients: &[(Hash, u64)], fee_rate: u64) -> Result<Maintx, WalletInnerError> {
+        if recipients.is_empty() {
+            return Err(WalletInnerError::NoRecipient);
+        }
+        let mut maintx = Maintx { version: 1, vin: Vec::new(), vout: Vec::new() };
+        let mut recipients_value: u64 = 0;
+        for (address, value) in recipients.iter() {
+            if *value == 0 {
+                return Err(WalletInnerError::ZeroRecipientValue);
+            }
+            recipients_value = recipients_value.checked_add(*value).ok_or(WalletInnerError::ValueOverflow)?;
+            maintx.vout.push(new_ecdsa_maintx_out(*value, address.clone()));
+        }
+        let target = (maintx.get_serialization_size() as u64)
+            .checked_mul(fee_rate)
+            .and_then(|fee| fee.checked_add(recipients_value))
+            .ok_or(WalletInnerError::ValueOverflow)?;
+        let cost_of_change = get_maintx_out_size() as u64 * fee_rate;
+
+        // resources worth less than the fee of spending them are left out
+        let mut candidates = Vec::new();
+        let mut effective_values = Vec::new();
+        let next_height = self.last_known_height + 1;
+        for (i, resource) in self.vresource.iter().enumerate() {
+            if !resource.available || !resource.is_unspent_resource() || !resource.is_mature(next_height, self.reward_maturity) {
+                continue;
+            }
+            let input_fee = self.get_maintx_in_size(resource)? as u64 * fee_rate;
+            if resource.value > input_fee {
+                candidates.push(i);
+                effective_values.push(resource.value - input_fee);
+            }
+        }
+        let selection = select_coins_bnb(&effective_values, target, cost_of_change)
+            .or_else(|| select_coins_largest_first(&effective_values, target, cost_of_change))
+            .ok_or_else(|| WalletInnerError::InsufficientFunds { available: effective_values.iter().sum(), required: target })?;
+
+        let selected: Vec<usize> = selection.indexes.iter().map(|index| candidates[*index]).collect();
+        for i in selected.iter() {
+            let resource = &self.vresource[*i];
+            let publickey = self.get_public_key_compressed_bytes(resource.key_index)?;
+            maintx.vin.push(new_maintx_in_ecdsa(resource.hash.clone(), resource.index, publickey));
+        }
+        if selection.with_change {
+            self.generate_change_key_set()?;
+            let change_address = self.get_address(self.vpks.len() - 1)?;
+            let change_value = selection.selected_value - target - cost_of_change;
+            maintx.vout.push(new_ecdsa_maintx_out(change_value, change_address));
+        }
+
+        for i in selected.iter() {
+            self.vresource[*i].available = false;
+        }
+        Ok(maintx)
+    }
+
+    /// Signs the ecdsa inputs of `maintx` with the key sets of their public keys.
+    pub fn sign_transaction(&self, maintx: &mut Maintx) -> Result<(), WalletInnerError> {
+        if self.is_watch_only() {
+            return Err(WalletInnerError::WatchOnly);
+        }
+        let maintx_hash = maintx.compute_hash();
+        for (i, vin) in maintx.vin.iter_mut().enumerate() {
+            if !vin.is_ecdsa() {
+                continue;
+            }
+            let publickey = vin.get_publickey()?;
+            let keyset = self
+                .vks
+                .iter()
+                .find(|ks| ks.get_public_key_compressed_bytes() == publickey)
+                .ok_or(WalletInnerError::UnknownInputPublicKey(i))?;
+            vin.set_signature(sign_messagehash(keyset, maintx_hash.clone())?)?;
+        }
+        Ok(())
+    }
+
+    /// Size of the signed input spending `resource`.
+    fn get_maintx_in_size(&self, resource: &Resource) -> Result<usize, WalletInnerError> {
+        let mut vin = new_maintx_in_ecdsa(resource.hash.clone(), resource.index, self.get_public_key_compressed_bytes(resource.key_index)?);
+        vin.set_signature(vec![0u8; SIGNATURE_SIZE])?;
+        let mut bw = BufferWriter::new();
+        vin.serialize(&mut bw, true);
+        Ok(bw.get_bytes().len())
+    }
+
     pub fn get_balance(&self) -> u64 {
         self.vresource
             .iter()
@@ -233,16 +547,31 @@ impl WalletInner {
     }
     */
 }
+/// Size of an output, whatever its value and address.
+fn get_maintx_out_size() -> usize {
+    let mut bw = BufferWriter::new();
+    new_ecdsa_maintx_out(0, Hash::new_empty()).serialize(&mut bw);
+    bw.get_bytes().len()
+}
 pub fn new_hardened_walletinner(wallet_seed: String) -> Result<WalletInner, WalletInnerError> {
     let tmp_master_extended_secret_key = derive_master_extended_secret_key(&wallet_seed)?;
     println!("derive_master_extended_secret_key: {:?}", tmp_master_extended_secret_key);
+    new_hardened_walletinner_from_master_key(tmp_master_extended_secret_key)
+}
+
+/// Same as `new_hardened_walletinner` for a master key already derived, e.g. from a BIP-39 mnemonic.
+pub fn new_hardened_walletinner_from_master_key(tmp_master_extended_secret_key: ExtendedSecretKey) -> Result<WalletInner, WalletInnerError> {
 
     let mut new_walletinner = WalletInner {
         version: 1,
         hardened:true,
-        master_extended_secret_key: tmp_master_extended_secret_key.clone(),
+        account: 0,
+        master_extended_secret_key: Some(tmp_master_extended_secret_key.clone()),
+        extended_public_key: None,
         vks: Vec::new(),
+        vpks: Vec::new(),
         last_known_height: 0,
+        reward_maturity: ChainParams::mainnet().reward_maturity,
         vresource: Vec::new(),
     };
 
@@ -254,6 +583,7 @@ pub fn new_hardened_walletinner(wallet_seed: String) -> Result<WalletInner, Wall
         let initial_ks_result = derive_child_key_set(&tmp_master_extended_secret_key, HARDENED_OFFSET+count, true);
         match initial_ks_result {
             Ok(initial_ks) => {
+                new_walletinner.vpks.push(initial_ks.get_public_key_set());
                 new_walletinner.vks.push(initial_ks);
                 for _ in 0..3 {
                     new_walletinner.generate_key_set()?;
@@ -276,6 +606,108 @@ pub fn new_hardened_walletinner(wallet_seed: String) -> Result<WalletInner, Wall
     //new_walletinner.vks.push(initial_ks);
 
 
+}
+/// Creates the wallet of `account` if there is one, otherwise the hardened wallet.
+pub fn new_walletinner_from_master_key(master_extended_secret_key: ExtendedSecretKey, account: Option<u32>) -> Result<WalletInner, WalletInnerError> {
+    match account {
+        Some(account) => new_account_walletinner_from_master_key(master_extended_secret_key, account),
+        None => new_hardened_walletinner_from_master_key(master_extended_secret_key),
+    }
+}
+
+/// Creates the wallet of `account`, whose receiving and change key sets are the non-hardened children
+/// m/account'/0/index and m/account'/1/index. Their public keys can also be derived by a watch-only wallet
+/// from the account key, and each account of a seed has its own funds.
+pub fn new_account_walletinner(wallet_seed: String, account: u32) -> Result<WalletInner, WalletInnerError> {
+    new_account_walletinner_from_master_key(derive_master_extended_secret_key(&wallet_seed)?, account)
+}
+
+/// Same as `new_account_walletinner` for a master key already derived, e.g. from a BIP-39 mnemonic.
+pub fn new_account_walletinner_from_master_key(master_extended_secret_key: ExtendedSecretKey, account: u32) -> Result<WalletInner, WalletInnerError> {
+    if account >= HARDENED_OFFSET {
+        return Err(WalletInnerError::InvalidAccount(account));
+    }
+    let account_extended_secret_key = derive_extended_secret_key_from_path(&master_extended_secret_key, &DerivationPath::account(account))?;
+    let extended_public_key = account_extended_secret_key.get_extended_public_key()?;
+    new_account_walletinner_with_key_sets(WalletInner {
+        version: 1,
+        hardened: false,
+        account,
+        master_extended_secret_key: Some(master_extended_secret_key),
+        extended_public_key: Some(extended_public_key),
+        vks: Vec::new(),
+        vpks: Vec::new(),
+        last_known_height: 0,
+        reward_maturity: ChainParams::mainnet().reward_maturity,
+        vresource: Vec::new(),
+    })
+}
+
+/// Creates a watch-only wallet from the extended public key of `account`. It has the same addresses as the
+/// wallet of that account, tracks their resources and builds unsigned maintxs, but cannot sign.
+pub fn new_watch_only_walletinner(extended_public_key: ExtendedPublicKey, account: u32) -> Result<WalletInner, WalletInnerError> {
+    if account >= HARDENED_OFFSET {
+        return Err(WalletInnerError::InvalidAccount(account));
+    }
+    new_account_walletinner_with_key_sets(WalletInner {
+        version: 1,
+        hardened: false,
+        account,
+        master_extended_secret_key: None,
+        extended_public_key: Some(extended_public_key),
+        vks: Vec::new(),
+        vpks: Vec::new(),
+        last_known_height: 0,
+        reward_maturity: ChainParams::mainnet().reward_maturity,
+        vresource: Vec::new(),
+    })
+}
+
+/// Generates the first 4 receiving key sets.
+fn new_account_walletinner_with_key_sets(mut new_walletinner: WalletInner) -> Result<WalletInner, WalletInnerError> {
+    for _ in 0..4 {
+        new_walletinner.generate_key_set()?;
+    }
+    Ok(new_walletinner)
+}
+
+/// Rebuilds a wallet from its master key, the derivation paths of its key sets and what it knew of the chain.
+pub fn restore_walletinner(
+    master_extended_secret_key: ExtendedSecretKey,
+    hardened: bool,
+    account: u32,
+    derivation_paths: &[DerivationPath],
+    vresource: Vec<Resource>,
+    last_known_height: usize,
+) -> Result<WalletInner, WalletInnerError> {
+    if account >= HARDENED_OFFSET {
+        return Err(WalletInnerError::InvalidAccount(account));
+    }
+    let extended_public_key = if hardened {
+        None
+    } else {
+        let account_path = DerivationPath::account(account);
+        Some(derive_extended_secret_key_from_path(&master_extended_secret_key, &account_path)?.get_extended_public_key()?)
+    };
+    let mut new_walletinner = WalletInner {
+        version: 1,
+        hardened,
+        account,
+        master_extended_secret_key: Some(master_extended_secret_key),
+        extended_public_key,
+        vks: Vec::new(),
+        vpks: Vec::new(),
+        last_known_height,
+        reward_maturity: ChainParams::mainnet().reward_maturity,
+        vresource,
+    };
+    for derivation_path in derivation_paths.iter() {
+        new_walletinner.push_key_set(derivation_path.clone())?;
+    }
+    if let Some(resource) = new_walletinner.vresource.iter().find(|r| r.key_index >= new_walletinner.vpks.len()) {
+        return Err(WalletInnerError::InvalidIndex(resource.key_index));
+    }
+    Ok(new_walletinner)
 }
 /*
 pub fn new_walletinner(wallet_seed: String) -> Result<WalletInner, WalletInnerError> {
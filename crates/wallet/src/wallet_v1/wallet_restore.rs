@@ -0,0 +1,113 @@
+use thiserror::Error;
+use maincore::maincore_inner::maincore_inner::MaincoreInner;
+use maincore::maincore_inner::maincore_inner::MaincoreInnerError;
+use utility::ecdsa::derivation_path::EXTERNAL_CHAIN;
+use utility::ecdsa::derivation_path::INTERNAL_CHAIN;
+
+use crate::wallet_v1::seed_scheme::WalletSeed;
+use crate::wallet_v1::seed_scheme::SeedSchemeError;
+use crate::wallet_v1::seed_scheme::new_legacy_wallet_seed;
+use crate::wallet_v1::wallet_inner::WalletInner;
+use crate::wallet_v1::wallet_inner::WalletInnerError;
+use crate::wallet_v1::wallet_inner::new_walletinner_from_master_key;
+
+/// Number of unused key sets derived after the last used one before a restore stops looking further.
+pub const DEFAULT_GAP_LIMIT: usize = 20;
+
+#[derive(Debug, Error)]
+pub enum WalletRestoreError {
+    #[error("Invalid seed phrase")]
+    InvalidSeed,
+    #[error("Gap limit cannot be zero")]
+    ZeroGapLimit,
+    #[error("Wallet error: {0}")]
+    WalletInnerError(#[from] WalletInnerError),
+    #[error("Seed scheme error: {0}")]
+    SeedSchemeError(#[from] SeedSchemeError),
+    #[error("Maincore error: {0}")]
+    MaincoreInnerError(#[from] MaincoreInnerError),
+}
+
+/// Restores the wallet of `account` of the legacy `seed`, or its hardened wallet if there is no account, from the
+/// mainblocks stored by `mci`. Key sets are derived on each chain until `gap_limit` of them follow the last one of
+/// that chain that received an output: the receiving and change chains of an account, the single chain of a hardened
+/// wallet. The outputs of the key sets derived after the first scan are looked up with `scan_new_key_sets`, since
+/// they may have been used in mainblocks already scanned.
+pub async fn restore_walletinner_from_seed(seed: &str, account: Option<u32>, gap_limit: usize, mci: &mut MaincoreInner) -> Result<WalletInner, WalletRestoreError> {
+    restore_walletinner_from_wallet_seed(&new_legacy_wallet_seed(seed.to_string()), account, gap_limit, mci).await
+}
+
+/// Same as `restore_walletinner_from_seed` for a seed of any scheme.
+pub async fn restore_walletinner_from_wallet_seed(seed: &WalletSeed, account: Option<u32>, gap_limit: usize, mci: &mut MaincoreInner) -> Result<WalletInner, WalletRestoreError> {
+    if !seed.is_valid() {
+        return Err(WalletRestoreError::InvalidSeed);
+    }
+    if gap_limit == 0 {
+        return Err(WalletRestoreError::ZeroGapLimit);
+    }
+    let mut wallet = new_walletinner_from_master_key(seed.derive_master_extended_secret_key()?, account)?;
+    let chains = if wallet.is_hardened() { vec![EXTERNAL_CHAIN] } else { vec![EXTERNAL_CHAIN, INTERNAL_CHAIN] };
+    for chain in chains.iter() {
+        wallet.extend_chain_key_sets(*chain, gap_limit)?;
+    }
+    rescan_walletinner(&mut wallet, mci).await?;
+    loop {
+        let first_new_key_index = wallet.get_keysets_count();
+        let mut extended = false;
+        for chain in chains.iter() {
+            let needed_count = wallet.get_last_used_chain_index(*chain).map(|index| index + 1).unwrap_or(0) + gap_limit;
+            if wallet.get_chain_key_sets_count(*chain) < needed_count {
+                println!("restore found key set {} of chain {} used, deriving up to {}", needed_count - gap_limit - 1, chain, needed_count);
+                wallet.extend_chain_key_sets(*chain, needed_count)?;
+                extended = true;
+            }
+        }
+        if !extended {
+            break;
+        }
+        scan_new_key_sets(&mut wallet, mci, first_new_key_index).await?;
+    }
+    Ok(wallet)
+}
+
+/// Adds the resources of the key sets from `first_key_index`, derived after the chain was scanned. Their outputs are
+/// read from the address index when it is enabled and up to date, otherwise the mainblocks are scanned again for
+/// these key sets only.
+pub async fn scan_new_key_sets(wallet: &mut WalletInner, mci: &mut MaincoreInner, first_key_index: usize) -> Result<(), WalletRestoreError> {
+    let mainblocks_count = mci.get_mainblocks_count();
+    if let Some(address_index) = mci.get_address_index().filter(|address_index| address_index.get_mainblocks_count() == mainblocks_count) {
+        for key_index in first_key_index..wallet.get_keysets_count() {
+            for entry in address_index.get_history(&wallet.get_address(key_index)?) {
+                // only an unspent reward needs the flag, to wait for its maturity
+                let is_reward = mci.get_mainstate().get_output(&entry.tx_hash, entry.output_index).is_some_and(|output| output.is_reward);
+                wallet.add_unspent_resource(entry.tx_hash.clone(), entry.output_index, entry.value, key_index, entry.mainblock_height, is_reward);
+                if !entry.is_unspent() {
+                    wallet.update_resource_to_spent(entry.tx_hash, entry.output_index);
+                }
+            }
+        }
+        return Ok(());
+    }
+    for height in 0..mainblocks_count {
+        let mb = mci.get_mainblock(height).await?;
+        for tx in mb.transactions.into_iter() {
+            wallet.update_key_set_resources(tx, height as u32, first_key_index)?;
+        }
+    }
+    Ok(())
+}
+
+/// Rebuilds the resources and `last_known_height` of the wallet from every mainblock of the main chain.
+pub async fn rescan_walletinner(wallet: &mut WalletInner, mci: &mut MaincoreInner) -> Result<(), WalletRestoreError> {
+    wallet.clear_resources();
+    wallet.set_reward_maturity(mci.get_chain_params().reward_maturity);
+    let mainblocks_count = mci.get_mainblocks_count();
+    for height in 0..mainblocks_count {
+        let mb = mci.get_mainblock(height).await?;
+        for tx in mb.transactions.into_iter() {
+            wallet.update_resources(tx, height as u32)?;
+        }
+    }
+    wallet.set_last_known_height(mainblocks_count.saturating_sub(1));
+    Ok(())
+}
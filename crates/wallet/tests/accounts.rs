@@ -0,0 +1,77 @@
+use std::time::Duration;
+use utility::ecdsa::derivation_path::parse_derivation_path;
+use utility::ecdsa::key_derivation_v1::HARDENED_OFFSET;
+use wallet::wallet_v1::seed_scheme::new_legacy_wallet_seed;
+use wallet::wallet_v1::wallet_file::WalletFile;
+use wallet::wallet_v1::wallet_file::WalletKdfParams;
+use wallet::wallet_v1::wallet_inner::new_account_walletinner;
+use wallet::wallet_v1::wallet_inner::new_hardened_walletinner;
+use wallet::wallet_v1::wallet_inner::WalletInnerError;
+
+const SEED: &str = "accounts seed";
+const PASSPHRASE: &str = "accounts passphrase";
+/// Small enough for the tests to run fast in debug builds.
+const TEST_KDF_PARAMS: WalletKdfParams = WalletKdfParams { memory_kib: 1024, iterations: 1, parallelism: 1 };
+
+#[test]
+fn test_account_chains() {
+    let mut wallet = new_account_walletinner(String::from(SEED), 1).unwrap();
+    assert_eq!(wallet.get_account(), Some(1));
+    let paths: Vec<String> = (0..4).map(|i| wallet.get_derivation_path(i).unwrap().to_string()).collect();
+    assert_eq!(paths, vec!["m/1'/0/0", "m/1'/0/1", "m/1'/0/2", "m/1'/0/3"]);
+
+    // receiving and change key sets follow their own chains
+    wallet.generate_change_key_set().unwrap();
+    wallet.generate_key_set().unwrap();
+    wallet.generate_change_key_set().unwrap();
+    assert_eq!(wallet.get_derivation_path(4).unwrap(), parse_derivation_path("m/1'/1/0").unwrap());
+    assert_eq!(wallet.get_derivation_path(5).unwrap(), parse_derivation_path("m/1'/0/4").unwrap());
+    assert_eq!(wallet.get_derivation_path(6).unwrap(), parse_derivation_path("m/1'/1/1").unwrap());
+    assert_eq!(wallet.get_keypair(6).unwrap().get_derivation_path(), &wallet.get_derivation_path(6).unwrap());
+
+    // each account of a seed has its own addresses
+    let other_account = new_account_walletinner(String::from(SEED), 2).unwrap();
+    assert!(other_account.get_addresses().iter().all(|address| !wallet.get_addresses().contains(address)));
+    assert!(matches!(new_account_walletinner(String::from(SEED), HARDENED_OFFSET), Err(WalletInnerError::InvalidAccount(_))));
+
+    // a hardened wallet keeps its single chain from the master key
+    let mut hardened = new_hardened_walletinner(String::from(SEED)).unwrap();
+    assert_eq!(hardened.get_account(), None);
+    hardened.generate_change_key_set().unwrap();
+    assert_eq!(hardened.get_derivation_path(4).unwrap().to_string(), "m/5'");
+}
+
+#[tokio::test]
+async fn test_account_wallet_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wallet = new_account_walletinner(String::from(SEED), 3).unwrap();
+    wallet.generate_change_key_set().unwrap();
+    let addresses = wallet.get_addresses();
+    let wallet_file = WalletFile::create_with_walletinner(dir.path(), String::from("team"), new_legacy_wallet_seed(String::from(SEED)), wallet, PASSPHRASE, TEST_KDF_PARAMS)
+        .await
+        .unwrap();
+    assert_eq!(wallet_file.get_addresses(), addresses);
+
+    let mut wallet_file = WalletFile::open(dir.path()).await.unwrap();
+    wallet_file.unlock(PASSPHRASE, Duration::from_secs(60)).unwrap();
+    let mut wallet = wallet_file.get_unlocked_wallet().unwrap();
+    assert_eq!(wallet.get_account(), Some(3));
+    assert_eq!(wallet.get_addresses(), addresses);
+    assert_eq!(wallet.get_derivation_path(4).unwrap().to_string(), "m/3'/1/0");
+    wallet.generate_key_set().unwrap();
+    assert_eq!(wallet.get_derivation_path(5).unwrap().to_string(), "m/3'/0/4");
+    assert!(wallet.get_extended_public_key().is_some());
+}
+
+#[tokio::test]
+async fn test_account_wallet_file_from_wallet_seed() {
+    let dir = tempfile::tempdir().unwrap();
+    let seed = new_legacy_wallet_seed(String::from(SEED));
+    WalletFile::create_from_wallet_seed(dir.path(), String::from("team"), seed, Some(4), PASSPHRASE, TEST_KDF_PARAMS).await.unwrap();
+    let mut wallet_file = WalletFile::open(dir.path()).await.unwrap();
+    assert_eq!(wallet_file.get_addresses(), new_account_walletinner(String::from(SEED), 4).unwrap().get_addresses());
+    wallet_file.unlock(PASSPHRASE, Duration::from_secs(60)).unwrap();
+    let wallet = wallet_file.get_unlocked_wallet().unwrap();
+    assert_eq!(wallet.get_account(), Some(4));
+    assert_eq!(wallet.get_derivation_path(0).unwrap().to_string(), "m/4'/0/0");
+}
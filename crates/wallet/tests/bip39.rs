@@ -0,0 +1,129 @@
+use std::time::Duration;
+use utility::buffer::buffer_reader::BufferReader;
+use utility::ecdsa::key_derivation_v1::derive_bip39_seed;
+use utility::ecdsa::key_derivation_v1::KeyDerivationError;
+use wallet::wallet_v1::bip39::bip39_mnemonic_from_entropy;
+use wallet::wallet_v1::bip39::bip39_mnemonic_to_entropy;
+use wallet::wallet_v1::bip39::check_bip39_mnemonic;
+use wallet::wallet_v1::bip39::generate_bip39_mnemonic;
+use wallet::wallet_v1::bip39::Bip39Error;
+use wallet::wallet_v1::seed_generation::generate_seed;
+use wallet::wallet_v1::seed_scheme::new_bip39_wallet_seed;
+use wallet::wallet_v1::seed_scheme::new_legacy_wallet_seed;
+use wallet::wallet_v1::seed_scheme::unserialize_wallet_seed;
+use wallet::wallet_v1::seed_scheme::SeedScheme;
+use wallet::wallet_v1::seed_scheme::SeedSchemeError;
+use wallet::wallet_v1::wallet_file::WalletFile;
+use wallet::wallet_v1::wallet_file::WalletKdfParams;
+use wallet::wallet_v1::wallet_inner::new_hardened_walletinner;
+use wallet::wallet_v1::wallet_inner::new_hardened_walletinner_from_master_key;
+
+/// Vectors of the BIP-39 reference implementation: entropy, mnemonic and seed with the passphrase "TREZOR".
+const TEST_VECTORS: [(&str, &str, &str); 4] = [
+    (
+        "00000000000000000000000000000000",
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+    ),
+    (
+        "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+        "legal winner thank year wave sausage worth useful legal winner thank yellow",
+        "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+    ),
+    (
+        "ffffffffffffffffffffffffffffffffffffffffffffffff",
+        "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo when",
+        "0cd6e5d827bb62eb8fc1e262254223817fd068a74b5b449cc2f667c3f1f985a76379b43348d952e2265b4cd129090758b3e3c2c49103b5051aac2eaeb890a528",
+    ),
+    (
+        "8080808080808080808080808080808080808080808080808080808080808080",
+        "letter advice cage absurd amount doctor acoustic avoid letter advice cage absurd amount doctor acoustic avoid letter advice cage absurd amount doctor acoustic bless",
+        "c0c519bd0e91a2ed54357d9d1ebef6f5af218a153624cf4f2da911a0ed8f7a09e2ef61af0aca007096df430022f7a2b6fb91661a9589097069720d015e4e982f",
+    ),
+];
+const PASSPHRASE: &str = "bip39 wallet passphrase";
+/// Small enough for the tests to run fast in debug builds.
+const TEST_KDF_PARAMS: WalletKdfParams = WalletKdfParams { memory_kib: 1024, iterations: 1, parallelism: 1 };
+
+fn from_hex(hex_string: &str) -> Vec<u8> {
+    (0..hex_string.len()).step_by(2).map(|i| u8::from_str_radix(&hex_string[i..i + 2], 16).unwrap()).collect()
+}
+
+#[test]
+fn test_bip39_vectors() {
+    for (entropy, mnemonic, seed) in TEST_VECTORS.iter() {
+        assert_eq!(bip39_mnemonic_from_entropy(&from_hex(entropy)).unwrap(), *mnemonic);
+        assert_eq!(bip39_mnemonic_to_entropy(mnemonic).unwrap(), from_hex(entropy));
+        assert_eq!(derive_bip39_seed(mnemonic, "TREZOR").unwrap().to_vec(), from_hex(seed));
+    }
+
+    let mnemonic = generate_bip39_mnemonic(24).unwrap();
+    assert_eq!(mnemonic.split_whitespace().count(), 24);
+    assert!(check_bip39_mnemonic(&mnemonic));
+    assert_eq!(generate_bip39_mnemonic(13), Err(Bip39Error::InvalidWordCount(13)));
+    assert_eq!(bip39_mnemonic_from_entropy(&[0u8; 15]), Err(Bip39Error::InvalidEntropySize(15)));
+    assert_eq!(bip39_mnemonic_to_entropy(&["abandon"; 12].join(" ")), Err(Bip39Error::ChecksumMismatch));
+    assert_eq!(bip39_mnemonic_to_entropy(&["abandon"; 11].join(" ")), Err(Bip39Error::InvalidWordCount(11)));
+    assert_eq!(
+        bip39_mnemonic_to_entropy("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon zoro"),
+        Err(Bip39Error::UnknownWord(String::from("zoro")))
+    );
+    assert!(matches!(derive_bip39_seed(TEST_VECTORS[0].1, "pässword"), Err(KeyDerivationError::NonAsciiBip39Input)));
+}
+
+#[test]
+fn test_seed_schemes() {
+    // a legacy phrase keeps its master key
+    let legacy_phrase = generate_seed();
+    let legacy_seed = new_legacy_wallet_seed(legacy_phrase.clone());
+    assert!(legacy_seed.is_valid());
+    let legacy_wallet = new_hardened_walletinner_from_master_key(legacy_seed.derive_master_extended_secret_key().unwrap()).unwrap();
+    assert_eq!(legacy_wallet.get_addresses(), new_hardened_walletinner(legacy_phrase).unwrap().get_addresses());
+
+    // the BIP-39 passphrase gives another master key
+    let mnemonic = String::from(TEST_VECTORS[1].1);
+    let bip39_seed = new_bip39_wallet_seed(mnemonic.clone(), String::new());
+    assert!(bip39_seed.is_valid());
+    assert!(!new_legacy_wallet_seed(mnemonic.clone()).is_valid());
+    let protected_seed = new_bip39_wallet_seed(mnemonic.clone(), String::from("TREZOR"));
+    let address = |seed: &wallet::wallet_v1::seed_scheme::WalletSeed| {
+        new_hardened_walletinner_from_master_key(seed.derive_master_extended_secret_key().unwrap()).unwrap().get_address(0).unwrap()
+    };
+    assert_ne!(address(&bip39_seed), address(&protected_seed));
+    assert_ne!(address(&bip39_seed), address(&new_legacy_wallet_seed(mnemonic)));
+    assert!(matches!(
+        new_bip39_wallet_seed(["abandon"; 12].join(" "), String::new()).derive_master_extended_secret_key(),
+        Err(SeedSchemeError::InvalidSeed)
+    ));
+    // a passphrase outside ASCII would need the NFKD normalization of the standard
+    let non_ascii_seed = new_bip39_wallet_seed(String::from(TEST_VECTORS[1].1), String::from("pässword"));
+    assert!(!non_ascii_seed.is_valid());
+    assert!(matches!(
+        non_ascii_seed.derive_master_extended_secret_key(),
+        Err(SeedSchemeError::KeyDerivationError(KeyDerivationError::NonAsciiBip39Input))
+    ));
+
+    // the scheme tag is checked when the seed is read back
+    let mut br = BufferReader::new(vec![2, 0, 0]);
+    assert!(matches!(unserialize_wallet_seed(&mut br), Err(SeedSchemeError::UnknownSeedScheme(2))));
+}
+
+#[tokio::test]
+async fn test_bip39_wallet_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let seed = new_bip39_wallet_seed(String::from(TEST_VECTORS[2].1), String::from("TREZOR"));
+    let expected_address = new_hardened_walletinner_from_master_key(seed.derive_master_extended_secret_key().unwrap())
+        .unwrap()
+        .get_address(0)
+        .unwrap();
+    WalletFile::create_from_wallet_seed(dir.path(), String::from("bip39"), seed, None, PASSPHRASE, TEST_KDF_PARAMS).await.unwrap();
+
+    let mut wallet_file = WalletFile::open(dir.path()).await.unwrap();
+    assert_eq!(wallet_file.get_addresses()[0], expected_address);
+    wallet_file.unlock(PASSPHRASE, Duration::from_secs(60)).unwrap();
+    let wallet = wallet_file.get_unlocked_wallet().unwrap();
+    let wallet_seed = wallet.get_wallet_seed();
+    assert_eq!(wallet_seed.get_scheme(), SeedScheme::Bip39);
+    assert_eq!(wallet_seed.get_phrase(), TEST_VECTORS[2].1);
+    assert_eq!(wallet_seed.get_passphrase(), "TREZOR");
+}
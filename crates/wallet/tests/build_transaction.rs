@@ -0,0 +1,180 @@
+use std::path::Path;
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainblock::mainblock::Mainblock;
+use maincore::maincore_inner::maincore_inner::MaincoreInner;
+use maincore::miner::mining_engine::MiningEngine;
+use utility::buffer::buffer_reader::BufferReader;
+use utility::buffer::buffer_writer::BufferWriter;
+use utility::hash::hash::Hash;
+use wallet::wallet_v1::coin_selection::select_coins_bnb;
+use wallet::wallet_v1::coin_selection::select_coins_largest_first;
+use wallet::wallet_v1::resource::unserialize_resource;
+use wallet::wallet_v1::wallet_inner::new_hardened_walletinner;
+use wallet::wallet_v1::wallet_inner::WalletInner;
+use wallet::wallet_v1::wallet_inner::WalletInnerError;
+
+const FEE_RATE: u64 = 10;
+
+async fn open_maincore(path: &Path) -> MaincoreInner {
+    let mut mci = MaincoreInner::new(path, ChainParams::regtest()).await.unwrap();
+    mci.init().await.unwrap();
+    mci.load_mainheaders().await.unwrap();
+    mci
+}
+
+/// Mines the txspool on top of the tip and pushes the maintxs of the mainblock into the wallet.
+async fn mine_tip(mci: &mut MaincoreInner, wallet: &mut WalletInner, reward_address: &Hash) {
+    mci.get_miner_mut().set_reward_address(reward_address.clone());
+    let template = mci.build_mainblock_template().unwrap();
+    let mh = MiningEngine::new(1).mine(&template.get_candidate_mainheader()).unwrap().unwrap();
+    let mb = Mainblock::new(mh, template.transactions);
+    mci.add_confirmed_mainblock(mb.clone()).await.unwrap();
+    let height = mci.get_mainblocks_count() - 1;
+    wallet.set_reward_maturity(mci.get_chain_params().reward_maturity);
+    for tx in mb.transactions.into_iter() {
+        wallet.update_resources(tx, height as u32).unwrap();
+    }
+    wallet.set_last_known_height(height);
+}
+
+fn get_node_balance(mci: &MaincoreInner, wallet: &WalletInner) -> u64 {
+    wallet.get_addresses().iter().map(|address| mci.get_balance(address)).sum()
+}
+
+#[test]
+fn test_coin_selection() {
+    // 5 + 3 matches the target without change, largest-first would take 7 + 5
+    let values = [7, 5, 3, 2];
+    let selection = select_coins_bnb(&values, 8, 0).unwrap();
+    let mut indexes = selection.indexes.clone();
+    indexes.sort();
+    assert_eq!(indexes, vec![1, 2]);
+    assert!(!selection.with_change);
+
+    // no subset falls in [target, target + cost_of_change]
+    assert_eq!(select_coins_bnb(&[10, 10], 5, 1), None);
+    let selection = select_coins_largest_first(&[4, 10, 6], 12, 1).unwrap();
+    assert_eq!((selection.indexes, selection.selected_value, selection.with_change), (vec![1, 2], 16, true));
+    // an excess below the cost of change goes to the fee
+    assert!(!select_coins_largest_first(&[10, 6], 15, 2).unwrap().with_change);
+    assert_eq!(select_coins_largest_first(&[4, 10], 15, 1), None);
+}
+
+#[tokio::test]
+async fn test_build_transaction() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mci = open_maincore(dir.path()).await;
+    let mut wallet = new_hardened_walletinner(String::from("build transaction seed")).unwrap();
+    let miner_address = Hash::compute_hash(b"miner address");
+    let recipient = Hash::compute_hash(b"recipient address");
+
+    // a single mature reward for the wallet
+    let wallet_address = wallet.get_address(0).unwrap();
+    mine_tip(&mut mci, &mut wallet, &wallet_address).await;
+    for _ in 0..mci.get_chain_params().reward_maturity {
+        mine_tip(&mut mci, &mut wallet, &miner_address).await;
+    }
+    let reward_value = wallet.get_balance();
+    assert_eq!(reward_value, get_node_balance(&mci, &wallet));
+
+    assert!(matches!(wallet.build_transaction(&[], FEE_RATE), Err(WalletInnerError::NoRecipient)));
+    assert!(matches!(wallet.build_transaction(&[(recipient.clone(), 0)], FEE_RATE), Err(WalletInnerError::ZeroRecipientValue)));
+    assert!(matches!(
+        wallet.build_transaction(&[(recipient.clone(), reward_value)], FEE_RATE),
+        Err(WalletInnerError::InsufficientFunds { .. })
+    ));
+
+    let keysets_count = wallet.get_keysets_count();
+    let tx = wallet.build_transaction(&[(recipient.clone(), reward_value / 2)], FEE_RATE).unwrap();
+    assert!(tx.verify_signatures());
+    assert_eq!(tx.vin.len(), 1);
+    assert_eq!(tx.vout.len(), 2);
+    // the change goes to a new key set and the fee pays for the size of the signed maintx
+    assert_eq!(wallet.get_keysets_count(), keysets_count + 1);
+    assert_eq!(tx.vout[1].get_address().unwrap(), wallet.get_address(keysets_count).unwrap());
+    let outputs_value: u64 = tx.vout.iter().map(|vout| vout.get_value().unwrap()).sum();
+    assert_eq!(reward_value - outputs_value, tx.get_serialization_size() as u64 * FEE_RATE);
+
+    // the pending resource cannot be selected again
+    assert!(matches!(
+        wallet.build_transaction(&[(recipient.clone(), 1000)], FEE_RATE),
+        Err(WalletInnerError::InsufficientFunds { available: 0, .. })
+    ));
+
+    mci.add_maintx(tx).unwrap();
+    mine_tip(&mut mci, &mut wallet, &miner_address).await;
+    assert_eq!(mci.get_balance(&recipient), reward_value / 2);
+    assert_eq!(wallet.get_balance(), get_node_balance(&mci, &wallet));
+    assert_eq!(wallet.get_balance(), outputs_value - reward_value / 2);
+
+    // the change can be spent in turn
+    let tx = wallet.build_transaction(&[(recipient.clone(), 1000)], FEE_RATE).unwrap();
+    mci.add_maintx(tx).unwrap();
+    mine_tip(&mut mci, &mut wallet, &miner_address).await;
+    assert_eq!(mci.get_balance(&recipient), reward_value / 2 + 1000);
+    assert_eq!(wallet.get_balance(), get_node_balance(&mci, &wallet));
+}
+
+#[tokio::test]
+async fn test_immature_reward_and_release_transaction() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mci = open_maincore(dir.path()).await;
+    let mut wallet = new_hardened_walletinner(String::from("immature reward seed")).unwrap();
+    let miner_address = Hash::compute_hash(b"miner address");
+    let recipient = Hash::compute_hash(b"recipient address");
+    let reward_maturity = mci.get_chain_params().reward_maturity;
+
+    let wallet_address = wallet.get_address(0).unwrap();
+    mine_tip(&mut mci, &mut wallet, &wallet_address).await;
+    let reward_height = wallet.get_last_known_height();
+    for _ in 0..reward_maturity - 2 {
+        mine_tip(&mut mci, &mut wallet, &miner_address).await;
+    }
+    let reward_value = wallet.get_balance();
+    assert!(reward_value > 0);
+    // the next mainblock is one short of the maturity of the reward
+    assert_eq!(wallet.get_last_known_height() + 1, reward_height + reward_maturity - 1);
+    assert!(matches!(
+        wallet.build_transaction(&[(recipient.clone(), 1000)], FEE_RATE),
+        Err(WalletInnerError::InsufficientFunds { available: 0, .. })
+    ));
+
+    mine_tip(&mut mci, &mut wallet, &miner_address).await;
+    let tx = wallet.build_transaction(&[(recipient.clone(), 1000)], FEE_RATE).unwrap();
+    assert!(matches!(
+        wallet.build_transaction(&[(recipient.clone(), 1000)], FEE_RATE),
+        Err(WalletInnerError::InsufficientFunds { available: 0, .. })
+    ));
+
+    // an abandoned maintx gives its resources back
+    wallet.release_transaction(&tx).unwrap();
+    let tx = wallet.build_transaction(&[(recipient.clone(), 1000)], FEE_RATE).unwrap();
+    mci.add_maintx(tx).unwrap();
+    mine_tip(&mut mci, &mut wallet, &miner_address).await;
+    assert_eq!(mci.get_balance(&recipient), 1000);
+    assert_eq!(wallet.get_balance(), get_node_balance(&mci, &wallet));
+}
+
+#[tokio::test]
+async fn test_resource_serialization() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mci = open_maincore(dir.path()).await;
+    let mut wallet = new_hardened_walletinner(String::from("resource serialization seed")).unwrap();
+    let recipient = Hash::compute_hash(b"recipient address");
+
+    let wallet_address = wallet.get_address(0).unwrap();
+    for _ in 0..mci.get_chain_params().reward_maturity + 1 {
+        mine_tip(&mut mci, &mut wallet, &wallet_address).await;
+    }
+    wallet.build_transaction(&[(recipient.clone(), 1000)], FEE_RATE).unwrap();
+    let resource = wallet.get_resources().iter().find(|r| !r.available).unwrap().clone();
+    assert!(resource.is_reward);
+
+    // the height and the reward flag are saved, a pending maintx is not
+    let mut bw = BufferWriter::new();
+    resource.serialize(&mut bw);
+    let loaded = unserialize_resource(&mut BufferReader::new(bw.get_bytes())).unwrap();
+    assert_eq!((loaded.hash, loaded.index, loaded.value), (resource.hash, resource.index, resource.value));
+    assert_eq!((loaded.mainblock_height, loaded.is_reward), (resource.mainblock_height, true));
+    assert!(loaded.available);
+}
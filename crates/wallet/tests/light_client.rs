@@ -0,0 +1,181 @@
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainblock::mainblock::Mainblock;
+use maincore::maincore_inner::maincore_inner::MaincoreInner;
+use maincore::miner::miner::MainblockTemplate;
+use maincore::miner::mining_engine::MiningEngine;
+use maincore::mainheader::mainheader::mine_mainheader_with_cpu;
+use maincore::mainheader::mainheader::Mainheader;
+use maincore::p2p::p2p_light_connection::P2pLightConnection;
+use maincore::p2p::p2p_node::P2pConfig;
+use maincore::p2p::p2p_node::P2pNode;
+use maintx::maintx::maintx::new_reward_transaction;
+use maintx::maintx::maintx::Maintx;
+use maintx::maintx_in::maintx_in::new_maintx_in_ecdsa;
+use maintx::maintx_out::maintx_out::new_ecdsa_maintx_out;
+use utility::ecdsa::ecdsa::sign_messagehash;
+use utility::hash::hash::Hash;
+use wallet::wallet_v1::light_client::LightClient;
+use wallet::wallet_v1::light_client::LightClientError;
+use wallet::wallet_v1::wallet_inner::new_hardened_walletinner;
+use wallet::wallet_v1::wallet_inner::WalletInner;
+
+const WALLET_SEED: &str = "light client seed";
+
+async fn mine_tip(maincore: &Arc<Mutex<MaincoreInner>>, reward_address: &Hash) -> Mainblock {
+    let mut mci = maincore.lock().await;
+    mci.get_miner_mut().set_reward_address(reward_address.clone());
+    let template = mci.build_mainblock_template().unwrap();
+    let mh = MiningEngine::new(1).mine(&template.get_candidate_mainheader()).unwrap().unwrap();
+    let mb = Mainblock::new(mh, template.transactions);
+    mci.add_confirmed_mainblock(mb.clone()).await.unwrap();
+    mb
+}
+
+/// Mines a mainblock holding only a reward transaction on top of `prev`.
+async fn mine_on(maincore: &Arc<Mutex<MaincoreInner>>, prev: &Mainblock, height: usize, reward_address: &Hash) -> Mainblock {
+    let mut mci = maincore.lock().await;
+    let reward_value = mci.get_chain_params().get_mainblock_subsidy(height);
+    let template = MainblockTemplate::new(
+        height,
+        prev.get_hash(),
+        prev.header.get_timestamp() + 1,
+        prev.header.get_bits(),
+        vec![new_reward_transaction(height as u32, reward_value, 0, reward_address.clone())],
+    );
+    let mh = MiningEngine::new(1).mine(&template.get_candidate_mainheader()).unwrap().unwrap();
+    let mb = Mainblock::new(mh, template.transactions);
+    mci.add_confirmed_mainblock(mb.clone()).await.unwrap();
+    mb
+}
+
+async fn get_node_balance(maincore: &Arc<Mutex<MaincoreInner>>, wallet: &WalletInner) -> u64 {
+    let mci = maincore.lock().await;
+    wallet.get_addresses().iter().map(|address| mci.get_balance(address)).sum()
+}
+
+/// `count` mainheaders on top of `prev`, `branch` tells the branches apart.
+fn mine_mainheaders(prev: &Mainheader, count: usize, branch: &[u8]) -> Vec<Mainheader> {
+    let mut mainheaders: Vec<Mainheader> = Vec::new();
+    for _ in 0..count {
+        let prev = mainheaders.last().unwrap_or(prev);
+        let mh = mine_mainheader_with_cpu(1, prev.get_hash(), Hash::compute_hash(branch), prev.get_timestamp() + 1, prev.get_bits()).unwrap();
+        mainheaders.push(mh);
+    }
+    mainheaders
+}
+
+#[tokio::test]
+async fn test_light_client_sync() {
+    let dir = tempfile::tempdir().unwrap();
+    let chain_params = ChainParams::regtest();
+    let mut mci = MaincoreInner::new(dir.path().join("node"), chain_params.clone()).await.unwrap();
+    mci.init().await.unwrap();
+    mci.load_mainheaders().await.unwrap();
+    let maincore = Arc::new(Mutex::new(mci));
+    let p2p = P2pNode::new(maincore.clone(), P2pConfig::new("127.0.0.1:0".parse().unwrap())).await;
+    let node_addr = p2p.start().await.unwrap();
+
+    let mut wallet = new_hardened_walletinner(String::from(WALLET_SEED)).unwrap();
+    let keyset = wallet.get_keypair(0).unwrap();
+    let miner_address = Hash::compute_hash(b"miner address");
+    for _ in 0..chain_params.reward_maturity + 1 {
+        mine_tip(&maincore, &keyset.get_address()).await;
+    }
+    {
+        let mut mci = maincore.lock().await;
+        let (outpoint, output) = mci
+            .get_unspent_outputs(&keyset.get_address())
+            .into_iter()
+            .min_by_key(|(_, output)| output.mainblock_height)
+            .unwrap();
+        let mut tx = Maintx {
+            version: 1,
+            vin: vec![new_maintx_in_ecdsa(outpoint.hash, outpoint.index, keyset.get_public_key_compressed_bytes())],
+            vout: vec![
+                new_ecdsa_maintx_out(1000, Hash::compute_hash(b"recipient address")),
+                new_ecdsa_maintx_out(output.value - 2000, wallet.get_address(1).unwrap()),
+            ],
+        };
+        let signature = sign_messagehash(&keyset, tx.compute_hash()).unwrap();
+        tx.vin[0].set_signature(signature).unwrap();
+        mci.add_maintx(tx).unwrap();
+    }
+    let spend_mb = mine_tip(&maincore, &miner_address).await;
+
+    let mut light_client = LightClient::new(dir.path().join("LightClient"), chain_params.clone());
+    let mut connection = P2pLightConnection::connect(node_addr, chain_params.p2p_magic).await.unwrap();
+    light_client.sync(&mut wallet, &mut connection).await.unwrap();
+    let tip_height = chain_params.reward_maturity + 2;
+    assert_eq!(light_client.get_mainheaders_count(), tip_height + 1);
+    assert_eq!(wallet.get_last_known_height(), tip_height);
+    assert_eq!(wallet.get_balance(), get_node_balance(&maincore, &wallet).await);
+    assert_eq!(light_client.get_maintx_proofs().len(), chain_params.reward_maturity + 2);
+
+    // a proof checked against another mainheader is rejected
+    let (_, mut proofs) = connection.get_maintx_proofs(wallet.get_addresses(), tip_height as u64).await.unwrap();
+    proofs[0].mainblock_height -= 1;
+    let result = light_client.add_maintx_proofs(&mut wallet, tip_height, proofs);
+    assert!(matches!(result, Err(LightClientError::InvalidMaintxProof { .. })));
+
+    // a reorganization replacing the spending mainblock gives the spent output back
+    let prev = maincore.lock().await.get_mainblock(tip_height - 1).await.unwrap();
+    let fork1 = mine_on(&maincore, &prev, tip_height, &miner_address).await;
+    mine_on(&maincore, &fork1, tip_height + 1, &miner_address).await;
+    assert_ne!(maincore.lock().await.get_last_inmem_mainheader().unwrap().get_hash(), spend_mb.get_hash());
+    light_client.sync(&mut wallet, &mut connection).await.unwrap();
+    assert_eq!(light_client.get_mainheaders_count(), tip_height + 2);
+    assert_eq!(wallet.get_last_known_height(), tip_height + 1);
+    assert_eq!(wallet.get_balance(), get_node_balance(&maincore, &wallet).await);
+    assert_eq!(light_client.get_maintx_proofs().len(), chain_params.reward_maturity + 1);
+
+    // a wallet restored from the seed gets its resources back from the saved proofs
+    light_client.save().await.unwrap();
+    let mut restored_client = LightClient::new(dir.path().join("LightClient"), chain_params.clone());
+    assert!(restored_client.load().await.unwrap());
+    let mut restored_wallet = new_hardened_walletinner(String::from(WALLET_SEED)).unwrap();
+    restored_client.replay_maintx_proofs(&mut restored_wallet).unwrap();
+    assert_eq!(restored_client.get_mainheaders_count(), light_client.get_mainheaders_count());
+    assert_eq!(restored_wallet.get_balance(), wallet.get_balance());
+
+    // the resources of a maintx built but not broadcast yet stay pending across a replay
+    wallet.build_transaction(&[(Hash::compute_hash(b"recipient address"), 1000)], 10).unwrap();
+    let get_pending = |wallet: &WalletInner| -> Vec<(Hash, u32)> {
+        wallet.get_resources().iter().filter(|r| !r.available).map(|r| (r.hash.clone(), r.index)).collect()
+    };
+    let pending = get_pending(&wallet);
+    assert!(!pending.is_empty());
+    light_client.replay_maintx_proofs(&mut wallet).unwrap();
+    assert_eq!(get_pending(&wallet), pending);
+
+    p2p.shutdown().await.unwrap();
+}
+
+#[test]
+fn test_better_branch_over_several_batches() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut light_client = LightClient::new(dir.path().join("LightClient"), ChainParams::regtest());
+    let mut wallet = new_hardened_walletinner(String::from(WALLET_SEED)).unwrap();
+    let genesis = light_client.get_mainheader(0).unwrap().clone();
+    let synced = mine_mainheaders(&genesis, 3, b"synced");
+    assert_eq!(light_client.add_mainheaders(&mut wallet, &synced).unwrap(), 3);
+
+    // the first batch of the better branch has less work, it is kept aside and asked to be continued
+    let better = mine_mainheaders(&genesis, 4, b"better");
+    assert_eq!(light_client.add_mainheaders(&mut wallet, &better[..2]).unwrap(), 2);
+    assert_eq!(light_client.get_last_mainheader().get_hash(), synced[2].get_hash());
+    assert_eq!(light_client.get_mainheader_locator()[0], better[1].get_hash());
+    // mainheaders sent again are skipped
+    assert_eq!(light_client.add_mainheaders(&mut wallet, &better[..2]).unwrap(), 0);
+
+    // the next batch makes it better
+    assert_eq!(light_client.add_mainheaders(&mut wallet, &better[2..]).unwrap(), 2);
+    assert_eq!(light_client.get_mainheaders_count(), 5);
+    assert_eq!(light_client.get_last_mainheader().get_hash(), better[3].get_hash());
+    assert_eq!(light_client.get_mainheader_locator()[0], better[3].get_hash());
+
+    // a branch without a known parent is rejected
+    let unknown = mine_mainheaders(&synced[2], 1, b"unknown");
+    assert!(matches!(light_client.add_mainheaders(&mut wallet, &unknown), Err(LightClientError::UnknownParent(_))));
+}
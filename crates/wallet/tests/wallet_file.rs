@@ -0,0 +1,115 @@
+use std::time::Duration;
+use maintx::maintx::maintx::new_reward_transaction;
+use wallet::wallet_v1::wallet_file::WalletFile;
+use wallet::wallet_v1::wallet_file::WalletFileError;
+use wallet::wallet_v1::wallet_file::WalletKdfParams;
+
+const SEED: &str = "wallet file seed";
+const PASSPHRASE: &str = "correct horse battery staple";
+/// Small enough for the tests to run fast in debug builds.
+const TEST_KDF_PARAMS: WalletKdfParams = WalletKdfParams { memory_kib: 1024, iterations: 1, parallelism: 1 };
+const UNLOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[tokio::test]
+async fn test_wallet_file_lock_and_persistence() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wallet_file = WalletFile::create(dir.path(), String::from("main"), String::from(SEED), PASSPHRASE, TEST_KDF_PARAMS)
+        .await
+        .unwrap();
+    assert!(matches!(
+        WalletFile::create(dir.path(), String::from("main"), String::from(SEED), PASSPHRASE, TEST_KDF_PARAMS).await,
+        Err(WalletFileError::AlreadyExists)
+    ));
+
+    // a locked wallet shows its addresses, but not its keys
+    assert!(wallet_file.is_locked());
+    assert!(matches!(wallet_file.get_unlocked_wallet(), Err(WalletFileError::Locked)));
+    assert!(matches!(wallet_file.unlock("wrong passphrase", UNLOCK_TIMEOUT), Err(WalletFileError::DecryptionFailed)));
+    let addresses = wallet_file.get_addresses();
+
+    wallet_file.unlock(PASSPHRASE, UNLOCK_TIMEOUT).unwrap();
+    let mut wallet = wallet_file.get_unlocked_wallet().unwrap();
+    assert_eq!(wallet.get_seed(), SEED);
+    assert_eq!(wallet.get_addresses(), addresses);
+    wallet.generate_key_set().unwrap();
+    let new_address = wallet.get_address(addresses.len()).unwrap();
+    let secret_key_bytes = wallet.get_secret_key_bytes(addresses.len()).unwrap();
+    wallet.update_resources(new_reward_transaction(3, 5000, 0, new_address.clone()), 3).unwrap();
+    wallet.set_last_known_height(3);
+    drop(wallet);
+    wallet_file.save().await.unwrap();
+    wallet_file.lock();
+    assert!(wallet_file.is_locked());
+    assert_eq!(wallet_file.get_balance(), 5000);
+
+    // what the wallet learned is saved in clear, its keys are restored by the passphrase
+    let mut wallet_file = WalletFile::open(dir.path()).await.unwrap();
+    assert!(wallet_file.is_locked());
+    assert_eq!(wallet_file.get_metadata().label, "main");
+    assert_eq!(wallet_file.get_addresses().last(), Some(&new_address));
+    assert_eq!(wallet_file.get_balance(), 5000);
+    assert_eq!(wallet_file.get_last_known_height(), 3);
+    wallet_file.unlock(PASSPHRASE, UNLOCK_TIMEOUT).unwrap();
+    let wallet = wallet_file.get_unlocked_wallet().unwrap();
+    assert_eq!(wallet.get_secret_key_bytes(addresses.len()).unwrap(), secret_key_bytes);
+    assert_eq!(wallet.get_balance(), 5000);
+    drop(wallet);
+
+    // a new passphrase replaces the old one
+    wallet_file.change_passphrase(PASSPHRASE, "new passphrase").await.unwrap();
+    let mut wallet_file = WalletFile::open(dir.path()).await.unwrap();
+    assert!(matches!(wallet_file.unlock(PASSPHRASE, UNLOCK_TIMEOUT), Err(WalletFileError::DecryptionFailed)));
+    wallet_file.unlock("new passphrase", UNLOCK_TIMEOUT).unwrap();
+    assert_eq!(wallet_file.get_unlocked_wallet().unwrap().get_seed(), SEED);
+}
+
+#[tokio::test]
+async fn test_wallet_file_unlock_timeout() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wallet_file = WalletFile::create(dir.path(), String::from("main"), String::from(SEED), PASSPHRASE, TEST_KDF_PARAMS)
+        .await
+        .unwrap();
+    wallet_file.unlock(PASSPHRASE, Duration::from_millis(100)).unwrap();
+    assert!(!wallet_file.is_locked());
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(wallet_file.is_locked());
+    assert!(matches!(wallet_file.get_unlocked_wallet(), Err(WalletFileError::Locked)));
+
+    // a wallet in use when the timeout expires is locked once released, keeping what it learned
+    wallet_file.unlock(PASSPHRASE, Duration::from_millis(100)).unwrap();
+    let mut wallet = wallet_file.get_unlocked_wallet().unwrap();
+    let address = wallet.get_address(0).unwrap();
+    wallet.update_resources(new_reward_transaction(3, 5000, 0, address), 3).unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    drop(wallet);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(wallet_file.is_locked());
+    assert_eq!(wallet_file.get_balance(), 5000);
+
+    // the timeout of a previous unlock leaves a newer one alone
+    wallet_file.unlock(PASSPHRASE, Duration::from_millis(100)).unwrap();
+    wallet_file.lock();
+    wallet_file.unlock(PASSPHRASE, UNLOCK_TIMEOUT).unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!wallet_file.is_locked());
+}
+
+#[tokio::test]
+async fn test_wallet_file_rejects_changed_kdf_params() {
+    let dir = tempfile::tempdir().unwrap();
+    let wallet_file = WalletFile::create(dir.path(), String::from("main"), String::from(SEED), PASSPHRASE, TEST_KDF_PARAMS)
+        .await
+        .unwrap();
+    // the parameters are authenticated: lowering them makes the secret undecryptable
+    let mut rawbytes = wallet_file.serialize();
+    // the file ends with the iterations, the parallelism, the salt, the nonce and the encrypted seed and master key,
+    // the seed being its scheme tag, its phrase and an empty passphrase
+    let ciphertext_size = (1 + (1 + SEED.len()) + 1) + (1 + 64) + 16;
+    let iterations_position = rawbytes.len() - (1 + (1 + 16) + (1 + 24) + (1 + ciphertext_size)) - 1;
+    assert_eq!(rawbytes[iterations_position], 1);
+    rawbytes[iterations_position] = 2;
+    std::fs::write(dir.path().join("Wallet"), &rawbytes).unwrap();
+    let mut wallet_file = WalletFile::open(dir.path()).await.unwrap();
+    assert_eq!(wallet_file.get_kdf_params().iterations, 2);
+    assert!(matches!(wallet_file.unlock(PASSPHRASE, UNLOCK_TIMEOUT), Err(WalletFileError::DecryptionFailed)));
+}
@@ -0,0 +1,139 @@
+use std::path::Path;
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainblock::mainblock::Mainblock;
+use maincore::maincore_inner::maincore_inner::MaincoreInner;
+use maincore::miner::mining_engine::MiningEngine;
+use utility::ecdsa::derivation_path::EXTERNAL_CHAIN;
+use utility::ecdsa::derivation_path::INTERNAL_CHAIN;
+use utility::hash::hash::Hash;
+use wallet::wallet_v1::seed_generation::generate_seed;
+use wallet::wallet_v1::wallet_inner::new_account_walletinner;
+use wallet::wallet_v1::wallet_inner::new_hardened_walletinner;
+use wallet::wallet_v1::wallet_inner::WalletInner;
+use wallet::wallet_v1::wallet_restore::rescan_walletinner;
+use wallet::wallet_v1::wallet_restore::restore_walletinner_from_seed;
+use wallet::wallet_v1::wallet_restore::WalletRestoreError;
+
+async fn open_maincore(path: &Path) -> MaincoreInner {
+    let mut mci = MaincoreInner::new(path, ChainParams::regtest()).await.unwrap();
+    mci.init().await.unwrap();
+    mci.load_mainheaders().await.unwrap();
+    mci
+}
+
+async fn mine_tip(mci: &mut MaincoreInner, reward_address: &Hash) {
+    mci.get_miner_mut().set_reward_address(reward_address.clone());
+    let template = mci.build_mainblock_template().unwrap();
+    let mh = MiningEngine::new(1).mine(&template.get_candidate_mainheader()).unwrap().unwrap();
+    mci.add_confirmed_mainblock(Mainblock::new(mh, template.transactions)).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_restore_with_gap_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mci = open_maincore(dir.path()).await;
+    let seed = generate_seed();
+    let mut original = new_hardened_walletinner(seed.clone()).unwrap();
+    original.extend_key_sets(13).unwrap();
+
+    // key set 12 is only found once key set 6 was found
+    for index in [0, 6, 12] {
+        mine_tip(&mut mci, &original.get_address(index).unwrap()).await;
+    }
+    mine_tip(&mut mci, &Hash::compute_hash(b"miner address")).await;
+    let balance: u64 = original.get_addresses().iter().map(|address| mci.get_balance(address)).sum();
+
+    let restored = restore_walletinner_from_seed(&seed, None, 7, &mut mci).await.unwrap();
+    assert_eq!(restored.get_balance(), balance);
+    assert_eq!(restored.get_keysets_count(), 13 + 7);
+    assert_eq!(restored.get_addresses()[..13], original.get_addresses()[..]);
+    assert_eq!(restored.get_last_known_height(), 4);
+    assert_eq!(restored.get_last_used_keyset_index(), Some(12));
+
+    // a smaller gap limit stops before key set 6
+    let restored = restore_walletinner_from_seed(&seed, None, 5, &mut mci).await.unwrap();
+    assert_eq!(restored.get_keysets_count(), 6);
+    assert_eq!(restored.get_balance(), mci.get_balance(&original.get_address(0).unwrap()));
+
+    assert!(matches!(restore_walletinner_from_seed(&seed, None, 0, &mut mci).await, Err(WalletRestoreError::ZeroGapLimit)));
+    assert!(matches!(restore_walletinner_from_seed("not a seed phrase", None, 7, &mut mci).await, Err(WalletRestoreError::InvalidSeed)));
+}
+
+#[tokio::test]
+async fn test_restore_account_with_gap_limit_on_both_chains() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mci = open_maincore(dir.path()).await;
+    let seed = generate_seed();
+    let mut original = new_account_walletinner(seed.clone(), 2).unwrap();
+    original.extend_chain_key_sets(EXTERNAL_CHAIN, 7).unwrap();
+    original.extend_chain_key_sets(INTERNAL_CHAIN, 6).unwrap();
+    let find_address = |path: &str| {
+        let index = (0..original.get_keysets_count()).find(|i| original.get_derivation_path(*i).unwrap().to_string() == path).unwrap();
+        original.get_address(index).unwrap()
+    };
+
+    // change key set 5 is beyond the receiving key sets used
+    for path in ["m/2'/0/0", "m/2'/0/6", "m/2'/1/5"] {
+        mine_tip(&mut mci, &find_address(path)).await;
+    }
+    mine_tip(&mut mci, &Hash::compute_hash(b"miner address")).await;
+    let balance: u64 = original.get_addresses().iter().map(|address| mci.get_balance(address)).sum();
+
+    let restored = restore_walletinner_from_seed(&seed, Some(2), 7, &mut mci).await.unwrap();
+    assert_eq!(restored.get_account(), Some(2));
+    assert_eq!(restored.get_balance(), balance);
+    assert_eq!(restored.get_chain_key_sets_count(EXTERNAL_CHAIN), 6 + 1 + 7);
+    assert_eq!(restored.get_chain_key_sets_count(INTERNAL_CHAIN), 5 + 1 + 7);
+    assert_eq!(restored.get_last_used_chain_index(EXTERNAL_CHAIN), Some(6));
+    assert_eq!(restored.get_last_used_chain_index(INTERNAL_CHAIN), Some(5));
+
+    // a smaller gap limit stops before receiving key set 6 and change key set 5
+    let restored = restore_walletinner_from_seed(&seed, Some(2), 5, &mut mci).await.unwrap();
+    assert_eq!(restored.get_chain_key_sets_count(EXTERNAL_CHAIN), 1 + 5);
+    assert_eq!(restored.get_chain_key_sets_count(INTERNAL_CHAIN), 5);
+    assert_eq!(restored.get_balance(), mci.get_balance(&find_address("m/2'/0/0")));
+
+    // another account of the seed has none of these funds
+    let other_account = restore_walletinner_from_seed(&seed, Some(3), 7, &mut mci).await.unwrap();
+    assert_eq!(other_account.get_balance(), 0);
+}
+
+#[tokio::test]
+async fn test_restore_with_address_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mci = open_maincore(dir.path()).await;
+    let seed = generate_seed();
+    let mut original = new_hardened_walletinner(seed.clone()).unwrap();
+    original.extend_key_sets(13).unwrap();
+    for index in [0, 6, 12] {
+        mine_tip(&mut mci, &original.get_address(index).unwrap()).await;
+    }
+    for _ in 0..mci.get_chain_params().reward_maturity {
+        mine_tip(&mut mci, &Hash::compute_hash(b"miner address")).await;
+    }
+    // one of the rewards is spent
+    rescan_walletinner(&mut original, &mut mci).await.unwrap();
+    let tx = original.build_transaction(&[(Hash::compute_hash(b"recipient address"), 1000)], 10).unwrap();
+    mci.add_maintx(tx).unwrap();
+    mine_tip(&mut mci, &Hash::compute_hash(b"miner address")).await;
+
+    let get_resources = |wallet: &WalletInner| -> Vec<(Hash, u32, u64, usize, u32, bool, bool)> {
+        let mut resources: Vec<_> = wallet
+            .get_resources()
+            .iter()
+            .map(|r| (r.hash.clone(), r.index, r.value, r.key_index, r.mainblock_height, r.is_reward, r.is_unspent_resource()))
+            .collect();
+        resources.sort();
+        resources
+    };
+    let scanned = restore_walletinner_from_seed(&seed, None, 7, &mut mci).await.unwrap();
+    mci.enable_address_index().await.unwrap();
+    let indexed = restore_walletinner_from_seed(&seed, None, 7, &mut mci).await.unwrap();
+    // the change of the spend went to key set 13
+    assert_eq!(indexed.get_keysets_count(), 14 + 7);
+    assert_eq!(scanned.get_keysets_count(), 14 + 7);
+    assert_eq!(get_resources(&indexed), get_resources(&scanned));
+    assert!(indexed.get_resources().iter().any(|r| !r.is_unspent_resource()));
+    let balance: u64 = indexed.get_addresses().iter().map(|address| mci.get_balance(address)).sum();
+    assert_eq!(indexed.get_balance(), balance);
+}
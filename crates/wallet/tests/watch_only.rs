@@ -0,0 +1,119 @@
+use std::path::Path;
+use maincore::chain_params::chain_params::ChainParams;
+use maincore::mainblock::mainblock::Mainblock;
+use maincore::maincore_inner::maincore_inner::MaincoreInner;
+use maincore::miner::mining_engine::MiningEngine;
+use utility::ecdsa::ecdsa::derive_child_key_set;
+use utility::ecdsa::ecdsa::derive_child_public_key_set;
+use utility::ecdsa::key_derivation_v1::derive_master_extended_secret_key;
+use utility::ecdsa::key_derivation_v1::unserialize_extended_public_key;
+use utility::ecdsa::key_derivation_v1::unserialize_extended_public_key_hex;
+use utility::ecdsa::key_derivation_v1::KeyDerivationError;
+use utility::hash::hash::Hash;
+use wallet::wallet_v1::wallet_inner::new_hardened_walletinner;
+use wallet::wallet_v1::wallet_inner::new_account_walletinner;
+use wallet::wallet_v1::wallet_inner::new_watch_only_walletinner;
+use wallet::wallet_v1::wallet_inner::WalletInner;
+use wallet::wallet_v1::wallet_inner::WalletInnerError;
+
+const WALLET_SEED: &str = "watch only seed";
+const FEE_RATE: u64 = 10;
+
+async fn open_maincore(path: &Path) -> MaincoreInner {
+    let mut mci = MaincoreInner::new(path, ChainParams::regtest()).await.unwrap();
+    mci.init().await.unwrap();
+    mci.load_mainheaders().await.unwrap();
+    mci
+}
+
+/// Mines the txspool on top of the tip and pushes the maintxs of the mainblock into the wallets.
+async fn mine_tip(mci: &mut MaincoreInner, wallets: &mut [&mut WalletInner], reward_address: &Hash) {
+    mci.get_miner_mut().set_reward_address(reward_address.clone());
+    let template = mci.build_mainblock_template().unwrap();
+    let mh = MiningEngine::new(1).mine(&template.get_candidate_mainheader()).unwrap().unwrap();
+    let mb = Mainblock::new(mh, template.transactions);
+    mci.add_confirmed_mainblock(mb.clone()).await.unwrap();
+    let height = mci.get_mainblocks_count() - 1;
+    for wallet in wallets.iter_mut() {
+        wallet.set_reward_maturity(mci.get_chain_params().reward_maturity);
+        for tx in mb.transactions.iter() {
+            wallet.update_resources(tx.clone(), height as u32).unwrap();
+        }
+        wallet.set_last_known_height(height);
+    }
+}
+
+#[test]
+fn test_extended_public_key() {
+    let master = derive_master_extended_secret_key(WALLET_SEED).unwrap();
+    let xpub = master.get_extended_public_key().unwrap();
+    let hex_string = xpub.to_hex_string();
+    assert_eq!(unserialize_extended_public_key_hex(&hex_string).unwrap(), xpub);
+
+    // the public derivation gives the public keys of the non-hardened secret derivation
+    for derivation_index in 0..4 {
+        let keyset = derive_child_key_set(&master, derivation_index, false).unwrap();
+        let public_keyset = derive_child_public_key_set(&xpub, derivation_index).unwrap();
+        assert_eq!(public_keyset, keyset.get_public_key_set());
+    }
+
+    let mut bytes = xpub.serialize();
+    bytes[10] ^= 1;
+    assert!(matches!(unserialize_extended_public_key(&bytes), Err(KeyDerivationError::ExtendedPublicKeyChecksumMismatch)));
+    assert!(matches!(unserialize_extended_public_key(&bytes[1..]), Err(KeyDerivationError::InvalidExtendedPublicKey)));
+    assert!(unserialize_extended_public_key_hex("not an extended public key").is_err());
+
+    // a hardened wallet has no extended public key to share
+    assert!(new_hardened_walletinner(String::from(WALLET_SEED)).unwrap().get_extended_public_key().is_none());
+}
+
+#[tokio::test]
+async fn test_watch_only_wallet() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mci = open_maincore(dir.path()).await;
+    let mut wallet = new_account_walletinner(String::from(WALLET_SEED), 0).unwrap();
+    let xpub = unserialize_extended_public_key_hex(&wallet.get_extended_public_key().unwrap().to_hex_string()).unwrap();
+    let mut watch_only = new_watch_only_walletinner(xpub, 0).unwrap();
+    assert!(watch_only.is_watch_only());
+    assert_eq!(watch_only.get_addresses(), wallet.get_addresses());
+    wallet.generate_key_set().unwrap();
+    watch_only.generate_key_set().unwrap();
+    assert_eq!(watch_only.get_addresses(), wallet.get_addresses());
+
+    let miner_address = Hash::compute_hash(b"miner address");
+    let recipient = Hash::compute_hash(b"recipient address");
+    let wallet_address = watch_only.get_address(2).unwrap();
+    mine_tip(&mut mci, &mut [&mut wallet, &mut watch_only], &wallet_address).await;
+    for _ in 0..mci.get_chain_params().reward_maturity {
+        mine_tip(&mut mci, &mut [&mut wallet, &mut watch_only], &miner_address).await;
+    }
+    let reward_value = watch_only.get_balance();
+    assert_eq!(reward_value, mci.get_balance(&wallet_address));
+    assert_eq!(wallet.get_balance(), reward_value);
+
+    // the watch-only wallet holds no secret
+    assert!(matches!(watch_only.get_keypair(0), Err(WalletInnerError::WatchOnly)));
+    assert!(matches!(watch_only.get_master_extended_secret_key(), Err(WalletInnerError::WatchOnly)));
+    assert!(matches!(watch_only.build_transaction(&[(recipient.clone(), 1000)], FEE_RATE), Err(WalletInnerError::WatchOnly)));
+
+    let mut tx = watch_only.build_unsigned_transaction(&[(recipient.clone(), reward_value / 2)], FEE_RATE).unwrap();
+    assert!(!tx.verify_signatures());
+    assert!(matches!(watch_only.sign_transaction(&mut tx), Err(WalletInnerError::WatchOnly)));
+    // the change goes to an address the spending wallet derives too
+    wallet.generate_change_key_set().unwrap();
+    assert_eq!(tx.vout[1].get_address().unwrap(), wallet.get_address(wallet.get_keysets_count() - 1).unwrap());
+
+    // a maintx spending another wallet's output cannot be signed
+    let mut foreign_tx = tx.clone();
+    let other = new_account_walletinner(String::from("another seed"), 0).unwrap();
+    assert!(matches!(other.sign_transaction(&mut foreign_tx), Err(WalletInnerError::UnknownInputPublicKey(0))));
+
+    wallet.sign_transaction(&mut tx).unwrap();
+    assert!(tx.verify_signatures());
+    mci.add_maintx(tx).unwrap();
+    mine_tip(&mut mci, &mut [&mut wallet, &mut watch_only], &miner_address).await;
+    assert_eq!(mci.get_balance(&recipient), reward_value / 2);
+    let node_balance: u64 = watch_only.get_addresses().iter().map(|address| mci.get_balance(address)).sum();
+    assert_eq!(watch_only.get_balance(), node_balance);
+    assert_eq!(wallet.get_balance(), node_balance);
+}